@@ -1,5 +1,9 @@
 use crate::{add_builtin, SloshVm};
-use slvm::{VMError, VMResult, Value};
+use slvm::{VMError, VMMap, VMResult, Value};
+
+mod bytes;
+mod persistent;
+mod set;
 
 pub fn vec_slice(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
     let (vector, start, end) = match registers.len() {
@@ -31,14 +35,14 @@ pub fn vec_slice(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
     };
     let len = vector.len();
     if start == len && end <= len {
-        Ok(vm.alloc_vector(Vec::new()))
+        vm.alloc_vector(Vec::new())
     } else if start >= len || end > len {
         Err(VMError::new_vm(
             "vec-slice: Invalid arguments- out of bounds".to_string(),
         ))
     } else {
         let new_vec = vector[start..end].to_vec();
-        Ok(vm.alloc_vector(new_vec))
+        vm.alloc_vector(new_vec)
     }
 }
 
@@ -59,7 +63,7 @@ pub fn vec_to_list(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
         let mut last = Value::Nil;
         for item in vector.iter().rev() {
             let old_last = last;
-            last = unsafe_vm.alloc_pair(*item, old_last);
+            last = unsafe_vm.alloc_pair(*item, old_last)?;
         }
         Ok(last)
     } else {
@@ -109,12 +113,41 @@ pub fn hash_keys(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
         for key in map.keys() {
             keys.push(*key);
         }
-        Ok(vm.alloc_vector(keys))
+        vm.alloc_vector(keys)
     } else {
         Err(VMError::new_vm("takes one argument (hash-map)".to_string()))
     }
 }
 
+pub fn make_weak_hash(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if !registers.len().is_multiple_of(2) {
+        return Err(VMError::new_vm(
+            "make-weak-hash: Invalid arguments (must be even, [key val]*)".to_string(),
+        ));
+    }
+    let mut map = VMMap::with_capacity(registers.len() / 2);
+    for kv in registers.chunks(2) {
+        map.insert(kv[0], kv[1])?;
+    }
+    vm.alloc_weak_map(map)
+}
+
+pub fn make_sorted_hash(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if !registers.len().is_multiple_of(2) {
+        return Err(VMError::new_vm(
+            "make-sorted-hash: Invalid arguments (must be even, [key val]*)".to_string(),
+        ));
+    }
+    let map = vm.alloc_map(VMMap::new_sorted())?;
+    let Value::Map(handle) = map else {
+        unreachable!("alloc_map returns a map");
+    };
+    for kv in registers.chunks(2) {
+        vm.map_insert(handle, kv[0], kv[1])?;
+    }
+    Ok(map)
+}
+
 pub fn setup_collection_builtins(env: &mut SloshVm) {
     add_builtin(
         env,
@@ -145,6 +178,51 @@ Section: vector
 ",
     );
 
+    add_builtin(
+        env,
+        "make-weak-hash",
+        make_weak_hash,
+        "Usage: (make-weak-hash key1 val1 .. keyN valN)
+
+Make a new hash map with weak keys.  Entries are removed once their key is garbage collected and
+the map only keeps a value alive while its key is alive (so a value referring to its own key does
+not pin the entry).  Useful for caches and registries keyed by objects.
+
+Section: hashmap
+
+Example:
+(def key (vec 1 2))
+(def cache (make-weak-hash key :cached))
+(test::assert-equal :cached (get cache key))
+(set! key nil)
+(gc)
+(test::assert-equal 0 (len cache))
+",
+    );
+    add_builtin(
+        env,
+        "make-sorted-hash",
+        make_sorted_hash,
+        "Usage: (make-sorted-hash key1 val1 .. keyN valN)
+
+Make a new hash map that keeps its keys in order (other maps keep them in insertion order).  Keys
+are ordered nil, booleans, numbers, chars, strings, symbols, keywords and then anything else, values
+of the same kind are ordered by value or text.
+
+Section: hashmap
+
+Example:
+(def sorted (make-sorted-hash :c 3 :a 1))
+(set! (get sorted :b) 2)
+(test::assert-equal \"{:a 1\\n:b 2\\n:c 3\\n}\" (str sorted))
+(test::assert-equal 2 (sorted :b))
+(test::assert-equal \"{1 nil\\n2 nil\\n:x nil\\n}\" (str (make-sorted-hash :x nil, 2 nil, 1 nil)))
+",
+    );
+    persistent::setup_persistent_builtins(env);
+    set::setup_set_builtins(env);
+    bytes::setup_bytes_builtins(env);
+
     /*  XXXX add these
         add_docstring(
             env,
@@ -0,0 +1,482 @@
+use crate::{add_builtin, SloshVm};
+use slvm::{VMError, VMResult, Value};
+
+const BASE64_CHARS: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+fn to_byte(vm: &SloshVm, name: &str, val: Value) -> VMResult<u8> {
+    val.get_int(vm)
+        .ok()
+        .and_then(|b| u8::try_from(b).ok())
+        .ok_or_else(|| {
+            VMError::new_vm(format!(
+                "{name}: bytes can only hold ints from 0 to 255, got {}",
+                val.display_value(vm)
+            ))
+        })
+}
+
+fn get_bytes<'vm>(vm: &'vm SloshVm, name: &str, val: Value) -> VMResult<&'vm [u8]> {
+    if let Value::Bytes(handle) = val {
+        Ok(vm.get_bytes(handle))
+    } else {
+        Err(VMError::new_vm(format!(
+            "{name}: Expected bytes, got {}",
+            val.display_type(vm)
+        )))
+    }
+}
+
+fn bytes(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    let bytes = registers
+        .iter()
+        .map(|val| to_byte(vm, "bytes", *val))
+        .collect::<VMResult<Vec<u8>>>()?;
+    vm.alloc_bytes(bytes)
+}
+
+fn make_bytes(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    let (len, fill) = match registers {
+        [len] => (len.get_int(vm)?, 0),
+        [len, fill] => (len.get_int(vm)?, to_byte(vm, "make-bytes", *fill)?),
+        _ => {
+            return Err(VMError::new_vm(
+                "make-bytes: Invalid arguments (length fill?)".to_string(),
+            ))
+        }
+    };
+    let len = usize::try_from(len)
+        .map_err(|_| VMError::new_vm(format!("make-bytes: Invalid length {len}")))?;
+    vm.alloc_bytes(vec![fill; len])
+}
+
+fn bytes_slice(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    let (bytes, start, end) = match registers {
+        [bytes, start] => {
+            let bytes = get_bytes(vm, "bytes-slice", *bytes)?;
+            (bytes, start.get_int(vm)?, bytes.len() as i64)
+        }
+        [bytes, start, end] => (
+            get_bytes(vm, "bytes-slice", *bytes)?,
+            start.get_int(vm)?,
+            end.get_int(vm)?,
+        ),
+        _ => {
+            return Err(VMError::new_vm(
+                "bytes-slice: Invalid arguments (bytes start end?)".to_string(),
+            ))
+        }
+    };
+    if start < 0 || start > end || end > bytes.len() as i64 {
+        return Err(VMError::new_vm(format!(
+            "bytes-slice: Invalid arguments- out of bounds ({start}..{end} with length {})",
+            bytes.len()
+        )));
+    }
+    let slice = bytes[start as usize..end as usize].to_vec();
+    vm.alloc_bytes(slice)
+}
+
+fn bytes_append(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    let mut res = Vec::new();
+    for val in registers {
+        res.extend_from_slice(get_bytes(vm, "bytes-append", *val)?);
+    }
+    vm.alloc_bytes(res)
+}
+
+fn str_to_bytes(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    match registers {
+        [string @ (Value::String(_) | Value::StringConst(_))] => {
+            let bytes = string.get_string(vm)?.as_bytes().to_vec();
+            vm.alloc_bytes(bytes)
+        }
+        _ => Err(VMError::new_vm(
+            "str->bytes: Invalid arguments (requires one string)".to_string(),
+        )),
+    }
+}
+
+fn bytes_to_str(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    let lossy = vm.intern("lossy");
+    let (bytes, lossy) = match registers {
+        [bytes] => (get_bytes(vm, "bytes->str", *bytes)?, false),
+        [bytes, Value::Keyword(k)] if *k == lossy => (get_bytes(vm, "bytes->str", *bytes)?, true),
+        _ => {
+            return Err(VMError::new_vm(
+                "bytes->str: Invalid arguments (bytes :lossy?)".to_string(),
+            ))
+        }
+    };
+    let string = if lossy {
+        String::from_utf8_lossy(bytes).into_owned()
+    } else {
+        std::str::from_utf8(bytes)
+            .map_err(|e| VMError::new_conversion(format!("bytes->str: {e}")))?
+            .to_string()
+    };
+    vm.alloc_string(string)
+}
+
+fn vec_to_bytes(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if let [Value::Vector(handle)] = registers {
+        let bytes = vm
+            .get_vector(*handle)
+            .iter()
+            .map(|val| to_byte(vm, "vec->bytes", *val))
+            .collect::<VMResult<Vec<u8>>>()?;
+        vm.alloc_bytes(bytes)
+    } else {
+        Err(VMError::new_vm(
+            "vec->bytes: Invalid arguments (requires one vector)".to_string(),
+        ))
+    }
+}
+
+fn bytes_to_vec(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if let [bytes] = registers {
+        let vec = get_bytes(vm, "bytes->vec", *bytes)?
+            .iter()
+            .map(|b| (*b as i64).into())
+            .collect();
+        vm.alloc_vector(vec)
+    } else {
+        Err(VMError::new_vm(
+            "bytes->vec: Invalid arguments (requires one bytes)".to_string(),
+        ))
+    }
+}
+
+fn bytes_to_hex(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if let [bytes] = registers {
+        let hex = get_bytes(vm, "bytes->hex", *bytes)?
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect();
+        vm.alloc_string(hex)
+    } else {
+        Err(VMError::new_vm(
+            "bytes->hex: Invalid arguments (requires one bytes)".to_string(),
+        ))
+    }
+}
+
+fn hex_to_bytes(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    let hex = match registers {
+        [string @ (Value::String(_) | Value::StringConst(_))] => string.get_string(vm)?,
+        _ => {
+            return Err(VMError::new_vm(
+                "hex->bytes: Invalid arguments (requires one string)".to_string(),
+            ))
+        }
+    };
+    if !hex.len().is_multiple_of(2) {
+        return Err(VMError::new_conversion(
+            "hex->bytes: odd number of hex digits",
+        ));
+    }
+    let bytes = (0..hex.len())
+        .step_by(2)
+        .map(|i| {
+            // from_str_radix allows a leading sign, only take plain digits.
+            hex.get(i..i + 2)
+                .filter(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit()))
+                .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                .ok_or_else(|| {
+                    VMError::new_conversion(format!("hex->bytes: invalid hex digit at {i}"))
+                })
+        })
+        .collect::<VMResult<Vec<u8>>>()?;
+    vm.alloc_bytes(bytes)
+}
+
+fn bytes_to_base64(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    let bytes = if let [bytes] = registers {
+        get_bytes(vm, "bytes->base64", *bytes)?
+    } else {
+        return Err(VMError::new_vm(
+            "bytes->base64: Invalid arguments (requires one bytes)".to_string(),
+        ));
+    };
+    let mut res = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - i * 8));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(BASE64_CHARS[(n >> (18 - i * 6)) as usize & 0x3f] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    vm.alloc_string(res)
+}
+
+fn base64_to_bytes(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    let text = match registers {
+        [string @ (Value::String(_) | Value::StringConst(_))] => string.get_string(vm)?,
+        _ => {
+            return Err(VMError::new_vm(
+                "base64->bytes: Invalid arguments (requires one string)".to_string(),
+            ))
+        }
+    };
+    let invalid = || VMError::new_conversion("base64->bytes: invalid base64 string");
+    if !text.len().is_multiple_of(4) {
+        return Err(invalid());
+    }
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let last = text.len() / 4;
+    for (chunk_idx, chunk) in text.as_bytes().chunks(4).enumerate() {
+        let pad = chunk.iter().rev().take_while(|c| **c == b'=').count();
+        // Padding is only allowed at the very end.
+        if pad > 2 || (pad > 0 && chunk_idx + 1 != last) {
+            return Err(invalid());
+        }
+        let mut n = 0u32;
+        for (i, c) in chunk[..4 - pad].iter().enumerate() {
+            let digit = BASE64_CHARS
+                .iter()
+                .position(|b| b == c)
+                .ok_or_else(invalid)?;
+            n |= (digit as u32) << (18 - i * 6);
+        }
+        for i in 0..3 - pad {
+            bytes.push((n >> (16 - i * 8)) as u8);
+        }
+    }
+    vm.alloc_bytes(bytes)
+}
+
+pub fn setup_bytes_builtins(env: &mut SloshVm) {
+    add_builtin(
+        env,
+        "bytes",
+        bytes,
+        "Usage: (bytes int*)
+
+Make a new byte vector from ints (each must be from 0 to 255).  The reader literal #u8(int*) makes a
+byte vector the same way (see byte-vector).  Use get (or call set! on get) to read or change a byte,
+len and clear! also work on byte vectors.  Setting an index past the end grows the byte vector (filling with 0).
+
+Section: bytes
+
+Example:
+(def b (bytes 1 2 #xff))
+(test::assert-equal 3 (len b))
+(test::assert-equal 255 (get b 2))
+(test::assert-equal 255 (get b -1))
+(test::assert-equal 2 (b 1))
+(set! (get b 0) 10)
+(test::assert-true (equal? #u8(10 2 255) b))
+(test::assert-error (bytes 256))
+",
+    );
+    add_builtin(
+        env,
+        "make-bytes",
+        make_bytes,
+        "Usage: (make-bytes length fill?)
+
+Make a new byte vector of length bytes all set to fill (default 0).
+
+Section: bytes
+
+Example:
+(test::assert-true (equal? #u8(0 0 0) (make-bytes 3)))
+(test::assert-true (equal? #u8(7 7) (make-bytes 2 7)))
+(test::assert-equal 0 (len (make-bytes 0)))
+",
+    );
+    add_builtin(
+        env,
+        "bytes-slice",
+        bytes_slice,
+        "Usage: (bytes-slice bytes start end?)
+
+Return a new byte vector with the bytes from start up to (not including) end (default the length).
+
+Section: bytes
+
+Example:
+(def b #u8(1 2 3 4))
+(test::assert-true (equal? #u8(2 3) (bytes-slice b 1 3)))
+(test::assert-true (equal? #u8(3 4) (bytes-slice b 2)))
+(test::assert-error (bytes-slice b 3 5))
+",
+    );
+    add_builtin(
+        env,
+        "bytes-append",
+        bytes_append,
+        "Usage: (bytes-append bytes*)
+
+Return a new byte vector with the bytes of each argument joined together.
+
+Section: bytes
+
+Example:
+(test::assert-true (equal? #u8(1 2 3) (bytes-append #u8(1) #u8() #u8(2 3))))
+",
+    );
+    add_builtin(
+        env,
+        "str->bytes",
+        str_to_bytes,
+        "Usage: (str->bytes string)
+
+Return the UTF-8 encoding of string as a byte vector.
+
+Section: bytes
+
+Example:
+(test::assert-true (equal? #u8(104 105) (str->bytes \"hi\")))
+(test::assert-equal 2 (len (str->bytes \"é\")))
+",
+    );
+    add_builtin(
+        env,
+        "bytes->str",
+        bytes_to_str,
+        "Usage: (bytes->str bytes :lossy?)
+
+Decode a UTF-8 byte vector into a string.  Raises a conversion error if the bytes are not valid
+UTF-8 unless :lossy is given, then invalid sequences are replaced with U+FFFD.
+
+Section: bytes
+
+Example:
+(test::assert-equal \"hi\" (bytes->str #u8(104 105)))
+(test::assert-error (bytes->str #u8(104 255)))
+(test::assert-equal \"h\\u{FFFD}\" (bytes->str #u8(104 255) :lossy))
+",
+    );
+    add_builtin(
+        env,
+        "vec->bytes",
+        vec_to_bytes,
+        "Usage: (vec->bytes vector)
+
+Make a new byte vector from a vector of ints (each must be from 0 to 255).
+
+Section: bytes
+
+Example:
+(test::assert-true (equal? #u8(1 2) (vec->bytes [1 2])))
+(test::assert-error (vec->bytes [1 -1]))
+",
+    );
+    add_builtin(
+        env,
+        "bytes->vec",
+        bytes_to_vec,
+        "Usage: (bytes->vec bytes)
+
+Make a new vector of ints from a byte vector.
+
+Section: bytes
+
+Example:
+(test::assert-equal [1 2] (bytes->vec #u8(1 2)))
+",
+    );
+    add_builtin(
+        env,
+        "bytes->hex",
+        bytes_to_hex,
+        "Usage: (bytes->hex bytes)
+
+Return a string with each byte as two lowercase hex digits.
+
+Section: bytes
+
+Example:
+(test::assert-equal \"00ff10\" (bytes->hex #u8(0 255 16)))
+",
+    );
+    add_builtin(
+        env,
+        "hex->bytes",
+        hex_to_bytes,
+        "Usage: (hex->bytes string)
+
+Decode a string of hex digits (two per byte, either case) into a byte vector.  Raises a conversion
+error if the string is not valid hex.
+
+Section: bytes
+
+Example:
+(test::assert-true (equal? #u8(0 255 16) (hex->bytes \"00FF10\")))
+(test::assert-error (hex->bytes \"abc\"))
+(test::assert-error (hex->bytes \"zz\"))
+(test::assert-error (hex->bytes \"+f\"))
+",
+    );
+    add_builtin(
+        env,
+        "bytes->base64",
+        bytes_to_base64,
+        "Usage: (bytes->base64 bytes)
+
+Return the standard (padded) base64 encoding of a byte vector.
+
+Section: bytes
+
+Example:
+(test::assert-equal \"aGk=\" (bytes->base64 (str->bytes \"hi\")))
+(test::assert-equal \"\" (bytes->base64 #u8()))
+",
+    );
+    add_builtin(
+        env,
+        "base64->bytes",
+        base64_to_bytes,
+        "Usage: (base64->bytes string)
+
+Decode a standard (padded) base64 string into a byte vector.  Raises a conversion error if the
+string is not valid base64.
+
+Section: bytes
+
+Example:
+(test::assert-equal \"hi\" (bytes->str (base64->bytes \"aGk=\")))
+(test::assert-error (base64->bytes \"aGk\"))
+",
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compile_state::state::new_slosh_vm;
+
+    #[test]
+    fn test_base64_hex() {
+        let mut vm = new_slosh_vm();
+        for text in ["", "f", "fo", "foo", "foob", "fooba", "foobar"] {
+            let bytes = vm.alloc_bytes(text.as_bytes().to_vec()).unwrap();
+            let encoded = bytes_to_base64(&mut vm, &[bytes]).unwrap();
+            let decoded = base64_to_bytes(&mut vm, &[encoded]).unwrap();
+            assert!(vm.is_equal_pair(bytes, decoded).unwrap().is_true());
+            let hex = bytes_to_hex(&mut vm, &[bytes]).unwrap();
+            let decoded = hex_to_bytes(&mut vm, &[hex]).unwrap();
+            assert!(vm.is_equal_pair(bytes, decoded).unwrap().is_true());
+        }
+        let bytes = vm.alloc_bytes(b"foobar".to_vec()).unwrap();
+        let encoded = bytes_to_base64(&mut vm, &[bytes]).unwrap();
+        assert_eq!(encoded.get_string(&vm).unwrap(), "Zm9vYmFy");
+        let bytes = vm.alloc_bytes(b"foob".to_vec()).unwrap();
+        let encoded = bytes_to_base64(&mut vm, &[bytes]).unwrap();
+        assert_eq!(encoded.get_string(&vm).unwrap(), "Zm9vYg==");
+        for bad in ["Zm9", "Zm=v", "Z===", "Zg==Zg==", "Zm9*"] {
+            let bad = vm.alloc_string(bad.to_string()).unwrap();
+            assert!(base64_to_bytes(&mut vm, &[bad]).is_err());
+        }
+        for bad in ["+f", "-1", "f+", "zz", "abc"] {
+            let bad = vm.alloc_string(bad.to_string()).unwrap();
+            assert!(hex_to_bytes(&mut vm, &[bad]).is_err());
+        }
+    }
+}
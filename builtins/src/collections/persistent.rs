@@ -0,0 +1,294 @@
+use crate::{add_builtin, SloshVm};
+use slvm::{PMap, PVec, VMError, VMMap, VMResult, Value};
+
+fn pvec(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    vm.alloc_pvec(registers.iter().copied().collect())
+}
+
+fn pmap(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if !registers.len().is_multiple_of(2) {
+        return Err(VMError::new_vm(
+            "pmap: Invalid arguments (must be even, [key val]*)".to_string(),
+        ));
+    }
+    vm.alloc_pmap(registers.chunks(2).map(|kv| (kv[0], kv[1])).collect())
+}
+
+fn conj(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if let Some((Value::PVec(handle), vals)) = registers.split_first() {
+        let mut vec = vm.get_pvec(*handle).clone();
+        for val in vals {
+            vec = vec.push(*val);
+        }
+        vm.alloc_pvec(vec)
+    } else {
+        Err(VMError::new_vm(
+            "conj: Invalid arguments (persistent-vector val*)".to_string(),
+        ))
+    }
+}
+
+fn assoc(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    let (coll, kvs) = match registers.split_first() {
+        Some((coll, kvs)) if !kvs.is_empty() && kvs.len().is_multiple_of(2) => (*coll, kvs),
+        _ => {
+            return Err(VMError::new_vm(
+                "assoc: Invalid arguments (collection [key val]+)".to_string(),
+            ))
+        }
+    };
+    match coll {
+        Value::PVec(handle) => {
+            let mut vec = vm.get_pvec(handle).clone();
+            for kv in kvs.chunks(2) {
+                let idx = kv[0].get_int(vm)?;
+                vec = usize::try_from(idx)
+                    .ok()
+                    .and_then(|idx| vec.set(idx, kv[1]))
+                    .ok_or_else(|| {
+                        VMError::new_vm(format!(
+                            "assoc: index {idx} out of bounds (length {}).",
+                            vec.len()
+                        ))
+                    })?;
+            }
+            vm.alloc_pvec(vec)
+        }
+        Value::PMap(handle) => {
+            let mut map = vm.get_pmap(handle).clone();
+            for kv in kvs.chunks(2) {
+                map = map.insert(kv[0], kv[1]);
+            }
+            vm.alloc_pmap(map)
+        }
+        _ => Err(VMError::new_vm(format!(
+            "assoc: Expected a persistent vector or map, got {}.",
+            coll.display_type(vm)
+        ))),
+    }
+}
+
+fn dissoc(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if let Some((Value::PMap(handle), keys)) = registers.split_first() {
+        let mut map = vm.get_pmap(*handle).clone();
+        for key in keys {
+            if let Some(new_map) = map.remove(key) {
+                map = new_map;
+            }
+        }
+        vm.alloc_pmap(map)
+    } else {
+        Err(VMError::new_vm(
+            "dissoc: Invalid arguments (persistent-map key*)".to_string(),
+        ))
+    }
+}
+
+fn to_pvec(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    let vec: PVec = match registers {
+        [Value::PVec(_)] => return Ok(registers[0]),
+        [Value::Vector(handle)] => vm.get_vector(*handle).iter().copied().collect(),
+        [val @ (Value::Pair(_) | Value::List(_, _) | Value::Nil)] => val.iter(vm).collect(),
+        _ => {
+            return Err(VMError::new_vm(
+                "->pvec: Invalid arguments (requires one vector or list)".to_string(),
+            ))
+        }
+    };
+    vm.alloc_pvec(vec)
+}
+
+fn to_pmap(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    let map: PMap = match registers {
+        [Value::PMap(_)] => return Ok(registers[0]),
+        [Value::Map(handle)] => vm.get_map(*handle).iter().map(|(k, v)| (*k, *v)).collect(),
+        _ => {
+            return Err(VMError::new_vm(
+                "->pmap: Invalid arguments (requires one hash map)".to_string(),
+            ))
+        }
+    };
+    vm.alloc_pmap(map)
+}
+
+fn pvec_to_vec(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if let [Value::PVec(handle)] = registers {
+        let vec = vm.get_pvec(*handle).iter().copied().collect();
+        vm.alloc_vector(vec)
+    } else {
+        Err(VMError::new_vm(
+            "pvec->vec: Invalid arguments (requires one persistent vector)".to_string(),
+        ))
+    }
+}
+
+fn pmap_to_hash(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if let [Value::PMap(handle)] = registers {
+        let map: VMMap = vm.get_pmap(*handle).iter().map(|(k, v)| (*k, *v)).collect();
+        vm.alloc_map(map)
+    } else {
+        Err(VMError::new_vm(
+            "pmap->hash: Invalid arguments (requires one persistent map)".to_string(),
+        ))
+    }
+}
+
+pub fn setup_persistent_builtins(env: &mut SloshVm) {
+    add_builtin(
+        env,
+        "pvec",
+        pvec,
+        "Usage: (pvec item*)
+
+Make a persistent vector.  A persistent vector can not be changed, conj and assoc return a new
+vector that shares most of its structure with the old one (so they are O(log n), not a copy).
+Works with get, len, equal? and can be called like a vector.
+
+Section: persistent
+
+Example:
+(def v (pvec 1 2 3))
+(test::assert-equal 3 (len v))
+(test::assert-equal 2 (get v 1))
+(test::assert-equal 3 (v -1))
+(test::assert-true (equal? v (pvec 1 2 3)))
+",
+    );
+    add_builtin(
+        env,
+        "pmap",
+        pmap,
+        "Usage: (pmap key1 val1 .. keyN valN)
+
+Make a persistent hash map.  A persistent map can not be changed, assoc and dissoc return a new map
+that shares most of its structure with the old one (so they are O(log n), not a copy).  Works with
+get, len, equal?, map destructuring and can be called like a hash map.
+
+Section: persistent
+
+Example:
+(def m (pmap :a 1 :b 2))
+(test::assert-equal 2 (len m))
+(test::assert-equal 1 (get m :a))
+(test::assert-equal :none (m :c :none))
+(test::assert-true (equal? m (pmap :b 2 :a 1)))
+",
+    );
+    add_builtin(
+        env,
+        "conj",
+        conj,
+        "Usage: (conj persistent-vector val*) -> persistent-vector
+
+Return a new persistent vector with the vals added to the end, the original is unchanged.
+
+Section: persistent
+
+Example:
+(def v (pvec 1 2))
+(test::assert-true (equal? (pvec 1 2 3 4) (conj v 3 4)))
+(test::assert-equal 2 (len v))
+",
+    );
+    add_builtin(
+        env,
+        "assoc",
+        assoc,
+        "Usage: (assoc persistent-collection key val [key val]*) -> persistent-collection
+
+Return a new persistent vector or map with each key set to its val, the original is unchanged.  For
+a vector the key is an index and may be the length to add an item to the end.
+
+Section: persistent
+
+Example:
+(def v (pvec 1 2))
+(test::assert-true (equal? (pvec 1 :x 3) (assoc v 1 :x 2 3)))
+(test::assert-true (equal? (pvec 1 2) v))
+(def m (pmap :a 1))
+(test::assert-true (equal? (pmap :a 10 :b 2) (assoc m :a 10 :b 2)))
+(test::assert-equal 1 (get m :a))
+(test::assert-error (assoc v 5 0))
+",
+    );
+    add_builtin(
+        env,
+        "dissoc",
+        dissoc,
+        "Usage: (dissoc persistent-map key*) -> persistent-map
+
+Return a new persistent map without the keys (keys that are not in the map are ignored), the
+original is unchanged.
+
+Section: persistent
+
+Example:
+(def m (pmap :a 1 :b 2 :c 3))
+(test::assert-true (equal? (pmap :b 2) (dissoc m :a :c :d)))
+(test::assert-equal 3 (len m))
+",
+    );
+    add_builtin(
+        env,
+        "->pvec",
+        to_pvec,
+        "Usage: (->pvec vector-or-list) -> persistent-vector
+
+Make a persistent vector with the items of a vector or list (a persistent vector is returned as is).
+
+Section: persistent
+
+Example:
+(test::assert-true (equal? (pvec 1 2 3) (->pvec [1 2 3])))
+(test::assert-true (equal? (pvec 1 2 3) (->pvec '(1 2 3))))
+",
+    );
+    add_builtin(
+        env,
+        "->pmap",
+        to_pmap,
+        "Usage: (->pmap hash-map) -> persistent-map
+
+Make a persistent map with the entries of a hash map (a persistent map is returned as is).
+
+Section: persistent
+
+Example:
+(test::assert-true (equal? (pmap :a 1 :b 2) (->pmap {:a 1 :b 2})))
+",
+    );
+    add_builtin(
+        env,
+        "pvec->vec",
+        pvec_to_vec,
+        "Usage: (pvec->vec persistent-vector) -> vector
+
+Make a new (mutable) vector with the items of a persistent vector.
+
+Section: persistent
+
+Example:
+(def v (pvec->vec (pvec 1 2)))
+(vec-push! v 3)
+(test::assert-equal 3 (len v))
+(test::assert-equal 3 (get v 2))
+",
+    );
+    add_builtin(
+        env,
+        "pmap->hash",
+        pmap_to_hash,
+        "Usage: (pmap->hash persistent-map) -> hash-map
+
+Make a new (mutable) hash map with the entries of a persistent map.
+
+Section: persistent
+
+Example:
+(def m (pmap->hash (pmap :a 1)))
+(set! (get m :b) 2)
+(test::assert-equal 2 (len m))
+(test::assert-equal 2 (get m :b))
+",
+    );
+}
@@ -0,0 +1,220 @@
+use crate::{add_builtin, SloshVm};
+use slvm::{Handle, VMError, VMResult, VMSet, Value};
+
+fn set_handles(name: &str, registers: &[Value]) -> VMResult<Vec<Handle>> {
+    registers
+        .iter()
+        .map(|val| match val {
+            Value::Set(handle) => Ok(*handle),
+            _ => Err(VMError::new_vm(format!(
+                "{name}: Invalid arguments (all arguments must be sets)"
+            ))),
+        })
+        .collect()
+}
+
+fn set(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    vm.alloc_set(registers.iter().copied().collect())
+}
+
+fn set_add(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if let Some((Value::Set(handle), items)) = registers.split_first() {
+        let set = vm.get_set_mut(*handle)?;
+        for item in items {
+            set.insert(*item);
+        }
+        Ok(registers[0])
+    } else {
+        Err(VMError::new_vm(
+            "set-add!: Invalid arguments (set item*)".to_string(),
+        ))
+    }
+}
+
+fn set_remove(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if let Some((Value::Set(handle), items)) = registers.split_first() {
+        let set = vm.get_set_mut(*handle)?;
+        for item in items {
+            set.remove(item);
+        }
+        Ok(registers[0])
+    } else {
+        Err(VMError::new_vm(
+            "set-remove!: Invalid arguments (set item*)".to_string(),
+        ))
+    }
+}
+
+fn set_contains(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if let [Value::Set(handle), item] = registers {
+        if vm.get_set(*handle).contains(item) {
+            Ok(Value::True)
+        } else {
+            Ok(Value::False)
+        }
+    } else {
+        Err(VMError::new_vm(
+            "set-contains?: Invalid arguments (set item)".to_string(),
+        ))
+    }
+}
+
+fn set_union(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    let mut union = VMSet::new();
+    for handle in set_handles("set-union", registers)? {
+        for item in vm.get_set(handle).iter() {
+            union.insert(*item);
+        }
+    }
+    vm.alloc_set(union)
+}
+
+fn set_intersection(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    let handles = set_handles("set-intersection", registers)?;
+    let Some((first, rest)) = handles.split_first() else {
+        return Err(VMError::new_vm(
+            "set-intersection: Invalid arguments (set set*)".to_string(),
+        ));
+    };
+    let intersection = vm
+        .get_set(*first)
+        .iter()
+        .filter(|item| rest.iter().all(|h| vm.get_set(*h).contains(item)))
+        .copied()
+        .collect();
+    vm.alloc_set(intersection)
+}
+
+fn set_difference(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    let handles = set_handles("set-difference", registers)?;
+    let Some((first, rest)) = handles.split_first() else {
+        return Err(VMError::new_vm(
+            "set-difference: Invalid arguments (set set*)".to_string(),
+        ));
+    };
+    let difference = vm
+        .get_set(*first)
+        .iter()
+        .filter(|item| !rest.iter().any(|h| vm.get_set(*h).contains(item)))
+        .copied()
+        .collect();
+    vm.alloc_set(difference)
+}
+
+pub fn setup_set_builtins(env: &mut SloshVm) {
+    add_builtin(
+        env,
+        "set",
+        set,
+        "Usage: (set item*)
+
+Make a new set containing the items (duplicates are dropped).  Items are kept in the order they were
+first added.  The reader literal #{item*} makes a set the same way (see make-set).  A set can be
+called with an item and returns it if it is in the set, otherwise nil (or the optional second
+argument).
+
+Section: set
+
+Example:
+(def s (set 1 2 2 3))
+(test::assert-equal 3 (len s))
+(test::assert-true (equal? s #{3 2 1}))
+(test::assert-equal 2 (s 2))
+(test::assert-equal nil (s 4))
+(test::assert-equal :none (s 4 :none))
+",
+    );
+    add_builtin(
+        env,
+        "set-add!",
+        set_add,
+        "Usage: (set-add! set item*) -> set
+
+Add the items to set (items already in the set are ignored) and return the set.
+
+Section: set
+
+Example:
+(def s (set 1))
+(test::assert-true (equal? #{1 2 3} (set-add! s 2 3 1)))
+(test::assert-equal 3 (len s))
+",
+    );
+    add_builtin(
+        env,
+        "set-remove!",
+        set_remove,
+        "Usage: (set-remove! set item*) -> set
+
+Remove the items from set (items not in the set are ignored) and return the set.
+
+Section: set
+
+Example:
+(def s (set 1 2 3))
+(test::assert-true (equal? #{2} (set-remove! s 1 3 4)))
+(test::assert-equal 1 (len s))
+",
+    );
+    add_builtin(
+        env,
+        "set-contains?",
+        set_contains,
+        "Usage: (set-contains? set item) -> #t/#f
+
+True if item is in set.
+
+Section: set
+
+Example:
+(def s #{:a :b})
+(test::assert-true (set-contains? s :a))
+(test::assert-false (set-contains? s :c))
+",
+    );
+    add_builtin(
+        env,
+        "set-union",
+        set_union,
+        "Usage: (set-union set*) -> set
+
+Return a new set with the items that are in any of the sets.
+
+Section: set
+
+Example:
+(test::assert-true (equal? #{1 2 3 4} (set-union #{1 2} #{2 3} #{4})))
+(test::assert-equal 0 (len (set-union)))
+",
+    );
+    add_builtin(
+        env,
+        "set-intersection",
+        set_intersection,
+        "Usage: (set-intersection set set*) -> set
+
+Return a new set with the items of the first set that are also in all the other sets.
+
+Section: set
+
+Example:
+(test::assert-true (equal? #{2} (set-intersection #{1 2 3} #{2 3 4} #{2})))
+(test::assert-true (equal? #{} (set-intersection #{1} #{2})))
+",
+    );
+    add_builtin(
+        env,
+        "set-difference",
+        set_difference,
+        "Usage: (set-difference set set*) -> set
+
+Return a new set with the items of the first set that are not in any of the other sets.
+
+Section: set
+
+Example:
+(test::assert-true (equal? #{1} (set-difference #{1 2 3} #{2} #{3 4})))
+(test::assert-true (equal? #{1 2} (set-difference #{1 2})))
+",
+    );
+}
@@ -11,7 +11,7 @@ fn to_sym(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
             Value::Symbol(i) => Ok(Value::Symbol(*i)),
             _ => {
                 let string = string.pretty_value(vm);
-                let i = vm.intern(&string);
+                let i = vm.intern_weak(&string);
                 Ok(Value::Symbol(i))
             }
         }
@@ -29,7 +29,7 @@ fn to_key(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
             Value::Symbol(i) => Ok(Value::Keyword(*i)),
             _ => {
                 let string = string.pretty_value(vm);
-                let i = vm.intern(&string);
+                let i = vm.intern_weak(&string);
                 Ok(Value::Keyword(i))
             }
         }
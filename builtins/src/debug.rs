@@ -0,0 +1,116 @@
+use crate::add_builtin;
+use compile_state::state::{SloshVm, SloshVmTrait};
+use slvm::{Backtrace, Interned, VMError, VMMap, VMResult, Value};
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// Names of the global lambdas and closures by chunk address, used to name backtrace frames.
+fn chunk_names(vm: &SloshVm) -> HashMap<usize, Interned> {
+    let mut names = HashMap::new();
+    for (name, slot) in vm.globals() {
+        let chunk = match vm.get_global(*slot as u32) {
+            Value::Lambda(h) => vm.get_lambda(h),
+            Value::Closure(h) => vm.get_closure(h).0,
+            _ => continue,
+        };
+        names.insert(Arc::as_ptr(&chunk) as usize, *name);
+    }
+    names
+}
+
+/// Human readable backtrace, one "at name (file:line)" line per frame innermost first.
+pub fn display_backtrace(vm: &SloshVm, backtrace: &Backtrace) -> String {
+    let names = chunk_names(vm);
+    let mut out = String::new();
+    for frame in &backtrace.frames {
+        let name = names
+            .get(&(Arc::as_ptr(&frame.chunk) as usize))
+            .map_or("<anonymous>", |n| vm.get_interned(*n));
+        let line = frame.line().unwrap_or(0);
+        let _ = writeln!(out, "  at {name} ({}:{line})", frame.chunk.file_name);
+        if let Some(registers) = &frame.registers {
+            let regs: Vec<String> = registers.iter().map(|r| r.display_value(vm)).collect();
+            let _ = writeln!(out, "      registers: [{}]", regs.join(" "));
+        }
+    }
+    if backtrace.skipped > 0 {
+        let _ = writeln!(out, "  ... {} more frames", backtrace.skipped);
+    }
+    out
+}
+
+fn err_backtrace(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    let backtrace = match registers {
+        [] => vm.last_backtrace(),
+        [Value::Error(h)] => vm.get_error(*h).backtrace,
+        [Value::Keyword(k), val] if vm.get_interned(*k) == "registers" => {
+            vm.set_backtrace_registers(!val.is_falsey());
+            return Ok(Value::Nil);
+        }
+        _ => {
+            return Err(VMError::new_vm(
+                "err-backtrace: takes an optional error value or :registers boolean".to_string(),
+            ))
+        }
+    };
+    let Some(backtrace) = backtrace else {
+        return Ok(Value::Nil);
+    };
+    let names = chunk_names(vm);
+    let name_key = Value::Keyword(vm.intern_static("name"));
+    let file_key = Value::Keyword(vm.intern_static("file"));
+    let line_key = Value::Keyword(vm.intern_static("line"));
+    let registers_key = Value::Keyword(vm.intern_static("registers"));
+    // Nothing is rooted until the result is returned.
+    vm.pause_gc();
+    // Dont return early until the unpause_gc() call below.
+    let res = backtrace
+        .frames
+        .iter()
+        .map(|frame| {
+            let mut map = VMMap::with_capacity(4);
+            let name = names.get(&(Arc::as_ptr(&frame.chunk) as usize));
+            map.insert(name_key, name.map_or(Value::Nil, |n| Value::Symbol(*n)))?;
+            let file = vm.intern(frame.chunk.file_name);
+            map.insert(file_key, Value::StringConst(file))?;
+            map.insert(
+                line_key,
+                frame.line().map_or(Value::Nil, |l| (l as i64).into()),
+            )?;
+            if let Some(registers) = &frame.registers {
+                let registers = vm.alloc_vector(registers.clone())?;
+                map.insert(registers_key, registers)?;
+            }
+            vm.alloc_map(map)
+        })
+        .collect::<VMResult<Vec<Value>>>()
+        .and_then(|frames| vm.alloc_vector(frames));
+    vm.unpause_gc();
+    res
+}
+
+pub fn add_debug_builtins(env: &mut SloshVm) {
+    add_builtin(
+        env,
+        "err-backtrace",
+        err_backtrace,
+        "Usage: (err-backtrace [error]) | (err-backtrace :registers boolean)
+
+Return the call stack captured for an error value (made with mk-err) or, with no arguments, for
+the last error raised.  The result is a vector of maps, innermost frame first, with :name (the
+global the function is bound to or nil), :file and :line.  If register snapshots are on each map
+also has :registers, a vector of the frame's registers.  Returns nil if there is no backtrace.
+(err-backtrace :registers #t) turns register snapshots on (they are off by default).
+
+Section: core
+
+Example:
+(def bt-test (fn () (err :test \"backtrace\")))
+(get-error (bt-test))
+(test::assert-equal 'bt-test (get (get (err-backtrace) 0) :name))
+(test::assert-true (get (get (err-backtrace (mk-err :test 1)) 0) :line))
+(test::assert-error (err-backtrace 1))
+",
+    );
+}
@@ -0,0 +1,334 @@
+use crate::add_builtin;
+use compile_state::state::SloshVm;
+use slvm::{VMError, VMMap, VMResult, Value};
+
+fn heap_stats(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if !registers.is_empty() {
+        return Err(VMError::new_vm(
+            "heap-stats: takes no arguments".to_string(),
+        ));
+    }
+    let stats = vm.heap_stats();
+    let fields: [(&str, Value); 24] = [
+        ("collections", (stats.collections as i64).into()),
+        (
+            "total-pause-ms",
+            (stats.total_pause.as_secs_f64() * 1000.0).into(),
+        ),
+        (
+            "last-pause-ms",
+            (stats.last_pause.as_secs_f64() * 1000.0).into(),
+        ),
+        ("string", (stats.strings as i64).into()),
+        ("vector", (stats.vectors as i64).into()),
+        ("map", (stats.maps as i64).into()),
+        ("set", (stats.sets as i64).into()),
+        ("bytes", (stats.bytes as i64).into()),
+        ("pair", (stats.pairs as i64).into()),
+        ("value", (stats.values as i64).into()),
+        ("lambda", (stats.lambdas as i64).into()),
+        ("closure", (stats.closures as i64).into()),
+        ("bigint", (stats.bigints as i64).into()),
+        ("pvec", (stats.pvecs as i64).into()),
+        ("pmap", (stats.pmaps as i64).into()),
+        ("weak", (stats.weaks as i64).into()),
+        ("host", (stats.hosts as i64).into()),
+        ("continuation", (stats.continuations as i64).into()),
+        ("callframe", (stats.callframes as i64).into()),
+        ("error", (stats.errors as i64).into()),
+        ("capacity", (stats.capacity as i64).into()),
+        ("grow-factor", stats.grow_factor.into()),
+        ("sticky", (stats.sticky as i64).into()),
+        ("gc-step", (vm.gc_step() as i64).into()),
+    ];
+    let mut map = VMMap::with_capacity(fields.len());
+    for (key, val) in fields {
+        map.insert(Value::Keyword(vm.intern_static(key)), val)?;
+    }
+    vm.alloc_map(map)
+}
+
+fn weak(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if registers.len() != 1 {
+        return Err(VMError::new_vm("weak: takes one argument".to_string()));
+    }
+    vm.alloc_weak(registers[0])
+}
+
+fn weak_get(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    match registers {
+        [Value::Weak(h)] => Ok(vm.get_weak(*h).unwrap_or(Value::Nil)),
+        [Value::Weak(h), default] => Ok(vm.get_weak(*h).unwrap_or(*default)),
+        _ => Err(VMError::new_vm(
+            "weak-get: takes a weak reference and optional default".to_string(),
+        )),
+    }
+}
+
+fn weak_alive(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if let [Value::Weak(h)] = registers {
+        Ok(if vm.get_weak(*h).is_some() {
+            Value::True
+        } else {
+            Value::False
+        })
+    } else {
+        Err(VMError::new_vm(
+            "weak-alive?: takes one weak reference".to_string(),
+        ))
+    }
+}
+
+fn gc(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if !registers.is_empty() {
+        return Err(VMError::new_vm("gc: takes no arguments".to_string()));
+    }
+    Ok(if vm.gc() { Value::True } else { Value::False })
+}
+
+fn set_grow_factor(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if registers.len() != 1 {
+        return Err(VMError::new_vm(
+            "set-grow-factor: takes one argument (factor)".to_string(),
+        ));
+    }
+    let grow_factor = registers[0].get_float(vm)?;
+    if !grow_factor.is_finite() || grow_factor <= 1.0 {
+        return Err(VMError::new_vm(
+            "set-grow-factor: factor must be greater than 1.0".to_string(),
+        ));
+    }
+    vm.set_grow_factor(grow_factor);
+    Ok(registers[0])
+}
+
+fn set_gc_step(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if registers.len() != 1 {
+        return Err(VMError::new_vm(
+            "set-gc-step: takes one argument (step)".to_string(),
+        ));
+    }
+    let step = registers[0].get_int(vm)?;
+    if step < 0 {
+        return Err(VMError::new_vm(
+            "set-gc-step: step can not be negative".to_string(),
+        ));
+    }
+    vm.set_gc_step(step as usize);
+    Ok(registers[0])
+}
+
+pub fn add_gc_builtins(env: &mut SloshVm) {
+    add_builtin(
+        env,
+        "heap-stats",
+        heap_stats,
+        "Usage: (heap-stats)
+
+Return a map of heap and garbage collector statistics.  Keys are :collections (completed
+collections), :total-pause-ms and :last-pause-ms (time spent in the collector), live object counts
+by kind (:string, :vector, :map, :set, :bytes, :pair, :value, :lambda, :closure, :bigint, :pvec,
+:pmap, :weak, :host, :continuation, :callframe and :error), :capacity (objects before the next
+collection starts), :grow-factor, :sticky (objects that are never collected) and :gc-step.
+
+Section: core
+
+Example:
+(def stats (heap-stats))
+(test::assert-true (>= (get stats :collections) 0))
+(test::assert-true (>= (get stats :capacity) (get stats :pair)))
+(test::assert-true (>= (get stats :set) 0))
+(test::assert-true (>= (get stats :host) 0))
+",
+    );
+    add_builtin(
+        env,
+        "weak",
+        weak,
+        "Usage: (weak value)
+
+Make a weak reference to value.  A weak reference does not keep value from being garbage collected,
+use weak-get to get the value back (if it is still alive).
+
+Section: core
+
+Example:
+(def target (vec 1 2 3))
+(def w (weak target))
+(test::assert-equal target (weak-get w))
+",
+    );
+    add_builtin(
+        env,
+        "weak-get",
+        weak_get,
+        "Usage: (weak-get weak-ref default?)
+
+Return the target of a weak reference, or default (nil if not provided) if it has been garbage
+collected.
+
+Section: core
+
+Example:
+(def target (vec 1 2 3))
+(def w (weak target))
+(test::assert-equal '(1 2 3) (weak-get w))
+(set! target nil)
+(gc)
+(test::assert-equal :gone (weak-get w :gone))
+",
+    );
+    add_builtin(
+        env,
+        "weak-alive?",
+        weak_alive,
+        "Usage: (weak-alive? weak-ref)
+
+True if the target of a weak reference has not been garbage collected.
+
+Section: core
+
+Example:
+(def target (vec 1 2 3))
+(def w (weak target))
+(test::assert-true (weak-alive? w))
+(set! target nil)
+(gc)
+(test::assert-false (weak-alive? w))
+",
+    );
+    add_builtin(
+        env,
+        "gc",
+        gc,
+        "Usage: (gc)
+
+Force a full garbage collection.  Returns false if garbage collection is paused (and nothing was
+collected), true otherwise.
+
+Section: core
+
+Example:
+(def before (get (heap-stats) :collections))
+(test::assert-true (gc))
+(test::assert-equal (+ before 1) (get (heap-stats) :collections))
+",
+    );
+    add_builtin(
+        env,
+        "set-grow-factor",
+        set_grow_factor,
+        "Usage: (set-grow-factor factor)
+
+Set the factor the heap grows by when a collection does not free enough space, must be greater
+than 1.0.  A larger factor means fewer collections at the cost of memory.
+
+Section: core
+
+Example:
+(def old (get (heap-stats) :grow-factor))
+(set-grow-factor 3.0)
+(test::assert-equal 3.0 (get (heap-stats) :grow-factor))
+(set-grow-factor old)
+(test::assert-error (set-grow-factor 1.0))
+",
+    );
+    add_builtin(
+        env,
+        "set-gc-step",
+        set_gc_step,
+        "Usage: (set-gc-step step)
+
+Collect garbage incrementally, tracing at most step objects per allocation while a collection is
+in progress.  This bounds GC pauses, a step of 0 does a full (stop the world) collection each time
+the heap fills.
+
+Section: core
+
+Example:
+(set-gc-step 100)
+(test::assert-equal 100 (get (heap-stats) :gc-step))
+(set-gc-step 0)
+(test::assert-error (set-gc-step -1))
+",
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compile_state::state::new_slosh_vm;
+    use slvm::{HostObject, VMSet};
+
+    fn stat(vm: &mut SloshVm, stats: Value, key: &'static str) -> Value {
+        let key = Value::Keyword(vm.intern_static(key));
+        let Value::Map(h) = stats else {
+            panic!("heap-stats should return a map");
+        };
+        *vm.get_map(h).get(&key).expect("missing heap stat")
+    }
+
+    struct Host;
+
+    impl HostObject for Host {
+        fn type_name(&self) -> &'static str {
+            "Host"
+        }
+    }
+
+    #[test]
+    fn test_heap_stats() -> VMResult<()> {
+        let mut vm = new_slosh_vm();
+        assert!(heap_stats(&mut vm, &[Value::Nil]).is_err());
+        vm.alloc_string("live".to_string()).unwrap();
+        vm.alloc_set(VMSet::new()).unwrap();
+        vm.alloc_host(Host)?;
+        let stats = heap_stats(&mut vm, &[])?;
+        let collections = stat(&mut vm, stats, "collections").get_int(&vm)?;
+        assert!(stat(&mut vm, stats, "string").get_int(&vm)? >= 1);
+        assert!(stat(&mut vm, stats, "set").get_int(&vm)? >= 1);
+        assert_eq!(stat(&mut vm, stats, "host"), Value::from(1));
+        assert_eq!(stat(&mut vm, stats, "gc-step"), Value::from(0));
+
+        assert_eq!(gc(&mut vm, &[])?, Value::True);
+        let stats = heap_stats(&mut vm, &[])?;
+        assert_eq!(
+            stat(&mut vm, stats, "collections").get_int(&vm)?,
+            collections + 1
+        );
+        vm.pause_gc();
+        assert_eq!(gc(&mut vm, &[])?, Value::False);
+        vm.unpause_gc();
+
+        set_grow_factor(&mut vm, &[3.0.into()])?;
+        assert!(set_grow_factor(&mut vm, &[1.0.into()]).is_err());
+        set_gc_step(&mut vm, &[10.into()])?;
+        assert!(set_gc_step(&mut vm, &[(-1).into()]).is_err());
+        let stats = heap_stats(&mut vm, &[])?;
+        assert_eq!(stat(&mut vm, stats, "grow-factor"), Value::from(3.0));
+        assert_eq!(stat(&mut vm, stats, "gc-step"), Value::from(10));
+        Ok(())
+    }
+
+    #[test]
+    fn test_weak() -> VMResult<()> {
+        let mut vm = new_slosh_vm();
+        let live = vm.alloc_vector(vec![]).unwrap();
+        let dead = vm.alloc_vector(vec![]).unwrap();
+        let weak_live = weak(&mut vm, &[live])?;
+        let weak_dead = weak(&mut vm, &[dead])?;
+        for val in [live, weak_live, weak_dead] {
+            let slot = vm.reserve_global();
+            vm.set_global(slot, val);
+        }
+        assert_eq!(weak_get(&mut vm, &[weak_dead])?, dead);
+        assert_eq!(weak_alive(&mut vm, &[weak_dead])?, Value::True);
+        gc(&mut vm, &[])?;
+        assert_eq!(weak_get(&mut vm, &[weak_live])?, live);
+        assert_eq!(weak_get(&mut vm, &[weak_dead])?, Value::Nil);
+        assert_eq!(weak_get(&mut vm, &[weak_dead, Value::True])?, Value::True);
+        assert_eq!(weak_alive(&mut vm, &[weak_dead])?, Value::False);
+        assert!(weak_get(&mut vm, &[live]).is_err());
+        Ok(())
+    }
+}
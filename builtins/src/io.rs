@@ -1,7 +1,6 @@
 use crate::add_builtin;
 use compile_state::state::SloshVm;
-use slvm::{VMError, VMResult, Value};
-use std::collections::HashMap;
+use slvm::{VMError, VMMap, VMResult, Value};
 use std::fs::File;
 use std::path::Path;
 
@@ -11,7 +10,7 @@ fn fs_meta(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
         let name = string.pretty_value(vm);
         let file = File::open(name)?;
         let meta = file.metadata()?;
-        let mut map = HashMap::new();
+        let mut map = VMMap::new();
         let ftype = if meta.is_dir() {
             "dir"
         } else if meta.is_file() {
@@ -26,17 +25,17 @@ fn fs_meta(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
         } else {
             Value::False
         };
-        map.insert(Value::Keyword(vm.intern_static("readonly")), ro);
+        map.insert(Value::Keyword(vm.intern_static("readonly")), ro)?;
         map.insert(
             Value::Keyword(vm.intern_static("len")),
             (meta.len() as i64).into(),
-        );
+        )?;
         map.insert(
             Value::Keyword(vm.intern_static("type")),
             Value::Keyword(vm.intern_static(ftype)),
-        );
+        )?;
         // XXX TODO- include times.
-        Ok(vm.alloc_map(map))
+        vm.alloc_map(map)
     } else {
         Err(VMError::new(
             "io",
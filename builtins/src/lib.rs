@@ -5,9 +5,14 @@ use slvm::{CallFuncSig, VMError, VMResult, Value};
 
 pub mod collections;
 pub mod conversions;
+pub mod debug;
+pub mod gc;
 pub mod io;
 pub mod print;
+pub mod profile;
+pub mod restart;
 pub mod string;
+pub mod trace;
 pub mod types;
 
 fn get_globals(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
@@ -20,7 +25,7 @@ fn get_globals(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
     for g in vm.globals().keys() {
         result.push(Value::Symbol(*g));
     }
-    Ok(vm.alloc_vector(result))
+    vm.alloc_vector(result)
 }
 
 fn get_prop(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
@@ -100,7 +105,7 @@ fn gensym(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
     }
     let line = vm.env().line();
     let sym_idx = vm.env_mut().next_gensym();
-    let sym = vm.intern(&format!("#<SYM:{line}:{sym_idx}>"));
+    let sym = vm.gensym(&format!("#<SYM:{line}:{sym_idx}>"));
     Ok(Value::Symbol(sym))
 }
 
@@ -170,9 +175,25 @@ pub fn add_builtin(
     doc_string: &str,
 ) {
     let si = env.set_global_builtin(name, func);
+    set_doc_string(env, si, doc_string);
+}
+
+/// Like add_builtin but for a closure, lets a builtin capture state (see
+/// GVm::add_builtin_closure).
+pub fn add_builtin_closure<F>(env: &mut SloshVm, name: &str, func: F, doc_string: &str)
+where
+    F: Fn(&mut SloshVm, &[Value]) -> VMResult<Value> + 'static,
+{
+    let si = env.set_global_builtin_closure(name, func);
+    set_doc_string(env, si, doc_string);
+}
+
+fn set_doc_string(env: &mut SloshVm, slot: u32, doc_string: &str) {
     let key = env.intern("doc-string");
-    let s = env.alloc_string(doc_string.to_string());
-    env.set_global_property(si, key, s);
+    let s = env
+        .alloc_string(doc_string.to_string())
+        .expect("no heap limit while adding builtins");
+    env.set_global_property(slot, key, s);
 }
 
 pub fn add_misc_builtins(env: &mut SloshVm) {
@@ -203,6 +224,11 @@ Example:
 (test::assert-error (% 1 2.0))
 ",
     );
+    gc::add_gc_builtins(env);
+    profile::add_profile_builtins(env);
+    debug::add_debug_builtins(env);
+    trace::add_trace_builtins(env);
+    restart::add_restart_builtins(env);
     add_builtin(
         env,
         "get-globals",
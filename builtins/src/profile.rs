@@ -0,0 +1,278 @@
+use crate::add_builtin;
+use compile_state::state::{SloshVm, SloshVmTrait};
+use slvm::{Callee, Interned, VMError, VMMap, VMResult, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[cfg(feature = "profile")]
+fn profile(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    let usage = || {
+        VMError::new_vm(
+            "profile: Invalid arguments (:start [interval]) | :stop | (:report [count]) | :folded"
+                .to_string(),
+        )
+    };
+    let (Some(Value::Keyword(cmd)), args) = (registers.first(), registers.get(1..).unwrap_or(&[]))
+    else {
+        return Err(usage());
+    };
+    let arg = |vm: &SloshVm, default: i64| -> VMResult<i64> {
+        match args {
+            [] => Ok(default),
+            [val] => val.get_int(vm),
+            _ => Err(usage()),
+        }
+    };
+    match vm.get_interned(*cmd) {
+        "start" => {
+            let interval = arg(vm, slvm::DEFAULT_PROFILE_INTERVAL as i64)?;
+            if interval < 1 {
+                return Err(VMError::new_vm(
+                    "profile: interval must be positive".to_string(),
+                ));
+            }
+            vm.start_profile(interval as u64);
+            Ok(Value::Nil)
+        }
+        "stop" if args.is_empty() => {
+            vm.stop_profile();
+            Ok(Value::Nil)
+        }
+        "report" => {
+            let count = arg(vm, 20)?.max(0) as usize;
+            let report = vm.profile().map(|p| p.report(count));
+            report.map_or(Ok(Value::Nil), |r| vm.alloc_string(r))
+        }
+        "folded" if args.is_empty() => {
+            let folded = vm.profile().map(|p| p.folded());
+            folded.map_or(Ok(Value::Nil), |f| vm.alloc_string(f))
+        }
+        _ => Err(usage()),
+    }
+}
+
+fn call_profile(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    match registers {
+        [] => {}
+        [Value::Keyword(cmd)] if vm.get_interned(*cmd) == "start" => {
+            vm.start_call_profile();
+            return Ok(Value::Nil);
+        }
+        [Value::Keyword(cmd)] if vm.get_interned(*cmd) == "stop" => {
+            vm.stop_call_profile();
+            return Ok(Value::Nil);
+        }
+        _ => {
+            return Err(VMError::new_vm(
+                "call-profile: Invalid arguments (:start | :stop)?".to_string(),
+            ))
+        }
+    }
+    // Name things by the global they are bound to if possible.
+    let mut names: HashMap<Callee, Interned> = HashMap::new();
+    for (name, slot) in vm.globals() {
+        let callee = match vm.get_global(*slot as u32) {
+            Value::Lambda(h) => Callee::Chunk(Arc::as_ptr(&vm.get_lambda(h)) as usize),
+            Value::Closure(h) => Callee::Chunk(Arc::as_ptr(&vm.get_closure(h).0) as usize),
+            Value::Builtin(idx) => Callee::Builtin(idx),
+            _ => continue,
+        };
+        names.insert(callee, *name);
+    }
+    let Some(profile) = vm.call_profile() else {
+        return Ok(Value::Nil);
+    };
+    let mut totals: HashMap<String, (u64, Duration, Duration)> = HashMap::new();
+    for (callee, stats) in &profile.stats {
+        let name = match (names.get(callee), callee, &stats.chunk) {
+            (Some(name), _, _) => vm.get_interned(*name).to_string(),
+            (None, _, Some(chunk)) => format!("{}:{}", chunk.file_name, chunk.start_line()),
+            (None, Callee::Builtin(idx), _) => format!("#<builtin {idx}>"),
+            (None, Callee::Chunk(_), None) => "#<unknown>".to_string(),
+        };
+        let total = totals.entry(name).or_default();
+        total.0 += stats.calls;
+        total.1 += stats.inclusive;
+        total.2 += stats.exclusive;
+    }
+    let calls_key = Value::Keyword(vm.intern_static("calls"));
+    let inclusive_key = Value::Keyword(vm.intern_static("inclusive-ms"));
+    let exclusive_key = Value::Keyword(vm.intern_static("exclusive-ms"));
+    // Nothing is rooted until the result is returned.
+    vm.pause_gc();
+    // Dont return early until the unpause_gc() call below.
+    let res = totals
+        .into_iter()
+        .map(|(name, (calls, inclusive, exclusive))| {
+            let mut stats = VMMap::with_capacity(3);
+            stats.insert(calls_key, (calls as i64).into())?;
+            stats.insert(inclusive_key, (inclusive.as_secs_f64() * 1000.0).into())?;
+            stats.insert(exclusive_key, (exclusive.as_secs_f64() * 1000.0).into())?;
+            Ok((vm.alloc_string(name)?, vm.alloc_map(stats)?))
+        })
+        .collect::<VMResult<VMMap>>()
+        .and_then(|map| vm.alloc_map(map));
+    vm.unpause_gc();
+    res
+}
+
+pub fn add_profile_builtins(env: &mut SloshVm) {
+    #[cfg(feature = "profile")]
+    add_builtin(
+        env,
+        "profile",
+        profile,
+        "Usage: (profile :start [interval]) | (profile :stop) | (profile :report [count]) | (profile :folded)
+
+Profile running code (only available when built with the profile feature).  :start begins a new
+profile, counting every op code and sampling the current line, function and call stack every
+interval op codes (default 1000).  :stop stops collecting but keeps the profile.  :report returns
+a string with the count (default 20) hottest lines, functions and op codes and :folded returns the
+sampled call stacks in the folded format used by flamegraph tools.  Both return nil if there is no
+profile.  Functions are named by the file and line they start on.
+
+Section: core
+
+Example:
+(profile :start 10)
+(def i 0)
+(while (< i 1000) (set! i (+ i 1)))
+(profile :stop)
+(test::assert-true (str-contains (profile :report) \"Op codes:\"))
+",
+    );
+    add_builtin(
+        env,
+        "call-profile",
+        call_profile,
+        "Usage: (call-profile :start) | (call-profile :stop) | (call-profile)
+
+Profile function calls.  :start begins a new profile and :stop stops collecting but keeps it.  With
+no arguments returns a map (nil if there is no profile) of function name to a map of :calls,
+:inclusive-ms (time from call to return) and :exclusive-ms (time not spent in other calls).
+Functions are named by the global they are bound to or the file and line they start on.  Calls are
+only timed while profiling, otherwise the profiler costs a flag check per call.
+
+Section: core
+
+Example:
+(def fib (fn (n) (if (< n 2) n (+ (fib (- n 1)) (fib (- n 2))))))
+(call-profile :start)
+(fib 10)
+(call-profile :stop)
+(test::assert-equal 177 (get (get (call-profile) \"fib\") :calls))
+",
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sizeof_value;
+    use compile_state::state::new_slosh_vm;
+
+    fn stat(vm: &mut SloshVm, stats: Value, key: &'static str) -> Value {
+        let key = Value::Keyword(vm.intern_static(key));
+        let Value::Map(h) = stats else {
+            panic!("call-profile stats should be a map");
+        };
+        *vm.get_map(h).get(&key).expect("missing call-profile stat")
+    }
+
+    #[cfg(feature = "profile")]
+    #[test]
+    fn test_profile() -> VMResult<()> {
+        use slvm::{Chunk, INC, JMPLT, REGI, SRET};
+
+        let mut vm = new_slosh_vm();
+        assert!(profile(&mut vm, &[]).is_err());
+        let report = Value::Keyword(vm.intern("report"));
+        assert_eq!(profile(&mut vm, &[report])?, Value::Nil);
+
+        let mut chunk = Chunk::new("profile_test", 1);
+        chunk.input_regs = 1;
+        chunk.extra_regs = 2;
+        chunk.encode2(REGI, 1, 0, Some(1))?;
+        chunk.encode2(REGI, 2, 5000, Some(1))?;
+        let top = chunk.add_jump(chunk.code.len() as u32) as u16;
+        chunk.encode2(INC, 1, 1, Some(2))?;
+        chunk.encode3(JMPLT, 1, 2, top, Some(3))?;
+        chunk.encode1(SRET, 1, Some(4))?;
+        let start = Value::Keyword(vm.intern("start"));
+        profile(&mut vm, &[start, 100.into()])?;
+        vm.execute(Arc::new(chunk))?;
+        let stop = Value::Keyword(vm.intern("stop"));
+        profile(&mut vm, &[stop])?;
+
+        let prof = vm.profile().expect("missing profile");
+        assert_eq!(prof.op_counts[INC as usize], 5000);
+        assert_eq!(prof.op_counts[JMPLT as usize], 5000);
+        assert_eq!(prof.samples, 10003 / 100);
+        assert_eq!(prof.lines.get(&("profile_test", 1)), None);
+        let folded = vm.profile().unwrap().folded();
+        assert_eq!(folded, format!("profile_test:1 {}\n", 10003 / 100));
+        let report = profile(&mut vm, &[report, 5.into()])?;
+        let report = report.get_string(&vm)?.to_string();
+        assert!(report.contains("profile_test:2"));
+        assert!(report.contains("JMPLT"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_call_profile() -> VMResult<()> {
+        use slvm::{Chunk, REGI, SRET};
+
+        let mut vm = new_slosh_vm();
+        assert_eq!(call_profile(&mut vm, &[])?, Value::Nil);
+        let mut inner = Chunk::new("inner_file", 10);
+        inner.args = 1;
+        inner.input_regs = 2;
+        inner.encode1(SRET, 1, Some(10))?;
+        let lambda = vm.alloc_lambda(Arc::new(inner)).unwrap();
+        let ident = vm.set_named_global("ident", lambda);
+        let size = vm.set_global_builtin("sizeof-value", sizeof_value);
+        let mut anon = Chunk::new("anon_file", 20);
+        anon.input_regs = 1;
+        anon.encode1(SRET, 0, Some(20))?;
+        let anon = vm.alloc_lambda(Arc::new(anon)).unwrap();
+        let anon_slot = vm.reserve_global();
+        vm.set_global(anon_slot, anon);
+
+        let mut chunk = Chunk::new("main_file", 1);
+        chunk.input_regs = 1;
+        chunk.extra_regs = 3;
+        for _ in 0..3 {
+            chunk.encode2(REGI, 3, 7, Some(1))?;
+            chunk.encode_callg(ident, 1, 2, Some(1))?;
+        }
+        chunk.encode_callg(size, 0, 2, Some(2))?;
+        chunk.encode_callg(anon_slot, 0, 2, Some(2))?;
+        chunk.encode1(SRET, 2, Some(3))?;
+        let start = Value::Keyword(vm.intern("start"));
+        call_profile(&mut vm, &[start])?;
+        vm.execute(Arc::new(chunk))?;
+        let stop = Value::Keyword(vm.intern("stop"));
+        call_profile(&mut vm, &[stop])?;
+
+        let prof = call_profile(&mut vm, &[])?;
+        let Value::Map(h) = prof else {
+            panic!("call-profile should return a map");
+        };
+        let map = vm.get_map(h).clone();
+        assert_eq!(map.len(), 3);
+        let mut calls = HashMap::new();
+        for (name, stats) in map.iter() {
+            let name = name.get_string(&vm)?.to_string();
+            let count = stat(&mut vm, *stats, "calls").get_int(&vm)?;
+            let inclusive = stat(&mut vm, *stats, "inclusive-ms").get_float(&vm)?;
+            let exclusive = stat(&mut vm, *stats, "exclusive-ms").get_float(&vm)?;
+            assert!(exclusive <= inclusive);
+            calls.insert(name, count);
+        }
+        assert_eq!(calls.get("ident"), Some(&3));
+        assert_eq!(calls.get("sizeof-value"), Some(&1));
+        assert_eq!(calls.get("anon_file:20"), Some(&1));
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+use crate::add_builtin;
+use compile_state::state::SloshVm;
+use slvm::{Handle, VMError, VMResult, Value};
+
+fn signal(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    let (key, data, restarts) = match registers {
+        [Value::Keyword(key), data] => (*key, *data, Value::Nil),
+        [Value::Keyword(key), data, restarts @ (Value::Map(_) | Value::Nil)] => {
+            (*key, *data, *restarts)
+        }
+        _ => {
+            return Err(VMError::new_vm(
+                "signal: takes a keyword, data and an optional map of restarts".to_string(),
+            ))
+        }
+    };
+    Err(vm.signal(key, data, restarts))
+}
+
+fn invoke_restart(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    let Some((Value::Keyword(name), args)) = registers.split_first() else {
+        return Err(VMError::new_vm(
+            "invoke-restart: takes a restart keyword and its arguments".to_string(),
+        ));
+    };
+    let restart = match vm.restarts() {
+        Some(Value::Map(h)) => vm.get_map(h).get(&Value::Keyword(*name)).copied(),
+        _ => None,
+    };
+    match restart {
+        Some(Value::Lambda(h)) => {
+            let func = vm.get_lambda(h);
+            vm.do_call(func, args, None)
+        }
+        Some(Value::Closure(h)) => {
+            let (func, caps) = vm.get_closure(h);
+            let caps: Vec<Handle> = caps.to_vec();
+            vm.do_call(func, args, Some(&caps[..]))
+        }
+        Some(Value::Builtin(idx)) => vm.get_builtin(idx).call(vm, args),
+        Some(val) => Ok(val),
+        None => Err(VMError::new_vm(format!(
+            "invoke-restart: no restart {} available",
+            vm.get_interned(*name)
+        ))),
+    }
+}
+
+fn restarts(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if !registers.is_empty() {
+        return Err(VMError::new_vm("restarts: takes no arguments".to_string()));
+    }
+    match vm.restarts() {
+        Some(Value::Map(h)) => {
+            let names = vm.get_map(h).keys().copied().collect();
+            vm.alloc_vector(names)
+        }
+        _ => Ok(Value::Nil),
+    }
+}
+
+pub fn add_restart_builtins(env: &mut SloshVm) {
+    add_builtin(
+        env,
+        "signal",
+        signal,
+        "Usage: (signal key data [restarts])
+
+Raise a resumable error.  If an on-error handler is installed it is called with key and data
+where signal was called and whatever it returns becomes the value of the signal, otherwise this
+is the same as (err key data).  restarts is a map of keyword to function (or value) the handler
+can pick from with invoke-restart, the handler stays installed for later errors.
+
+Section: core
+
+Example:
+(def parse-rec (fn (rec)
+    (if (< rec 0) (signal :bad-record rec {:skip (fn () :skipped) :use-value (fn (v) v)}) rec)))
+(test::assert-equal [1 0 3]
+    ((fn ()
+        (on-error (fn (key data) (invoke-restart :use-value 0)))
+        [(parse-rec 1) (parse-rec -2) (parse-rec 3)])))
+(test::assert-error (parse-rec -1))
+",
+    );
+    add_builtin(
+        env,
+        "invoke-restart",
+        invoke_restart,
+        "Usage: (invoke-restart name arg*)
+
+Call the restart name (a keyword) offered by the signal being handled with the args and return
+its result (a restart that is not a function is returned as is).  Only valid from an on-error
+handler called for a signal with restarts.
+
+Section: core
+
+Example:
+(test::assert-equal :skipped
+    ((fn () (on-error (fn (key data) (invoke-restart :skip))) (signal :bad-record 1 {:skip :skipped}))))
+(test::assert-error (invoke-restart :skip))
+",
+    );
+    add_builtin(
+        env,
+        "restarts",
+        restarts,
+        "Usage: (restarts)
+
+Return a vector of the restart names offered by the signal being handled, nil if none.
+
+Section: core
+
+Example:
+(test::assert-equal [:retry]
+    ((fn () (on-error (fn (key data) (restarts))) (signal :bad-record 1 {:retry 1}))))
+(test::assert-equal nil (restarts))
+",
+    );
+}
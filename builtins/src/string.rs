@@ -9,15 +9,15 @@ fn str_trim(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
     match (i.next(), i.next(), i.next()) {
         (Some(string), None, None) => {
             let string = string.get_string(vm)?.trim().to_string();
-            Ok(vm.alloc_string(string))
+            vm.alloc_string(string)
         }
         (Some(string), Some(Value::Keyword(i)), None) if *i == right => {
             let string = string.get_string(vm)?.trim_end().to_string();
-            Ok(vm.alloc_string(string))
+            vm.alloc_string(string)
         }
         (Some(string), Some(Value::Keyword(i)), None) if *i == left => {
             let string = string.get_string(vm)?.trim_start().to_string();
-            Ok(vm.alloc_string(string))
+            vm.alloc_string(string)
         }
         _ => Err(VMError::new_vm(
             "str-trim: takes one argument with optional left/right keyword".to_string(),
@@ -62,7 +62,7 @@ fn str_replace(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
         let from = from.get_string(vm)?;
         let to = to.get_string(vm)?;
         let new_string = string.get_string(vm)?.replace(from, to);
-        Ok(vm.alloc_string(new_string))
+        vm.alloc_string(new_string)
     } else {
         Err(VMError::new_vm(
             "str-replace: takes three arguments".to_string(),
@@ -154,7 +154,7 @@ fn str_map_inner(vm: &mut SloshVm, func: Value, string: Value) -> VMResult<Strin
     };
     let mut res = String::new();
     for ch in UnicodeSegmentation::graphemes(string, true) {
-        let param = vm.alloc_char(ch);
+        let param = vm.alloc_char(ch)?;
         // Dont use '?' or return early until the heap_unsticky() call below.
         vm.heap_sticky(param);
         let val = match func {
@@ -167,7 +167,7 @@ fn str_map_inner(vm: &mut SloshVm, func: Value, string: Value) -> VMResult<Strin
                 let caps: Vec<Handle> = caps.to_vec();
                 vm.do_call(func, &[param], Some(&caps[..]))
             }
-            Value::Builtin(idx) => vm.get_builtin(idx)(vm, &[param]),
+            Value::Builtin(idx) => vm.get_builtin(idx).call(vm, &[param]),
             _ => Err(VMError::new_vm(
                 "str-map: second arg must be callable".to_string(),
             )),
@@ -193,7 +193,7 @@ fn str_map(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
     let mut i = registers.iter();
     if let (Some(string), Some(func), None) = (i.next(), i.next(), i.next()) {
         let res = str_map_inner(vm, *func, *string)?;
-        Ok(vm.alloc_string(res))
+        vm.alloc_string(res)
     } else {
         Err(VMError::new_vm(
             "str-map: takes a string and a lambda".to_string(),
@@ -251,20 +251,22 @@ fn str_split(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
     if let (Some(string), Some(pat), None) = (i.next(), i.next(), i.next()) {
         let string = string.pretty_value(vm);
         let pat = pat.pretty_value(vm);
-        let mut splits = Vec::new();
         vm.pause_gc();
-        if pat == ":whitespace" {
-            for s in string.split_whitespace() {
-                splits.push(vm.alloc_string(s.to_string()));
-            }
+        // Dont use '?' or return early until the unpause_gc() call below.
+        let ret = if pat == ":whitespace" {
+            string
+                .split_whitespace()
+                .map(|s| vm.alloc_string(s.to_string()))
+                .collect::<VMResult<Vec<Value>>>()
         } else {
-            for s in string.split(&pat) {
-                splits.push(vm.alloc_string(s.to_string()));
-            }
+            string
+                .split(&pat)
+                .map(|s| vm.alloc_string(s.to_string()))
+                .collect::<VMResult<Vec<Value>>>()
         }
-        let ret = vm.alloc_vector(splits);
+        .and_then(|splits| vm.alloc_vector(splits));
         vm.unpause_gc();
-        Ok(ret)
+        ret
     } else {
         Err(VMError::new_vm(
             "str-split: takes a string and a pattern".to_string(),
@@ -536,8 +538,8 @@ mod tests {
     #[test]
     fn test_str_push() -> VMResult<()> {
         let mut vm = new_slosh_vm();
-        let dest = vm.alloc_string("XXX".to_string());
-        let add = vm.alloc_string(" 123".to_string());
+        let dest = vm.alloc_string("XXX".to_string()).unwrap();
+        let add = vm.alloc_string(" 123".to_string()).unwrap();
         let res = str_push(&mut vm, &vec![dest, add])?;
         assert_eq!(dest, res);
         check_str(&vm, dest, "XXX 123");
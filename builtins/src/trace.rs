@@ -0,0 +1,126 @@
+use crate::add_builtin;
+use compile_state::state::{SloshVm, SloshVmTrait};
+use slvm::{Interned, VMError, VMResult, Value};
+
+/// The global bound to symbol sym for trace/untrace.
+fn trace_global(vm: &SloshVm, fn_name: &str, sym: Value) -> VMResult<(Interned, Value)> {
+    let Value::Symbol(name) = sym else {
+        return Err(VMError::new_vm(format!(
+            "{fn_name}: expected a symbol, got {}",
+            sym.display_type(vm)
+        )));
+    };
+    match vm.global_intern_slot(name) {
+        Some(slot) => Ok((name, vm.get_global(slot))),
+        None => Err(VMError::new_vm(format!(
+            "{fn_name}: {} is not defined",
+            vm.get_interned(name)
+        ))),
+    }
+}
+
+fn trace(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if registers.is_empty() {
+        let traced = vm.traced().into_iter().map(Value::Symbol).collect();
+        return vm.alloc_vector(traced);
+    }
+    for sym in registers {
+        let (name, val) = trace_global(vm, "trace", *sym)?;
+        vm.trace(name, val)?;
+    }
+    Ok(Value::Nil)
+}
+
+fn untrace(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    if registers.is_empty() {
+        vm.untrace_all();
+    }
+    for sym in registers {
+        let Value::Symbol(name) = sym else {
+            return Err(VMError::new_vm(format!(
+                "untrace: expected a symbol, got {}",
+                sym.display_type(vm)
+            )));
+        };
+        vm.untrace(*name);
+    }
+    Ok(Value::Nil)
+}
+
+fn set_trace_callback(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+    match registers {
+        [Value::Nil] => vm.set_trace_callback(None)?,
+        [callback] => vm.set_trace_callback(Some(*callback))?,
+        _ => {
+            return Err(VMError::new_vm(
+                "set-trace-callback: takes one argument".to_string(),
+            ))
+        }
+    }
+    Ok(Value::Nil)
+}
+
+pub fn add_trace_builtins(env: &mut SloshVm) {
+    add_builtin(
+        env,
+        "trace",
+        trace,
+        "Usage: (trace symbol*)
+
+Trace calls to the functions bound to the global symbols.  Every call into a traced function
+reports the call depth, the arguments and then the return value or error, tail calls are labeled
+as such.  Events are printed to stderr unless a callback was set with set-trace-callback.  With
+no arguments returns a vector of the traced symbols.
+
+Section: core
+
+Example:
+(def fact (fn (n acc) (if (< n 2) acc (fact (- n 1) (* n acc)))))
+(trace 'fact)
+(test::assert-equal ['fact] (trace))
+(untrace 'fact)
+(test::assert-equal [] (trace))
+",
+    );
+    add_builtin(
+        env,
+        "untrace",
+        untrace,
+        "Usage: (untrace symbol*)
+
+Stop tracing the functions bound to the global symbols, with no arguments stop tracing everything.
+
+Section: core
+
+Example:
+(def fact (fn (n acc) (if (< n 2) acc (fact (- n 1) (* n acc)))))
+(trace 'fact)
+(untrace)
+(test::assert-equal [] (trace))
+",
+    );
+    add_builtin(
+        env,
+        "set-trace-callback",
+        set_trace_callback,
+        "Usage: (set-trace-callback callback)
+
+Hand trace events to callback instead of printing them, nil goes back to printing.  The callback
+is called with the event (:call, :tail-call, :return or :error), the call depth (starting at 1),
+the traced symbol and a vector of the arguments, the return value or a pair of the error key and
+object.  Calls made by the callback are not traced.
+
+Section: core
+
+Example:
+(def events '())
+(def fact (fn (n acc) (if (< n 2) acc (fact (- n 1) (* n acc)))))
+(set-trace-callback (fn (event depth name data) (set! events (cons event events))))
+(trace 'fact)
+(fact 3 1)
+(untrace 'fact)
+(set-trace-callback nil)
+(test::assert-equal '(:return :return :return :tail-call :tail-call :call) events)
+",
+    );
+}
@@ -63,6 +63,12 @@
 //!                             |                             |     &emsp;* Note: Always does an allocation and returns a [`Value`]`::String` type.
 //!                             |                             |     &emsp;- [`SlFromRef`] `&`[`Value`] for [`LooseString`]
 //!                             |                             |
+//! `&`T / `&mut `T where T: [`host::SlHost`] | [`Value`]`::Host` |
+//!                             |                             | S -> R
+//!                             |                             |     &emsp;- [`SlIntoRef`] `&`T / `&mut `T for `&`[`Value`] (checked downcast)
+//!                             |                             | R -> S
+//!                             |                             |     &emsp;- [`SlFrom`] [`host::Host`]`<T>` for [`Value`]
+//!                             |                             |
 //! Value::Int32                |                             |
 //! Value::UInt32               |                             |
 //! Value::Int64                |                             |
@@ -94,6 +100,7 @@
 //! Value::Error                |                             |
 //! Value::StringConst          |                             |
 
+pub mod host;
 pub mod numbers;
 pub mod string_char;
 
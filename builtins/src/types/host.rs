@@ -0,0 +1,105 @@
+use crate::types::{SlFrom, SlFromRef};
+use compile_state::state::SloshVm;
+use slvm::{HostObject, VMResult, Value};
+
+/// Marker for Rust types that are handed to slosh as host objects ([`Value`]`::Host`).  Lets
+/// bridged builtins take them as `&T` or `&mut T` arguments (checked downcast, a value of the
+/// wrong type is a conversion error).
+pub trait SlHost: HostObject {}
+
+/// Return a host object from a bridged builtin, moves it onto the heap.
+pub struct Host<T>(pub T);
+
+impl<T: SlHost> SlFrom<Host<T>> for Value {
+    fn sl_from(value: Host<T>, vm: &mut SloshVm) -> VMResult<Self> {
+        vm.alloc_host(value.0)
+    }
+}
+
+impl<'a, T: SlHost> SlFromRef<'a, &Value> for &'a T {
+    fn sl_from_ref(value: &Value, vm: &'a mut SloshVm) -> VMResult<Self> {
+        vm.get_host_ref(*value)
+    }
+}
+
+impl<'a, T: SlHost> SlFromRef<'a, &Value> for &'a mut T {
+    fn sl_from_ref(value: &Value, vm: &'a mut SloshVm) -> VMResult<Self> {
+        vm.get_host_mut(*value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::types::{SlInto, SlIntoRef};
+    use compile_state::state::new_slosh_vm;
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    struct Counter {
+        count: i64,
+        held: Value,
+        finalized: Rc<Cell<bool>>,
+    }
+
+    impl HostObject for Counter {
+        fn type_name(&self) -> &'static str {
+            "Counter"
+        }
+
+        fn trace(&self, mark: &mut dyn FnMut(Value)) {
+            mark(self.held);
+        }
+
+        fn finalize(&mut self) {
+            self.finalized.set(true);
+        }
+    }
+
+    impl SlHost for Counter {}
+
+    struct Other;
+
+    impl HostObject for Other {
+        fn type_name(&self) -> &'static str {
+            "Other"
+        }
+    }
+
+    impl SlHost for Other {}
+
+    #[test]
+    fn test_host_object() -> VMResult<()> {
+        let mut vm = new_slosh_vm();
+        let finalized = Rc::new(Cell::new(false));
+        let held = vm.alloc_string("held".to_string()).unwrap();
+        let counter = Counter {
+            count: 1,
+            held,
+            finalized: finalized.clone(),
+        };
+        let val: Value = Host(counter).sl_into(&mut vm)?;
+        let slot = vm.reserve_global();
+        vm.set_global(slot, val);
+        assert_eq!(val.display_type(&vm), "Counter");
+        assert_eq!(val.display_value(&vm), "#<Counter>");
+
+        let counter: &mut Counter = (&val).sl_into_ref(&mut vm)?;
+        counter.count += 1;
+        let counter: &Counter = (&val).sl_into_ref(&mut vm)?;
+        assert_eq!(counter.count, 2);
+        let other: VMResult<&Other> = (&val).sl_into_ref(&mut vm);
+        assert!(other.is_err());
+        let not_host: VMResult<&Counter> = (&held).sl_into_ref(&mut vm);
+        assert!(not_host.is_err());
+
+        // The trace hook keeps held alive, the finalizer runs once the counter is unreachable.
+        vm.gc();
+        assert!(!finalized.get());
+        assert_eq!(vm.get_string(held.get_handle().unwrap()), "held");
+        vm.set_global(slot, Value::Nil);
+        vm.gc();
+        assert!(finalized.get());
+        Ok(())
+    }
+}
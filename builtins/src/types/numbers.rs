@@ -15,6 +15,17 @@ impl SlFrom<u32> for Value {
     }
 }
 
+impl SlFrom<i64> for Value {
+    fn sl_from(value: i64, vm: &mut SloshVm) -> VMResult<Self> {
+        vm.alloc_int(value)
+    }
+}
+impl SlFrom<u64> for Value {
+    fn sl_from(value: u64, vm: &mut SloshVm) -> VMResult<Self> {
+        vm.alloc_bigint(value.into())
+    }
+}
+
 impl SlFrom<&Value> for i32 {
     fn sl_from(value: &Value, vm: &mut SloshVm) -> VMResult<i32> {
         match value {
@@ -32,3 +43,18 @@ impl SlFrom<&Value> for i32 {
         }
     }
 }
+
+impl SlFrom<&Value> for i64 {
+    fn sl_from(value: &Value, vm: &mut SloshVm) -> VMResult<i64> {
+        match value {
+            Value::Byte(_) | Value::Int(_) | Value::BigInt(_) => value.get_int(vm).map_err(|_| {
+                VMError::new_conversion(
+                    "Provided slosh value too large to fit desired type.".to_string(),
+                )
+            }),
+            _ => Err(VMError::new_conversion(
+                ErrorStrings::fix_me_mismatched_type(ValueType::Int.into(), value.display_type(vm)),
+            )),
+        }
+    }
+}
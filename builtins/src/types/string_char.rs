@@ -31,8 +31,8 @@ impl<'a> SlFromRef<'a, &Value> for LooseString<'a, str> {
 impl<'a> SlFromRef<'a, LooseString<'a, str>> for Value {
     fn sl_from_ref(value: LooseString<'a, str>, vm: &'a mut SloshVm) -> VMResult<Self> {
         match value {
-            LooseString::Borrowed(s) => Ok(vm.alloc_string(s.to_string())),
-            LooseString::Owned(s) => Ok(vm.alloc_string(s)),
+            LooseString::Borrowed(s) => vm.alloc_string(s.to_string()),
+            LooseString::Owned(s) => vm.alloc_string(s),
         }
     }
 }
@@ -90,8 +90,8 @@ impl<'a> SlFromRef<'a, SloshChar<'a>> for Value {
         match value {
             SloshChar::Char(ch) => Ok(Value::CodePoint(ch)),
             SloshChar::String(cow) => match cow {
-                Cow::Borrowed(s) => Ok(vm.alloc_char(s)),
-                Cow::Owned(s) => Ok(vm.alloc_char(s.as_str())),
+                Cow::Borrowed(s) => vm.alloc_char(s),
+                Cow::Owned(s) => vm.alloc_char(s.as_str()),
             },
         }
     }
@@ -110,7 +110,7 @@ impl<'a> SlAsMut<'a, String> for &Value {
 
 impl SlFrom<String> for Value {
     fn sl_from(value: String, vm: &mut SloshVm) -> VMResult<Self> {
-        Ok(vm.alloc_string(value))
+        vm.alloc_string(value)
     }
 }
 
@@ -119,7 +119,7 @@ where
     T: ToString + ?Sized,
 {
     fn sl_from(value: &T, vm: &mut SloshVm) -> VMResult<Self> {
-        Ok(vm.alloc_string(value.to_string()))
+        vm.alloc_string(value.to_string())
     }
 }
 
@@ -128,7 +128,7 @@ where
     T: ToString + ?Sized,
 {
     fn sl_from(value: &mut T, vm: &mut SloshVm) -> VMResult<Self> {
-        Ok(vm.alloc_string(value.to_string()))
+        vm.alloc_string(value.to_string())
     }
 }
 
@@ -155,13 +155,13 @@ mod test {
     pub const CHAR_CLUSTER_LONG: &'static str = "👩‍💻";
 
     pub fn create_char_cluster(vm: &mut SloshVm) -> Value {
-        let val = vm.alloc_char(CHAR_CLUSTER);
+        let val = vm.alloc_char(CHAR_CLUSTER).unwrap();
         assert!(matches!(val, Value::CharCluster(_, _)));
         val
     }
 
     pub fn create_char_cluster_long(vm: &mut SloshVm) -> Value {
-        let val = vm.alloc_char(CHAR_CLUSTER_LONG);
+        let val = vm.alloc_char(CHAR_CLUSTER_LONG).unwrap();
         assert!(matches!(val, Value::CharClusterLong(_)));
         val
     }
@@ -171,9 +171,11 @@ mod test {
     }
 
     pub fn create_string(vm: &mut SloshVm) -> Value {
-        let val = vm.alloc_string(
-            CHAR_CLUSTER.to_string() + CHAR_CLUSTER_LONG + CODE_POINT.to_string().as_str(),
-        );
+        let val = vm
+            .alloc_string(
+                CHAR_CLUSTER.to_string() + CHAR_CLUSTER_LONG + CODE_POINT.to_string().as_str(),
+            )
+            .unwrap();
         assert!(matches!(val, Value::String(_)));
         val
     }
@@ -227,7 +229,7 @@ mod test {
     fn try_str_mut() {
         let mut vm = new_slosh_vm();
         let to_mutate = " hello world ";
-        let test_str = vm.alloc_string(to_mutate.to_string());
+        let test_str = vm.alloc_string(to_mutate.to_string()).unwrap();
         let args = &[test_str];
         str_test_mut(&mut vm, args).unwrap();
         match args[0] {
@@ -289,7 +291,7 @@ mod test {
     }
 
     fn str_trim_test(vm: &mut SloshVm, test_str: String) -> VMResult<Value> {
-        let test_str = vm.alloc_string(test_str);
+        let test_str = vm.alloc_string(test_str).unwrap();
         let args = [test_str];
         let fn_name = "str_trim";
         const PARAMS_LEN: usize = 1usize;
@@ -1,6 +1,7 @@
-use slvm::{from_i56, CallFuncSig, Chunk, GVm, Interned, Value};
+use slvm::{from_i56, CallFuncSig, Chunk, GVm, Interned, VMResult, Value};
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::io::{Read, Write};
 use std::rc::Rc;
 
 #[derive(Clone, Debug)]
@@ -165,6 +166,8 @@ pub struct Specials {
     pub xar: Interned,
     pub xdr: Interned,
     pub make_hash: Interned,
+    pub make_set: Interned,
+    pub byte_vector: Interned,
     pub vec: Interned,
     pub make_vec: Interned,
     pub vec_pop: Interned,
@@ -200,6 +203,8 @@ pub struct Specials {
     pub is_err: Interned,
     pub is_ok: Interned,
     pub ret: Interned,
+    pub def_dynamic: Interned,
+    pub binding: Interned,
 
     pub rest: Interned,
     pub optional: Interned,
@@ -241,6 +246,38 @@ associations will be evaluated.
 
 Section: hashmap
 
+",
+            ),
+            make_set: add_special(
+                vm,
+                "make-set",
+                "Usage: (make-set item*)
+
+Make a new set containing the items (duplicates are dropped).  The reader literal #{item*} is
+compiled to make-set.
+
+Section: set
+
+Example:
+(test::assert-equal 0 (len (make-set)))
+(test::assert-equal 2 (len (make-set 1 2 1)))
+(test::assert-true (equal? #{1 2} (make-set 2 1)))
+",
+            ),
+            byte_vector: add_special(
+                vm,
+                "byte-vector",
+                "Usage: (byte-vector int*)
+
+Make a new byte vector from ints (each must be from 0 to 255).  The reader literal #u8(int*) is
+compiled to byte-vector.
+
+Section: bytes
+
+Example:
+(test::assert-equal 0 (len (byte-vector)))
+(test::assert-true (equal? #u8(1 2 255) (byte-vector 1 2 #xff)))
+(test::assert-error (byte-vector 256))
 ",
             ),
             vec: add_special(
@@ -379,6 +416,41 @@ Example:
             is_err: add_special(vm, "err?", ""),
             is_ok: add_special(vm, "ok?", ""),
             ret: add_special(vm, "return", ""),
+            def_dynamic: add_special(
+                vm,
+                "defdynamic",
+                "Usage: (defdynamic symbol expression?)
+
+Define a global like def and mark it dynamic so it can be rebound with binding.
+
+Section: core
+
+Example:
+(defdynamic *test-dynamic* 1)
+(test::assert-equal 1 *test-dynamic*)
+",
+            ),
+            binding: add_special(
+                vm,
+                "binding",
+                "Usage: (binding (symbol value ...) body ...)
+
+Evaluate the values then set each dynamic global symbol (see defdynamic) to its value for the
+extent of body, the old values are put back when body returns, an error unwinds out of it, a
+continuation jumps out of it or running it is aborted (out of fuel for instance).  Returns the
+result of the last form in body.
+
+Section: core
+
+Example:
+(defdynamic *test-depth* 1)
+(def test-get-depth (fn () *test-depth*))
+(test::assert-equal 2 (binding (*test-depth* 2) (test-get-depth)))
+(test::assert-equal 1 *test-depth*)
+(test::assert-equal 3 (call/cc (fn (k) (binding (*test-depth* 3) (k *test-depth*)))))
+(test::assert-equal 1 *test-depth*)
+",
+            ),
 
             rest: vm.intern_static("&"),
             optional: vm.intern_static("%"),
@@ -392,7 +464,9 @@ fn add_special(env: &mut SloshVm, name: &'static str, doc_string: &str) -> Inter
     let val = Value::Special(i);
     let si = env.set_named_global(name, val);
     let key = env.intern("doc-string");
-    let s = env.alloc_string(doc_string.to_string());
+    let s = env
+        .alloc_string(doc_string.to_string())
+        .expect("no heap limit while adding specials");
     env.set_global_property(si, key, s);
     i
 }
@@ -509,6 +583,10 @@ pub trait SloshVmTrait {
     fn get_reserve_global(&mut self, symbol: Interned) -> u32;
     fn set_named_global(&mut self, string: &str, value: Value) -> u32;
     fn set_global_builtin(&mut self, string: &str, func: CallFuncSig<CompileEnvironment>) -> u32;
+    /// Like set_global_builtin but for a closure (see GVm::add_builtin_closure).
+    fn set_global_builtin_closure<F>(&mut self, string: &str, func: F) -> u32
+    where
+        F: Fn(&mut SloshVm, &[Value]) -> VMResult<Value> + 'static;
     fn dump_globals(&self);
     fn globals(&self) -> &HashMap<Interned, usize>;
     fn own_line(&self) -> Option<u32>;
@@ -516,6 +594,16 @@ pub trait SloshVmTrait {
     fn line_num(&self) -> u32;
     fn specials(&self) -> &Specials;
     fn global_intern_slot(&self, symbol: Interned) -> Option<u32>;
+    /// Save chunk as bytecode, globals are saved by name so it can be loaded into another VM.
+    fn write_chunk(&self, chunk: &Chunk, out: &mut dyn Write) -> VMResult<()>;
+    /// Load a chunk saved with write_chunk, reserving any globals it uses that do not exist yet.
+    fn read_chunk(&mut self, input: &mut dyn Read) -> VMResult<Chunk>;
+    /// Save an image of the VM (see GVm::write_image) along with the compiler's global names.
+    fn save_image(&mut self, out: &mut dyn Write) -> VMResult<()>;
+    /// Load an image saved with save_image replacing the current VM state.  This should be a VM
+    /// set up the same way as the one that saved the image (same builtins added in the same
+    /// order), for instance a new VM with the builtins added but before loading any code.
+    fn load_image(&mut self, input: &mut dyn Read) -> VMResult<()>;
 }
 
 pub fn new_slosh_vm() -> SloshVm {
@@ -545,6 +633,8 @@ impl SloshVmTrait for SloshVm {
             *idx as u32
         } else {
             let idx = self.reserve_global();
+            // The global's name is only held by the global map, don't let the interner reclaim it.
+            self.keep_symbol(symbol);
             self.env_mut().global_map.insert(symbol, idx as usize);
             idx
         }
@@ -562,6 +652,14 @@ impl SloshVmTrait for SloshVm {
         self.set_named_global(string, f_val)
     }
 
+    fn set_global_builtin_closure<F>(&mut self, string: &str, func: F) -> u32
+    where
+        F: Fn(&mut SloshVm, &[Value]) -> VMResult<Value> + 'static,
+    {
+        let f_val = self.add_builtin_closure(func);
+        self.set_named_global(string, f_val)
+    }
+
     fn dump_globals(&self) {
         println!("GLOBALS:");
         let mut ordered_keys = Vec::with_capacity(self.env().global_map.len());
@@ -617,4 +715,60 @@ impl SloshVmTrait for SloshVm {
             .copied()
             .map(|i| i as u32)
     }
+
+    fn write_chunk(&self, chunk: &Chunk, mut out: &mut dyn Write) -> VMResult<()> {
+        let names: HashMap<u32, Interned> = self
+            .env()
+            .global_map
+            .iter()
+            .map(|(k, v)| (*v as u32, *k))
+            .collect();
+        chunk.write_bytecode(self, |slot| names.get(&slot).copied(), &mut out)
+    }
+
+    fn read_chunk(&mut self, mut input: &mut dyn Read) -> VMResult<Chunk> {
+        Chunk::read_bytecode(self, |vm, name| vm.get_reserve_global(name), &mut input)
+    }
+
+    fn save_image(&mut self, mut out: &mut dyn Write) -> VMResult<()> {
+        let names: HashMap<u32, Interned> = self
+            .env()
+            .global_map
+            .iter()
+            .map(|(k, v)| (*v as u32, *k))
+            .collect();
+        self.write_image_named(&mut out, |slot| names.get(&slot).copied())?;
+        let env = self.env();
+        out.write_all(&(env.global_map.len() as u32).to_be_bytes())?;
+        for (name, slot) in &env.global_map {
+            out.write_all(&name.id.to_be_bytes())?;
+            out.write_all(&(*slot as u32).to_be_bytes())?;
+        }
+        out.write_all(&(env.gensym_idx as u64).to_be_bytes())?;
+        Ok(())
+    }
+
+    fn load_image(&mut self, mut input: &mut dyn Read) -> VMResult<()> {
+        fn read_u32(input: &mut dyn Read) -> VMResult<u32> {
+            let mut buf = [0_u8; 4];
+            input.read_exact(&mut buf)?;
+            Ok(u32::from_be_bytes(buf))
+        }
+
+        self.read_image(&mut input)?;
+        let len = read_u32(input)?;
+        let mut global_map = HashMap::with_capacity(len as usize);
+        for _ in 0..len {
+            let name = Interned {
+                id: read_u32(input)?,
+            };
+            global_map.insert(name, read_u32(input)? as usize);
+        }
+        let mut buf = [0_u8; 8];
+        input.read_exact(&mut buf)?;
+        let env = self.env_mut();
+        env.global_map = global_map;
+        env.gensym_idx = u64::from_be_bytes(buf) as usize;
+        Ok(())
+    }
 }
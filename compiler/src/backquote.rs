@@ -113,24 +113,24 @@ impl Tag {
     }
 }
 
-fn quote(vm: &mut SloshVm, exp: Value) -> Value {
-    let cdr = vm.alloc_pair_ro(exp, Value::Nil);
+fn quote(vm: &mut SloshVm, exp: Value) -> VMResult<Value> {
+    let cdr = vm.alloc_pair_ro(exp, Value::Nil)?;
     let q_i = vm.intern_static("quote");
     vm.alloc_pair_ro(Value::Symbol(q_i), cdr)
 }
 
-fn list(vm: &mut SloshVm, exp: Value) -> Value {
-    let cdr = vm.alloc_pair_ro(exp, Value::Nil);
+fn list(vm: &mut SloshVm, exp: Value) -> VMResult<Value> {
+    let cdr = vm.alloc_pair_ro(exp, Value::Nil)?;
     let q_i = vm.intern_static("list");
     vm.alloc_pair_ro(Value::Symbol(q_i), cdr)
 }
 
-fn vec(vm: &mut SloshVm, v: &[Value]) -> Value {
+fn vec(vm: &mut SloshVm, v: &[Value]) -> VMResult<Value> {
     let mut last_pair = Value::Nil;
     if !v.is_empty() {
         let mut i = v.len();
         while i > 0 {
-            last_pair = vm.alloc_pair_ro(v[i - 1], last_pair);
+            last_pair = vm.alloc_pair_ro(v[i - 1], last_pair)?;
             i -= 1;
         }
     }
@@ -138,39 +138,39 @@ fn vec(vm: &mut SloshVm, v: &[Value]) -> Value {
     vm.alloc_pair_ro(Value::Symbol(q_i), last_pair)
 }
 
-fn list2(vm: &mut SloshVm, exp: Value) -> Value {
+fn list2(vm: &mut SloshVm, exp: Value) -> VMResult<Value> {
     let q_i = vm.intern_static("list");
     vm.alloc_pair_ro(Value::Symbol(q_i), exp)
 }
 
-fn append(vm: &mut SloshVm, exp1: Value, exp2: Value) -> Value {
-    let cdr1 = vm.alloc_pair_ro(exp2, Value::Nil);
-    let cdr2 = vm.alloc_pair_ro(exp1, cdr1);
+fn append(vm: &mut SloshVm, exp1: Value, exp2: Value) -> VMResult<Value> {
+    let cdr1 = vm.alloc_pair_ro(exp2, Value::Nil)?;
+    let cdr2 = vm.alloc_pair_ro(exp1, cdr1)?;
     let q_i = vm.intern_static("list-append");
     vm.alloc_pair_ro(Value::Symbol(q_i), cdr2)
 }
 
-fn rewrap(vm: &mut SloshVm, exp: Value, sym: &'static str) -> Value {
-    let cdr = vm.alloc_pair_ro(exp, Value::Nil);
+fn rewrap(vm: &mut SloshVm, exp: Value, sym: &'static str) -> VMResult<Value> {
+    let cdr = vm.alloc_pair_ro(exp, Value::Nil)?;
     let q_i = vm.intern_static(sym);
-    let car = quote(vm, Value::Symbol(q_i));
-    let cdr = vm.alloc_pair_ro(car, cdr);
+    let car = quote(vm, Value::Symbol(q_i))?;
+    let cdr = vm.alloc_pair_ro(car, cdr)?;
     list2(vm, cdr)
 }
 
-fn unquote(vm: &mut SloshVm, exp: Value) -> Value {
+fn unquote(vm: &mut SloshVm, exp: Value) -> VMResult<Value> {
     rewrap(vm, exp, "unquote")
 }
 
-fn splice(vm: &mut SloshVm, exp: Value) -> Value {
+fn splice(vm: &mut SloshVm, exp: Value) -> VMResult<Value> {
     rewrap(vm, exp, "unquote-splice")
 }
 
-fn splice_bang(vm: &mut SloshVm, exp: Value) -> Value {
+fn splice_bang(vm: &mut SloshVm, exp: Value) -> VMResult<Value> {
     rewrap(vm, exp, "unquote-splice!")
 }
 
-fn back_quote(vm: &mut SloshVm, exp: Value) -> Value {
+fn back_quote(vm: &mut SloshVm, exp: Value) -> VMResult<Value> {
     rewrap(vm, exp, "back-quote")
 }
 
@@ -183,7 +183,7 @@ fn qq_expand(vm: &mut SloshVm, exp: Value, line: u32, depth: u32) -> VMResult<Va
             Ok(Tag::data(vm, exp)?)
         } else {
             let expand = qq_expand(vm, Tag::data(vm, exp)?, line, depth - 1)?;
-            Ok(unquote(vm, expand))
+            unquote(vm, expand)
         }
     } else if tag.is_splice(vm, exp) {
         if depth == 0 {
@@ -192,7 +192,7 @@ fn qq_expand(vm: &mut SloshVm, exp: Value, line: u32, depth: u32) -> VMResult<Va
             )))
         } else {
             let expand = qq_expand(vm, Tag::data(vm, exp)?, line, depth - 1)?;
-            Ok(splice(vm, expand))
+            splice(vm, expand)
         }
     } else if tag.is_splice_bang(vm, exp) {
         if depth == 0 {
@@ -201,11 +201,11 @@ fn qq_expand(vm: &mut SloshVm, exp: Value, line: u32, depth: u32) -> VMResult<Va
             )))
         } else {
             let expand = qq_expand(vm, Tag::data(vm, exp)?, line, depth - 1)?;
-            Ok(splice_bang(vm, expand))
+            splice_bang(vm, expand)
         }
     } else if tag.is_backquote(vm, exp) {
         let inner = qq_expand(vm, Tag::data(vm, exp)?, line, depth + 1)?;
-        Ok(back_quote(vm, inner))
+        back_quote(vm, inner)
     } else {
         match exp {
             Value::Pair(_) | Value::List(_, _) => {
@@ -215,7 +215,7 @@ fn qq_expand(vm: &mut SloshVm, exp: Value, line: u32, depth: u32) -> VMResult<Va
                     Ok(l1)
                 } else {
                     let l2 = qq_expand(vm, cdr, line, depth)?;
-                    Ok(append(vm, l1, l2))
+                    append(vm, l1, l2)
                 }
             }
             Value::Vector(handle) => {
@@ -224,9 +224,9 @@ fn qq_expand(vm: &mut SloshVm, exp: Value, line: u32, depth: u32) -> VMResult<Va
                 for i in &mut new_vec {
                     *i = qq_expand(vm, *i, line, depth)?;
                 }
-                Ok(vec(vm, &new_vec[..]))
+                vec(vm, &new_vec[..])
             }
-            _ => Ok(quote(vm, exp)),
+            _ => quote(vm, exp),
         }
     }
 }
@@ -236,43 +236,43 @@ fn qq_expand_list(vm: &mut SloshVm, exp: Value, line: u32, depth: u32) -> VMResu
     if tag.is_unquote(vm, exp) {
         if depth == 0 {
             let data = Tag::data(vm, exp)?;
-            Ok(list(vm, data))
+            list(vm, data)
         } else {
             let expand = qq_expand(vm, Tag::data(vm, exp)?, line, depth - 1)?;
-            let inner = unquote(vm, expand);
-            Ok(list(vm, inner))
+            let inner = unquote(vm, expand)?;
+            list(vm, inner)
         }
     } else if tag.is_splice(vm, exp) {
         if depth == 0 {
             Ok(Tag::data(vm, exp)?)
         } else {
             let expand = qq_expand(vm, Tag::data(vm, exp)?, line, depth - 1)?;
-            let inner = splice(vm, expand);
-            Ok(list(vm, inner))
+            let inner = splice(vm, expand)?;
+            list(vm, inner)
         }
     } else if tag.is_splice_bang(vm, exp) {
         if depth == 0 {
             Ok(Tag::data(vm, exp)?)
         } else {
             let expand = qq_expand(vm, Tag::data(vm, exp)?, line, depth - 1)?;
-            let inner = splice_bang(vm, expand);
-            Ok(list(vm, inner))
+            let inner = splice_bang(vm, expand)?;
+            list(vm, inner)
         }
     } else if tag.is_backquote(vm, exp) {
         let inner = qq_expand(vm, Tag::data(vm, exp)?, line, depth + 1)?;
-        let inner = back_quote(vm, inner);
-        Ok(list(vm, inner))
+        let inner = back_quote(vm, inner)?;
+        list(vm, inner)
     } else {
         match exp {
             Value::Pair(_) | Value::List(_, _) => {
                 let (car, cdr) = exp.get_pair(vm).expect("List/Pair not a List/Pair?");
                 let l1 = qq_expand_list(vm, car, line, depth)?;
                 if cdr.is_nil() {
-                    Ok(list(vm, l1))
+                    list(vm, l1)
                 } else {
                     let l2 = qq_expand(vm, cdr, line, depth)?;
-                    let app = append(vm, l1, l2);
-                    Ok(list(vm, app))
+                    let app = append(vm, l1, l2)?;
+                    list(vm, app)
                 }
             }
             Value::Vector(handle) => {
@@ -281,12 +281,12 @@ fn qq_expand_list(vm: &mut SloshVm, exp: Value, line: u32, depth: u32) -> VMResu
                 for i in &mut new_vec {
                     *i = qq_expand(vm, *i, line, depth)?;
                 }
-                let vv = vec(vm, &new_vec[..]);
-                Ok(list(vm, vv))
+                let vv = vec(vm, &new_vec[..])?;
+                list(vm, vv)
             }
             _ => {
-                let q = quote(vm, exp);
-                Ok(list(vm, q))
+                let q = quote(vm, exp)?;
+                list(vm, q)
             }
         }
     }
@@ -324,7 +324,7 @@ mod tests {
         let res = if exps.len() == 1 {
             Ok(exps[0])
         } else {
-            Ok(vm.alloc_vector_ro(exps))
+            vm.alloc_vector_ro(exps).map_err(ReadError::from)
         };
         vm.unpause_gc();
         res
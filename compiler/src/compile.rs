@@ -8,10 +8,10 @@ use crate::compile::compile_call::{
 };
 use crate::compile::compile_cond::{compile_and, compile_if, compile_or, compile_while};
 use crate::compile::compile_fn::compile_fn;
-use crate::compile::compile_let::compile_let;
+use crate::compile::compile_let::{compile_binding, compile_let};
 use crate::compile::compile_math::compile_math;
 use crate::compile::compile_seq::{compile_cons, compile_vec};
-use crate::compile::compile_store::{compile_def, compile_set};
+use crate::compile::compile_store::{compile_def, compile_def_dynamic, compile_set};
 use crate::pass1::pass1;
 
 mod compile_call;
@@ -92,6 +92,10 @@ fn compile_special(
                 state.tail = false;
                 compile_def(env, state, cdr, result)?;
             }
+            Value::Special(i) if i == env.specials().def_dynamic => {
+                state.tail = false;
+                compile_def_dynamic(env, state, cdr, result)?;
+            }
             Value::Special(i) if i == env.specials().set => {
                 state.tail = false;
                 compile_set(env, state, cdr, result)?;
@@ -227,6 +231,9 @@ fn compile_special(
             Value::Special(i) if i == env.specials().let_ => {
                 compile_let(env, state, cdr, result)?;
             }
+            Value::Special(i) if i == env.specials().binding => {
+                compile_binding(env, state, cdr, result)?;
+            }
             Value::Special(i) if i == env.specials().call_cc => {
                 if cdr.len() != 1 {
                     return Err(VMError::new_compile("Requires one argument."));
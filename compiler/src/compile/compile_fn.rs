@@ -29,7 +29,7 @@ pub fn mk_state(
             next_is_opt = false;
             continue;
         }
-        let a = resolve_destruct_containers(env, a);
+        let a = resolve_destruct_containers(env, a)?;
         match a {
             Value::Symbol(i) => {
                 if i == env.specials().rest {
@@ -154,6 +154,7 @@ pub(crate) fn compile_fn(
     env.pause_gc();
     let lambda = env.alloc_lambda(Arc::new(new_state.chunk));
     env.unpause_gc();
+    let lambda = lambda?;
     if is_macro {
         // Unwrap safe since we just allocated lambda on the heap.
         env.set_heap_property(lambda, ":macro", Value::True);
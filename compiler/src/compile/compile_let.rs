@@ -62,7 +62,7 @@ fn let_inner(
                 a.display_value(env)
             )));
         };
-        let a = resolve_destruct_containers(env, *a);
+        let a = resolve_destruct_containers(env, *a)?;
         match a {
             Value::Symbol(i) => {
                 if symbols.borrow().contains_symbol(i) {
@@ -166,12 +166,113 @@ pub(crate) fn compile_let(
     result
 }
 
+fn binding_inner(
+    env: &mut SloshVm,
+    state: &mut CompileState,
+    binds: &[(u32, Value)],
+    body: &[Value],
+    result: usize,
+) -> VMResult<()> {
+    let start_defers = state.defers;
+    let symbols = Rc::new(RefCell::new(Symbols::with_let(state.symbols.clone())));
+    state.symbols = symbols.clone();
+    let mut first_reg = symbols.borrow().regs_count();
+    while first_reg <= result {
+        // Make sure we do not step on the result or any other regs in temp use below it.
+        first_reg = symbols.borrow_mut().reserve_reg();
+    }
+    // Evaluate all the new values before binding any of them (like let).
+    let mut regs = Vec::with_capacity(binds.len());
+    for (_, val) in binds {
+        let reg = symbols.borrow_mut().reserve_reg();
+        compile_let_value(env, state, *val, reg as u16)?;
+        regs.push(reg);
+    }
+    for ((slot, _), reg) in binds.iter().zip(regs.iter()) {
+        state
+            .chunk
+            .encode_bind(*reg as u16, *slot, env.own_line())?;
+    }
+    let free_reg = state.reserved_regs();
+    if body.is_empty() {
+        state.chunk.encode1(REGN, free_reg as u16, env.own_line())?;
+    }
+    for r in body {
+        compile(env, state, *r, free_reg)?;
+    }
+    state
+        .chunk
+        .encode2(MOV, result as u16, free_reg as u16, env.own_line())?;
+    for _ in start_defers..state.defers {
+        state.chunk.encode0(DFRPOP, env.own_line())?;
+    }
+    state
+        .chunk
+        .encode1(UNBIND, binds.len() as u16, env.own_line())?;
+    for i in first_reg..symbols.borrow().regs_count() {
+        if i != result {
+            state.chunk.encode1(CLRREG, i as u16, env.own_line())?;
+        }
+    }
+    Ok(())
+}
+
+/// Compile (binding (symbol value ...) body ...), the values are bound with BIND and restored by
+/// UNBIND after body.  The VM restores them if body is left any other way (see binding.rs).
+/// The body is never in tail position, a tail call would skip the UNBIND.
+pub(crate) fn compile_binding(
+    env: &mut SloshVm,
+    state: &mut CompileState,
+    cdr: &[Value],
+    result: usize,
+) -> VMResult<()> {
+    if cdr.is_empty() {
+        return Err(VMError::new_compile(
+            "binding: requires a list of bindings and a body",
+        ));
+    }
+    let args: Vec<Value> = get_args_iter(env, cdr[0], "binding")?.collect();
+    if !args.len().is_multiple_of(2) {
+        return Err(VMError::new_compile(
+            "binding: each symbol must have a value",
+        ));
+    }
+    let dynamic = env.intern("dynamic");
+    let mut binds = Vec::with_capacity(args.len() / 2);
+    for pair in args.chunks(2) {
+        let Value::Symbol(si) = pair[0] else {
+            return Err(VMError::new_compile("binding: must be a symbol"));
+        };
+        let slot = env.global_intern_slot(si).filter(|slot| {
+            env.get_global_property(*slot, dynamic)
+                .is_some_and(|v| !v.is_falsey())
+        });
+        let Some(slot) = slot else {
+            return Err(VMError::new_compile(format!(
+                "binding: {} is not a dynamic global (use defdynamic)",
+                env.get_interned(si)
+            )));
+        };
+        binds.push((slot, pair[1]));
+    }
+    let old_symbols = state.symbols.clone();
+    let old_tail = state.tail;
+    state.tail = false;
+    let old_defers = state.defers;
+    let res = binding_inner(env, state, &binds, &cdr[1..], result);
+    state.tail = old_tail;
+    state.symbols = old_symbols;
+    state.defers = old_defers;
+    res
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::test_utils::{assert_vals, exec, exec_compile_error, exec_runtime_error, read_test};
     use builtins::print::{dasm, prn};
     use compile_state::state::new_slosh_vm;
+    use std::sync::Arc;
 
     #[test]
     fn test_let() {
@@ -539,4 +640,73 @@ mod tests {
         let expected = read_test(&mut env, "(1 2 3)");
         assert_vals(&env, expected, result);
     }
+
+    #[test]
+    fn test_binding() {
+        let mut env = new_slosh_vm();
+        exec(&mut env, "(defdynamic *depth* 1)");
+        exec(&mut env, "(def get-depth (fn () *depth*))");
+        let result = exec(
+            &mut env,
+            "(binding (*depth* 2) (let (outer (get-depth)) (binding (*depth* (+ *depth* 1)) (list outer (get-depth)))))",
+        );
+        let expected = read_test(&mut env, "(2 3)");
+        assert_vals(&env, expected, result);
+        let result = exec(&mut env, "*depth*");
+        assert_vals(&env, 1.into(), result);
+
+        // Jumping out with a continuation.
+        let result = exec(
+            &mut env,
+            "(list (call/cc (fn (k) (binding (*depth* 5) (k (get-depth))))) *depth*)",
+        );
+        let expected = read_test(&mut env, "(5 1)");
+        assert_vals(&env, expected, result);
+
+        // Errors, caught by a handler that returns or escapes and uncaught (unwound by reset).
+        exec(
+            &mut env,
+            "(def fail (fn () (binding (*depth* 7) (err :fail *depth*))))",
+        );
+        let result = exec(
+            &mut env,
+            "(list ((fn () (on-error (fn (k v) v)) (fail))) *depth*)",
+        );
+        let expected = read_test(&mut env, "(7 1)");
+        assert_vals(&env, expected, result);
+        let result = exec(
+            &mut env,
+            "(list (call/cc (fn (k) (on-error (fn (key v) (k v))) (fail))) *depth*)",
+        );
+        let expected = read_test(&mut env, "(7 1)");
+        assert_vals(&env, expected, result);
+        exec_runtime_error(&mut env, "(fail)");
+        let result = exec(&mut env, "*depth*");
+        assert_vals(&env, 1.into(), result);
+
+        // An error out of a lambda called by a builtin.
+        builtins::string::add_str_builtins(&mut env);
+        let result = exec(
+            &mut env,
+            "(list (call/cc (fn (k) (on-error (fn (key v) (k v))) (str-map \"a\" (fn (ch) (fail))))) *depth*)",
+        );
+        let expected = read_test(&mut env, "(7 1)");
+        assert_vals(&env, expected, result);
+
+        // Aborts skip defers, the binding is still restored (without a reset).
+        env.set_fuel(Some(100));
+        let exp = read_test(&mut env, "(binding (*depth* 2) (while #t nil))");
+        let mut state = CompileState::new();
+        compile(&mut env, &mut state, exp, 0).unwrap();
+        state.chunk.encode0(RET, Some(1)).unwrap();
+        let err = env.execute(Arc::new(state.chunk)).unwrap_err();
+        assert!(err.is_abort());
+        env.set_fuel(None);
+        let result = exec(&mut env, "*depth*");
+        assert_vals(&env, 1.into(), result);
+
+        exec(&mut env, "(def not-dynamic 1)");
+        exec_compile_error(&mut env, "(binding (not-dynamic 2) not-dynamic)");
+        exec_compile_error(&mut env, "(binding (*depth*) *depth*)");
+    }
 }
@@ -140,9 +140,13 @@ pub(crate) fn compile_math(
                     "Malformed -, requires at least one argument.",
                 ));
             } else if cdr.len() == 1 {
-                if let Ok(i) = cdr[0].get_int(env) {
-                    // XXX TODO- handle int 64
-                    compile(env, state, (-i).into(), result)?;
+                if let Value::BigInt(_) = cdr[0] {
+                    let i = cdr[0].get_bigint(env)?;
+                    let var = env.alloc_bigint(-i);
+                    compile(env, state, var?, result)?;
+                } else if let Ok(i) = cdr[0].get_int(env) {
+                    let var = env.alloc_int(-i);
+                    compile(env, state, var?, result)?;
                 } else if let Ok(f) = cdr[0].get_float(env) {
                     let var = (-f).into();
                     compile(env, state, var, result)?;
@@ -145,6 +145,36 @@ pub(crate) fn compile_vec(
                 env.own_line(),
             )?;
         }
+        Value::Special(i) if i == env.specials().make_set => {
+            state.tail = false;
+            let mut max = 0;
+            for r in cdr {
+                compile(env, state, *r, result + max + 1)?;
+                max += 1;
+            }
+            state.chunk.encode3(
+                SETMK,
+                result as u16,
+                (result + 1) as u16,
+                (result + max + 1) as u16,
+                env.own_line(),
+            )?;
+        }
+        Value::Special(i) if i == env.specials().byte_vector => {
+            state.tail = false;
+            let mut max = 0;
+            for r in cdr {
+                compile(env, state, *r, result + max + 1)?;
+                max += 1;
+            }
+            state.chunk.encode3(
+                BYTESMK,
+                result as u16,
+                (result + 1) as u16,
+                (result + max + 1) as u16,
+                env.own_line(),
+            )?;
+        }
         Value::Special(i) if i == env.specials().vec => {
             state.tail = false;
             let mut max = 0;
@@ -36,6 +36,21 @@ pub(crate) fn compile_def(
     Ok(())
 }
 
+/// Compile (defdynamic symbol value?), a def that marks the global dynamic for binding.
+pub(crate) fn compile_def_dynamic(
+    env: &mut SloshVm,
+    state: &mut CompileState,
+    cdr: &[Value],
+    result: usize,
+) -> VMResult<()> {
+    if let Some(Value::Symbol(si)) = cdr.first() {
+        let si_const = env.get_reserve_global(*si);
+        let key = env.intern("dynamic");
+        env.set_global_property(si_const, key, Value::True);
+    }
+    compile_def(env, state, cdr, result)
+}
+
 pub(crate) fn compile_set(
     env: &mut SloshVm,
     state: &mut CompileState,
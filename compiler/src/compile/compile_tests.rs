@@ -1,9 +1,14 @@
 #[cfg(test)]
 mod tests {
+    use crate::pass1::pass1;
     use crate::test_utils::{assert_vals, exec, read_test};
+    use crate::{compile, CompileState};
     use builtins::print::{dasm, prn};
     use compile_state::state::new_slosh_vm;
-    use compile_state::state::SloshVmTrait;
+    use compile_state::state::{SloshVm, SloshVmTrait};
+    use slvm::{VMError, VMResult, Value, RET};
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
 
     #[test]
     fn test_def_set() {
@@ -224,4 +229,854 @@ mod tests {
         let expected = read_test(&mut env, "(:test . \"error\")");
         assert_vals(&env, expected, result);
     }
+
+    #[test]
+    fn test_stack_grow() {
+        let mut env = new_slosh_vm();
+        exec(
+            &mut env,
+            "(def deep (fn (n) (if (= n 0) 0 (let (r (deep (- n 1))) (+ r 1)))))",
+        );
+        let result = exec(&mut env, "(deep 5000)");
+        let expected = read_test(&mut env, "5000");
+        assert_vals(&env, expected, result);
+
+        let get_error = r#"
+        (def get-error (macro (& body)
+        `(let (old-error (on-error nil))
+            (defer (on-error old-error))
+            (call/cc (fn (k) (on-error (fn (key val) (k (cons key val))))
+            (cons :ok (do ~@body)))))))"#;
+        exec(&mut env, get_error);
+        exec(&mut env, "(def forever (fn (n) (let (r (forever n)) r)))");
+        let result = exec(&mut env, "(car (get-error (forever 1)))");
+        let expected = read_test(&mut env, ":stack");
+        assert_vals(&env, expected, result);
+        assert_eq!(env.stack_capacity(), env.stack_limit());
+
+        // VM is still usable after the overflow.
+        let result = exec(&mut env, "(deep 10)");
+        let expected = read_test(&mut env, "10");
+        assert_vals(&env, expected, result);
+    }
+    #[test]
+    fn test_gc_frame_defers() {
+        let mut env = new_slosh_vm();
+        builtins::add_misc_builtins(&mut env);
+        // The defer closure is only held by outer's call frame while inner runs the collection.
+        exec(&mut env, "(def deferred nil)");
+        exec(&mut env, "(def inner (fn () (gc) 1))");
+        exec(
+            &mut env,
+            "(def outer (fn () (let (x (str \"a\" \"b\")) (defer (set! deferred x)) (inner))))",
+        );
+        let result = exec(&mut env, "(do (outer) deferred)");
+        let expected = read_test(&mut env, "\"ab\"");
+        assert_vals(&env, expected, result);
+    }
+    #[test]
+    fn test_bytecode_save_load() {
+        let mut env = new_slosh_vm();
+        exec(&mut env, "(def bc-a 1)");
+        exec(&mut env, "(def bc-b 1)");
+        let exp = read_test(
+            &mut env,
+            "(do (def bc-fn (fn (x) (list x 'y \"s\" 100000 bc-b))) (bc-fn bc-a))",
+        );
+        let mut state = CompileState::new();
+        pass1(&mut env, &mut state, exp).unwrap();
+        compile(&mut env, &mut state, exp, 0).unwrap();
+        state.chunk.encode0(RET, Some(1)).unwrap();
+        let mut bytes = Vec::new();
+        env.write_chunk(&state.chunk, &mut bytes).unwrap();
+
+        // Load into a VM with a different global layout.
+        let mut env2 = new_slosh_vm();
+        exec(&mut env2, "(def bc-b 2)");
+        exec(&mut env2, "(def bc-a 3)");
+        let chunk = Arc::new(env2.read_chunk(&mut &bytes[..]).unwrap());
+        let lambda = env2.alloc_lambda(chunk.clone()).unwrap();
+        env2.heap_sticky(lambda);
+        let result = env2.execute(chunk).unwrap();
+        let expected = read_test(&mut env2, "(3 y \"s\" 100000 2)");
+        assert_vals(&env2, expected, result);
+        let bc_fn = env2.intern("bc-fn");
+        assert!(env2.global_intern_slot(bc_fn).is_some());
+    }
+
+    #[test]
+    fn test_image_save_load() {
+        let mut env = new_slosh_vm();
+        env.set_global_builtin("prn", prn);
+        exec(
+            &mut env,
+            r#"(def defmacro (macro (name args & body) `(def ~name (macro ~args ~@body))))"#,
+        );
+        exec(&mut env, "(defmacro add-one (x) `(+ ~x 1))");
+        exec(&mut env, "(def make-adder (fn (n) (fn (x) (+ x n))))");
+        exec(&mut env, "(def add-ten (make-adder 10))");
+        exec(&mut env, "(def img-list (list 'a \"b\" 3))");
+        let mut bytes = Vec::new();
+        env.save_image(&mut bytes).unwrap();
+
+        // Load into a fresh VM set up the same way.
+        let mut env2 = new_slosh_vm();
+        env2.set_global_builtin("prn", prn);
+        env2.load_image(&mut &bytes[..]).unwrap();
+        let result = exec(&mut env2, "(add-one (add-ten 1))");
+        let expected = read_test(&mut env2, "12");
+        assert_vals(&env2, expected, result);
+        let result = exec(&mut env2, "((make-adder 2) 3)");
+        let expected = read_test(&mut env2, "5");
+        assert_vals(&env2, expected, result);
+        let result = exec(&mut env2, "img-list");
+        let expected = read_test(&mut env2, "(a \"b\" 3)");
+        assert_vals(&env2, expected, result);
+        let result = exec(&mut env2, "(do (def img-new 4) (+ img-new 1))");
+        let expected = read_test(&mut env2, "5");
+        assert_vals(&env2, expected, result);
+
+        // A VM with different builtins can not load it.
+        let mut env3 = new_slosh_vm();
+        assert!(env3.load_image(&mut &bytes[..]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "f64")]
+    fn test_float64() {
+        let mut env = new_slosh_vm();
+        // Not representable as an f32.
+        let result = exec(&mut env, "(- 16777217.0 16777216.0)");
+        let expected = read_test(&mut env, "1.0");
+        assert_vals(&env, expected, result);
+        let result = exec(&mut env, "(+ 0.1 0.2)");
+        assert_eq!(result.display_value(&env), "0.30000000000000004");
+        let result = exec(&mut env, "(* 1234567.25 1000)");
+        assert_eq!(result.display_value(&env), "1234567250");
+        let result = exec(&mut env, "(/ 1 3.0)");
+        assert_eq!(result.display_value(&env), "0.3333333333333333");
+        let result = exec(&mut env, "(< 1699999999.5 1699999999.75)");
+        let expected = read_test(&mut env, "#t");
+        assert_vals(&env, expected, result);
+    }
+
+    #[test]
+    fn test_incremental_gc() {
+        let mut env = new_slosh_vm();
+        env.set_gc_step(2);
+        let code = r#"(do
+            (def acc nil)
+            (def keep (list nil nil))
+            (def counter ((fn () (let (c (list 0 "0")) (fn () (set! c (list (+ (car c) 1) (str (car c)))) c)))))
+            (def i 0)
+            (while (< i 3000)
+                (set! acc (cons (str "s" i) acc))
+                (xar! keep (list i (str "car" i)))
+                (xdr! keep (cons (str "cdr" i) nil))
+                (counter)
+                (set! i (+ i 1)))
+            (def n 0)
+            (def l acc)
+            (while l (set! n (+ n 1)) (set! l (cdr l)))
+            (list n (car acc) (car (cdr (car keep))) (car (cdr keep)) (car (counter))))"#;
+        let result = exec(&mut env, code);
+        assert_eq!(
+            result.display_value(&env),
+            r#"(3000 "s2999" "car2999" "cdr2999" 3001)"#
+        );
+    }
+
+    #[test]
+    fn test_bignum() {
+        let mut env = new_slosh_vm();
+        let result = exec(&mut env, "(+ 36028797018963967 1)");
+        assert!(matches!(result, Value::BigInt(_)));
+        assert_eq!(result.display_value(&env), "36028797018963968");
+        let result = exec(&mut env, "(- -36028797018963968 1)");
+        assert_eq!(result.display_value(&env), "-36028797018963969");
+        let result = exec(&mut env, "(* 99999999999 99999999999)");
+        assert_eq!(result.display_value(&env), "9999999999800000000001");
+        let result = exec(&mut env, "(/ (* 99999999999 99999999999) 99999999999)");
+        assert_eq!(result.display_value(&env), "99999999999");
+        // Results that fit are Ints again.
+        let result = exec(&mut env, "(- (+ 36028797018963967 1) 1)");
+        assert!(matches!(result, Value::Int(_)));
+        let expected = read_test(&mut env, "36028797018963967");
+        assert_vals(&env, expected, result);
+        let result = exec(&mut env, "(let (x 36028797018963967) (inc! x) x)");
+        assert_eq!(result.display_value(&env), "36028797018963968");
+        let result = exec(&mut env, "(let (x 36028797018963968) (dec! x 2) x)");
+        assert!(matches!(result, Value::Int(_)));
+        assert_eq!(result.display_value(&env), "36028797018963966");
+        let result = exec(&mut env, "(- 123456789012345678901234567890)");
+        assert_eq!(
+            result.display_value(&env),
+            "-123456789012345678901234567890"
+        );
+        let result = exec(&mut env, "#xffffffffffffffffff");
+        assert_eq!(result.display_value(&env), "4722366482869645213695");
+
+        // Comparisons.
+        let result = exec(&mut env, "(< 36028797018963967 100000000000000000000 1e30)");
+        assert_eq!(result, Value::True);
+        let result = exec(
+            &mut env,
+            "(> 100000000000000000000 5 -100000000000000000000)",
+        );
+        assert_eq!(result, Value::True);
+        let result = exec(
+            &mut env,
+            "(= (* 10000000000 10000000000) 100000000000000000000)",
+        );
+        assert_eq!(result, Value::True);
+        let result = exec(&mut env, "(= 100000000000000000000 100000000000000000001)");
+        assert_eq!(result, Value::False);
+        let result = exec(&mut env, "(equal? 100000000000000000000 1)");
+        assert_eq!(result, Value::False);
+        let result = exec(
+            &mut env,
+            "(equal? (* 10000000000 10000000000) 100000000000000000000)",
+        );
+        assert_eq!(result, Value::True);
+        let result = exec(&mut env, "(+ 100000000000000000000 0.5)");
+        assert!(matches!(result, Value::Float(_)));
+
+        // Equal big ints are the same value, so they hash the same.
+        let a = exec(&mut env, "(* 10000000000 10000000000)");
+        let b = exec(&mut env, "100000000000000000000");
+        assert_eq!(a, b);
+        let mut map = std::collections::HashMap::new();
+        map.insert(a, 1);
+        assert_eq!(map.get(&b), Some(&1));
+    }
+
+    fn exec_result(env: &mut SloshVm, input: &'static str) -> VMResult<Value> {
+        let exp = read_test(env, input);
+        let mut state = CompileState::new();
+        pass1(env, &mut state, exp).unwrap();
+        compile(env, &mut state, exp, 0).unwrap();
+        state.chunk.encode0(RET, Some(1)).unwrap();
+        // So the GC sees the registers (builtin arguments for instance).
+        state.chunk.extra_regs = state.max_regs;
+        env.execute(Arc::new(state.chunk))
+    }
+
+    #[test]
+    fn test_reset_drops_defers() {
+        let mut env = new_slosh_vm();
+        exec(&mut env, "(def ran nil)");
+        let err = exec_result(&mut env, "(do (defer (set! ran #t)) (err :fail 1))").unwrap_err();
+        assert_eq!(err.key, "fail");
+        // Reset only cleans up, the defer left by the error is not run.
+        env.reset();
+        let result = exec(&mut env, "ran");
+        assert_vals(&env, Value::Nil, result);
+    }
+
+    #[test]
+    fn test_do_call_error() {
+        let mut env = new_slosh_vm();
+        builtins::add_builtin_closure(
+            &mut env,
+            "try-call",
+            |vm, registers| {
+                let Value::Lambda(h) = registers[0] else {
+                    return Err(VMError::new_vm("try-call: takes a lambda"));
+                };
+                let l = vm.get_lambda(h);
+                match vm.do_call(l, &[], None) {
+                    Ok(v) => Ok(v),
+                    Err(e) => Ok(Value::Keyword(vm.intern(e.key))),
+                }
+            },
+            "Usage: (try-call lambda)",
+        );
+        exec(&mut env, "(def ran nil)");
+        exec(&mut env, "(defdynamic *depth* 1)");
+        exec(
+            &mut env,
+            "(def fails (fn () (binding (*depth* 2) (defer (set! ran #t)) (err :fail 1))))",
+        );
+        // A builtin gets the error back and carries on, the unwound defers are not run and the
+        // bindings are restored.
+        let result = exec(
+            &mut env,
+            "(list (try-call fails) ran *depth* (try-call (fn () 3)))",
+        );
+        assert_eq!(result.display_value(&env), "(:fail nil 1 3)");
+
+        // An uncaught error from the outermost do_call runs them.
+        let Value::Lambda(h) = exec(&mut env, "fails") else {
+            panic!("expected a lambda");
+        };
+        let l = env.get_lambda(h);
+        let err = env.do_call(l, &[], None).unwrap_err();
+        assert_eq!(err.key, "fail");
+        let result = exec(&mut env, "(list ran *depth*)");
+        assert_eq!(result.display_value(&env), "(true 1)");
+    }
+
+    #[test]
+    fn test_fuel() {
+        let mut env = new_slosh_vm();
+        exec(&mut env, "(def i 0)");
+        env.set_fuel(Some(1000));
+        let err = exec_result(&mut env, "(while #t (set! i (+ i 1)))").unwrap_err();
+        assert_eq!(err.key, "fuel");
+        assert_eq!(env.fuel(), Some(0));
+        let i = exec(&mut env, "i").get_int(&env).unwrap();
+        assert!(i > 0 && i <= 1000);
+
+        // Stays out of fuel until refilled, the VM is usable again without a reset.
+        let err = exec_result(&mut env, "((fn () 1))").unwrap_err();
+        assert_eq!(err.key, "fuel");
+        env.set_fuel(Some(1000));
+        let result = exec(&mut env, "((fn (x) (+ x 1)) 1)");
+        let expected = read_test(&mut env, "2");
+        assert_vals(&env, expected, result);
+
+        // An on-error handler sees the error but can not run past the budget.
+        env.set_fuel(Some(1000));
+        let result = exec(
+            &mut env,
+            "(do (def caught nil) (on-error (fn (k v) (set! caught k) :handled)) (while #t (set! i (+ i 1))))",
+        );
+        env.set_fuel(None);
+        let expected = read_test(&mut env, ":handled");
+        assert_vals(&env, expected, result);
+        let result = exec(&mut env, "caught");
+        let expected = read_test(&mut env, ":fuel");
+        assert_vals(&env, expected, result);
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let mut env = new_slosh_vm();
+        let flag = env.interrupt_flag();
+        let handle = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(50));
+            flag.store(true, Ordering::Relaxed);
+        });
+        exec(&mut env, "(def spin (fn (n) (spin (+ n 1))))");
+        let err = exec_result(&mut env, "(spin 0)").unwrap_err();
+        handle.join().unwrap();
+        assert_eq!(err.key, "interrupt");
+        // The flag is cleared and the VM can be used again.
+        assert!(!env.interrupt_flag().load(Ordering::Relaxed));
+        let result = exec(
+            &mut env,
+            "(do (def x 0) (while (< x 10) (set! x (+ x 1))) x)",
+        );
+        let expected = read_test(&mut env, "10");
+        assert_vals(&env, expected, result);
+    }
+
+    #[test]
+    fn test_heap_max() {
+        let mut env = new_slosh_vm();
+        exec(&mut env, "(def acc nil)");
+        env.set_heap_max(Some(env.heap_stats().used_bytes + 100_000));
+        let err = exec_result(&mut env, "(while #t (set! acc (cons 1 acc)))").unwrap_err();
+        assert_eq!(err.key, "oom");
+
+        // Once the garbage is gone the VM works again (the reader runs with GC paused so collect first).
+        env.set_named_global("acc", Value::Nil);
+        env.gc();
+        let result = exec(&mut env, "(do (def l (list 1 2 3)) (car (cdr l)))");
+        let expected = read_test(&mut env, "2");
+        assert_vals(&env, expected, result);
+
+        // Garbage alone does not run out of memory.
+        let result = exec(
+            &mut env,
+            "(do (def i 0) (while (< i 20000) (set! acc (cons i nil)) (set! i (+ i 1))) (car acc))",
+        );
+        let expected = read_test(&mut env, "19999");
+        assert_vals(&env, expected, result);
+
+        // An on-error handler sees the error.
+        let result = exec(
+            &mut env,
+            "(do (def caught nil) (on-error (fn (k v) (set! caught k) :handled)) (while #t (set! acc (cons 1 acc))))",
+        );
+        let expected = read_test(&mut env, ":handled");
+        assert_vals(&env, expected, result);
+        env.set_named_global("acc", Value::Nil);
+        env.gc();
+        let result = exec(&mut env, "caught");
+        let expected = read_test(&mut env, ":oom");
+        assert_vals(&env, expected, result);
+
+        // A builtin fails at the allocation that goes over the limit.
+        fn fill(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
+            let [Value::Vector(h)] = registers else {
+                return Err(VMError::new_vm("fill: requires a vector"));
+            };
+            for _ in 0..20_000 {
+                let s = vm.alloc_string("x".to_string())?;
+                vm.get_vector_mut(*h)?.push(s);
+            }
+            Ok(Value::Nil)
+        }
+        env.set_global_builtin("fill", fill);
+        let err = exec_result(&mut env, "(fill (vec))").unwrap_err();
+        assert_eq!(err.key, "oom");
+    }
+
+    #[test]
+    fn test_verify_chunks() {
+        let mut env = new_slosh_vm();
+        env.set_verify_chunks(true);
+        let result = exec(
+            &mut env,
+            "(do (def v (vec 1 2 3)) (def f (fn (x & rest) (let ([a b] v) (+ x a b (len rest))))) (def i 0) (while (< i 3) (set! i (+ i 1))) (f i 1 2))",
+        );
+        let expected = read_test(&mut env, "8");
+        assert_vals(&env, expected, result);
+
+        let mut chunk = slvm::Chunk::new("no_file", 1);
+        chunk.encode2(slvm::MOV, 10, 0, Some(1)).unwrap();
+        chunk.encode0(RET, Some(1)).unwrap();
+        let err = env.execute(Arc::new(chunk)).unwrap_err();
+        assert!(err.to_string().contains("Verify"));
+    }
+
+    fn trace_events(env: &mut SloshVm) -> Vec<String> {
+        let events = exec(env, "events");
+        let mut events: Vec<String> = events.iter(env).map(|e| e.display_value(env)).collect();
+        events.reverse();
+        exec(env, "(set! events '())");
+        events
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut env = new_slosh_vm();
+        exec(&mut env, "(def events '())");
+        exec(
+            &mut env,
+            "(def fact (fn (n acc) (if (< n 2) acc (fact (- n 1) (* n acc)))))",
+        );
+        exec(&mut env, "(def outer (fn (n) (let (r (fact n 1)) (+ r 1))))");
+        exec(&mut env, "(def bad (fn (x) (car x)))");
+        let callback = exec(
+            &mut env,
+            "(fn (event depth name data) (set! events (cons (list event depth name data) events)))",
+        );
+        env.set_trace_callback(Some(callback)).unwrap();
+        for name in ["fact", "outer", "bad"] {
+            let name = env.intern(name);
+            let val = env.get_global(env.global_intern_slot(name).unwrap());
+            env.trace(name, val).unwrap();
+        }
+
+        let result = exec(&mut env, "(outer 3)");
+        assert_eq!(result.get_int(&env).unwrap(), 7);
+        assert_eq!(
+            trace_events(&mut env),
+            vec![
+                "(:call 1 outer [3])",
+                "(:call 2 fact [3 1])",
+                "(:tail-call 2 fact [2 3])",
+                "(:tail-call 2 fact [1 6])",
+                "(:return 2 fact 6)",
+                "(:return 1 outer 7)",
+            ]
+        );
+
+        // A caught error unwinds the erroring frame, it is reported as an error not dropped.
+        exec(
+            &mut env,
+            "(def safe (fn (x) (on-error (fn (k v) :caught)) (let (r (bad x)) r)))",
+        );
+        assert_eq!(exec(&mut env, "(safe 1)").display_value(&env), ":caught");
+        let events = trace_events(&mut env);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0], "(:call 1 bad [1])");
+        assert!(events[1].starts_with("(:error 1 bad (:"));
+
+        assert!(exec_result(&mut env, "(outer (bad 1))").is_err());
+        env.reset();
+        let events = trace_events(&mut env);
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0], "(:call 1 bad [1])");
+        assert!(events[1].starts_with("(:error 1 bad "));
+
+        let fact = env.intern("fact");
+        assert!(env.untrace(fact));
+        assert!(!env.untrace(fact));
+        exec(&mut env, "(fact 3 1)");
+        assert!(trace_events(&mut env).is_empty());
+        env.untrace_all();
+        env.set_trace_callback(None).unwrap();
+        assert!(env.traced().is_empty());
+    }
+
+    #[test]
+    fn test_backtrace() {
+        let mut env = new_slosh_vm();
+        exec(&mut env, "(def inner (fn (x) (err :boom x)))");
+        exec(&mut env, "(def outer (fn (x) (let (r (inner x)) r)))");
+        let chunk = |env: &mut SloshVm, name: &str| {
+            let name = env.intern(name);
+            let Value::Lambda(h) = env.get_global(env.global_intern_slot(name).unwrap()) else {
+                panic!("expected a lambda");
+            };
+            env.get_lambda(h)
+        };
+        let inner = chunk(&mut env, "inner");
+        let outer = chunk(&mut env, "outer");
+
+        let err = exec_result(&mut env, "(outer 1)").unwrap_err();
+        let backtrace = err.backtrace.expect("raised errors have a backtrace");
+        assert!(Arc::ptr_eq(&backtrace, &env.last_backtrace().unwrap()));
+        assert_eq!(backtrace.frames.len(), 3);
+        assert!(Arc::ptr_eq(&backtrace.frames[0].chunk, &inner));
+        assert!(Arc::ptr_eq(&backtrace.frames[1].chunk, &outer));
+        assert!(backtrace.frames.iter().all(|f| f.line().is_some()));
+        assert!(backtrace.frames[0].registers.is_none());
+        env.reset();
+
+        // Errors caught by on-error capture a backtrace too.
+        let result = exec(&mut env, "(do (on-error (fn (k v) :caught)) (outer 2))");
+        assert_eq!(result.display_value(&env), ":caught");
+        assert!(env.last_backtrace().is_some());
+
+        let err = exec(&mut env, "(mk-err :test 1)");
+        let Value::Error(h) = err else {
+            panic!("expected an error value");
+        };
+        let backtrace = env.get_error(h).backtrace.expect("mk-err has a backtrace");
+        assert_eq!(backtrace.frames.len(), 1);
+
+        env.set_backtrace_registers(true);
+        assert!(exec_result(&mut env, "(outer 5)").is_err());
+        let backtrace = env.last_backtrace().unwrap();
+        let registers = backtrace.frames[0].registers.as_ref().unwrap();
+        assert_eq!(registers[1].get_int(&env).unwrap(), 5);
+        env.reset();
+    }
+
+    #[test]
+    fn test_map_order() {
+        let mut env = new_slosh_vm();
+        builtins::collections::setup_collection_builtins(&mut env);
+        // Map literals and make-hash keep insertion order, setting a key keeps its place.
+        let result = exec(
+            &mut env,
+            "(do (def m {:z 1 :a 2}) (set! (get m :m) 3) (set! (get m :z) 4) m)",
+        );
+        assert_eq!(result.display_value(&env), "{:z 4\n:a 2\n:m 3\n}");
+        let result = exec(&mut env, "(make-hash 3 :c 1 :a 2 :b)");
+        assert_eq!(result.display_value(&env), "{3 :c\n1 :a\n2 :b\n}");
+
+        exec(&mut env, "(def s (make-sorted-hash :c 3 \"b\" 2 1 1))");
+        exec(&mut env, "(set! (get s :a) 0)");
+        exec(&mut env, "(set! (get s 1) 10)");
+        let result = exec(&mut env, "s");
+        assert_eq!(result.display_value(&env), "{1 10\n\"b\" 2\n:a 0\n:c 3\n}");
+        let result = exec(&mut env, "(list (s :a) (get s \"b\") (s :x :none))");
+        let expected = read_test(&mut env, "(0 2 :none)");
+        assert_vals(&env, expected, result);
+        let result = exec(
+            &mut env,
+            "(let ({a :a, c :c, x :x, :or {:x 5}} s) (list a c x))",
+        );
+        let expected = read_test(&mut env, "(0 3 5)");
+        assert_vals(&env, expected, result);
+        let result = exec_result(&mut env, "(make-sorted-hash :a)");
+        assert!(result.is_err());
+        env.reset();
+    }
+
+    #[test]
+    fn test_persistent() {
+        let mut env = new_slosh_vm();
+        builtins::collections::setup_collection_builtins(&mut env);
+        exec(&mut env, "(def v (pvec 1 2 3))");
+        exec(&mut env, "(def v2 (assoc (conj v 4 5) 0 :x))");
+        let result = exec(
+            &mut env,
+            "(list (len v) (len v2) (get v 0) (get v2 0) (v2 -1) (v 5 :none))",
+        );
+        let expected = read_test(&mut env, "(3 5 1 :x 5 :none)");
+        assert_vals(&env, expected, result);
+        let result = exec(&mut env, "v2");
+        assert_eq!(result.display_value(&env), "(pvec :x 2 3 4 5)");
+        let result = exec(
+            &mut env,
+            "(list (equal? v (pvec 1 2 3)) (equal? v v2) (equal? v [1 2 3]))",
+        );
+        assert_eq!(result.display_value(&env), "(true false false)");
+
+        exec(&mut env, "(def m (->pmap {:a 1 :b 2}))");
+        exec(&mut env, "(def m2 (dissoc (assoc m :c 3 :a 10) :b))");
+        let result = exec(
+            &mut env,
+            "(list (len m) (len m2) (get m :a) (m2 :a) (m2 :b :none))",
+        );
+        let expected = read_test(&mut env, "(2 2 1 10 :none)");
+        assert_vals(&env, expected, result);
+        let result = exec(&mut env, "(let ({a :a, c :c} m2) (list a c))");
+        let expected = read_test(&mut env, "(10 3)");
+        assert_vals(&env, expected, result);
+        let result = exec(
+            &mut env,
+            "(list (equal? m2 (pmap :c 3 :a 10)) (equal? m m2))",
+        );
+        assert_eq!(result.display_value(&env), "(true false)");
+
+        // Back to the mutable types.
+        let result = exec(
+            &mut env,
+            "(let (h (pmap->hash m2)) (set! (get h :d) 4) (list (len h) (len m2)))",
+        );
+        let expected = read_test(&mut env, "(3 2)");
+        assert_vals(&env, expected, result);
+        let result = exec(&mut env, "(pvec->vec (->pvec '(1 2)))");
+        let expected = exec(&mut env, "[1 2]");
+        assert_vals(&env, expected, result);
+
+        // Persistent collections can not be changed in place.
+        for code in [
+            "(set! (get v 0) 0)",
+            "(set! (get m :a) 0)",
+            "(assoc v 4 0)",
+            "(conj m 1)",
+        ] {
+            assert!(exec_result(&mut env, code).is_err(), "{code}");
+            env.reset();
+        }
+    }
+
+    #[test]
+    fn test_sets() {
+        let mut env = new_slosh_vm();
+        builtins::collections::setup_collection_builtins(&mut env);
+        exec(&mut env, "(def s #{1 :a 2 :a})");
+        let result = exec(&mut env, "s");
+        assert_eq!(result.display_value(&env), "#{1 :a 2}");
+        let result = exec(
+            &mut env,
+            "(list (len s) (s :a) (s 3) (s 3 :none) (len #{}))",
+        );
+        let expected = read_test(&mut env, "(3 :a nil :none 0)");
+        assert_vals(&env, expected, result);
+        let result = exec(
+            &mut env,
+            "(list (equal? s (set 2 1 :a)) (equal? s #{1 2}) (set-contains? s 2) (set-contains? s 3))",
+        );
+        assert_eq!(result.display_value(&env), "(true false true false)");
+
+        exec(&mut env, "(set-add! s 3 1)");
+        exec(&mut env, "(set-remove! s :a 4)");
+        let result = exec(&mut env, "s");
+        assert_eq!(result.display_value(&env), "#{1 2 3}");
+        let result = exec(
+            &mut env,
+            "(list (set-union s #{4} #{1 5}) (set-intersection s #{3 2 9}) (set-difference s #{2}))",
+        );
+        assert_eq!(result.display_value(&env), "(#{1 2 3 4 5} #{2 3} #{1 3})");
+        exec(&mut env, "(clear! s)");
+        let result = exec(&mut env, "(len s)");
+        assert_vals(&env, 0.into(), result);
+
+        // A frozen set can not be changed.
+        let frozen = exec(&mut env, "(def f #{1 2})");
+        env.heap_immutable(frozen);
+        for code in [
+            "(set-add! f 3)",
+            "(set-remove! f 1)",
+            "(clear! f)",
+            "(set-add! [] 1)",
+        ] {
+            assert!(exec_result(&mut env, code).is_err(), "{code}");
+            env.reset();
+        }
+        let result = exec(&mut env, "(list (len f) (set-contains? f 1))");
+        assert_eq!(result.display_value(&env), "(2 true)");
+
+        // The literal does not go through the set global, a local named set does not break it.
+        let result = exec(&mut env, "(let (set 1) #{set 2})");
+        assert_eq!(result.display_value(&env), "#{1 2}");
+    }
+
+    #[test]
+    fn test_bytes() {
+        let mut env = new_slosh_vm();
+        builtins::collections::setup_collection_builtins(&mut env);
+        exec(&mut env, "(def b #u8(1 2 #xff))");
+        let result = exec(&mut env, "b");
+        assert_eq!(result.display_value(&env), "#u8(1 2 255)");
+        let result = exec(
+            &mut env,
+            "(list (len b) (get b 0) (b 1) (get b -1) (b 3 :none) (len #u8()))",
+        );
+        let expected = read_test(&mut env, "(3 1 2 255 :none 0)");
+        assert_vals(&env, expected, result);
+        exec(&mut env, "(set! (get b 0) 10)");
+        exec(&mut env, "(set! (get b 4) 4)");
+        let result = exec(&mut env, "b");
+        assert_eq!(result.display_value(&env), "#u8(10 2 255 0 4)");
+        let result = exec(
+            &mut env,
+            "(list (bytes-slice b 1 3) (bytes-append (make-bytes 2 7) #u8(1)) (vec->bytes [3 4]))",
+        );
+        assert_eq!(
+            result.display_value(&env),
+            "(#u8(2 255) #u8(7 7 1) #u8(3 4))"
+        );
+        let result = exec(&mut env, "(bytes->vec #u8(1 2))");
+        let expected = exec(&mut env, "[1 2]");
+        assert_vals(&env, expected, result);
+        let result = exec(
+            &mut env,
+            "(list (bytes->str (str->bytes \"héllo\")) (bytes->str #u8(104 255) :lossy) (bytes->hex #u8(0 171)) (bytes->base64 #u8(104 105)))",
+        );
+        assert_eq!(
+            result.display_value(&env),
+            "(\"héllo\" \"h\u{fffd}\" \"00ab\" \"aGk=\")"
+        );
+        let result = exec(
+            &mut env,
+            "(list (equal? #u8(1 2) (hex->bytes \"0102\")) (equal? #u8(104 105) (base64->bytes \"aGk=\")) (equal? #u8(1) #u8(2)) (equal? \"h\\u{FFFD}\" (bytes->str #u8(104 255) :lossy)))",
+        );
+        assert_eq!(result.display_value(&env), "(true true false true)");
+        exec(&mut env, "(clear! b)");
+        let result = exec(&mut env, "(len b)");
+        assert_vals(&env, 0.into(), result);
+
+        for code in [
+            "(bytes->str #u8(255))",
+            "(hex->bytes \"0g\")",
+            "(base64->bytes \"a\")",
+            "(set! (get b 0) 256)",
+            "(bytes 1 -1)",
+            "(hex->bytes \"+f\")",
+            "#u8(1 256)",
+        ] {
+            assert!(exec_result(&mut env, code).is_err(), "{code}");
+            env.reset();
+        }
+
+        // The literal does not go through the bytes global, a local named bytes does not break it.
+        let result = exec(&mut env, "(let (bytes 7) (equal? #u8(bytes 7) (make-bytes 2 7)))");
+        assert_eq!(result.display_value(&env), "true");
+    }
+
+    #[test]
+    fn test_signal_restarts() {
+        let mut env = new_slosh_vm();
+        builtins::add_misc_builtins(&mut env);
+        exec(
+            &mut env,
+            "(def check (fn (rec) (if (< rec 0) (signal :bad-record rec {:skip :skipped :negate (fn () (- 0 rec))}) rec)))",
+        );
+        exec(
+            &mut env,
+            "(def run (fn (recs out) (if recs (run (cdr recs) (cons (check (car recs)) out)) out)))",
+        );
+        // Bad records are fixed up or skipped and the batch keeps going, the handler stays installed.
+        let result = exec(
+            &mut env,
+            "(do (on-error (fn (k v) (if (= v -2) (invoke-restart :skip) (invoke-restart :negate)))) (run '(1 -2 3 -4) '()))",
+        );
+        let expected = read_test(&mut env, "(4 3 :skipped 1)");
+        assert_vals(&env, expected, result);
+
+        // Not in tail position, the handler's result is used by the rest of the function.
+        exec(
+            &mut env,
+            "(def plus-one (fn (x) (let (r (signal :bad x {:use-value (fn (v) v)})) (+ r 1))))",
+        );
+        let result = exec(
+            &mut env,
+            "(do (on-error (fn (k v) (let (r (restarts)) (invoke-restart (get r 0) (* v 10))))) (plus-one 2))",
+        );
+        let expected = read_test(&mut env, "21");
+        assert_vals(&env, expected, result);
+        let result = exec(&mut env, "(restarts)");
+        assert_vals(&env, Value::Nil, result);
+
+        // Errors from the handler are not handled again.
+        let err = exec_result(
+            &mut env,
+            "(do (on-error (fn (k v) (err :other v))) (check -1))",
+        )
+        .unwrap_err();
+        assert_eq!(err.key, "other");
+        env.reset();
+
+        // Without a handler a signal is a normal error.
+        let err = exec_result(&mut env, "(check -1)").unwrap_err();
+        assert_eq!(err.key, "bad-record");
+        env.reset();
+        let err = exec_result(&mut env, "(invoke-restart :skip)").unwrap_err();
+        assert_eq!(err.key, "rt");
+        env.reset();
+    }
+
+    #[test]
+    fn test_gensym() {
+        let mut env = new_slosh_vm();
+        builtins::add_misc_builtins(&mut env);
+        builtins::conversions::add_conv_builtins(&mut env);
+        let result = exec(
+            &mut env,
+            "(list (equal? (gensym) (gensym)) (let (g (gensym)) (equal? g g)))",
+        );
+        assert_eq!(result.display_value(&env), "(false true)");
+        // An uninterned symbol is not the same as a symbol with its name.
+        let Value::Symbol(g) = exec(&mut env, "(gensym)") else {
+            panic!("gensym did not return a symbol");
+        };
+        let name = env.get_interned(g).to_string();
+        assert_ne!(env.intern(&name), g);
+
+        exec(
+            &mut env,
+            "(def twice (macro (x) (let (g (gensym)) `(let (~g ~x) (+ ~g ~g)))))",
+        );
+        exec(&mut env, "(def kept (list (gensym) (->key 12345)))");
+        exec(&mut env, "(do (->sym 67890) (gensym) (gc))");
+        let result = exec(&mut env, "(twice 3)");
+        assert_vals(&env, 6.into(), result);
+        assert!(env.get_if_interned("67890").is_none());
+        let result = exec(&mut env, "(equal? (car (cdr kept)) :12345)");
+        assert_vals(&env, Value::True, result);
+        let result = exec(&mut env, "(car kept)");
+        assert!(matches!(result, Value::Symbol(s) if env.is_uninterned(s)));
+        // Gensyms from compiled macro expansions are reclaimed.
+        exec(&mut env, "(gc)");
+        let symbols = env.symbols_len();
+        for _ in 0..10 {
+            exec(&mut env, "(twice 3)");
+        }
+        exec(&mut env, "(gc)");
+        assert_eq!(env.symbols_len(), symbols);
+    }
+
+    #[test]
+    fn test_builtin_closure() {
+        let mut env = new_slosh_vm();
+        let (tx, rx) = std::sync::mpsc::channel();
+        let prefix = "log: ".to_string();
+        builtins::add_builtin_closure(
+            &mut env,
+            "send-log",
+            move |vm, registers| {
+                for val in registers {
+                    tx.send(format!("{prefix}{}", val.pretty_value(vm)))
+                        .map_err(|e| VMError::new_vm(format!("send-log: {e}")))?;
+                }
+                Ok(Value::Nil)
+            },
+            "Usage: (send-log val*)",
+        );
+        exec(&mut env, "(send-log 1 :two)");
+        exec(&mut env, "(def f (fn (x) (send-log x)))");
+        exec(&mut env, "(f \"three\")");
+        let sent: Vec<String> = rx.try_iter().collect();
+        assert_eq!(sent, vec!["log: 1", "log: :two", "log: three"]);
+    }
 }
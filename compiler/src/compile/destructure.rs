@@ -1,7 +1,6 @@
 use compile_state::state::CompileState;
 use slvm::opcodes::*;
-use slvm::{Handle, Interned, VMError, VMResult, Value};
-use std::collections::HashMap;
+use slvm::{Handle, Interned, VMError, VMMap, VMResult, Value};
 
 use crate::{compile, mkconst, SloshVm, SloshVmTrait};
 
@@ -35,7 +34,7 @@ pub struct DestructState {
 /// Takes a Value and either returns it or the literal vec or hash-map if it is a call to create one.
 /// Only works with Value::List for detection currently, this is what will come from the reader in
 /// these cases, may need to expand this to handle Value::Pair as well for macros (? TODO).
-pub fn resolve_destruct_containers(env: &mut SloshVm, arg: Value) -> Value {
+pub fn resolve_destruct_containers(env: &mut SloshVm, arg: Value) -> VMResult<Value> {
     if let Value::List(h, s) = arg {
         let v = env.get_vector(h);
         let s = s as usize;
@@ -43,26 +42,23 @@ pub fn resolve_destruct_containers(env: &mut SloshVm, arg: Value) -> Value {
         let i_hash = env.specials().make_hash;
         match &v[s] {
             Value::Symbol(i) if *i == i_vec => {
-                let v = env.alloc_vector(v[s + 1..].to_vec());
+                let v = env.alloc_vector(v[s + 1..].to_vec())?;
                 env.heap_sticky(v);
-                v
+                Ok(v)
             }
             Value::Symbol(i) if *i == i_hash => {
-                let mut iter = v[s + 1..].iter();
-                let mut map = HashMap::new();
-                while let Some(key) = iter.next() {
-                    if let Some(val) = iter.next() {
-                        map.insert(*key, *val);
-                    }
-                }
-                let v = env.alloc_map(map);
+                let map: VMMap = v[s + 1..]
+                    .chunks_exact(2)
+                    .map(|kv| (kv[0], kv[1]))
+                    .collect();
+                let v = env.alloc_map(map)?;
                 env.heap_sticky(v);
-                v
+                Ok(v)
             }
-            _ => arg,
+            _ => Ok(arg),
         }
     } else {
-        arg
+        Ok(arg)
     }
 }
 
@@ -203,7 +199,7 @@ impl DestructState {
                     opt_comps.push((reg, *name));
                 }
             } else {
-                let name = resolve_destruct_containers(env, *name);
+                let name = resolve_destruct_containers(env, *name)?;
                 match &name {
                     Value::Symbol(i) if *i == env.specials().rest => {
                         len -= 1;
@@ -286,18 +282,18 @@ impl DestructState {
         let mut len = map.len();
         let mut register_labels = Vec::new();
         let optionals = if let Some(opts) = map.get(&Value::Keyword(or_i)) {
-            let opts = resolve_destruct_containers(env, *opts);
+            let opts = resolve_destruct_containers(env, *opts)?;
             if let Value::Map(handle) = opts {
                 env.get_map(handle).clone()
             } else {
                 return Err(VMError::new_compile(":or must be followed by a map"));
             }
         } else {
-            HashMap::new()
+            VMMap::new()
         };
         let start_reg = *next_reg;
         for (key, val) in &map {
-            let key = resolve_destruct_containers(env, *key);
+            let key = resolve_destruct_containers(env, *key)?;
             match &key {
                 Value::Keyword(i) if *i == or_i => {
                     len -= 1;
@@ -0,0 +1,435 @@
+//! A high level API for embedding slosh in a Rust program.  Slosh wraps a VM with the standard
+//! builtins and takes care of reading, compiling and running code, converting Rust values to and
+//! from slosh values (with SlFrom/SlInto) and turning errors into a SloshError.
+//!
+//! Values returned by eval_str, load_file and call are not rooted, they are only good until the
+//! next call that runs code or allocates.  Use the typed versions (eval_as, call_as) or from_value
+//! to convert them right away.
+
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+use builtins::collections::setup_collection_builtins;
+use builtins::conversions::add_conv_builtins;
+use builtins::io::add_io_builtins;
+use builtins::print::add_print_builtins;
+use builtins::string::add_str_builtins;
+use builtins::types::{SlFrom, SlInto};
+use builtins::{add_builtin_closure, add_misc_builtins};
+use compile_state::state::{new_slosh_vm, CompileState, SloshVm, SloshVmTrait};
+use slvm::{Chunk, Handle, VMError, VMResult, Value, RET};
+
+use crate::pass1::pass1;
+use crate::{compile, ReadError, Reader};
+
+/// Errors returned by Slosh.
+#[derive(Debug)]
+pub enum SloshError {
+    /// The code could not be read.
+    Read(ReadError),
+    /// The code could not be compiled.
+    Compile(VMError),
+    /// Running the code raised an error.
+    Runtime(VMError),
+    /// An argument or result could not be converted.
+    Conversion(VMError),
+    /// The named global is not defined.
+    Undefined(String),
+    /// The named global is not a function.
+    NotCallable(String),
+    /// A file could not be opened.
+    Io(io::Error),
+}
+
+impl SloshError {
+    /// The error raised by the VM (if any).
+    pub fn vm_error(&self) -> Option<&VMError> {
+        match self {
+            SloshError::Compile(e) | SloshError::Runtime(e) | SloshError::Conversion(e) => Some(e),
+            _ => None,
+        }
+    }
+
+    /// Like to_string but uses vm to print any value in the error.
+    pub fn display(&self, vm: &SloshVm) -> String {
+        match self {
+            SloshError::Compile(e) => format!("compile error: {}", e.display(vm)),
+            SloshError::Runtime(e) | SloshError::Conversion(e) => e.display(vm),
+            _ => self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for SloshError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SloshError::Read(e) => write!(f, "read error: {e}"),
+            SloshError::Compile(e) => write!(f, "compile error: {e}"),
+            SloshError::Runtime(e) | SloshError::Conversion(e) => write!(f, "{e}"),
+            SloshError::Undefined(name) => write!(f, "{name} is not defined"),
+            SloshError::NotCallable(name) => write!(f, "{name} is not callable"),
+            SloshError::Io(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl Error for SloshError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SloshError::Read(e) => Some(e),
+            SloshError::Compile(e) | SloshError::Runtime(e) | SloshError::Conversion(e) => Some(e),
+            SloshError::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ReadError> for SloshError {
+    fn from(item: ReadError) -> Self {
+        SloshError::Read(item)
+    }
+}
+
+impl From<io::Error> for SloshError {
+    fn from(item: io::Error) -> Self {
+        SloshError::Io(item)
+    }
+}
+
+pub type SloshResult<T> = Result<T, SloshError>;
+
+/// Arguments for Slosh::call, a tuple of things that convert to a Value (with SlFrom) or Values.
+pub trait SlArgs {
+    fn sl_args(self, vm: &mut SloshVm) -> VMResult<Vec<Value>>;
+}
+
+impl SlArgs for &[Value] {
+    fn sl_args(self, _vm: &mut SloshVm) -> VMResult<Vec<Value>> {
+        Ok(self.to_vec())
+    }
+}
+
+impl SlArgs for Vec<Value> {
+    fn sl_args(self, _vm: &mut SloshVm) -> VMResult<Vec<Value>> {
+        Ok(self)
+    }
+}
+
+impl SlArgs for () {
+    fn sl_args(self, _vm: &mut SloshVm) -> VMResult<Vec<Value>> {
+        Ok(Vec::new())
+    }
+}
+
+macro_rules! sl_args_tuple {
+    ($($arg:ident),+) => {
+        impl<$($arg),+> SlArgs for ($($arg,)+)
+        where
+            $(Value: SlFrom<$arg>),+
+        {
+            #[allow(non_snake_case)]
+            fn sl_args(self, vm: &mut SloshVm) -> VMResult<Vec<Value>> {
+                let ($($arg,)+) = self;
+                Ok(vec![$($arg.sl_into(vm)?),+])
+            }
+        }
+    };
+}
+
+sl_args_tuple!(A);
+sl_args_tuple!(A, B);
+sl_args_tuple!(A, B, C);
+sl_args_tuple!(A, B, C, D);
+sl_args_tuple!(A, B, C, D, E);
+sl_args_tuple!(A, B, C, D, E, F);
+
+/// A slosh VM with the standard builtins, ready to run code.
+pub struct Slosh {
+    vm: SloshVm,
+}
+
+impl Default for Slosh {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Slosh {
+    /// A new VM with the builtins that do not need a shell (collections, strings, printing, io,
+    /// conversions and misc).
+    pub fn new() -> Self {
+        let mut vm = new_slosh_vm();
+        setup_collection_builtins(&mut vm);
+        add_print_builtins(&mut vm);
+        add_str_builtins(&mut vm);
+        add_misc_builtins(&mut vm);
+        add_io_builtins(&mut vm);
+        add_conv_builtins(&mut vm);
+        Self { vm }
+    }
+
+    /// Wrap a VM that is already set up.
+    pub fn from_vm(vm: SloshVm) -> Self {
+        Self { vm }
+    }
+
+    pub fn vm(&self) -> &SloshVm {
+        &self.vm
+    }
+
+    pub fn vm_mut(&mut self) -> &mut SloshVm {
+        &mut self.vm
+    }
+
+    pub fn into_vm(self) -> SloshVm {
+        self.vm
+    }
+
+    /// Read, compile and run all the forms in code, returns the value of the last one.
+    pub fn eval_str(&mut self, code: &str) -> SloshResult<Value> {
+        let reader = Reader::from_string(code.to_string(), &mut self.vm, "<eval>", 1, 0);
+        run_reader(reader, "<eval>")
+    }
+
+    /// Same as eval_str but converts the result to T.
+    pub fn eval_as<T>(&mut self, code: &str) -> SloshResult<T>
+    where
+        T: for<'a> SlFrom<&'a Value>,
+    {
+        let val = self.eval_str(code)?;
+        self.from_value(val)
+    }
+
+    /// Read, compile and run all the forms in the file at path, returns the value of the last one.
+    pub fn load_file<P: AsRef<Path>>(&mut self, path: P) -> SloshResult<Value> {
+        let path = path.as_ref();
+        let file = File::open(path)?;
+        let name = self.vm.intern(&path.to_string_lossy());
+        let name = self.vm.get_interned(name);
+        let line_num = self.vm.line_num();
+        self.vm.set_line_num(1);
+        let reader = Reader::from_file(file, &mut self.vm, name, 1, 0);
+        let res = run_reader(reader, name);
+        self.vm.set_line_num(line_num);
+        res
+    }
+
+    /// Call the global function name with args.
+    pub fn call<A: SlArgs>(&mut self, name: &str, args: A) -> SloshResult<Value> {
+        let func = self
+            .get_global(name)
+            .ok_or_else(|| SloshError::Undefined(name.to_string()))?;
+        if !matches!(
+            func,
+            Value::Lambda(_) | Value::Closure(_) | Value::Builtin(_)
+        ) {
+            return Err(SloshError::NotCallable(name.to_string()));
+        }
+        self.call_value(func, args)
+    }
+
+    /// Same as call but converts the result to T.
+    pub fn call_as<T, A>(&mut self, name: &str, args: A) -> SloshResult<T>
+    where
+        T: for<'a> SlFrom<&'a Value>,
+        A: SlArgs,
+    {
+        let val = self.call(name, args)?;
+        self.from_value(val)
+    }
+
+    /// Call func (a lambda, closure or builtin) with args.
+    pub fn call_value<A: SlArgs>(&mut self, func: Value, args: A) -> SloshResult<Value> {
+        // The converted args are not rooted until the call puts them on the stack.
+        self.vm.pause_gc();
+        let args = args.sl_args(&mut self.vm);
+        self.vm.unpause_gc();
+        let args = args.map_err(SloshError::Conversion)?;
+        let res = match func {
+            Value::Lambda(h) => {
+                let chunk = self.vm.get_lambda(h);
+                self.vm.do_call(chunk, &args, None)
+            }
+            Value::Closure(h) => {
+                let (chunk, caps) = self.vm.get_closure(h);
+                let caps: Vec<Handle> = caps.to_vec();
+                self.vm.do_call(chunk, &args, Some(&caps[..]))
+            }
+            Value::Builtin(idx) => self.vm.get_builtin(idx).call(&mut self.vm, &args),
+            _ => return Err(SloshError::NotCallable(func.display_value(&self.vm))),
+        };
+        res.map_err(|e| {
+            self.vm.reset();
+            SloshError::Runtime(e)
+        })
+    }
+
+    /// Convert val to a Rust type.
+    pub fn from_value<T>(&mut self, val: Value) -> SloshResult<T>
+    where
+        T: for<'a> SlFrom<&'a Value>,
+    {
+        T::sl_from(&val, &mut self.vm).map_err(SloshError::Conversion)
+    }
+
+    /// Convert a Rust value to a Value.
+    pub fn to_value<T>(&mut self, val: T) -> SloshResult<Value>
+    where
+        Value: SlFrom<T>,
+    {
+        val.sl_into(&mut self.vm).map_err(SloshError::Conversion)
+    }
+
+    /// The value of the global name, None if it is not defined.
+    pub fn get_global(&self, name: &str) -> Option<Value> {
+        let sym = self.vm.get_if_interned(name)?;
+        let slot = self.vm.global_intern_slot(sym)?;
+        match self.vm.get_global(slot) {
+            Value::Undefined => None,
+            val => Some(val),
+        }
+    }
+
+    /// Define (or set) the global name to val converted to a Value.
+    pub fn set_global<T>(&mut self, name: &str, val: T) -> SloshResult<()>
+    where
+        Value: SlFrom<T>,
+    {
+        let val = self.to_value(val)?;
+        self.vm.set_named_global(name, val);
+        Ok(())
+    }
+
+    /// Define the global name as a builtin that runs func (see GVm::add_builtin_closure).
+    pub fn add_builtin<F>(&mut self, name: &str, func: F, doc_string: &str)
+    where
+        F: Fn(&mut SloshVm, &[Value]) -> VMResult<Value> + 'static,
+    {
+        add_builtin_closure(&mut self.vm, name, func, doc_string);
+    }
+}
+
+fn run_reader(mut reader: Reader, name: &'static str) -> SloshResult<Value> {
+    let mut last = Value::Nil;
+    let mut doc_string = None;
+    while let Some(exp) = reader.next() {
+        let vm = reader.vm();
+        let exp = exp?;
+        vm.heap_sticky(exp);
+        let res = compile_one(vm, exp, name, doc_string);
+        vm.heap_unsticky(exp);
+        let (chunk, new_doc_string) = res.map_err(|e| {
+            vm.reset();
+            SloshError::Compile(e)
+        })?;
+        doc_string = new_doc_string;
+        last = vm.execute(chunk).map_err(|e| {
+            vm.reset();
+            SloshError::Runtime(e)
+        })?;
+    }
+    Ok(last)
+}
+
+fn compile_one(
+    vm: &mut SloshVm,
+    exp: Value,
+    name: &'static str,
+    doc_string: Option<Value>,
+) -> VMResult<(Arc<Chunk>, Option<Value>)> {
+    let line_num = vm.line_num();
+    let mut state = CompileState::new_state(name, line_num, None);
+    state.chunk.dbg_args = Some(Vec::new());
+    state.doc_string = doc_string;
+    pass1(vm, &mut state, exp)?;
+    compile(vm, &mut state, exp, 0)?;
+    state.chunk.encode0(RET, vm.own_line())?;
+    state.chunk.extra_regs = state.max_regs;
+    Ok((Arc::new(state.chunk), state.doc_string))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_eval_and_call() -> SloshResult<()> {
+        let mut slosh = Slosh::new();
+        assert_eq!(slosh.eval_as::<i64>("(def x 20) (+ x 1)")?, 21);
+        slosh.eval_str("(def add (fn (a b) (+ a b)))")?;
+        assert_eq!(slosh.call_as::<i64, _>("add", (1, 2))?, 3);
+        slosh.eval_str("(def trim (fn (s) (str-trim s)))")?;
+        assert_eq!(
+            slosh.call_as::<String, _>("trim", (" world ".to_string(),))?,
+            "world"
+        );
+        let x = slosh.get_global("x").expect("x is defined");
+        assert_eq!(slosh.call_as::<i64, _>("add", &[x, x][..])?, 40);
+
+        slosh.set_global("limit", 10)?;
+        slosh.add_builtin(
+            "scale",
+            move |vm, registers| match registers {
+                [val] => Ok((val.get_int(vm)? * 3).into()),
+                _ => Err(VMError::new_vm("scale: takes one argument")),
+            },
+            "Usage: (scale n)",
+        );
+        assert_eq!(slosh.eval_as::<i64>("(scale limit)")?, 30);
+        assert_eq!(slosh.call_as::<i64, _>("scale", (5,))?, 15);
+        Ok(())
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut slosh = Slosh::new();
+        assert!(matches!(slosh.eval_str("(+ 1"), Err(SloshError::Read(_))));
+        assert!(matches!(
+            slosh.eval_str("(not-defined 1)"),
+            Err(SloshError::Compile(_))
+        ));
+        match slosh.eval_str("(err :oops \"bad\")") {
+            Err(SloshError::Runtime(e)) => assert_eq!(e.key, "oops"),
+            res => panic!("expected a runtime error, got {res:?}"),
+        }
+        assert!(matches!(
+            slosh.call("missing", ()),
+            Err(SloshError::Undefined(_))
+        ));
+        slosh.eval_str("(def n 1)").unwrap();
+        assert!(matches!(
+            slosh.call("n", ()),
+            Err(SloshError::NotCallable(_))
+        ));
+        assert!(matches!(
+            slosh.eval_as::<String>("1"),
+            Err(SloshError::Conversion(_))
+        ));
+        assert!(matches!(
+            slosh.load_file("/does/not/exist.slosh"),
+            Err(SloshError::Io(_))
+        ));
+        // The VM is still usable after errors.
+        assert_eq!(slosh.eval_as::<i64>("(+ n 1)").unwrap(), 2);
+    }
+
+    #[test]
+    fn test_load_file() -> SloshResult<()> {
+        let path = std::env::temp_dir().join(format!("embed-test-{}.slosh", std::process::id()));
+        std::fs::write(
+            &path,
+            "(def loaded 5)\n(def twice (fn (x) (* x 2)))\n(twice loaded)\n",
+        )?;
+        let mut slosh = Slosh::new();
+        let res = slosh.load_file(&path);
+        std::fs::remove_file(&path)?;
+        let res = res?;
+        assert_eq!(slosh.from_value::<i64>(res)?, 10);
+        assert_eq!(slosh.call_as::<i64, _>("twice", (4,))?, 8);
+        Ok(())
+    }
+}
@@ -12,6 +12,9 @@ pub use crate::backquote::*;
 pub mod compile;
 pub mod pass1;
 
+pub mod embed;
+pub use crate::embed::*;
+
 #[cfg(test)]
 pub mod test_utils;
 
@@ -7,7 +7,7 @@ use std::io::{BufReader, Cursor};
 use std::num::{ParseFloatError, ParseIntError};
 
 use compile_state::state::{SloshVm, SloshVmTrait};
-use slvm::{Chunk, Value};
+use slvm::{BigInt, Chunk, Interned, VMError, Value};
 use unicode_reader::Graphemes;
 
 pub trait PeekableIterator: std::iter::Iterator {
@@ -58,6 +58,15 @@ pub struct ReadError {
 
 impl Error for ReadError {}
 
+// The reader only gets VM errors from allocating (going over the heap limit).
+impl From<VMError> for ReadError {
+    fn from(err: VMError) -> Self {
+        ReadError {
+            reason: err.to_string(),
+        }
+    }
+}
+
 impl fmt::Display for ReadError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "{}", self.reason)
@@ -132,6 +141,7 @@ fn is_digit(ch: &str) -> bool {
     )
 }
 
+#[derive(Copy, Clone)]
 enum ReadReturn {
     None,
     List,
@@ -216,25 +226,31 @@ impl<'vm> Reader<'vm> {
         &mut **self.char_iter.as_mut().expect("Invalid Reader!")
     }
 
-    fn alloc_pair(&mut self, car: Value, cdr: Value, line: u32, column: u32) -> Value {
-        let result = self.vm.alloc_pair_ro(car, cdr);
+    fn alloc_pair(
+        &mut self,
+        car: Value,
+        cdr: Value,
+        line: u32,
+        column: u32,
+    ) -> Result<Value, ReadError> {
+        let result = self.vm.alloc_pair_ro(car, cdr)?;
         // Just allocated this so the unwrap is safe.
         let file_name = self.vm.intern_static(self.file_name);
         self.vm
             .set_heap_property(result, "dbg-file", Value::StringConst(file_name));
         self.vm.set_heap_property(result, "dbg-line", line.into());
         self.vm.set_heap_property(result, "dbg-col", column.into());
-        result
+        Ok(result)
     }
 
-    fn alloc_list(&mut self, list: Vec<Value>, line: u32, column: u32) -> Value {
-        let result = self.vm.alloc_list_ro(list);
+    fn alloc_list(&mut self, list: Vec<Value>, line: u32, column: u32) -> Result<Value, ReadError> {
+        let result = self.vm.alloc_list_ro(list)?;
         let file_name = self.vm.intern_static(self.file_name);
         self.vm
             .set_heap_property(result, "dbg-file", Value::StringConst(file_name));
         self.vm.set_heap_property(result, "dbg-line", line.into());
         self.vm.set_heap_property(result, "dbg-col", column.into());
-        result
+        Ok(result)
     }
 
     fn escape_to_char(&mut self) -> Result<char, ReadError> {
@@ -333,7 +349,7 @@ impl<'vm> Reader<'vm> {
                     }
                 }
             }
-            Ok(self.vm.alloc_char(&ch))
+            Ok(self.vm.alloc_char(&ch)?)
         } else {
             let reason = format!(
                 "Not a valid char, missing: line {}, col: {}",
@@ -478,7 +494,7 @@ impl<'vm> Reader<'vm> {
                 args.push(Value::StringConst(self.vm.intern(symbol)));
                 symbol.clear();
             }
-            Ok(self.alloc_list(args, line, column))
+            self.alloc_list(args, line, column)
         }
     }
 
@@ -512,31 +528,34 @@ impl<'vm> Reader<'vm> {
         })
     }
 
-    fn do_atom(&mut self, symbol: &str, is_number: bool) -> Value {
+    fn do_atom(&mut self, symbol: &str, is_number: bool) -> Result<Value, ReadError> {
         if is_number {
             let mut num_str = symbol.to_string();
             num_str.retain(|ch| ch != '_');
             let potential_int: Result<i64, ParseIntError> = num_str.parse();
             match potential_int {
-                Ok(v) => v.into(),
+                Ok(v) => Ok(self.vm.alloc_int(v)?),
                 Err(_) => {
+                    if let Ok(v) = num_str.parse::<BigInt>() {
+                        return Ok(self.vm.alloc_bigint(v)?);
+                    }
                     let potential_float: Result<f64, ParseFloatError> = num_str.parse();
                     match potential_float {
-                        Ok(f) => f.into(),
-                        Err(_) => Value::Symbol(self.vm.intern(symbol)),
+                        Ok(f) => Ok(f.into()),
+                        Err(_) => Ok(Value::Symbol(self.vm.intern(symbol))),
                     }
                 }
             }
         } else {
             if symbol.is_empty() {
-                return Value::Nil;
+                return Ok(Value::Nil);
             }
             if symbol == "nil" {
-                Value::Nil
+                Ok(Value::Nil)
             } else if symbol.len() > 1 && symbol.starts_with(':') {
-                Value::Keyword(self.vm.intern(&symbol[1..]))
+                Ok(Value::Keyword(self.vm.intern(&symbol[1..])))
             } else {
-                Value::Symbol(self.vm.intern(symbol))
+                Ok(Value::Symbol(self.vm.intern(symbol)))
             }
         }
     }
@@ -648,14 +667,17 @@ impl<'vm> Reader<'vm> {
         buffer: &mut String,
         radix: u32,
         read_table_term: &HashMap<&'static str, Value>,
-    ) -> Result<i64, ReadError> {
+    ) -> Result<Value, ReadError> {
         buffer.clear();
         self.read_symbol(buffer, true, true, read_table_term);
         match i64::from_str_radix(buffer, radix) {
-            Ok(n) => Ok(n),
-            Err(e) => Err(ReadError {
-                reason: e.to_string(),
-            }),
+            Ok(n) => Ok(self.vm.alloc_int(n)?),
+            Err(e) => match BigInt::parse_bytes(buffer.as_bytes(), radix) {
+                Some(n) => Ok(self.vm.alloc_bigint(n)?),
+                None => Err(ReadError {
+                    reason: e.to_string(),
+                }),
+            },
         }
     }
 
@@ -677,7 +699,7 @@ impl<'vm> Reader<'vm> {
                 Ok(exp) => {
                     if let Some(Value::Symbol(i)) = &exp {
                         if *i == close_intern {
-                            return Ok(self.alloc_list(v, line, column));
+                            return self.alloc_list(v, line, column);
                         }
                     }
                     exp
@@ -698,6 +720,77 @@ impl<'vm> Reader<'vm> {
         })
     }
 
+    /// Read items up to close into a list starting with head, used by the #{...} and #u8(...)
+    /// literals.  what names the literal in the unclosed error.
+    fn read_literal_list(
+        &mut self,
+        buffer: &mut String,
+        in_back_quote: bool,
+        head: Interned,
+        close: &'static str,
+        read_return: ReadReturn,
+        what: &str,
+    ) -> Result<Value, ReadError> {
+        let mut v: Vec<Value> = Vec::new();
+        let mut cont = true;
+        v.push(Value::Symbol(head));
+        let line = self.line() as u32;
+        let column = self.column() as u32;
+
+        let close_intern = self.vm.intern(close);
+        while cont {
+            let exp = match self.read_inner(buffer, in_back_quote, read_return) {
+                Ok(exp) => {
+                    if let Some(Value::Symbol(i)) = &exp {
+                        if *i == close_intern {
+                            return self.alloc_list(v, line, column);
+                        }
+                    }
+                    exp
+                }
+                Err(err) => {
+                    return Err(err);
+                }
+            };
+            let pch = self.chars().peek();
+            if let Some(exp) = exp {
+                v.push(exp);
+            } else if pch.is_none() {
+                cont = false;
+            }
+        }
+        Err(ReadError {
+            reason: format!("Unclosed {what}"),
+        })
+    }
+
+    fn read_set(&mut self, buffer: &mut String, in_back_quote: bool) -> Result<Value, ReadError> {
+        let make_set = self.vm.specials().make_set;
+        self.read_literal_list(buffer, in_back_quote, make_set, "}", ReadReturn::Map, "set")
+    }
+
+    fn read_bytes(&mut self, buffer: &mut String, in_back_quote: bool) -> Result<Value, ReadError> {
+        for expected in ["8", "("] {
+            if self.chars().next().as_deref() != Some(expected) {
+                let reason = format!(
+                    "Invalid byte vector literal, expected #u8(: line {}, col: {}",
+                    self.line(),
+                    self.column()
+                );
+                return Err(ReadError { reason });
+            }
+        }
+        let make_bytes = self.vm.specials().byte_vector;
+        self.read_literal_list(
+            buffer,
+            in_back_quote,
+            make_bytes,
+            ")",
+            ReadReturn::List,
+            "byte vector",
+        )
+    }
+
     fn read_map(&mut self, buffer: &mut String, in_back_quote: bool) -> Result<Value, ReadError> {
         //let mut map: HashMap<Value, Value> = HashMap::new();
         let mut cont = true;
@@ -713,7 +806,7 @@ impl<'vm> Reader<'vm> {
                 Ok(exp) => {
                     if let Some(Value::Symbol(i)) = &exp {
                         if *i == close_intern {
-                            return Ok(self.alloc_list(list, line, column));
+                            return self.alloc_list(list, line, column);
                         }
                     }
                     exp
@@ -849,7 +942,7 @@ impl<'vm> Reader<'vm> {
                                 reason: "Invalid dotted pair syntax with unquote.".to_string(),
                             });
                         }
-                        self.vm.alloc_vector_ro(v)
+                        self.vm.alloc_vector_ro(v)?
                     } else {
                         exp
                     };
@@ -879,7 +972,7 @@ impl<'vm> Reader<'vm> {
             if let Some(last) = list_iter.next() {
                 let mut last = *last;
                 for v in list_iter {
-                    last = self.alloc_pair(*v, last, line, column);
+                    last = self.alloc_pair(*v, last, line, column)?;
                 }
                 Ok(last)
             } else {
@@ -888,11 +981,11 @@ impl<'vm> Reader<'vm> {
         } else if list.is_empty() {
             Ok(Value::Nil)
         } else {
-            Ok(self.alloc_list(list, line, column))
+            self.alloc_list(list, line, column)
         }
     }
 
-    fn parse_get(&mut self, buffer: &str) -> Option<Value> {
+    fn parse_get(&mut self, buffer: &str) -> Result<Option<Value>, ReadError> {
         if buffer.contains('.') {
             let i_get = self.vm.intern("get");
             let mut vals = vec![Value::Symbol(i_get)];
@@ -903,7 +996,7 @@ impl<'vm> Reader<'vm> {
             for p in parts {
                 if !p.is_empty() {
                     if i != 0 && i % 2 == 0 {
-                        let inner = self.alloc_list(vals, line, column);
+                        let inner = self.alloc_list(vals, line, column)?;
                         vals = vec![Value::Symbol(i_get)];
                         vals.push(inner);
                     }
@@ -925,19 +1018,19 @@ impl<'vm> Reader<'vm> {
                             vec![Value::Symbol(i_quote), Value::Symbol(i_p)],
                             line,
                             column,
-                        ));
+                        )?);
                     }
                     i += 1;
                 }
             }
             if i > 1 {
-                let res = self.alloc_list(vals, line, column);
-                Some(res)
+                let res = self.alloc_list(vals, line, column)?;
+                Ok(Some(res))
             } else {
-                None
+                Ok(None)
             }
         } else {
-            None
+            Ok(None)
         }
     }
 
@@ -965,8 +1058,8 @@ impl<'vm> Reader<'vm> {
                 }
                 "'" => match self.read_inner(buffer, in_back_quote, ReadReturn::None) {
                     Ok(Some(exp)) => {
-                        let cdr = self.alloc_pair(exp, Value::Nil, line, column);
-                        let qlist = self.alloc_pair(Value::Symbol(i_quote), cdr, line, column);
+                        let cdr = self.alloc_pair(exp, Value::Nil, line, column)?;
+                        let qlist = self.alloc_pair(Value::Symbol(i_quote), cdr, line, column)?;
                         return Ok(Some(qlist));
                     }
                     Ok(None) => {
@@ -980,8 +1073,9 @@ impl<'vm> Reader<'vm> {
                 },
                 "`" => match self.read_inner(buffer, true, ReadReturn::None) {
                     Ok(Some(exp)) => {
-                        let cdr = self.alloc_pair(exp, Value::Nil, line, column);
-                        let qlist = self.alloc_pair(Value::Symbol(i_backquote), cdr, line, column);
+                        let cdr = self.alloc_pair(exp, Value::Nil, line, column)?;
+                        let qlist =
+                            self.alloc_pair(Value::Symbol(i_backquote), cdr, line, column)?;
                         return Ok(Some(qlist));
                     }
                     Ok(None) => {
@@ -1005,8 +1099,8 @@ impl<'vm> Reader<'vm> {
                     };
                     match self.read_inner(buffer, in_back_quote, ReadReturn::None) {
                         Ok(Some(exp)) => {
-                            let cdr = self.alloc_pair(exp, Value::Nil, line, column);
-                            return Ok(Some(self.alloc_pair(sym, cdr, line, column)));
+                            let cdr = self.alloc_pair(exp, Value::Nil, line, column)?;
+                            return Ok(Some(self.alloc_pair(sym, cdr, line, column)?));
                         }
                         Ok(None) => {
                             return Err(ReadError {
@@ -1042,7 +1136,7 @@ impl<'vm> Reader<'vm> {
                                     let doc_sym = Value::Symbol(self.vm.intern("doc-string"));
                                     let doc_string = s;
                                     let list =
-                                        self.alloc_list(vec![doc_sym, doc_string], line, column);
+                                        self.alloc_list(vec![doc_sym, doc_string], line, column)?;
                                     return Ok(Some(list));
                                 }
                                 Err(e) => return Err(e),
@@ -1062,6 +1156,12 @@ impl<'vm> Reader<'vm> {
                         "f" => {
                             return Ok(Some(Value::False));
                         }
+                        "{" => {
+                            return Ok(Some(self.read_set(buffer, in_back_quote)?));
+                        }
+                        "u" => {
+                            return Ok(Some(self.read_bytes(buffer, in_back_quote)?));
+                        }
                         "\"" => match self.read_string_literal(buffer) {
                             Ok(s) => return Ok(Some(Value::StringConst(self.vm.intern(s)))),
                             Err(e) => return Err(e),
@@ -1072,17 +1172,17 @@ impl<'vm> Reader<'vm> {
                         // Read an octal int
                         "o" => {
                             let exp = self.read_num_radix(buffer, 8, &read_table_term)?;
-                            return Ok(Some(exp.into()));
+                            return Ok(Some(exp));
                         }
                         // Read a hex int
                         "x" => {
                             let exp = self.read_num_radix(buffer, 16, &read_table_term)?;
-                            return Ok(Some(exp.into()));
+                            return Ok(Some(exp));
                         }
                         // Read a binary int
                         "b" => {
                             let exp = self.read_num_radix(buffer, 2, &read_table_term)?;
-                            return Ok(Some(exp.into()));
+                            return Ok(Some(exp));
                         }
                         ";" => {
                             match self.read_inner(buffer, in_back_quote, ReadReturn::None) {
@@ -1149,11 +1249,11 @@ impl<'vm> Reader<'vm> {
                     buffer.push_str(&ch);
                     let is_number = self.read_symbol(buffer, false, false, &read_table_term);
                     if is_number {
-                        return Ok(Some(self.do_atom(buffer, is_number)));
-                    } else if let Some(get) = self.parse_get(buffer) {
+                        return Ok(Some(self.do_atom(buffer, is_number)?));
+                    } else if let Some(get) = self.parse_get(buffer)? {
                         return Ok(Some(get));
                     } else {
-                        return Ok(Some(self.do_atom(buffer, is_number)));
+                        return Ok(Some(self.do_atom(buffer, is_number)?));
                     }
                 }
             }
@@ -1230,7 +1330,7 @@ mod tests {
         let res = if exps.len() == 1 {
             Ok(exps[0])
         } else {
-            Ok(vm.alloc_vector_ro(exps))
+            vm.alloc_vector_ro(exps).map_err(ReadError::from)
         };
         vm.unpause_gc();
         res
@@ -1263,7 +1363,7 @@ mod tests {
         for exp in read_iter {
             token_exps.push(exp.unwrap());
         }
-        let val = vm.alloc_vector_ro(token_exps);
+        let val = vm.alloc_vector_ro(token_exps).unwrap();
         to_strs(vm, &mut tokens, val);
         tokens
     }
@@ -1495,6 +1595,25 @@ mod tests {
         assert!(tokens[10] == "nil");
         assert!(tokens[11] == ")");
 
+        let tokens = tokenize(&mut vm, "#{one 2 #{}}");
+        assert!(tokens.len() == 8);
+        assert!(tokens[0] == "(");
+        assert!(tokens[1] == "Symbol:make-set");
+        assert!(tokens[2] == "Symbol:one");
+        assert!(tokens[3] == "Int:2");
+        assert!(tokens[4] == "(");
+        assert!(tokens[5] == "Symbol:make-set");
+        assert!(tokens[6] == ")");
+        assert!(tokens[7] == ")");
+
+        let tokens = tokenize(&mut vm, "#u8(1 #xff)");
+        assert!(tokens.len() == 5);
+        assert!(tokens[0] == "(");
+        assert!(tokens[1] == "Symbol:byte-vector");
+        assert!(tokens[2] == "Int:1");
+        assert!(tokens[3] == "Int:255");
+        assert!(tokens[4] == ")");
+
         let tokens = tokenize(&mut vm, "one 2 3.0 \"four\" \\B #t nil 3.5 ()");
         assert!(tokens.len() == 11);
         assert!(tokens[0] == "[");
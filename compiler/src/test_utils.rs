@@ -13,7 +13,7 @@ pub fn read_test(vm: &mut SloshVm, text: &'static str) -> Value {
     let res = if exps.len() == 1 {
         exps[0]
     } else {
-        vm.alloc_vector_ro(exps)
+        vm.alloc_vector_ro(exps).unwrap()
     };
     vm.unpause_gc();
     // Make sure we don't GC this stuff.  Don't bother with unsticky since we are testing (not the GC).
@@ -33,10 +33,10 @@ pub fn exec(env: &mut SloshVm, input: &'static str) -> Value {
         }
         state.chunk.encode0(RET, Some(1)).unwrap();
         let chunk = Arc::new(state.chunk);
-        env.unpause_gc();
-        let c_alloc = env.alloc_lambda(chunk.clone());
+        let c_alloc = env.alloc_lambda(chunk.clone()).unwrap();
         // Keep chunk from getting GCed...
         env.set_named_global("#<remember-me>", c_alloc);
+        env.unpause_gc();
         env.execute(chunk).unwrap();
     } else {
         env.pause_gc();
@@ -44,10 +44,10 @@ pub fn exec(env: &mut SloshVm, input: &'static str) -> Value {
         compile(env, &mut state, exp, 0).unwrap();
         state.chunk.encode0(RET, Some(1)).unwrap();
         let chunk = Arc::new(state.chunk);
-        env.unpause_gc();
-        let c_alloc = env.alloc_lambda(chunk.clone());
+        let c_alloc = env.alloc_lambda(chunk.clone()).unwrap();
         // Keep chunk from getting GCed...
         env.set_named_global("#<remember-me>", c_alloc);
+        env.unpause_gc();
         env.execute(chunk).unwrap();
     }
     env.stack(0).unref(env)
@@ -139,15 +139,20 @@ impl ShellCompleter {
             // XXX TODO- namespace this when we have namespaces.
             let i_val = env.intern("__completion_hook");
             if let Some(idx) = env.global_intern_slot(i_val) {
-                let mut v: Vec<Value> = self
-                    .args
-                    .drain(..)
-                    .map(|a| {
-                        let val = env.alloc_string(a);
-                        env.heap_sticky(val);
-                        val
-                    })
-                    .collect();
+                let mut v: Vec<Value> = Vec::with_capacity(self.args.len());
+                for a in self.args.drain(..) {
+                    match env.alloc_string(a) {
+                        Ok(val) => {
+                            env.heap_sticky(val);
+                            v.push(val);
+                        }
+                        Err(e) => {
+                            eprintln!("Error calling completion hook: {e}");
+                            v.drain(..).for_each(|val| env.heap_unsticky(val));
+                            return HookResult::Default;
+                        }
+                    }
+                }
                 let res = match env.get_global(idx) {
                     Value::Lambda(h) => {
                         let l = env.get_lambda(h);
@@ -5,6 +5,10 @@ pub struct Config {
     pub command: Option<String>,
     pub script: Option<String>,
     pub args: Vec<String>,
+    /// Image to start from instead of running init.slosh.
+    pub image: Option<String>,
+    /// Save an image here after init.slosh (and the script if any) has run then exit.
+    pub save_image: Option<String>,
 }
 
 pub const VERSION_STRING: &str = env!("VERSION_STRING");
@@ -19,7 +23,11 @@ FLAGS:
     -h, --help     Print help (this) and exit.
 
 OPTIONS:
-    -c             Command to run instead of entering the REPL.
+    -c                        Command to run instead of entering the REPL.
+    -i, --image <file>        Start from an image saved with --save-image instead of running
+                              ~/.config/slosh/init.slosh.
+    --save-image <file>       Run ~/.config/slosh/init.slosh (or start from --image) and the
+                              script if one is given, save an image of the result to file and exit.
 
 ARGS:
     <args>...      Script to run with arguments."#;
@@ -45,6 +53,8 @@ fn get_arg(exe_name: &str, args: &mut Vec<OsString>) -> Option<String> {
 pub fn get_config() -> Option<Config> {
     let mut command: Option<String> = None;
     let mut script: Option<String> = None;
+    let mut image: Option<String> = None;
+    let mut save_image: Option<String> = None;
     let mut command_args: Vec<String> = Vec::new();
 
     let mut args: Vec<OsString> = env::args_os().collect();
@@ -63,6 +73,20 @@ pub fn get_config() -> Option<Config> {
                         }
                         command = Some(get_arg(&exe_name, &mut args)?);
                     }
+                    "-i" | "--image" => {
+                        if image.is_some() {
+                            help(&exe_name);
+                            return None;
+                        }
+                        image = Some(get_arg(&exe_name, &mut args)?);
+                    }
+                    "--save-image" => {
+                        if save_image.is_some() {
+                            help(&exe_name);
+                            return None;
+                        }
+                        save_image = Some(get_arg(&exe_name, &mut args)?);
+                    }
                     "-v" | "--version" => {
                         version();
                         return None;
@@ -89,5 +113,7 @@ pub fn get_config() -> Option<Config> {
         command,
         script,
         args: command_args,
+        image,
+        save_image,
     })
 }
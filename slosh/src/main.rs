@@ -3,8 +3,8 @@ extern crate sl_liner;
 use std::cell::RefCell;
 use std::env;
 use std::ffi::OsString;
-use std::fs::create_dir_all;
-use std::io::{BufRead, ErrorKind};
+use std::fs::{create_dir_all, File};
+use std::io::{BufRead, BufReader, ErrorKind};
 use std::sync::Arc;
 
 use slvm::opcodes::*;
@@ -16,6 +16,7 @@ use sl_compiler::reader::*;
 use builtins::add_misc_builtins;
 use builtins::collections::setup_collection_builtins;
 use builtins::conversions::add_conv_builtins;
+use builtins::debug::display_backtrace;
 use builtins::io::add_io_builtins;
 use builtins::print::{add_print_builtins, display_value};
 use builtins::string::add_str_builtins;
@@ -37,7 +38,7 @@ use config::*;
 use debug::*;
 use shell::platform::{Platform, Sys, STDIN_FILENO};
 use sl_compiler::pass1::pass1;
-use slvm::{Value, INT_BITS, INT_MAX, INT_MIN};
+use slvm::{VMResult, Value, INT_BITS, INT_MAX, INT_MIN};
 
 thread_local! {
     /// Env (job control status, etc) for the shell.
@@ -141,7 +142,10 @@ fn get_color_closure() -> Option<ColorClosure> {
                     ENV.with(|renv| {
                         let mut env = renv.borrow_mut();
                         let line_handler = env.get_lambda(h);
-                        let param = env.alloc_string(input.to_string());
+                        let param = match env.alloc_string(input.to_string()) {
+                            Ok(param) => param,
+                            Err(e) => return format!("ERROR {e}"),
+                        };
                         env.heap_sticky(param);
                         let res = match env.do_call(line_handler, &[param], None) {
                             Ok(v) => match v {
@@ -162,7 +166,10 @@ fn get_color_closure() -> Option<ColorClosure> {
                         let mut env = renv.borrow_mut();
                         let (line_handler, tcaps) = env.get_closure(h);
                         let caps = Vec::from(tcaps);
-                        let param = env.alloc_string(input.to_string());
+                        let param = match env.alloc_string(input.to_string()) {
+                            Ok(param) => param,
+                            Err(e) => return format!("ERROR {e}"),
+                        };
                         env.heap_sticky(param);
                         let res = match env.do_call(line_handler, &[param], Some(&caps[..])) {
                             Ok(v) => match v {
@@ -199,6 +206,13 @@ fn main() {
             add_io_builtins(&mut env);
             add_conv_builtins(&mut env);
             env.set_global_builtin("dump-regs", builtin_dump_regs);
+            // The image replaces the globals so has to be loaded before setting any.
+            if let Some(image) = &config.image {
+                if let Err(err) = load_image_file(&mut env, image) {
+                    eprintln!("ERROR: Unable to load image {image}: {err}");
+                    std::process::exit(1);
+                }
+            }
             let uid = Sys::current_uid();
             let euid = Sys::effective_uid();
             env::set_var("UID", format!("{uid}"));
@@ -216,8 +230,21 @@ fn main() {
                 env::set_var("PWD", dir);
             }
         });
-        if config.command.is_none() && config.script.is_none() {
-            load_sloshrc();
+        if let Some(image) = config.save_image {
+            if config.image.is_none() {
+                load_sloshrc();
+            }
+            if let Some(script) = config.script {
+                load_script(&script);
+            }
+            if let Err(err) = ENV.with(|env| save_image_file(&mut env.borrow_mut(), &image)) {
+                eprintln!("ERROR: Unable to save image {image}: {err}");
+                std::process::exit(1);
+            }
+        } else if config.command.is_none() && config.script.is_none() {
+            if config.image.is_none() {
+                load_sloshrc();
+            }
             if Sys::is_tty(STDIN_FILENO) {
                 let mut con = Context::new();
                 //con.set_completer(Box::new(FilenameCompleter::new(Some("."))));
@@ -361,20 +388,42 @@ fn main() {
             });
             std::process::exit(status);
         } else if let Some(script) = config.script {
-            load_sloshrc();
-            ENV.with(|renv| {
-                let mut env = renv.borrow_mut();
-                let script = env.intern(&script);
-                let script = env.get_interned(script);
-                match load_internal(&mut env, script) {
-                    Ok(_) => {}
-                    Err(err) => println!("ERROR: {err}"),
-                }
-            });
+            if config.image.is_none() {
+                load_sloshrc();
+            }
+            load_script(&script);
         }
     }
 }
 
+fn load_script(script: &str) {
+    ENV.with(|renv| {
+        let mut env = renv.borrow_mut();
+        let script = env.intern(script);
+        let script = env.get_interned(script);
+        match load_internal(&mut env, script) {
+            Ok(_) => {}
+            Err(err) => println!("ERROR: {err}"),
+        }
+    });
+}
+
+/// Replace the VM state with an image saved by save_image_file, env must have the same builtins
+/// as the VM that saved it.
+fn load_image_file(env: &mut SloshVm, path: &str) -> VMResult<()> {
+    let mut input = BufReader::new(File::open(path)?);
+    env.load_image(&mut input)
+}
+
+/// Save an image of the VM state (globals, heap and compiler state) to path.  The image is built
+/// in memory first so a failure (a live host object for instance) does not leave a broken file.
+fn save_image_file(env: &mut SloshVm, path: &str) -> VMResult<()> {
+    let mut out = Vec::new();
+    env.save_image(&mut out)?;
+    std::fs::write(path, out)?;
+    Ok(())
+}
+
 fn exec_expression(res: String, env: &mut SloshVm) {
     let reader = Reader::from_string(res, env, "", 1, 0);
     let exps: Result<Vec<Value>, ReadError> = reader.collect();
@@ -408,6 +457,9 @@ fn exec_expression(res: String, env: &mut SloshVm) {
                     }
                     Err(err) => {
                         eprintln!("ERROR: {}", err.display(env));
+                        if let Some(backtrace) = &err.backtrace {
+                            eprint!("{}", display_backtrace(env, backtrace));
+                        }
                         if let Some(err_frame) = env.err_frame() {
                             let line = err_frame.current_line().unwrap_or(0);
                             eprintln!(
@@ -78,7 +78,7 @@ fn sh_str(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
         let line = line?;
         val.push_str(line.trim());
     }
-    Ok(vm.alloc_string(val))
+    vm.alloc_string(val)
 }
 
 fn env_var(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
@@ -94,8 +94,8 @@ fn env_var(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
             }
         };
         match env::var(name) {
-            Ok(val) => Ok(vm.alloc_string(val)),
-            Err(VarError::NotPresent) => Ok(vm.alloc_string("".to_string())),
+            Ok(val) => vm.alloc_string(val),
+            Err(VarError::NotPresent) => vm.alloc_string("".to_string()),
             Err(err) => Err(VMError::new_compile(format!(
                 "env: error finding env var {name}: {err}"
             ))),
@@ -111,7 +111,7 @@ fn version(vm: &mut SloshVm, registers: &[Value]) -> VMResult<Value> {
     if !registers.is_empty() {
         return Err(VMError::new_compile("version: requires no argument"));
     }
-    Ok(vm.alloc_string(VERSION_STRING.to_string()))
+    vm.alloc_string(VERSION_STRING.to_string())
 }
 
 pub fn add_shell_builtins(env: &mut SloshVm) {
@@ -3,8 +3,10 @@ use std::cmp::Ordering;
 use crate::opcodes::*;
 use crate::{Interned, VMError, VMResult, Value};
 
+pub mod bytecode;
 #[macro_use]
 pub mod disassemble;
+pub mod verify;
 
 #[derive(Clone, Debug)]
 pub struct Chunk {
@@ -101,6 +103,11 @@ impl Chunk {
         }
     }
 
+    /// Line this chunk starts on.
+    pub fn start_line(&self) -> u32 {
+        self.start_line
+    }
+
     pub fn offset_to_line(&self, offset: usize) -> Option<u32> {
         let mut line = self.start_line;
         let mut current: usize = 0;
@@ -247,6 +254,20 @@ impl Chunk {
     }
 
     pub fn encode_refi(&mut self, reg: u16, global: u32, line_number: Option<u32>) -> VMResult<()> {
+        self.encode_reg_global(REFI, reg, global, line_number)
+    }
+
+    pub fn encode_bind(&mut self, reg: u16, global: u32, line_number: Option<u32>) -> VMResult<()> {
+        self.encode_reg_global(BIND, reg, global, line_number)
+    }
+
+    fn encode_reg_global(
+        &mut self,
+        op_code: OpCode,
+        reg: u16,
+        global: u32,
+        line_number: Option<u32>,
+    ) -> VMResult<()> {
         let mut bytes: u8 = 4;
         let mut wide = false;
         if reg > u8::MAX as u16 || global > u16::MAX as u32 {
@@ -257,7 +278,7 @@ impl Chunk {
         }
 
         self.encode_line_number(bytes, line_number)?;
-        self.code.push(REFI);
+        self.code.push(op_code);
         self.encode_operand(reg, wide);
         if wide {
             self.code.push(((global & 0xFF00_0000) >> 24) as u8);
@@ -0,0 +1,977 @@
+//! Binary (on disk) format for compiled chunks.
+//!
+//! The file starts with the magic bytes "SLBC" and a version number followed by the top level
+//! chunk.  All integers are big endian.  Interned values (symbols, keywords, etc) are written as
+//! strings and re-interned on load so a file can be loaded into a different VM than the one that
+//! compiled it.  Global slots are also VM specific so every instruction that references a global
+//! gets a relocation entry (code offset and global name) that is resolved when loading.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use crate::opcodes::*;
+use crate::{
+    BigInt, Chunk, GVm, Interned, Interner, PMap, PVec, VMError, VMMap, VMResult, VMSet, Value,
+};
+
+/// Magic bytes at the start of a bytecode file.
+pub const BYTECODE_MAGIC: &[u8; 4] = b"SLBC";
+/// Current version of the bytecode format, bump on any incompatible change.
+pub const BYTECODE_VERSION: u16 = 2;
+
+// Tags for serialized values.
+const TAG_UNDEFINED: u8 = 0;
+const TAG_NIL: u8 = 1;
+const TAG_TRUE: u8 = 2;
+const TAG_FALSE: u8 = 3;
+const TAG_BYTE: u8 = 4;
+const TAG_INT: u8 = 5;
+const TAG_FLOAT: u8 = 6;
+const TAG_CODEPOINT: u8 = 7;
+const TAG_CHAR_CLUSTER: u8 = 8;
+const TAG_CHAR_CLUSTER_LONG: u8 = 9;
+const TAG_SYMBOL: u8 = 10;
+const TAG_KEYWORD: u8 = 11;
+const TAG_STRING_CONST: u8 = 12;
+const TAG_SPECIAL: u8 = 13;
+const TAG_BUILTIN: u8 = 14;
+const TAG_STRING: u8 = 15;
+const TAG_VECTOR: u8 = 16;
+const TAG_MAP: u8 = 17;
+const TAG_BYTES: u8 = 18;
+const TAG_PAIR: u8 = 19;
+const TAG_LIST: u8 = 20;
+const TAG_LAMBDA: u8 = 21;
+// Only used by the raw (image) encoding.
+const TAG_CLOSURE: u8 = 22;
+const TAG_CONTINUATION: u8 = 23;
+const TAG_CALLFRAME: u8 = 24;
+const TAG_VALUE: u8 = 25;
+const TAG_ERROR: u8 = 26;
+const TAG_BIGINT: u8 = 27;
+const TAG_WEAK: u8 = 28;
+const TAG_HOST: u8 = 29;
+// Written like TAG_MAP (in key order), read back as a sorted map.
+const TAG_SORTED_MAP: u8 = 30;
+const TAG_PVEC: u8 = 31;
+const TAG_PMAP: u8 = 32;
+const TAG_SET: u8 = 33;
+
+pub(crate) fn write_u8<W: Write>(out: &mut W, val: u8) -> VMResult<()> {
+    out.write_all(&[val])?;
+    Ok(())
+}
+
+pub(crate) fn write_u16<W: Write>(out: &mut W, val: u16) -> VMResult<()> {
+    out.write_all(&val.to_be_bytes())?;
+    Ok(())
+}
+
+pub(crate) fn write_u32<W: Write>(out: &mut W, val: u32) -> VMResult<()> {
+    out.write_all(&val.to_be_bytes())?;
+    Ok(())
+}
+
+pub(crate) fn write_u64<W: Write>(out: &mut W, val: u64) -> VMResult<()> {
+    out.write_all(&val.to_be_bytes())?;
+    Ok(())
+}
+
+pub(crate) fn write_len<W: Write>(out: &mut W, len: usize) -> VMResult<()> {
+    let len: u32 = len
+        .try_into()
+        .map_err(|_| VMError::new_chunk("Bytecode: length to large to save."))?;
+    write_u32(out, len)
+}
+
+pub(crate) fn write_bytes<W: Write>(out: &mut W, bytes: &[u8]) -> VMResult<()> {
+    write_len(out, bytes.len())?;
+    out.write_all(bytes)?;
+    Ok(())
+}
+
+pub(crate) fn write_str<W: Write>(out: &mut W, s: &str) -> VMResult<()> {
+    write_bytes(out, s.as_bytes())
+}
+
+pub(crate) fn read_u8<R: Read>(input: &mut R) -> VMResult<u8> {
+    let mut buf = [0_u8; 1];
+    input.read_exact(&mut buf)?;
+    Ok(buf[0])
+}
+
+pub(crate) fn read_u16<R: Read>(input: &mut R) -> VMResult<u16> {
+    let mut buf = [0_u8; 2];
+    input.read_exact(&mut buf)?;
+    Ok(u16::from_be_bytes(buf))
+}
+
+pub(crate) fn read_u32<R: Read>(input: &mut R) -> VMResult<u32> {
+    let mut buf = [0_u8; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_be_bytes(buf))
+}
+
+pub(crate) fn read_u64<R: Read>(input: &mut R) -> VMResult<u64> {
+    let mut buf = [0_u8; 8];
+    input.read_exact(&mut buf)?;
+    Ok(u64::from_be_bytes(buf))
+}
+
+pub(crate) fn read_bytes<R: Read>(input: &mut R) -> VMResult<Vec<u8>> {
+    let len = read_u32(input)? as usize;
+    let mut bytes = Vec::new();
+    input.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(VMError::new_chunk("Bytecode: unexpected end of file."));
+    }
+    Ok(bytes)
+}
+
+pub(crate) fn read_string<R: Read>(input: &mut R) -> VMResult<String> {
+    String::from_utf8(read_bytes(input)?)
+        .map_err(|_| VMError::new_chunk("Bytecode: invalid UTF8 string."))
+}
+
+fn read_interned<ENV, R: Read>(vm: &mut GVm<ENV>, input: &mut R) -> VMResult<Interned> {
+    let s = read_string(input)?;
+    Ok(vm.intern(&s))
+}
+
+/// Find every global slot operand in code, returns (offset, wide) for each.
+fn global_operands(code: &[u8]) -> VMResult<Vec<(usize, bool)>> {
+    let mut globals = Vec::new();
+    let mut ip = 0;
+    let mut wide = false;
+    while ip < code.len() {
+        let op = code[ip];
+        ip += 1;
+        if op == WIDE {
+            wide = true;
+            continue;
+        }
+        let operands = op_operands(op)
+            .ok_or_else(|| VMError::new_chunk(format!("Bytecode: invalid op code {op:#04x}.")))?;
+        for operand in operands {
+            if *operand == Operand::Global {
+                globals.push((ip, wide));
+            }
+            ip += operand.size(wide);
+        }
+        wide = false;
+    }
+    if ip > code.len() {
+        return Err(VMError::new_chunk("Bytecode: truncated instruction."));
+    }
+    Ok(globals)
+}
+
+fn decode_global(code: &[u8], offset: usize, wide: bool) -> u32 {
+    if wide {
+        u32::from_be_bytes([
+            code[offset],
+            code[offset + 1],
+            code[offset + 2],
+            code[offset + 3],
+        ])
+    } else {
+        u16::from_be_bytes([code[offset], code[offset + 1]]) as u32
+    }
+}
+
+fn patch_global(code: &mut [u8], offset: usize, wide: bool, slot: u32) -> VMResult<()> {
+    if wide {
+        code[offset..offset + 4].copy_from_slice(&slot.to_be_bytes());
+    } else {
+        let slot: u16 = slot.try_into().map_err(|_| {
+            VMError::new_chunk(format!(
+                "Bytecode: global slot {slot} does not fit a narrow instruction."
+            ))
+        })?;
+        code[offset..offset + 2].copy_from_slice(&slot.to_be_bytes());
+    }
+    Ok(())
+}
+
+impl Chunk {
+    /// Write this chunk (and any lambdas in its constants) to out in the binary bytecode format.
+    /// global_name is used to map global slots referenced by the code to names, slots it does
+    /// not name are saved as is (only valid if loaded into a VM with the same globals).
+    /// Note: builtins are saved by index, so they are only valid in a VM that adds the same
+    /// builtins in the same order.
+    pub fn write_bytecode<ENV, W, G>(
+        &self,
+        vm: &GVm<ENV>,
+        global_name: G,
+        out: &mut W,
+    ) -> VMResult<()>
+    where
+        W: Write,
+        G: Fn(u32) -> Option<Interned>,
+    {
+        out.write_all(BYTECODE_MAGIC)?;
+        write_u16(out, BYTECODE_VERSION)?;
+        self.write_chunk(vm, &global_name, out)
+    }
+
+    /// Read a chunk saved with write_bytecode.  global_slot will be called with the name of each
+    /// global referenced by the code and should return its slot in vm (reserving it if needed).
+    /// Heap constants are allocated read only, GC is paused while loading but the caller is
+    /// responsible for keeping the result alive (for instance by putting it in a lambda).
+    /// The chunk and its lambdas are verified (see GVm::verify_chunk) before being returned.
+    pub fn read_bytecode<ENV, R, G>(
+        vm: &mut GVm<ENV>,
+        mut global_slot: G,
+        input: &mut R,
+    ) -> VMResult<Chunk>
+    where
+        R: Read,
+        G: FnMut(&mut GVm<ENV>, Interned) -> u32,
+    {
+        let mut magic = [0_u8; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != BYTECODE_MAGIC {
+            return Err(VMError::new_chunk("Bytecode: not a bytecode file."));
+        }
+        let version = read_u16(input)?;
+        if version != BYTECODE_VERSION {
+            return Err(VMError::new_chunk(format!(
+                "Bytecode: unsupported version {version}, expected {BYTECODE_VERSION}."
+            )));
+        }
+        vm.pause_gc();
+        let res = Chunk::read_chunk(vm, &mut global_slot, input).and_then(|chunk| {
+            vm.verify_chunk(&chunk)?;
+            Ok(chunk)
+        });
+        vm.unpause_gc();
+        res
+    }
+
+    fn write_chunk<ENV, W, G>(&self, vm: &GVm<ENV>, global_name: &G, out: &mut W) -> VMResult<()>
+    where
+        W: Write,
+        G: Fn(u32) -> Option<Interned>,
+    {
+        write_str(out, self.file_name)?;
+        write_u32(out, self.start_line)?;
+        write_u32(out, self.last_line)?;
+        write_bytes(out, &self.line_numbers)?;
+        write_bytes(out, &self.code)?;
+        write_len(out, self.constants.len())?;
+        for constant in &self.constants {
+            write_value(vm, *constant, global_name, out)?;
+        }
+        write_len(out, self.jump_table.len())?;
+        for jump in &self.jump_table {
+            write_u32(out, *jump)?;
+        }
+        if let Some(captures) = &self.captures {
+            write_u8(out, 1)?;
+            write_len(out, captures.len())?;
+            for cap in captures {
+                write_u32(out, *cap)?;
+            }
+        } else {
+            write_u8(out, 0)?;
+        }
+        write_len(out, self.input_regs)?;
+        write_len(out, self.extra_regs)?;
+        write_u16(out, self.args)?;
+        write_u16(out, self.opt_args)?;
+        write_u8(out, if self.rest { 1 } else { 0 })?;
+        if let Some(dbg_args) = &self.dbg_args {
+            write_u8(out, 1)?;
+            write_len(out, dbg_args.len())?;
+            for arg in dbg_args {
+                write_str(out, vm.get_interned(*arg))?;
+            }
+        } else {
+            write_u8(out, 0)?;
+        }
+        let mut relocs = Vec::new();
+        for (offset, wide) in global_operands(&self.code)? {
+            if let Some(name) = global_name(decode_global(&self.code, offset, wide)) {
+                relocs.push((offset, wide, name));
+            }
+        }
+        write_len(out, relocs.len())?;
+        for (offset, wide, name) in relocs {
+            write_len(out, offset)?;
+            write_u8(out, if wide { 1 } else { 0 })?;
+            write_str(out, vm.get_interned(name))?;
+        }
+        Ok(())
+    }
+
+    fn read_chunk<ENV, R, G>(
+        vm: &mut GVm<ENV>,
+        global_slot: &mut G,
+        input: &mut R,
+    ) -> VMResult<Chunk>
+    where
+        R: Read,
+        G: FnMut(&mut GVm<ENV>, Interned) -> u32,
+    {
+        let file_name = read_interned(vm, input)?;
+        let file_name = vm.get_interned(file_name);
+        let mut chunk = Chunk::new(file_name, read_u32(input)?);
+        chunk.last_line = read_u32(input)?;
+        chunk.line_numbers = read_bytes(input)?;
+        chunk.code = read_bytes(input)?;
+        let num_constants = read_u32(input)?;
+        for _ in 0..num_constants {
+            let constant = read_value(vm, global_slot, input)?;
+            chunk.constants.push(constant);
+        }
+        let num_jumps = read_u32(input)?;
+        for _ in 0..num_jumps {
+            chunk.jump_table.push(read_u32(input)?);
+        }
+        if read_u8(input)? != 0 {
+            let num_captures = read_u32(input)?;
+            let mut captures = Vec::new();
+            for _ in 0..num_captures {
+                captures.push(read_u32(input)?);
+            }
+            chunk.captures = Some(captures);
+        }
+        chunk.input_regs = read_u32(input)? as usize;
+        chunk.extra_regs = read_u32(input)? as usize;
+        chunk.args = read_u16(input)?;
+        chunk.opt_args = read_u16(input)?;
+        chunk.rest = read_u8(input)? != 0;
+        if read_u8(input)? != 0 {
+            let num_args = read_u32(input)?;
+            let mut dbg_args = Vec::new();
+            for _ in 0..num_args {
+                dbg_args.push(read_interned(vm, input)?);
+            }
+            chunk.dbg_args = Some(dbg_args);
+        }
+        let valid_globals: HashMap<usize, bool> =
+            global_operands(&chunk.code)?.into_iter().collect();
+        let num_relocs = read_u32(input)?;
+        for _ in 0..num_relocs {
+            let offset = read_u32(input)? as usize;
+            let wide = read_u8(input)? != 0;
+            let name = read_interned(vm, input)?;
+            if valid_globals.get(&offset) != Some(&wide) {
+                return Err(VMError::new_chunk(format!(
+                    "Bytecode: invalid global relocation at offset {offset}."
+                )));
+            }
+            let slot = global_slot(vm, name);
+            patch_global(&mut chunk.code, offset, wide, slot)?;
+        }
+        Ok(chunk)
+    }
+}
+
+fn write_value<ENV, W, G>(vm: &GVm<ENV>, val: Value, global_name: &G, out: &mut W) -> VMResult<()>
+where
+    W: Write,
+    G: Fn(u32) -> Option<Interned>,
+{
+    match val {
+        Value::Undefined => write_u8(out, TAG_UNDEFINED),
+        Value::Nil => write_u8(out, TAG_NIL),
+        Value::True => write_u8(out, TAG_TRUE),
+        Value::False => write_u8(out, TAG_FALSE),
+        Value::Byte(b) => {
+            write_u8(out, TAG_BYTE)?;
+            write_u8(out, b)
+        }
+        Value::Int(i) => {
+            write_u8(out, TAG_INT)?;
+            out.write_all(&i)?;
+            Ok(())
+        }
+        Value::Float(f) => {
+            write_u8(out, TAG_FLOAT)?;
+            write_u64(out, f.get().to_bits())
+        }
+        Value::CodePoint(ch) => {
+            write_u8(out, TAG_CODEPOINT)?;
+            write_u32(out, ch as u32)
+        }
+        Value::CharCluster(len, chars) => {
+            write_u8(out, TAG_CHAR_CLUSTER)?;
+            write_u8(out, len)?;
+            out.write_all(&chars)?;
+            Ok(())
+        }
+        Value::CharClusterLong(handle) => {
+            write_u8(out, TAG_CHAR_CLUSTER_LONG)?;
+            write_str(out, vm.get_string(handle))
+        }
+        Value::Symbol(i) => {
+            write_u8(out, TAG_SYMBOL)?;
+            write_str(out, vm.interned_name(i))
+        }
+        Value::Keyword(i) => {
+            write_u8(out, TAG_KEYWORD)?;
+            write_str(out, vm.interned_name(i))
+        }
+        Value::StringConst(i) => {
+            write_u8(out, TAG_STRING_CONST)?;
+            write_str(out, vm.get_interned(i))
+        }
+        Value::Special(i) => {
+            write_u8(out, TAG_SPECIAL)?;
+            write_str(out, vm.get_interned(i))
+        }
+        Value::Builtin(idx) => {
+            write_u8(out, TAG_BUILTIN)?;
+            write_u32(out, idx)
+        }
+        Value::String(handle) => {
+            write_u8(out, TAG_STRING)?;
+            write_str(out, vm.get_string(handle))
+        }
+        Value::Vector(handle) => {
+            write_u8(out, TAG_VECTOR)?;
+            let v = vm.get_vector(handle);
+            write_len(out, v.len())?;
+            for val in v {
+                write_value(vm, *val, global_name, out)?;
+            }
+            Ok(())
+        }
+        Value::Map(handle) => {
+            let map = vm.get_map(handle);
+            write_u8(
+                out,
+                if map.is_sorted() {
+                    TAG_SORTED_MAP
+                } else {
+                    TAG_MAP
+                },
+            )?;
+            write_len(out, map.len())?;
+            for (key, val) in map {
+                write_value(vm, *key, global_name, out)?;
+                write_value(vm, *val, global_name, out)?;
+            }
+            Ok(())
+        }
+        Value::Set(handle) => {
+            write_u8(out, TAG_SET)?;
+            let set = vm.get_set(handle);
+            write_len(out, set.len())?;
+            for item in set.iter() {
+                write_value(vm, *item, global_name, out)?;
+            }
+            Ok(())
+        }
+        Value::PVec(handle) => {
+            write_u8(out, TAG_PVEC)?;
+            let v = vm.get_pvec(handle);
+            write_len(out, v.len())?;
+            for val in v {
+                write_value(vm, *val, global_name, out)?;
+            }
+            Ok(())
+        }
+        Value::PMap(handle) => {
+            write_u8(out, TAG_PMAP)?;
+            let map = vm.get_pmap(handle);
+            write_len(out, map.len())?;
+            for (key, val) in map {
+                write_value(vm, *key, global_name, out)?;
+                write_value(vm, *val, global_name, out)?;
+            }
+            Ok(())
+        }
+        Value::Bytes(handle) => {
+            write_u8(out, TAG_BYTES)?;
+            write_bytes(out, vm.get_bytes(handle))
+        }
+        Value::Pair(handle) => {
+            write_u8(out, TAG_PAIR)?;
+            let (car, cdr) = vm.get_pair(handle);
+            write_value(vm, car, global_name, out)?;
+            write_value(vm, cdr, global_name, out)
+        }
+        Value::List(handle, start) => {
+            write_u8(out, TAG_LIST)?;
+            let v = &vm.get_vector(handle)[start as usize..];
+            write_len(out, v.len())?;
+            for val in v {
+                write_value(vm, *val, global_name, out)?;
+            }
+            Ok(())
+        }
+        Value::Lambda(handle) => {
+            write_u8(out, TAG_LAMBDA)?;
+            vm.get_lambda(handle).write_chunk(vm, global_name, out)
+        }
+        Value::BigInt(handle) => {
+            write_u8(out, TAG_BIGINT)?;
+            write_bytes(out, &vm.get_bigint(handle).to_signed_bytes_be())
+        }
+        Value::Closure(_)
+        | Value::Continuation(_)
+        | Value::CallFrame(_)
+        | Value::Value(_)
+        | Value::Error(_)
+        | Value::Weak(_)
+        | Value::Host(_) => Err(VMError::new_chunk(format!(
+            "Bytecode: can not save a {} constant.",
+            val.display_type(vm)
+        ))),
+    }
+}
+
+fn read_value<ENV, R, G>(vm: &mut GVm<ENV>, global_slot: &mut G, input: &mut R) -> VMResult<Value>
+where
+    R: Read,
+    G: FnMut(&mut GVm<ENV>, Interned) -> u32,
+{
+    let tag = read_u8(input)?;
+    Ok(match tag {
+        TAG_UNDEFINED => Value::Undefined,
+        TAG_NIL => Value::Nil,
+        TAG_TRUE => Value::True,
+        TAG_FALSE => Value::False,
+        TAG_BYTE => Value::Byte(read_u8(input)?),
+        TAG_INT => {
+            let mut i = [0_u8; 7];
+            input.read_exact(&mut i)?;
+            Value::Int(i)
+        }
+        TAG_FLOAT => f64::from_bits(read_u64(input)?).into(),
+        TAG_CODEPOINT => Value::CodePoint(
+            char::from_u32(read_u32(input)?)
+                .ok_or_else(|| VMError::new_chunk("Bytecode: invalid code point."))?,
+        ),
+        TAG_CHAR_CLUSTER => {
+            let len = read_u8(input)?;
+            let mut chars = [0_u8; 6];
+            input.read_exact(&mut chars)?;
+            Value::CharCluster(len, chars)
+        }
+        TAG_CHAR_CLUSTER_LONG => {
+            let s = read_string(input)?;
+            vm.alloc_char(&s)?
+        }
+        TAG_SYMBOL => Value::Symbol(read_interned(vm, input)?),
+        TAG_KEYWORD => Value::Keyword(read_interned(vm, input)?),
+        TAG_STRING_CONST => Value::StringConst(read_interned(vm, input)?),
+        TAG_SPECIAL => Value::Special(read_interned(vm, input)?),
+        TAG_BUILTIN => Value::Builtin(read_u32(input)?),
+        TAG_STRING => {
+            let s = read_string(input)?;
+            vm.alloc_string_ro(s)?
+        }
+        TAG_VECTOR => {
+            let len = read_u32(input)?;
+            let mut v = Vec::new();
+            for _ in 0..len {
+                v.push(read_value(vm, global_slot, input)?);
+            }
+            vm.alloc_vector_ro(v)?
+        }
+        TAG_MAP | TAG_SORTED_MAP => {
+            let len = read_u32(input)?;
+            let mut map = if tag == TAG_SORTED_MAP {
+                VMMap::new_sorted()
+            } else {
+                VMMap::new()
+            };
+            for _ in 0..len {
+                let key = read_value(vm, global_slot, input)?;
+                let val = read_value(vm, global_slot, input)?;
+                if map.is_sorted() {
+                    let sort_key = vm.sort_key(key);
+                    map.insert_sorted(key, sort_key, val);
+                } else {
+                    map.insert(key, val)?;
+                }
+            }
+            vm.alloc_map_ro(map)?
+        }
+        TAG_SET => {
+            let len = read_u32(input)?;
+            let mut set = VMSet::new();
+            for _ in 0..len {
+                set.insert(read_value(vm, global_slot, input)?);
+            }
+            vm.alloc_set_ro(set)?
+        }
+        TAG_PVEC => {
+            let len = read_u32(input)?;
+            let mut v = PVec::new();
+            for _ in 0..len {
+                v = v.push(read_value(vm, global_slot, input)?);
+            }
+            vm.alloc_pvec(v)?
+        }
+        TAG_PMAP => {
+            let len = read_u32(input)?;
+            let mut map = PMap::new();
+            for _ in 0..len {
+                let key = read_value(vm, global_slot, input)?;
+                let val = read_value(vm, global_slot, input)?;
+                map = map.insert(key, val);
+            }
+            vm.alloc_pmap(map)?
+        }
+        TAG_BYTES => {
+            let bytes = read_bytes(input)?;
+            let b = vm.alloc_bytes(bytes)?;
+            vm.heap_immutable(b);
+            b
+        }
+        TAG_PAIR => {
+            let car = read_value(vm, global_slot, input)?;
+            let cdr = read_value(vm, global_slot, input)?;
+            vm.alloc_pair_ro(car, cdr)?
+        }
+        TAG_LIST => {
+            let len = read_u32(input)?;
+            let mut v = Vec::new();
+            for _ in 0..len {
+                v.push(read_value(vm, global_slot, input)?);
+            }
+            vm.alloc_list_ro(v)?
+        }
+        TAG_BIGINT => vm.alloc_bigint(BigInt::from_signed_bytes_be(&read_bytes(input)?))?,
+        TAG_LAMBDA => {
+            let chunk = Chunk::read_chunk(vm, global_slot, input)?;
+            vm.alloc_lambda(Arc::new(chunk))?
+        }
+        _ => {
+            return Err(VMError::new_chunk(format!(
+                "Bytecode: invalid value tag {tag:#04x}."
+            )))
+        }
+    })
+}
+
+/// Write val in the raw format used by VM images.  Unlike write_value heap handles and interned
+/// ids are written as is, so this is only valid when the heap and interner are saved with it.
+pub(crate) fn write_raw_value<W: Write>(out: &mut W, val: Value) -> VMResult<()> {
+    match val {
+        Value::Undefined => write_u8(out, TAG_UNDEFINED),
+        Value::Nil => write_u8(out, TAG_NIL),
+        Value::True => write_u8(out, TAG_TRUE),
+        Value::False => write_u8(out, TAG_FALSE),
+        Value::Byte(b) => {
+            write_u8(out, TAG_BYTE)?;
+            write_u8(out, b)
+        }
+        Value::Int(i) => {
+            write_u8(out, TAG_INT)?;
+            out.write_all(&i)?;
+            Ok(())
+        }
+        Value::Float(f) => {
+            write_u8(out, TAG_FLOAT)?;
+            write_u64(out, f.get().to_bits())
+        }
+        Value::CodePoint(ch) => {
+            write_u8(out, TAG_CODEPOINT)?;
+            write_u32(out, ch as u32)
+        }
+        Value::CharCluster(len, chars) => {
+            write_u8(out, TAG_CHAR_CLUSTER)?;
+            write_u8(out, len)?;
+            out.write_all(&chars)?;
+            Ok(())
+        }
+        Value::Symbol(i) => write_raw_tagged(out, TAG_SYMBOL, i.id),
+        Value::Keyword(i) => write_raw_tagged(out, TAG_KEYWORD, i.id),
+        Value::StringConst(i) => write_raw_tagged(out, TAG_STRING_CONST, i.id),
+        Value::Special(i) => write_raw_tagged(out, TAG_SPECIAL, i.id),
+        Value::Builtin(idx) => write_raw_tagged(out, TAG_BUILTIN, idx),
+        Value::CharClusterLong(h) => write_raw_tagged(out, TAG_CHAR_CLUSTER_LONG, h.idx() as u32),
+        Value::String(h) => write_raw_tagged(out, TAG_STRING, h.idx() as u32),
+        Value::Vector(h) => write_raw_tagged(out, TAG_VECTOR, h.idx() as u32),
+        Value::Map(h) => write_raw_tagged(out, TAG_MAP, h.idx() as u32),
+        Value::Bytes(h) => write_raw_tagged(out, TAG_BYTES, h.idx() as u32),
+        Value::Pair(h) => write_raw_tagged(out, TAG_PAIR, h.idx() as u32),
+        Value::List(h, start) => {
+            write_raw_tagged(out, TAG_LIST, h.idx() as u32)?;
+            write_u16(out, start)
+        }
+        Value::Lambda(h) => write_raw_tagged(out, TAG_LAMBDA, h.idx() as u32),
+        Value::Closure(h) => write_raw_tagged(out, TAG_CLOSURE, h.idx() as u32),
+        Value::Continuation(h) => write_raw_tagged(out, TAG_CONTINUATION, h.idx() as u32),
+        Value::CallFrame(h) => write_raw_tagged(out, TAG_CALLFRAME, h.idx() as u32),
+        Value::Value(h) => write_raw_tagged(out, TAG_VALUE, h.idx() as u32),
+        Value::Error(h) => write_raw_tagged(out, TAG_ERROR, h.idx() as u32),
+        Value::BigInt(h) => write_raw_tagged(out, TAG_BIGINT, h.idx() as u32),
+        Value::Set(h) => write_raw_tagged(out, TAG_SET, h.idx() as u32),
+        Value::PVec(h) => write_raw_tagged(out, TAG_PVEC, h.idx() as u32),
+        Value::PMap(h) => write_raw_tagged(out, TAG_PMAP, h.idx() as u32),
+        Value::Weak(h) => write_raw_tagged(out, TAG_WEAK, h.idx() as u32),
+        Value::Host(h) => write_raw_tagged(out, TAG_HOST, h.idx() as u32),
+    }
+}
+
+fn write_raw_tagged<W: Write>(out: &mut W, tag: u8, val: u32) -> VMResult<()> {
+    write_u8(out, tag)?;
+    write_u32(out, val)
+}
+
+/// Read a value written with write_raw_value.
+pub(crate) fn read_raw_value<R: Read>(input: &mut R) -> VMResult<Value> {
+    let tag = read_u8(input)?;
+    Ok(match tag {
+        TAG_UNDEFINED => Value::Undefined,
+        TAG_NIL => Value::Nil,
+        TAG_TRUE => Value::True,
+        TAG_FALSE => Value::False,
+        TAG_BYTE => Value::Byte(read_u8(input)?),
+        TAG_INT => {
+            let mut i = [0_u8; 7];
+            input.read_exact(&mut i)?;
+            Value::Int(i)
+        }
+        TAG_FLOAT => f64::from_bits(read_u64(input)?).into(),
+        TAG_CODEPOINT => Value::CodePoint(
+            char::from_u32(read_u32(input)?)
+                .ok_or_else(|| VMError::new_chunk("Image: invalid code point."))?,
+        ),
+        TAG_CHAR_CLUSTER => {
+            let len = read_u8(input)?;
+            let mut chars = [0_u8; 6];
+            input.read_exact(&mut chars)?;
+            Value::CharCluster(len, chars)
+        }
+        TAG_SYMBOL => Value::Symbol(Interned {
+            id: read_u32(input)?,
+        }),
+        TAG_KEYWORD => Value::Keyword(Interned {
+            id: read_u32(input)?,
+        }),
+        TAG_STRING_CONST => Value::StringConst(Interned {
+            id: read_u32(input)?,
+        }),
+        TAG_SPECIAL => Value::Special(Interned {
+            id: read_u32(input)?,
+        }),
+        TAG_BUILTIN => Value::Builtin(read_u32(input)?),
+        TAG_CHAR_CLUSTER_LONG => Value::CharClusterLong(read_u32(input)?.into()),
+        TAG_STRING => Value::String(read_u32(input)?.into()),
+        TAG_VECTOR => Value::Vector(read_u32(input)?.into()),
+        TAG_MAP => Value::Map(read_u32(input)?.into()),
+        TAG_BYTES => Value::Bytes(read_u32(input)?.into()),
+        TAG_SET => Value::Set(read_u32(input)?.into()),
+        TAG_PVEC => Value::PVec(read_u32(input)?.into()),
+        TAG_PMAP => Value::PMap(read_u32(input)?.into()),
+        TAG_PAIR => Value::Pair(read_u32(input)?.into()),
+        TAG_LIST => {
+            let handle = read_u32(input)?.into();
+            Value::List(handle, read_u16(input)?)
+        }
+        TAG_LAMBDA => Value::Lambda(read_u32(input)?.into()),
+        TAG_CLOSURE => Value::Closure(read_u32(input)?.into()),
+        TAG_CONTINUATION => Value::Continuation(read_u32(input)?.into()),
+        TAG_CALLFRAME => Value::CallFrame(read_u32(input)?.into()),
+        TAG_VALUE => Value::Value(read_u32(input)?.into()),
+        TAG_ERROR => Value::Error(read_u32(input)?.into()),
+        TAG_BIGINT => Value::BigInt(read_u32(input)?.into()),
+        TAG_WEAK => Value::Weak(read_u32(input)?.into()),
+        TAG_HOST => Value::Host(read_u32(input)?.into()),
+        _ => {
+            return Err(VMError::new_chunk(format!(
+                "Image: invalid value tag {tag:#04x}."
+            )))
+        }
+    })
+}
+
+impl Chunk {
+    /// Write this chunk in the raw format used by VM images.  Constants are written with
+    /// write_raw_value and global slots are left as is.
+    pub(crate) fn write_raw<W: Write>(&self, out: &mut W) -> VMResult<()> {
+        write_str(out, self.file_name)?;
+        write_u32(out, self.start_line)?;
+        write_u32(out, self.last_line)?;
+        write_bytes(out, &self.line_numbers)?;
+        write_bytes(out, &self.code)?;
+        write_len(out, self.constants.len())?;
+        for constant in &self.constants {
+            write_raw_value(out, *constant)?;
+        }
+        write_len(out, self.jump_table.len())?;
+        for jump in &self.jump_table {
+            write_u32(out, *jump)?;
+        }
+        if let Some(captures) = &self.captures {
+            write_u8(out, 1)?;
+            write_len(out, captures.len())?;
+            for cap in captures {
+                write_u32(out, *cap)?;
+            }
+        } else {
+            write_u8(out, 0)?;
+        }
+        write_len(out, self.input_regs)?;
+        write_len(out, self.extra_regs)?;
+        write_u16(out, self.args)?;
+        write_u16(out, self.opt_args)?;
+        write_u8(out, if self.rest { 1 } else { 0 })?;
+        if let Some(dbg_args) = &self.dbg_args {
+            write_u8(out, 1)?;
+            write_len(out, dbg_args.len())?;
+            for arg in dbg_args {
+                write_u32(out, arg.id)?;
+            }
+        } else {
+            write_u8(out, 0)?;
+        }
+        Ok(())
+    }
+
+    /// Read a chunk written with write_raw, the file name is interned in interner.
+    pub(crate) fn read_raw<R: Read>(interner: &mut Interner, input: &mut R) -> VMResult<Chunk> {
+        let file_name = read_string(input)?;
+        let file_name = interner.intern(&file_name);
+        let file_name = interner
+            .get_string(file_name)
+            .expect("just interned the file name");
+        let mut chunk = Chunk::new(file_name, read_u32(input)?);
+        chunk.last_line = read_u32(input)?;
+        chunk.line_numbers = read_bytes(input)?;
+        chunk.code = read_bytes(input)?;
+        let num_constants = read_u32(input)?;
+        for _ in 0..num_constants {
+            chunk.constants.push(read_raw_value(input)?);
+        }
+        let num_jumps = read_u32(input)?;
+        for _ in 0..num_jumps {
+            chunk.jump_table.push(read_u32(input)?);
+        }
+        if read_u8(input)? != 0 {
+            let num_captures = read_u32(input)?;
+            let mut captures = Vec::new();
+            for _ in 0..num_captures {
+                captures.push(read_u32(input)?);
+            }
+            chunk.captures = Some(captures);
+        }
+        chunk.input_regs = read_u32(input)? as usize;
+        chunk.extra_regs = read_u32(input)? as usize;
+        chunk.args = read_u16(input)?;
+        chunk.opt_args = read_u16(input)?;
+        chunk.rest = read_u8(input)? != 0;
+        if read_u8(input)? != 0 {
+            let num_args = read_u32(input)?;
+            let mut dbg_args = Vec::new();
+            for _ in 0..num_args {
+                dbg_args.push(Interned {
+                    id: read_u32(input)?,
+                });
+            }
+            chunk.dbg_args = Some(dbg_args);
+        }
+        Ok(chunk)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Vm;
+
+    #[test]
+    fn test_bytecode_round_trip() -> VMResult<()> {
+        let mut vm = Vm::new();
+        let slot = vm.reserve_global();
+        let mut inner = Chunk::new("inner_file", 3);
+        inner.args = 1;
+        inner.input_regs = 2;
+        inner.dbg_args = Some(vec![vm.intern("x")]);
+        inner.encode1(SRET, 1, Some(3))?;
+        let inner = vm.alloc_lambda(Arc::new(inner)).unwrap();
+
+        let mut chunk = Chunk::new("test_file", 1);
+        let s = vm.alloc_string_ro("a string".to_string()).unwrap();
+        let sym = Value::Symbol(vm.intern("sym"));
+        let list = vm.alloc_pair_ro(sym, Value::Nil).unwrap();
+        chunk.add_constant(s);
+        chunk.add_constant(list);
+        chunk.add_constant(inner);
+        chunk.add_constant(1.5.into());
+        chunk.encode2(CONST, 1, 0, Some(1))?;
+        chunk.encode_def(1, slot, Some(2), false)?;
+        chunk.encode2(CONST, 2, 2, Some(3))?;
+        chunk.encode_callg(slot, 1, 3, Some(4))?;
+        let jmp = chunk.add_jump(0);
+        chunk.encode1(JMP, jmp as u16, Some(4))?;
+        chunk.encode0(RET, Some(5))?;
+        chunk.extra_regs = 4;
+
+        let mut out = Vec::new();
+        let name = vm.intern("my-global");
+        chunk.write_bytecode(&vm, |s| if s == slot { Some(name) } else { None }, &mut out)?;
+
+        // Load into a fresh VM with a different global layout.
+        let mut vm2 = Vm::new();
+        vm2.reserve_global();
+        vm2.reserve_global();
+        let new_slot = vm2.reserve_global();
+        let mut resolved = Vec::new();
+        let loaded = Chunk::read_bytecode(
+            &mut vm2,
+            |vm, name| {
+                resolved.push(vm.get_interned(name).to_string());
+                new_slot
+            },
+            &mut &out[..],
+        )?;
+        assert_eq!(resolved, vec!["my-global", "my-global"]);
+        assert_eq!(loaded.file_name, "test_file");
+        assert_eq!(loaded.extra_regs, 4);
+        assert_eq!(loaded.jump_table, chunk.jump_table);
+        assert_eq!(loaded.offset_to_line(0), chunk.offset_to_line(0));
+        assert_eq!(loaded.code.len(), chunk.code.len());
+        assert_eq!(loaded.constants.len(), 4);
+        assert_eq!(
+            vm2.get_string(loaded.constants[0].get_handle().unwrap()),
+            "a string"
+        );
+        let (car, cdr) = loaded.constants[1].get_pair(&vm2).unwrap();
+        assert_eq!(car, Value::Symbol(vm2.intern("sym")));
+        assert_eq!(cdr, Value::Nil);
+        if let Value::Lambda(h) = loaded.constants[2] {
+            let l = vm2.get_lambda(h);
+            assert_eq!(l.file_name, "inner_file");
+            assert_eq!(l.args, 1);
+            assert_eq!(l.dbg_args, Some(vec![vm2.intern("x")]));
+        } else {
+            panic!("expected a lambda constant");
+        }
+        assert_eq!(loaded.constants[3], 1.5.into());
+        // Globals were relocated to the new VM's slots.
+        let globals = global_operands(&loaded.code)?;
+        assert_eq!(globals.len(), 2);
+        for (offset, wide) in globals {
+            assert_eq!(decode_global(&loaded.code, offset, wide), 2);
+        }
+
+        // A slot with no name is saved as is, it has to exist in the loading VM.
+        let mut unnamed = Chunk::new("test_file", 1);
+        unnamed.encode_def(1, slot, Some(1), false)?;
+        unnamed.encode0(RET, Some(1))?;
+        unnamed.extra_regs = 1;
+        let mut out = Vec::new();
+        unnamed.write_bytecode(&vm, |_| None, &mut out)?;
+        let mut vm3 = Vm::new();
+        assert!(
+            Chunk::read_bytecode(&mut vm3, |vm, _| vm.reserve_global(), &mut &out[..]).is_err()
+        );
+        vm3.reserve_global();
+        Chunk::read_bytecode(&mut vm3, |vm, _| vm.reserve_global(), &mut &out[..])?;
+
+        let bad = b"NOPE\x00\x01";
+        assert!(
+            Chunk::read_bytecode(&mut vm2, |vm, _| vm.reserve_global(), &mut &bad[..]).is_err()
+        );
+        Ok(())
+    }
+}
@@ -848,6 +848,41 @@ impl Chunk {
                 println!();
                 Ok(false)
             }
+            BIND => {
+                print!("BIND    \t");
+                disassemble_operand!(code, true, wide);
+                print!("\t");
+                print!("G[");
+                disassemble_immediate_global!(code, wide, _vm);
+                println!("]");
+                Ok(false)
+            }
+            UNBIND => {
+                print!("UNBIND  \t");
+                disassemble_immediate!(code, wide);
+                println!();
+                Ok(false)
+            }
+            SETMK => {
+                print!("SETMK   \t");
+                disassemble_operand!(code, true, wide);
+                print!("\t");
+                disassemble_operand!(code, true, wide);
+                print!("\t");
+                disassemble_operand!(code, true, wide);
+                println!();
+                Ok(false)
+            }
+            BYTESMK => {
+                print!("BYTESMK \t");
+                disassemble_operand!(code, true, wide);
+                print!("\t");
+                disassemble_operand!(code, true, wide);
+                print!("\t");
+                disassemble_operand!(code, true, wide);
+                println!();
+                Ok(false)
+            }
             _ => Err(VMError::new_chunk(format!("ERROR: unknown opcode {op}"))),
         }
     }
@@ -0,0 +1,210 @@
+//! Static checks on a chunk's bytecode so that exec_loop can trust its operands.
+
+use crate::opcodes::*;
+use crate::{Chunk, VMError, VMResult};
+
+fn verify_err<T>(offset: usize, msg: impl AsRef<str>) -> VMResult<T> {
+    Err(VMError::new_chunk(format!(
+        "Verify: {} (offset {offset}).",
+        msg.as_ref()
+    )))
+}
+
+/// Decode the operand at ip (does not check bounds, the caller has).
+fn decode_operand(code: &[u8], ip: usize, operand: Operand, wide: bool) -> usize {
+    match operand.size(wide) {
+        1 => code[ip] as usize,
+        2 => u16::from_be_bytes([code[ip], code[ip + 1]]) as usize,
+        _ => u32::from_be_bytes([code[ip], code[ip + 1], code[ip + 2], code[ip + 3]]) as usize,
+    }
+}
+
+impl Chunk {
+    /// Check the bytecode of this chunk before running it.  Verifies that every op code is valid
+    /// (up to MAX_OP_CODE), WIDE only prefixes an instruction with operands, instructions are not
+    /// truncated, registers (including the ranges used by calls and multi register ops) fit in
+    /// input_regs + extra_regs, constant, global (less than globals) and jump table indexes exist,
+    /// jumps land on an instruction and the code can not run off the end.
+    /// Lambdas in the constants are not checked, see GVm::verify_chunk for that.
+    pub fn verify(&self, globals: usize) -> VMResult<()> {
+        let code = &self.code[..];
+        let max_reg = self.input_regs + self.extra_regs;
+        let mut boundaries = vec![false; code.len()];
+        let mut ip = 0;
+        let mut last_op = None;
+        while ip < code.len() {
+            let start = ip;
+            boundaries[start] = true;
+            let mut op = code[ip];
+            ip += 1;
+            let wide = op == WIDE;
+            if wide {
+                if ip >= code.len() {
+                    return verify_err(start, "WIDE at end of code");
+                }
+                op = code[ip];
+                ip += 1;
+            }
+            if op > MAX_OP_CODE {
+                return verify_err(start, format!("invalid op code {op:#04x}"));
+            }
+            let operands = match op_operands(op) {
+                Some(operands) => operands,
+                None => return verify_err(start, format!("invalid op code {op:#04x}")),
+            };
+            if wide && operands.is_empty() {
+                return verify_err(start, format!("WIDE prefix on op {op:#04x}"));
+            }
+            let mut args = [0_usize; 3];
+            for (i, operand) in operands.iter().enumerate() {
+                let size = operand.size(wide);
+                if ip + size > code.len() {
+                    return verify_err(start, format!("truncated op {op:#04x}"));
+                }
+                let arg = decode_operand(code, ip, *operand, wide);
+                ip += size;
+                args[i] = arg;
+                match operand {
+                    // The end of VEC and MAPMK is exclusive, checked with the ranges below.
+                    Operand::Register if i == 2 && matches!(op, VEC | MAPMK) => {}
+                    Operand::Register if arg > max_reg => {
+                        return verify_err(
+                            start,
+                            format!("op {op:#04x} register {arg} out of range"),
+                        );
+                    }
+                    Operand::Constant if arg >= self.constants.len() => {
+                        return verify_err(
+                            start,
+                            format!("op {op:#04x} constant {arg} out of range"),
+                        );
+                    }
+                    Operand::Global if arg >= globals => {
+                        return verify_err(
+                            start,
+                            format!("op {op:#04x} global {arg} out of range"),
+                        );
+                    }
+                    Operand::Jump if arg >= self.jump_table.len() => {
+                        return verify_err(start, format!("op {op:#04x} jump {arg} out of range"));
+                    }
+                    _ => {}
+                }
+            }
+            // Registers used past the ones named by an operand, last register used for each.
+            let ranges: &[usize] = match op {
+                BMOV => &[args[0] + args[2], args[1] + args[2]],
+                LDSC | LDSCR | MDSC | JMPRU | JMPRNU => &[args[0] + args[1]],
+                VEC | MAPMK => &[args[2]],
+                CALL | CALLG => &[args[2] + args[1] + 1],
+                CALLM => &[args[1] + args[0] + 1],
+                TCALL | TCALLG => &[args[1] + 1],
+                TCALLM => &[args[0] + 1],
+                _ => &[],
+            };
+            for end in ranges {
+                if *end > max_reg + 1 {
+                    return verify_err(start, format!("op {op:#04x} register range out of range"));
+                }
+            }
+            last_op = Some((start, op));
+        }
+        match last_op {
+            Some((_, RET | SRET | HALT | JMP | TCALL | TCALLG | TCALLM)) => {}
+            Some((start, op)) => {
+                return verify_err(
+                    start,
+                    format!("code can run off the end after op {op:#04x}"),
+                )
+            }
+            None => return verify_err(0, "no code"),
+        }
+        for target in &self.jump_table {
+            let target = *target as usize;
+            if target >= code.len() || !boundaries[target] {
+                return verify_err(target, "jump target is not an instruction");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(input_regs: usize, extra_regs: usize) -> Chunk {
+        let mut chunk = Chunk::new("no_file", 1);
+        chunk.input_regs = input_regs;
+        chunk.extra_regs = extra_regs;
+        chunk
+    }
+
+    #[test]
+    fn test_verify() -> VMResult<()> {
+        let mut good = chunk(1, 2);
+        let c = good.add_constant(crate::Value::Nil) as u16;
+        good.encode2(CONST, 1, c, Some(1))?;
+        let jmp = good.add_jump(good.code.len() as u32) as u16;
+        good.encode3(CALL, 1, 1, 1, Some(1))?;
+        good.encode2(JMPF, 2, jmp, Some(1))?;
+        good.encode1(SRET, 3, Some(1))?;
+        good.verify(0)?;
+
+        // Register past input_regs + extra_regs.
+        let mut bad = chunk(1, 2);
+        bad.encode2(MOV, 4, 1, Some(1))?;
+        bad.encode0(RET, Some(1))?;
+        assert!(bad.verify(0).is_err());
+
+        // Call arguments past the last register.
+        let mut bad = chunk(1, 2);
+        bad.encode3(CALL, 1, 3, 1, Some(1))?;
+        bad.encode0(RET, Some(1))?;
+        assert!(bad.verify(0).is_err());
+
+        // Missing constant.
+        let mut bad = chunk(1, 2);
+        bad.encode2(CONST, 1, 0, Some(1))?;
+        bad.encode0(RET, Some(1))?;
+        assert!(bad.verify(0).is_err());
+
+        // Missing global.
+        let mut bad = chunk(1, 2);
+        bad.encode_def(1, 1, Some(1), false)?;
+        bad.encode0(RET, Some(1))?;
+        bad.verify(2)?;
+        assert!(bad.verify(1).is_err());
+
+        // Missing jump and a jump into the middle of an instruction.
+        let mut bad = chunk(1, 2);
+        bad.encode1(JMP, 0, Some(1))?;
+        assert!(bad.verify(0).is_err());
+        bad.add_jump(1);
+        assert!(bad.verify(0).is_err());
+
+        // A jump to a WIDE instruction has to land on the prefix.
+        let mut wide = chunk(1, 300);
+        wide.encode2(MOV, 299, 1, Some(1))?;
+        wide.add_jump(0);
+        wide.encode1(JMP, 0, Some(1))?;
+        wide.verify(0)?;
+        wide.jump_table[0] = 1;
+        assert!(wide.verify(0).is_err());
+
+        // Invalid op codes, WIDE on an op with no operands and truncated code.
+        let mut bad = chunk(1, 2);
+        bad.code = vec![MAX_OP_CODE + 1, RET];
+        assert!(bad.verify(0).is_err());
+        bad.code = vec![WIDE, RET];
+        assert!(bad.verify(0).is_err());
+        bad.code = vec![RET, MOV, 1];
+        assert!(bad.verify(0).is_err());
+
+        // Can not fall off the end.
+        let mut bad = chunk(1, 2);
+        bad.encode2(MOV, 1, 2, Some(1))?;
+        assert!(bad.verify(0).is_err());
+        Ok(())
+    }
+}
@@ -1,7 +1,8 @@
-use crate::{GVm, Value};
+use crate::{Backtrace, GVm, Value};
 use std::error::Error;
 use std::fmt;
 use std::io;
+use std::sync::Arc;
 
 #[derive(Clone, Debug)]
 pub enum VMErrorObj {
@@ -13,6 +14,8 @@ pub enum VMErrorObj {
 pub struct VMError {
     pub key: &'static str,
     pub obj: VMErrorObj,
+    /// Call stack where the error was raised, set when it is raised by running code.
+    pub backtrace: Option<Arc<Backtrace>>,
 }
 
 impl Error for VMError {}
@@ -38,6 +41,7 @@ impl VMError {
         VMError {
             key,
             obj: VMErrorObj::Message(reason),
+            backtrace: None,
         }
     }
 
@@ -64,6 +68,27 @@ impl VMError {
         VMError::new("mem", reason)
     }
 
+    pub fn new_stack<S: Into<String>>(reason: S) -> Self {
+        VMError::new("stack", reason)
+    }
+
+    pub fn new_fuel<S: Into<String>>(reason: S) -> Self {
+        VMError::new("fuel", reason)
+    }
+
+    pub fn new_interrupt<S: Into<String>>(reason: S) -> Self {
+        VMError::new("interrupt", reason)
+    }
+
+    pub fn new_oom<S: Into<String>>(reason: S) -> Self {
+        VMError::new("oom", reason)
+    }
+
+    /// True if this error aborted execution (out of fuel or memory or interrupted).
+    pub fn is_abort(&self) -> bool {
+        self.key == "fuel" || self.key == "interrupt" || self.key == "oom"
+    }
+
     pub fn new_value<S: Into<String>>(reason: S) -> Self {
         VMError::new("rt", reason)
     }
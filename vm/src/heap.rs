@@ -1,15 +1,32 @@
-use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use crate::bits::FLAG_MUT;
-use crate::{get_code, Chunk, FxHashMap, Interned, VMError, VMResult, Value};
+use num_bigint::BigInt;
+
+use crate::bits::{is_live, is_weak, FLAG_MUT, FLAG_STICKY, FLAG_WEAK};
+use crate::{
+    get_code, is_bit_set, Backtrace, Chunk, FxHashMap, FxHashSet, Interned, VMError, VMResult,
+    Value,
+};
 pub mod handle;
 pub use crate::handle::Handle;
 use crate::heap::storage::Storage;
 
 pub mod bits;
+mod host;
+mod image;
+mod map;
+mod persistent;
+mod set;
 mod storage;
 
+use host::HostBox;
+pub use host::HostObject;
+pub use image::RefStep;
+pub use map::{SortKey, VMMap};
+pub use persistent::{PMap, PMapIter, PVec, PVecIter};
+pub use set::VMSet;
+
 #[derive(Clone, Debug)]
 pub struct CallFrame {
     pub id: usize,
@@ -49,7 +66,8 @@ pub struct Continuation {
 enum Object {
     String(Arc<String>),
     Vector(Arc<Vec<Value>>),
-    Map(Arc<HashMap<Value, Value>>),
+    Map(Arc<VMMap>),
+    Set(Arc<VMSet>),
     Bytes(Arc<Vec<u8>>),
     Pair(Arc<(Value, Value)>),
     Value(Value),
@@ -57,14 +75,76 @@ enum Object {
     // Everything below here is always read only.
     Lambda(Arc<Chunk>),
     Closure(Arc<(Arc<Chunk>, Vec<Handle>)>),
+    BigInt(Arc<BigInt>),
+    PVec(Arc<PVec>),
+    PMap(Arc<PMap>),
+    // Target of a weak reference, Undefined once the target has been collected.
+    Weak(Value),
+    // Rust value owned by the VM, mutable through Arc::get_mut (the Arc is only shared while the
+    // collector is tracing it).  Finalized when the last reference is dropped.
+    Host(Arc<HostBox>),
     // Place holder for an empty object slot.
     Empty,
 }
 
-#[derive(Clone, Copy)]
+impl Object {
+    /// A value that refers to this object (for the grey list), None for an empty slot.
+    fn value(&self, handle: Handle) -> Option<Value> {
+        match self {
+            Object::String(_) => Some(Value::String(handle)),
+            Object::Vector(_) => Some(Value::Vector(handle)),
+            Object::Map(_) => Some(Value::Map(handle)),
+            Object::Set(_) => Some(Value::Set(handle)),
+            Object::Bytes(_) => Some(Value::Bytes(handle)),
+            Object::Pair(_) => Some(Value::Pair(handle)),
+            Object::Value(_) => Some(Value::Value(handle)),
+            Object::Lambda(_) => Some(Value::Lambda(handle)),
+            Object::Closure(_) => Some(Value::Closure(handle)),
+            Object::BigInt(_) => Some(Value::BigInt(handle)),
+            Object::PVec(_) => Some(Value::PVec(handle)),
+            Object::PMap(_) => Some(Value::PMap(handle)),
+            Object::Weak(_) => Some(Value::Weak(handle)),
+            Object::Host(_) => Some(Value::Host(handle)),
+            Object::Empty => None,
+        }
+    }
+
+    /// Approximate bytes used by this object, its slot plus the memory it owns.
+    fn size(&self) -> usize {
+        let val = size_of::<Value>();
+        let owned = match self {
+            Object::String(s) => s.capacity(),
+            Object::Vector(v) => v.capacity() * val,
+            Object::Map(map) => map.len() * 2 * val,
+            Object::Set(set) => set.len() * val,
+            Object::Bytes(b) => b.capacity(),
+            Object::Pair(_) => 2 * val,
+            Object::Lambda(chunk) => chunk.code.len() + chunk.constants.len() * val,
+            Object::Closure(closure) => closure.1.len() * size_of::<Handle>(),
+            Object::BigInt(i) => (i.bits() as usize).div_ceil(8),
+            Object::PVec(v) => v.len() * val,
+            Object::PMap(map) => map.len() * 2 * val,
+            Object::Host(obj) => size_of_val::<dyn HostObject>(&***obj),
+            Object::Value(_) | Object::Weak(_) | Object::Empty => 0,
+        };
+        size_of::<Object>() + owned
+    }
+}
+
+fn callframe_size(frame: &CallFrame) -> usize {
+    size_of::<CallFrame>() + frame.defers.len() * size_of::<Value>()
+}
+
+fn continuation_size(k: &Continuation) -> usize {
+    size_of::<Continuation>() + k.stack.len() * size_of::<Value>()
+}
+
+#[derive(Clone)]
 pub struct Error {
     pub keyword: Interned,
     pub data: Value,
+    /// Call stack where the error was created (by MKERR).
+    pub backtrace: Option<Arc<Backtrace>>,
 }
 
 pub enum MutState {
@@ -81,6 +161,41 @@ impl MutState {
     }
 }
 
+/// A snapshot of heap and garbage collector statistics.
+#[derive(Clone, Debug, Default)]
+pub struct HeapStats {
+    /// Completed collections.
+    pub collections: usize,
+    /// Time spent in the collector (full collections and incremental steps).
+    pub total_pause: Duration,
+    /// Time spent in the most recent collection or incremental step.
+    pub last_pause: Duration,
+    pub strings: usize,
+    pub vectors: usize,
+    pub maps: usize,
+    pub sets: usize,
+    pub bytes: usize,
+    pub pairs: usize,
+    pub values: usize,
+    pub lambdas: usize,
+    pub closures: usize,
+    pub bigints: usize,
+    pub pvecs: usize,
+    pub pmaps: usize,
+    pub weaks: usize,
+    pub hosts: usize,
+    pub continuations: usize,
+    pub callframes: usize,
+    pub errors: usize,
+    /// Approximate bytes used by live objects, this is what the heap limit counts.
+    pub used_bytes: usize,
+    /// Object capacity before the next collection (or incremental collection) starts.
+    pub capacity: usize,
+    pub grow_factor: f64,
+    /// Objects (of any kind) marked sticky.
+    pub sticky: usize,
+}
+
 //#[derive(Debug)]
 pub struct Heap {
     objects: Storage<Object>,
@@ -88,8 +203,31 @@ pub struct Heap {
     continuations: Storage<Continuation>,
     errors: Storage<Error>,
     props: Option<FxHashMap<Value, Arc<FxHashMap<Interned, Value>>>>,
+    // Every live big int, used to make sure equal big ints share a handle (so Value's Eq and Hash
+    // work for them).
+    bigints: FxHashMap<Arc<BigInt>, Handle>,
     greys: Vec<Value>,
+    // Weak map entries whose key was not marked when the map was traced, the value is traced if
+    // the key turns out to be live.
+    weak_pending: Vec<(Value, Value)>,
     paused: u32,
+    // Objects to trace per allocation during an incremental collection, 0 for stop the world.
+    gc_step: usize,
+    // True while an incremental collection is marking.
+    marking: bool,
+    // True while the roots are rescanned to finish an incremental collection.
+    rescanning: bool,
+    // Limit on the approximate bytes used by live objects, None to grow without bound.
+    max_bytes: Option<usize>,
+    // Approximate bytes used by objects, recounted by each collection and grown by allocations.
+    used_bytes: usize,
+    collections: usize,
+    total_pause: Duration,
+    last_pause: Duration,
+    // Symbols seen while a full collection is marking (None otherwise).
+    symbol_marks: Option<FxHashSet<u32>>,
+    // Live symbols from the last full collection, waiting for the VM to reclaim the rest.
+    live_symbols: Option<FxHashSet<u32>>,
 }
 
 impl Default for Heap {
@@ -105,6 +243,7 @@ macro_rules! value_op {
             $crate::Value::String(handle) => $heap.objects.$op(handle.idx()),
             $crate::Value::Vector(handle) => $heap.objects.$op(handle.idx()),
             $crate::Value::Map(handle) => $heap.objects.$op(handle.idx()),
+            $crate::Value::Set(handle) => $heap.objects.$op(handle.idx()),
             $crate::Value::Bytes(handle) => $heap.objects.$op(handle.idx()),
             $crate::Value::Pair(handle) => $heap.objects.$op(handle.idx()),
             $crate::Value::List(handle, _) => $heap.objects.$op(handle.idx()),
@@ -115,6 +254,11 @@ macro_rules! value_op {
             $crate::Value::Value(handle) => $heap.objects.$op(handle.idx()),
 
             $crate::Value::Error(handle) => $heap.errors.$op(handle.idx()),
+            $crate::Value::BigInt(handle) => $heap.objects.$op(handle.idx()),
+            $crate::Value::PVec(handle) => $heap.objects.$op(handle.idx()),
+            $crate::Value::PMap(handle) => $heap.objects.$op(handle.idx()),
+            $crate::Value::Weak(handle) => $heap.objects.$op(handle.idx()),
+            $crate::Value::Host(handle) => $heap.objects.$op(handle.idx()),
 
             $crate::Value::Byte(_)
             | $crate::Value::Int(_)
@@ -148,8 +292,20 @@ impl Heap {
             continuations: Storage::default(),
             errors: Storage::default(),
             props: Some(FxHashMap::default()),
+            bigints: FxHashMap::default(),
             greys: vec![],
+            weak_pending: vec![],
             paused: 0,
+            gc_step: 0,
+            marking: false,
+            rescanning: false,
+            max_bytes: None,
+            used_bytes: 0,
+            collections: 0,
+            total_pause: Duration::ZERO,
+            last_pause: Duration::ZERO,
+            symbol_marks: None,
+            live_symbols: None,
         }
     }
 
@@ -181,13 +337,200 @@ impl Heap {
         self.objects.set_grow_factor(grow_factor);
     }
 
-    fn alloc<MarkFunc>(&mut self, obj: Object, flags: u8, mark_roots: MarkFunc) -> Handle
+    pub fn grow_factor(&self) -> f64 {
+        self.objects.grow_factor()
+    }
+
+    /// Collect incrementally, tracing at most step objects per allocation while marking.  This
+    /// bounds GC pauses at the cost of some floating garbage and extra memory while a collection is
+    /// in progress.  A step of 0 (the default) does a stop the world collection when the heap fills.
+    pub fn set_gc_step(&mut self, step: usize) {
+        self.gc_step = step;
+    }
+
+    pub fn gc_step(&self) -> usize {
+        self.gc_step
+    }
+
+    /// Is an incremental collection in progress.
+    pub fn is_marking(&self) -> bool {
+        self.marking
+    }
+
+    /// Are the roots being rescanned to finish an incremental collection.  Roots stored through
+    /// root_barrier (globals for instance) were traced when they were stored so can be skipped.
+    pub fn is_rescanning(&self) -> bool {
+        self.rescanning
+    }
+
+    /// Write barrier for roots that are not rescanned when an incremental collection finishes,
+    /// call when storing val in one.
+    pub fn root_barrier(&mut self, val: Value) {
+        if self.marking {
+            self.mark_trace(val);
+        }
+    }
+
+    /// Do any GC work needed before an allocation, full is true if the storage being allocated
+    /// from is at capacity.
+    fn gc_check<MarkFunc>(&mut self, full: bool, mark_roots: MarkFunc)
     where
         MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
     {
-        if self.objects.live_objects() >= self.objects.capacity() && self.paused == 0 {
+        if self.paused > 0 || !(self.marking || full) {
+            return;
+        }
+        let start = Instant::now();
+        if self.marking {
+            self.mark_step(mark_roots);
+        } else if self.gc_step == 0 {
             self.collect(mark_roots);
+        } else {
+            self.start_marking(mark_roots);
         }
+        self.record_pause(start);
+    }
+
+    /// Limit the approximate bytes used by live objects, None removes the limit.  An allocation
+    /// that would go over the limit, even after a full collection, fails with an :oom error.
+    /// Call frames, continuations and errors count toward the limit but are always allocated (an
+    /// error may be needed to report running out of memory).
+    /// Objects that grow after they are allocated are only counted again by the next collection.
+    pub fn set_max_bytes(&mut self, max: Option<usize>) {
+        self.max_bytes = max;
+    }
+
+    pub fn max_bytes(&self) -> Option<usize> {
+        self.max_bytes
+    }
+
+    fn record_pause(&mut self, start: Instant) {
+        self.last_pause = start.elapsed();
+        self.total_pause += self.last_pause;
+    }
+
+    /// Force a full collection (finishing any incremental collection in progress).  Does nothing
+    /// and returns false if GC is paused.
+    pub fn gc<MarkFunc>(&mut self, mark_roots: MarkFunc) -> bool
+    where
+        MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
+    {
+        if self.paused > 0 {
+            return false;
+        }
+        let start = Instant::now();
+        self.collect(mark_roots);
+        self.record_pause(start);
+        true
+    }
+
+    pub fn stats(&self) -> HeapStats {
+        let mut stats = HeapStats {
+            collections: self.collections,
+            total_pause: self.total_pause,
+            last_pause: self.last_pause,
+            continuations: self.continuations.live_objects(),
+            callframes: self.callframes.live_objects(),
+            errors: self.errors.live_objects(),
+            used_bytes: self.used_bytes,
+            capacity: self.objects.capacity(),
+            grow_factor: self.objects.grow_factor(),
+            sticky: self.objects.sticky_objects()
+                + self.continuations.sticky_objects()
+                + self.callframes.sticky_objects()
+                + self.errors.sticky_objects(),
+            ..HeapStats::default()
+        };
+        let objects = self.objects.flags().iter().zip(self.objects.vals());
+        for (_, obj) in objects.filter(|(flag, _)| is_live(**flag)) {
+            match obj {
+                Object::String(_) => stats.strings += 1,
+                Object::Vector(_) => stats.vectors += 1,
+                Object::Map(_) => stats.maps += 1,
+                Object::Set(_) => stats.sets += 1,
+                Object::Bytes(_) => stats.bytes += 1,
+                Object::Pair(_) => stats.pairs += 1,
+                Object::Value(_) => stats.values += 1,
+                Object::Lambda(_) => stats.lambdas += 1,
+                Object::Closure(_) => stats.closures += 1,
+                Object::BigInt(_) => stats.bigints += 1,
+                Object::PVec(_) => stats.pvecs += 1,
+                Object::PMap(_) => stats.pmaps += 1,
+                Object::Weak(_) => stats.weaks += 1,
+                Object::Host(_) => stats.hosts += 1,
+                Object::Empty => {}
+            }
+        }
+        stats
+    }
+
+    /// Recount used_bytes from the live objects.
+    fn count_bytes(&mut self) {
+        let objects = self.objects.flags().iter().zip(self.objects.vals());
+        let mut bytes: usize = objects
+            .filter(|(flag, _)| is_live(**flag))
+            .map(|(_, obj)| obj.size())
+            .sum();
+        let callframes = self.callframes.flags().iter().zip(self.callframes.vals());
+        bytes += callframes
+            .filter(|(flag, _)| is_live(**flag))
+            .map(|(_, frame)| callframe_size(frame))
+            .sum::<usize>();
+        let continuations = self.continuations.flags().iter();
+        bytes += continuations
+            .zip(self.continuations.vals())
+            .filter(|(flag, _)| is_live(**flag))
+            .map(|(_, k)| continuation_size(k))
+            .sum::<usize>();
+        bytes += self.errors.live_objects() * size_of::<Error>();
+        self.used_bytes = bytes;
+    }
+
+    /// Do any GC work needed before allocating size bytes (full is true if the storage being
+    /// allocated from is at capacity).  Returns false if this would go over max_bytes even after
+    /// a full collection.
+    fn alloc_check<MarkFunc>(&mut self, size: usize, full: bool, mark_roots: MarkFunc) -> bool
+    where
+        MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
+    {
+        match self.max_bytes {
+            Some(max) if self.used_bytes + size > max => {
+                // Only out of memory if a full collection can not get back under the limit.
+                self.gc(mark_roots);
+                self.used_bytes + size <= max
+            }
+            _ => {
+                self.gc_check(full, mark_roots);
+                true
+            }
+        }
+    }
+
+    fn alloc<MarkFunc>(&mut self, obj: Object, flags: u8, mark_roots: MarkFunc) -> VMResult<Handle>
+    where
+        MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
+    {
+        let full = self.objects.live_objects() >= self.objects.capacity();
+        let size = obj.size();
+        if !self.alloc_check(size, full, mark_roots) {
+            return Err(VMError::new_oom(format!(
+                "Out of memory, heap is limited to {} bytes.",
+                self.max_bytes.unwrap_or(0)
+            )));
+        }
+        self.used_bytes += size;
+        Ok(Handle::new32(self.objects.alloc(obj, flags)))
+    }
+
+    /// Like alloc but ignores max_bytes (the object still counts toward it).
+    fn alloc_always<MarkFunc>(&mut self, obj: Object, flags: u8, mark_roots: MarkFunc) -> Handle
+    where
+        MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
+    {
+        let full = self.objects.live_objects() >= self.objects.capacity();
+        let size = obj.size();
+        self.alloc_check(size, full, mark_roots);
+        self.used_bytes += size;
         Handle::new32(self.objects.alloc(obj, flags))
     }
 
@@ -197,15 +540,15 @@ impl Heap {
         cdr: Value,
         mutable: MutState,
         mark_roots: MarkFunc,
-    ) -> Value
+    ) -> VMResult<Value>
     where
         MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
     {
-        Value::Pair(self.alloc(
+        Ok(Value::Pair(self.alloc(
             Object::Pair(Arc::new((car, cdr))),
             mutable.flag(),
             mark_roots,
-        ))
+        )?))
     }
 
     pub fn alloc_string<MarkFunc>(
@@ -213,11 +556,15 @@ impl Heap {
         s: String,
         mutable: MutState,
         mark_roots: MarkFunc,
-    ) -> Value
+    ) -> VMResult<Value>
     where
         MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
     {
-        Value::String(self.alloc(Object::String(Arc::new(s)), mutable.flag(), mark_roots))
+        Ok(Value::String(self.alloc(
+            Object::String(Arc::new(s)),
+            mutable.flag(),
+            mark_roots,
+        )?))
     }
 
     pub fn alloc_vector<MarkFunc>(
@@ -225,24 +572,48 @@ impl Heap {
         v: Vec<Value>,
         mutable: MutState,
         mark_roots: MarkFunc,
-    ) -> Value
+    ) -> VMResult<Value>
     where
         MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
     {
-        Value::Vector(self.alloc(Object::Vector(Arc::new(v)), mutable.flag(), mark_roots))
+        Ok(Value::Vector(self.alloc(
+            Object::Vector(Arc::new(v)),
+            mutable.flag(),
+            mark_roots,
+        )?))
         //Value::Vector(self.alloc(Object::Vector(v), mutable.flag(), mark_roots))
     }
 
     pub fn alloc_map<MarkFunc>(
         &mut self,
-        map: HashMap<Value, Value>,
+        map: VMMap,
         mutable: MutState,
         mark_roots: MarkFunc,
-    ) -> Value
+    ) -> VMResult<Value>
     where
         MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
     {
-        Value::Map(self.alloc(Object::Map(Arc::new(map)), mutable.flag(), mark_roots))
+        Ok(Value::Map(self.alloc(
+            Object::Map(Arc::new(map)),
+            mutable.flag(),
+            mark_roots,
+        )?))
+    }
+
+    pub fn alloc_set<MarkFunc>(
+        &mut self,
+        set: VMSet,
+        mutable: MutState,
+        mark_roots: MarkFunc,
+    ) -> VMResult<Value>
+    where
+        MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
+    {
+        Ok(Value::Set(self.alloc(
+            Object::Set(Arc::new(set)),
+            mutable.flag(),
+            mark_roots,
+        )?))
     }
 
     pub fn alloc_bytes<MarkFunc>(
@@ -250,18 +621,26 @@ impl Heap {
         v: Vec<u8>,
         mutable: MutState,
         mark_roots: MarkFunc,
-    ) -> Value
+    ) -> VMResult<Value>
     where
         MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
     {
-        Value::Bytes(self.alloc(Object::Bytes(Arc::new(v)), mutable.flag(), mark_roots))
+        Ok(Value::Bytes(self.alloc(
+            Object::Bytes(Arc::new(v)),
+            mutable.flag(),
+            mark_roots,
+        )?))
     }
 
-    pub fn alloc_lambda<MarkFunc>(&mut self, l: Arc<Chunk>, mark_roots: MarkFunc) -> Value
+    pub fn alloc_lambda<MarkFunc>(&mut self, l: Arc<Chunk>, mark_roots: MarkFunc) -> VMResult<Value>
     where
         MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
     {
-        Value::Lambda(self.alloc(Object::Lambda(l), 0, mark_roots))
+        Ok(Value::Lambda(self.alloc(
+            Object::Lambda(l),
+            0,
+            mark_roots,
+        )?))
     }
 
     pub fn alloc_closure<MarkFunc>(
@@ -269,30 +648,127 @@ impl Heap {
         l: Arc<Chunk>,
         v: Vec<Handle>,
         mark_roots: MarkFunc,
-    ) -> Value
+    ) -> VMResult<Value>
     where
         MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
     {
-        Value::Closure(self.alloc(Object::Closure(Arc::new((l, v))), 0, mark_roots))
+        Ok(Value::Closure(self.alloc(
+            Object::Closure(Arc::new((l, v))),
+            0,
+            mark_roots,
+        )?))
     }
 
-    pub fn alloc_continuation<MarkFunc>(&mut self, k: Continuation, mark_roots: MarkFunc) -> Value
+    /// Allocate the read only rest (&) argument list for a call, always allocates (like call
+    /// frames it is made in the middle of setting up a call), see set_max_bytes.
+    pub fn alloc_rest_list<MarkFunc>(&mut self, v: Vec<Value>, mark_roots: MarkFunc) -> Value
     where
         MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
     {
-        if self.continuations.live_objects() >= self.continuations.capacity() && self.paused == 0 {
-            self.collect(mark_roots);
+        Value::List(
+            self.alloc_always(Object::Vector(Arc::new(v)), 0, mark_roots),
+            0,
+        )
+    }
+
+    /// Allocate a big int, if an equal big int is already on the heap return it instead.
+    /// Callers should only use this for values that do not fit an Int (see Value::from_bigint).
+    pub fn alloc_bigint<MarkFunc>(&mut self, i: BigInt, mark_roots: MarkFunc) -> VMResult<Value>
+    where
+        MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
+    {
+        if let Some(handle) = self.bigints.get(&i) {
+            return Ok(Value::BigInt(*handle));
         }
+        let i = Arc::new(i);
+        let handle = self.alloc(Object::BigInt(i.clone()), 0, mark_roots)?;
+        self.bigints.insert(i, handle);
+        Ok(Value::BigInt(handle))
+    }
+
+    /// Allocate a map with weak keys, entries are removed once their key is collected and a value
+    /// is only kept alive by the map while its key is alive.
+    pub fn alloc_weak_map<MarkFunc>(&mut self, map: VMMap, mark_roots: MarkFunc) -> VMResult<Value>
+    where
+        MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
+    {
+        Ok(Value::Map(self.alloc(
+            Object::Map(Arc::new(map)),
+            FLAG_MUT | FLAG_WEAK,
+            mark_roots,
+        )?))
+    }
+
+    /// Allocate a weak reference to target, it will not keep target alive.
+    pub fn alloc_weak<MarkFunc>(&mut self, target: Value, mark_roots: MarkFunc) -> VMResult<Value>
+    where
+        MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
+    {
+        Ok(Value::Weak(self.alloc(
+            Object::Weak(target),
+            0,
+            mark_roots,
+        )?))
+    }
+
+    pub fn alloc_pvec<MarkFunc>(&mut self, vec: PVec, mark_roots: MarkFunc) -> VMResult<Value>
+    where
+        MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
+    {
+        Ok(Value::PVec(self.alloc(
+            Object::PVec(Arc::new(vec)),
+            0,
+            mark_roots,
+        )?))
+    }
+
+    pub fn alloc_pmap<MarkFunc>(&mut self, map: PMap, mark_roots: MarkFunc) -> VMResult<Value>
+    where
+        MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
+    {
+        Ok(Value::PMap(self.alloc(
+            Object::PMap(Arc::new(map)),
+            0,
+            mark_roots,
+        )?))
+    }
+
+    pub fn alloc_host<MarkFunc>(
+        &mut self,
+        obj: Box<dyn HostObject>,
+        mark_roots: MarkFunc,
+    ) -> VMResult<Value>
+    where
+        MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
+    {
+        Ok(Value::Host(self.alloc(
+            Object::Host(HostBox::new(obj)),
+            FLAG_MUT,
+            mark_roots,
+        )?))
+    }
+
+    /// Always allocates, see set_max_bytes.
+    pub fn alloc_continuation<MarkFunc>(&mut self, k: Continuation, mark_roots: MarkFunc) -> Value
+    where
+        MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
+    {
+        let full = self.continuations.live_objects() >= self.continuations.capacity();
+        let size = continuation_size(&k);
+        self.alloc_check(size, full, mark_roots);
+        self.used_bytes += size;
         Value::Continuation(Handle::new32(self.continuations.alloc(k, 0)))
     }
 
+    /// Always allocates, see set_max_bytes.
     pub fn alloc_callframe<MarkFunc>(&mut self, frame: CallFrame, mark_roots: MarkFunc) -> Value
     where
         MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
     {
-        if self.callframes.live_objects() >= self.callframes.capacity() && self.paused == 0 {
-            self.collect(mark_roots);
-        }
+        let full = self.callframes.live_objects() >= self.callframes.capacity();
+        let size = callframe_size(&frame);
+        self.alloc_check(size, full, mark_roots);
+        self.used_bytes += size;
         Value::CallFrame(Handle::new32(self.callframes.alloc(frame, 0)))
     }
 
@@ -301,13 +777,18 @@ impl Heap {
         val: Value,
         mutable: MutState,
         mark_roots: MarkFunc,
-    ) -> Value
+    ) -> VMResult<Value>
     where
         MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
     {
-        Value::Value(self.alloc(Object::Value(val), mutable.flag(), mark_roots))
+        Ok(Value::Value(self.alloc(
+            Object::Value(val),
+            mutable.flag(),
+            mark_roots,
+        )?))
     }
 
+    /// Always allocates, see set_max_bytes.
     pub fn alloc_error<MarkFunc>(
         &mut self,
         error: Error,
@@ -317,9 +798,9 @@ impl Heap {
     where
         MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
     {
-        if self.errors.live_objects() >= self.errors.capacity() && self.paused == 0 {
-            self.collect(mark_roots);
-        }
+        let full = self.errors.live_objects() >= self.errors.capacity();
+        self.alloc_check(size_of::<Error>(), full, mark_roots);
+        self.used_bytes += size_of::<Error>();
         Value::Error(self.errors.alloc(error, mutable.flag()).into())
     }
 
@@ -354,6 +835,7 @@ impl Heap {
         if !self.objects.is_mutable(handle.idx()) {
             return Err(VMError::new_heap("Vector is not mutable!"));
         }
+        self.write_barrier(Value::Vector(handle));
         if let Some(Object::Vector(v)) = self.objects.get_mut(handle.idx()) {
             Ok(Arc::make_mut(v))
         } else {
@@ -361,7 +843,7 @@ impl Heap {
         }
     }
 
-    pub fn get_map(&self, handle: Handle) -> &HashMap<Value, Value> {
+    pub fn get_map(&self, handle: Handle) -> &VMMap {
         if let Some(Object::Map(map)) = self.objects.get(handle.idx()) {
             map
         } else {
@@ -369,10 +851,11 @@ impl Heap {
         }
     }
 
-    pub fn get_map_mut(&mut self, handle: Handle) -> VMResult<&mut HashMap<Value, Value>> {
+    pub fn get_map_mut(&mut self, handle: Handle) -> VMResult<&mut VMMap> {
         if !self.objects.is_mutable(handle.idx()) {
             return Err(VMError::new_heap("Map is not mutable!"));
         }
+        self.write_barrier(Value::Map(handle));
         if let Some(Object::Map(map)) = self.objects.get_mut(handle.idx()) {
             Ok(Arc::make_mut(map))
         } else {
@@ -380,6 +863,26 @@ impl Heap {
         }
     }
 
+    pub fn get_set(&self, handle: Handle) -> &VMSet {
+        if let Some(Object::Set(set)) = self.objects.get(handle.idx()) {
+            set
+        } else {
+            panic!("Handle {} is not a set!", handle.idx());
+        }
+    }
+
+    pub fn get_set_mut(&mut self, handle: Handle) -> VMResult<&mut VMSet> {
+        if !self.objects.is_mutable(handle.idx()) {
+            return Err(VMError::new_heap("Set is not mutable!"));
+        }
+        self.write_barrier(Value::Set(handle));
+        if let Some(Object::Set(set)) = self.objects.get_mut(handle.idx()) {
+            Ok(Arc::make_mut(set))
+        } else {
+            panic!("Handle {} is not a set!", handle.idx());
+        }
+    }
+
     pub fn get_bytes(&self, handle: Handle) -> &[u8] {
         if let Some(Object::Bytes(v)) = self.objects.get(handle.idx()) {
             v
@@ -388,6 +891,33 @@ impl Heap {
         }
     }
 
+    pub fn get_bytes_mut(&mut self, handle: Handle) -> VMResult<&mut Vec<u8>> {
+        if !self.objects.is_mutable(handle.idx()) {
+            return Err(VMError::new_heap("Bytes are not mutable!"));
+        }
+        if let Some(Object::Bytes(v)) = self.objects.get_mut(handle.idx()) {
+            Ok(Arc::make_mut(v))
+        } else {
+            panic!("Handle {} is not bytes!", handle.idx());
+        }
+    }
+
+    pub fn get_pvec(&self, handle: Handle) -> &PVec {
+        if let Some(Object::PVec(vec)) = self.objects.get(handle.idx()) {
+            vec
+        } else {
+            panic!("Handle {} is not a persistent vector!", handle.idx());
+        }
+    }
+
+    pub fn get_pmap(&self, handle: Handle) -> &PMap {
+        if let Some(Object::PMap(map)) = self.objects.get(handle.idx()) {
+            map
+        } else {
+            panic!("Handle {} is not a persistent map!", handle.idx());
+        }
+    }
+
     pub fn get_pair(&self, handle: Handle) -> (Value, Value) {
         if let Some(Object::Pair(ptr)) = self.objects.get(handle.idx()) {
             (ptr.0, ptr.1)
@@ -400,6 +930,7 @@ impl Heap {
         if !self.objects.is_mutable(handle.idx()) {
             return Err(VMError::new_heap("Pair is not mutable!"));
         }
+        self.write_barrier(Value::Pair(handle));
         if let Some(Object::Pair(ptr)) = self.objects.get_mut(handle.idx()) {
             let data = Arc::make_mut(ptr);
             Ok((&mut data.0, &mut data.1))
@@ -409,6 +940,7 @@ impl Heap {
     }
 
     pub fn get_pair_mut_override(&mut self, handle: Handle) -> (&mut Value, &mut Value) {
+        self.write_barrier(Value::Pair(handle));
         if let Some(Object::Pair(ptr)) = self.objects.get_mut(handle.idx()) {
             let data = Arc::make_mut(ptr);
             (&mut data.0, &mut data.1)
@@ -441,6 +973,53 @@ impl Heap {
         }
     }
 
+    pub fn get_bigint(&self, handle: Handle) -> &BigInt {
+        if let Some(Object::BigInt(i)) = self.objects.get(handle.idx()) {
+            i
+        } else {
+            panic!("Handle {} is not a big int!", handle.idx());
+        }
+    }
+
+    /// Target of a weak reference, None if it has been collected.
+    pub fn get_weak(&self, handle: Handle) -> Option<Value> {
+        if let Some(Object::Weak(target)) = self.objects.get(handle.idx()) {
+            if let Value::Undefined = target {
+                None
+            } else {
+                Some(*target)
+            }
+        } else {
+            panic!("Handle {} is not a weak reference!", handle.idx());
+        }
+    }
+
+    pub fn get_host(&self, handle: Handle) -> &dyn HostObject {
+        if let Some(Object::Host(obj)) = self.objects.get(handle.idx()) {
+            &***obj
+        } else {
+            panic!("Handle {} is not a host object!", handle.idx());
+        }
+    }
+
+    pub fn get_host_mut(&mut self, handle: Handle) -> VMResult<&mut dyn HostObject> {
+        if !self.objects.is_mutable(handle.idx()) {
+            return Err(VMError::new_heap("Host object is not mutable!"));
+        }
+        self.write_barrier(Value::Host(handle));
+        if let Some(Object::Host(obj)) = self.objects.get_mut(handle.idx()) {
+            Arc::get_mut(obj)
+                .map(|obj| &mut **obj)
+                .ok_or_else(|| VMError::new_heap("Host object is in use!"))
+        } else {
+            panic!("Handle {} is not a host object!", handle.idx());
+        }
+    }
+
+    pub fn is_weak_map(&self, handle: Handle) -> bool {
+        self.objects.is_weak(handle.idx())
+    }
+
     pub fn get_continuation(&self, handle: Handle) -> &Continuation {
         if let Some(cont) = self.continuations.get(handle.idx()) {
             cont
@@ -467,6 +1046,7 @@ impl Heap {
     }
 
     pub fn get_value_mut(&mut self, handle: Handle) -> &mut Value {
+        self.write_barrier(Value::Value(handle));
         if let Some(Object::Value(value)) = self.objects.get_mut(handle.idx()) {
             value
         } else {
@@ -476,7 +1056,7 @@ impl Heap {
 
     pub fn get_error(&self, handle: Handle) -> Error {
         if let Some(error) = self.errors.get(handle.idx()) {
-            *error
+            error.clone()
         } else {
             panic!("Handle {} is not an error!", handle.idx());
         }
@@ -494,6 +1074,10 @@ impl Heap {
 
     pub fn sticky(&mut self, val: Value) {
         value_op!(self, val, sticky, ());
+        if self.marking {
+            // Sticky objects were put on the grey list when marking started, catch up.
+            self.mark_trace(val);
+        }
     }
 
     pub fn unsticky(&mut self, val: Value) {
@@ -505,18 +1089,58 @@ impl Heap {
     }
 
     pub fn mark(&mut self, value: Value) {
+        self.mark_value_symbol(value);
         mark!(self, value);
+        if self.marking {
+            // An incremental collection traces the roots from the grey list.
+            self.greys.push(value);
+        }
+    }
+
+    /// Write barrier, call before mutating an object.  If an incremental collection already traced
+    /// the object put it back on the grey list so whatever is stored in it gets traced.
+    fn write_barrier(&mut self, val: Value) {
+        if self.marking && value_op!(self, val, untrace, false) {
+            self.greys.push(val);
+        }
     }
 
     fn mark_trace(&mut self, val: Value) {
+        self.mark_value_symbol(val);
         mark!(self, val);
         self.greys.push(val);
     }
 
-    fn mark_chunk(&mut self, chunk: &Chunk) {
+    /// Record symbol as live if a full collection is marking (see take_live_symbols).
+    pub fn mark_symbol(&mut self, symbol: Interned) {
+        if let Some(marks) = &mut self.symbol_marks {
+            marks.insert(symbol.id);
+        }
+    }
+
+    fn mark_value_symbol(&mut self, val: Value) {
+        if let Value::Symbol(i) | Value::Keyword(i) | Value::StringConst(i) | Value::Special(i) =
+            val
+        {
+            self.mark_symbol(i);
+        }
+    }
+
+    /// Symbols that were live at the end of the last full collection, None if there has not been
+    /// one since the last call.  Incremental collections do not record symbols.
+    pub fn take_live_symbols(&mut self) -> Option<FxHashSet<u32>> {
+        self.live_symbols.take()
+    }
+
+    /// Mark the constants (and argument names) of a chunk that is not on the heap (a chunk being
+    /// executed for instance) as roots.
+    pub fn mark_chunk(&mut self, chunk: &Chunk) {
         for constant in &chunk.constants {
             self.mark_trace(*constant);
         }
+        for arg in chunk.dbg_args.iter().flatten() {
+            self.mark_symbol(*arg);
+        }
     }
 
     fn mark_call_frame(&mut self, call_frame: &CallFrame) {
@@ -533,7 +1157,7 @@ impl Heap {
         self.mark_trace(call_frame.called);
     }
 
-    fn trace_object(&mut self, obj: &Object) {
+    fn trace_object(&mut self, idx: usize, obj: &Object) {
         match obj {
             Object::String(_) => {}
             Object::Vector(vec) => {
@@ -541,13 +1165,40 @@ impl Heap {
                     self.mark_trace(*v);
                 }
             }
+            Object::Map(map) if self.objects.is_weak(idx) => {
+                for (key, val) in map.iter() {
+                    self.mark_value_symbol(*key);
+                    if self.is_live(*key) {
+                        self.mark_trace(*val);
+                    } else {
+                        self.weak_pending.push((*key, *val));
+                    }
+                }
+            }
             Object::Map(map) => {
                 for (key, val) in map.iter() {
                     self.mark_trace(*key);
                     self.mark_trace(*val);
                 }
             }
-            Object::Bytes(_) => {}
+            Object::Set(set) => {
+                for item in set.iter() {
+                    self.mark_trace(*item);
+                }
+            }
+            Object::PVec(vec) => {
+                for v in vec.iter() {
+                    self.mark_trace(*v);
+                }
+            }
+            Object::PMap(map) => {
+                for (key, val) in map.iter() {
+                    self.mark_trace(*key);
+                    self.mark_trace(*val);
+                }
+            }
+            Object::Bytes(_) | Object::BigInt(_) | Object::Weak(_) => {}
+            Object::Host(obj) => obj.trace(&mut |val| self.mark_trace(val)),
             Object::Pair(data) => {
                 self.mark_trace(data.0);
                 self.mark_trace(data.1);
@@ -571,7 +1222,8 @@ impl Heap {
         if let Some(props) = props.get(&val) {
             // Make sure we don't do anything that can access self.props here since that will panic...
             // trace any properties for val.
-            for val in props.values() {
+            for (key, val) in props.iter() {
+                self.mark_symbol(*key);
                 self.mark_trace(*val);
             }
         }
@@ -581,26 +1233,37 @@ impl Heap {
             | Value::String(handle)
             | Value::Vector(handle)
             | Value::Map(handle)
+            | Value::Set(handle)
             | Value::Bytes(handle)
             | Value::Pair(handle)
             | Value::List(handle, _)
             | Value::Lambda(handle)
             | Value::Closure(handle)
-            | Value::Value(handle) => {
+            | Value::Value(handle)
+            | Value::BigInt(handle)
+            | Value::PVec(handle)
+            | Value::PMap(handle)
+            | Value::Weak(handle)
+            | Value::Host(handle) => {
                 let obj = self
                     .objects
                     .get(handle.idx())
                     .expect("Invalid object handle!")
                     .clone();
-                self.trace_object(&obj);
+                self.trace_object(handle.idx(), &obj);
             }
 
             Value::Error(handle) => {
                 let err = self
                     .errors
                     .get(handle.idx())
-                    .expect("Invalid error handle!");
+                    .expect("Invalid error handle!")
+                    .clone();
+                self.mark_symbol(err.keyword);
                 self.mark_trace(err.data);
+                for val in err.backtrace.iter().flat_map(|b| b.values()) {
+                    self.mark_trace(val);
+                }
             }
             Value::Continuation(handle) => {
                 let k = self
@@ -643,27 +1306,213 @@ impl Heap {
     where
         MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
     {
+        // A full collection replaces an incremental one in progress.
+        self.stop_marking();
+        self.symbol_marks = Some(FxHashSet::default());
+        self.greys.clear();
+        self.weak_pending.clear();
         self.objects.clear_marks();
         self.errors.clear_marks();
+        self.callframes.clear_marks();
+        self.continuations.clear_marks();
         mark_roots(self).expect("Failed to mark the roots!");
         let mut objs = Vec::new();
-        self.objects.trace_all_live(|obj| {
+        self.objects.trace_all_live(|idx, obj| {
             // this cloning is not great...
-            objs.push(obj.clone());
+            objs.push((idx, obj.clone()));
         });
-        for obj in &objs {
-            self.trace_object(obj);
+        for (idx, obj) in &objs {
+            self.trace_object(*idx, obj);
+        }
+        // Call frames (and continuations) marked as roots hold defers, on_error handlers, etc that
+        // need to stay alive, trace them like the objects above.
+        let mut frames = Vec::new();
+        self.callframes
+            .trace_all_live(|_, frame| frames.push(frame.clone()));
+        for frame in &frames {
+            self.mark_call_frame(frame);
+        }
+        let mut ks = Vec::new();
+        self.continuations.trace_all_live(|_, k| ks.push(k.clone()));
+        for k in &ks {
+            self.mark_call_frame(&k.frame);
+            for obj in &k.stack {
+                self.mark_trace(*obj);
+            }
         }
+        self.trace_greys();
+        self.trace_weak_maps();
+        self.sweep();
+        self.live_symbols = self.symbol_marks.take();
+    }
+
+    /// Trace the values of weak map entries whose keys turned out to be live, tracing them can
+    /// make more keys live so repeat until nothing changes.
+    fn trace_weak_maps(&mut self) {
+        loop {
+            let pending = std::mem::take(&mut self.weak_pending);
+            let (ready, pending): (Vec<_>, Vec<_>) =
+                pending.into_iter().partition(|(key, _)| self.is_live(*key));
+            self.weak_pending = pending;
+            if ready.is_empty() {
+                break;
+            }
+            for (_, val) in ready {
+                self.mark_trace(val);
+            }
+            self.trace_greys();
+        }
+        self.weak_pending.clear();
+    }
+
+    fn trace_greys(&mut self) {
         while let Some(val) = self.greys.pop() {
             if !self.is_traced_and_set(val) {
                 self.trace(val);
             }
         }
+    }
+
+    fn sweep(&mut self) {
+        self.collections += 1;
         // Sweep out collected properties.
         let mut props = self.props.take().expect("missing heap props");
         props.retain(|key, _val| self.is_live(*key));
         self.props = Some(props);
+        let objects = &self.objects;
+        self.bigints
+            .retain(|_, handle| objects.is_live(handle.idx()));
+        self.sweep_weak();
         self.objects.set_all_dead(Object::Empty);
+        self.objects.sweep();
+        self.errors.sweep();
+        self.callframes.sweep();
+        self.continuations.sweep();
+        self.count_bytes();
+    }
+
+    /// Clear weak references to dead objects and remove weak map entries with dead keys.
+    fn sweep_weak(&mut self) {
+        let mut weak = Vec::new();
+        let objects = self.objects.flags().iter().zip(self.objects.vals());
+        for (idx, (flag, obj)) in objects.enumerate() {
+            if is_live(*flag) && (is_weak(*flag) || matches!(obj, Object::Weak(_))) {
+                weak.push(idx);
+            }
+        }
+        for idx in weak {
+            match self.objects.get(idx) {
+                Some(Object::Weak(target)) if !self.is_live(*target) => {
+                    if let Some(obj) = self.objects.get_mut(idx) {
+                        *obj = Object::Weak(Value::Undefined);
+                    }
+                }
+                Some(Object::Map(map)) => {
+                    let dead: Vec<Value> = map
+                        .keys()
+                        .filter(|key| !self.is_live(**key))
+                        .copied()
+                        .collect();
+                    if let (false, Some(Object::Map(map))) =
+                        (dead.is_empty(), self.objects.get_mut(idx))
+                    {
+                        let map = Arc::make_mut(map);
+                        for key in dead {
+                            map.remove(&key);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn stop_marking(&mut self) {
+        self.marking = false;
+        self.objects.finish_marking();
+        self.errors.finish_marking();
+        self.callframes.finish_marking();
+        self.continuations.finish_marking();
+    }
+
+    /// Start an incremental collection, the roots and sticky objects go on the grey list to be
+    /// traced a step at a time by later allocations.
+    fn start_marking<MarkFunc>(&mut self, mut mark_roots: MarkFunc)
+    where
+        MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
+    {
+        self.greys.clear();
+        self.weak_pending.clear();
+        self.objects.start_marking();
+        self.errors.start_marking();
+        self.callframes.start_marking();
+        self.continuations.start_marking();
+        self.marking = true;
+        mark_roots(self).expect("Failed to mark the roots!");
+        let objects = self.objects.flags().iter().zip(self.objects.vals());
+        for (idx, (flag, obj)) in objects.enumerate() {
+            if is_bit_set!(*flag, FLAG_STICKY) {
+                if let Some(val) = obj.value(Handle::new(idx)) {
+                    self.greys.push(val);
+                }
+            }
+        }
+        for (idx, flag) in self.errors.flags().iter().enumerate() {
+            if is_bit_set!(*flag, FLAG_STICKY) {
+                self.greys.push(Value::Error(Handle::new(idx)));
+            }
+        }
+        for (idx, flag) in self.callframes.flags().iter().enumerate() {
+            if is_bit_set!(*flag, FLAG_STICKY) {
+                self.greys.push(Value::CallFrame(Handle::new(idx)));
+            }
+        }
+        for (idx, flag) in self.continuations.flags().iter().enumerate() {
+            if is_bit_set!(*flag, FLAG_STICKY) {
+                self.greys.push(Value::Continuation(Handle::new(idx)));
+            }
+        }
+    }
+
+    /// Trace up to gc_step objects from the grey list, finish the collection once it is empty.
+    fn mark_step<MarkFunc>(&mut self, mark_roots: MarkFunc)
+    where
+        MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
+    {
+        let mut budget = if self.gc_step == 0 {
+            usize::MAX
+        } else {
+            self.gc_step
+        };
+        while budget > 0 {
+            let Some(val) = self.greys.pop() else {
+                break;
+            };
+            if !self.is_traced_and_set(val) {
+                self.trace(val);
+                budget -= 1;
+            }
+        }
+        if self.greys.is_empty() {
+            self.finish_marking(mark_roots);
+        }
+    }
+
+    fn finish_marking<MarkFunc>(&mut self, mut mark_roots: MarkFunc)
+    where
+        MarkFunc: FnMut(&mut Heap) -> VMResult<()>,
+    {
+        // The stack and registers have no write barrier so rescan them, anything they reference
+        // that is not traced yet was stored since marking started.  Roots with a barrier (see
+        // root_barrier) are skipped so this only traces what is new.
+        self.rescanning = true;
+        let res = mark_roots(self);
+        self.rescanning = false;
+        res.expect("Failed to mark the roots!");
+        self.trace_greys();
+        self.trace_weak_maps();
+        self.stop_marking();
+        self.sweep();
     }
 
     pub fn capacity(&self) -> usize {
@@ -684,6 +1533,10 @@ impl Heap {
     }
 
     pub fn set_property(&mut self, key_value: Value, prop: Interned, value: Value) {
+        if self.marking {
+            // Write barrier, the key may already be traced.
+            self.mark_trace(value);
+        }
         if let Some(map) = self.props_mut().get_mut(&key_value) {
             let map = Arc::make_mut(map);
             map.insert(prop, value);
@@ -711,6 +1564,66 @@ mod tests {
     //    test_send_sync(Object::Value(Value::Nil));
     //}
 
+    #[test]
+    fn test_bigint_shared() -> VMResult<()> {
+        let mut heap = Heap::default();
+        let big = BigInt::from(i64::MAX) * BigInt::from(3);
+        let keep = heap.alloc_bigint(big.clone(), |_| Ok(())).unwrap();
+        let other = heap.alloc_bigint(big.clone() + 1, |_| Ok(())).unwrap();
+        assert_eq!(heap.alloc_bigint(big.clone(), |_| Ok(())).unwrap(), keep);
+        assert_ne!(keep, other);
+        // Collect everything but keep, the dead big int must not be handed out again.
+        let mark_roots = |heap: &mut Heap| -> VMResult<()> {
+            heap.mark(keep);
+            Ok(())
+        };
+        heap.collect(mark_roots);
+        assert!(!heap.is_live(other));
+        assert_eq!(heap.alloc_bigint(big.clone(), mark_roots).unwrap(), keep);
+        let other2 = heap.alloc_bigint(big.clone() + 1, mark_roots).unwrap();
+        assert_eq!(heap.get_bigint(other2.get_handle().unwrap()), &(big + 1));
+        Ok(())
+    }
+
+    #[test]
+    fn test_max_bytes() -> VMResult<()> {
+        let mut heap = Heap::default();
+        let live = std::rc::Rc::new(std::cell::RefCell::new(Vec::new()));
+        let mark_roots = |heap: &mut Heap| -> VMResult<()> {
+            for v in live.borrow().iter() {
+                heap.mark(*v);
+            }
+            Ok(())
+        };
+        heap.set_max_bytes(Some(10_000));
+        let big = "x".repeat(4_000);
+        for _ in 0..2 {
+            let s = heap.alloc_string(big.clone(), MutState::Mutable, mark_roots)?;
+            live.borrow_mut().push(s);
+        }
+        // The allocation that would go over the limit fails and nothing is allocated.
+        let live_objects = heap.live_objects();
+        let err = heap
+            .alloc_string(big.clone(), MutState::Mutable, mark_roots)
+            .unwrap_err();
+        assert_eq!(err.key, "oom");
+        assert_eq!(heap.live_objects(), live_objects);
+        // Garbage is collected to make room.
+        live.borrow_mut().pop();
+        let s = heap.alloc_string(big, MutState::Mutable, mark_roots)?;
+        live.borrow_mut().push(s);
+        assert!(heap.stats().used_bytes <= 10_000);
+        // Small objects are counted too.
+        heap.set_max_bytes(Some(heap.stats().used_bytes + 100));
+        let live_objects = heap.live_objects();
+        while let Ok(pair) = heap.alloc_pair(1.into(), Value::Nil, MutState::Mutable, mark_roots) {
+            live.borrow_mut().push(pair);
+        }
+        assert!(heap.live_objects() > live_objects);
+        assert!(heap.live_objects() < live_objects + 10);
+        Ok(())
+    }
+
     #[test]
     fn test_basic() -> VMResult<()> {
         let mut heap = Heap::default();
@@ -718,7 +1631,8 @@ mod tests {
         assert!(heap.capacity() == 512);
         assert!(heap.live_objects() == 0);
         for x in 0..512 {
-            heap.alloc_pair(x.into(), Value::Nil, MutState::Mutable, mark_roots);
+            heap.alloc_pair(x.into(), Value::Nil, MutState::Mutable, mark_roots)
+                .unwrap();
         }
         assert!(heap.capacity() == 512);
         assert!(heap.live_objects() == 512);
@@ -729,7 +1643,8 @@ mod tests {
                 panic!();
             }
         }
-        heap.alloc_pair(512.into(), Value::Nil, MutState::Mutable, mark_roots);
+        heap.alloc_pair(512.into(), Value::Nil, MutState::Mutable, mark_roots)
+            .unwrap();
         assert!(heap.capacity() == 512);
         assert!(heap.live_objects() == 1);
         if let (Value::Int(v), Value::Nil) = heap.get_pair(Handle::new(0)) {
@@ -744,7 +1659,8 @@ mod tests {
             Ok(())
         };
         for x in 0..512 {
-            heap.alloc_pair(x.into(), Value::Nil, MutState::Mutable, mark_roots);
+            heap.alloc_pair(x.into(), Value::Nil, MutState::Mutable, mark_roots)
+                .unwrap();
         }
         assert!(heap.capacity() == 1024);
         assert!(heap.live_objects() == 513);
@@ -775,14 +1691,18 @@ mod tests {
         assert!(heap.capacity() == 1024);
         assert!(heap.live_objects() == 0);
         for x in 0..512 {
-            let h = heap.alloc_pair(x.into(), Value::Nil, MutState::Mutable, mark_roots);
+            let h = heap
+                .alloc_pair(x.into(), Value::Nil, MutState::Mutable, mark_roots)
+                .unwrap();
             heap.sticky(h);
         }
         heap.collect(mark_roots);
         assert!(heap.capacity() == 1024);
         assert!(heap.live_objects() == 512);
         for x in 512..1024 {
-            let _h = heap.alloc_pair(x.into(), Value::Nil, MutState::Mutable, mark_roots);
+            let _h = heap
+                .alloc_pair(x.into(), Value::Nil, MutState::Mutable, mark_roots)
+                .unwrap();
         }
         let mark_roots = |heap: &mut Heap| -> VMResult<()> {
             for idx in 0..1024 {
@@ -793,7 +1713,8 @@ mod tests {
         heap.collect(mark_roots);
         assert!(heap.capacity() == 1024);
         assert!(heap.live_objects() == 1024);
-        heap.alloc_string("steve".into(), MutState::Mutable, mark_roots);
+        heap.alloc_string("steve".into(), MutState::Mutable, mark_roots)
+            .unwrap();
         assert!(heap.capacity() == 2048);
         assert!(heap.live_objects() == 1025);
         Ok(())
@@ -814,13 +1735,13 @@ mod tests {
             Ok(())
         };
         for x in 0..256 {
-            let inner = heap.alloc_pair(x.into(), Value::Nil, MutState::Mutable, mark_roots);
-            outers.borrow_mut().push(heap.alloc_pair(
-                inner,
-                Value::Nil,
-                MutState::Mutable,
-                mark_roots,
-            ));
+            let inner = heap
+                .alloc_pair(x.into(), Value::Nil, MutState::Mutable, mark_roots)
+                .unwrap();
+            outers.borrow_mut().push(
+                heap.alloc_pair(inner, Value::Nil, MutState::Mutable, mark_roots)
+                    .unwrap(),
+            );
         }
         assert!(heap.capacity() == 512);
         assert!(heap.live_objects() == 512);
@@ -856,6 +1777,247 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_incremental() -> VMResult<()> {
+        let mut heap = Heap::default();
+        heap.set_gc_step(1);
+        let roots = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+        let roots_mark = roots.clone();
+        let mark_roots = |heap: &mut Heap| -> VMResult<()> {
+            for h in roots_mark.borrow().iter() {
+                heap.mark(*h);
+            }
+            Ok(())
+        };
+        let b = heap
+            .alloc_pair(1.into(), Value::Nil, MutState::Mutable, mark_roots)
+            .unwrap();
+        let w = heap
+            .alloc_vector(vec![b], MutState::Mutable, mark_roots)
+            .unwrap();
+        let v = heap
+            .alloc_vector(vec![], MutState::Mutable, mark_roots)
+            .unwrap();
+        roots.borrow_mut().push(w);
+        roots.borrow_mut().push(v);
+
+        // Trace v (last root marked so first off the grey list) then move b from the untraced w
+        // into it, only the write barrier keeps b alive.
+        heap.start_marking(mark_roots);
+        heap.mark_step(mark_roots);
+        assert!(heap.is_marking());
+        // Unmarked objects still count as live until the collection is done.
+        assert_eq!(heap.live_objects(), 3);
+        heap.get_vector_mut(v.get_handle().unwrap())?.push(b);
+        heap.get_vector_mut(w.get_handle().unwrap())?[0] = Value::Nil;
+        // Allocating while marking must not reuse the slots of unmarked objects.
+        let garbage = heap
+            .alloc_pair(2.into(), Value::Nil, MutState::Mutable, mark_roots)
+            .unwrap();
+        while heap.is_marking() {
+            heap.mark_step(mark_roots);
+        }
+        assert!(heap.is_live(b));
+        assert!(heap.is_live(garbage));
+        assert_eq!(heap.live_objects(), 4);
+        assert_eq!(heap.get_pair(b.get_handle().unwrap()).0, 1.into());
+        assert_eq!(heap.get_pair(garbage.get_handle().unwrap()).0, 2.into());
+        heap.collect(mark_roots);
+        assert!(heap.is_live(b));
+        assert!(!heap.is_live(garbage));
+        assert_eq!(heap.live_objects(), 3);
+
+        // Lots of allocation driving incremental collections keeps everything reachable.
+        let mut list = Value::Nil;
+        for i in 0..4000 {
+            let pair = heap
+                .alloc_pair(i.into(), list, MutState::Mutable, mark_roots)
+                .unwrap();
+            if i % 4 == 0 {
+                list = pair;
+                heap.get_vector_mut(v.get_handle().unwrap())?[0] = list;
+            }
+        }
+        let mut i = 3996;
+        while let Value::Pair(h) = list {
+            let (car, cdr) = heap.get_pair(h);
+            assert_eq!(car, i.into());
+            i -= 4;
+            list = cdr;
+        }
+        assert_eq!(i, -4);
+        assert!(heap.capacity() < 8192);
+        Ok(())
+    }
+
+    #[test]
+    fn test_host_finalize() -> VMResult<()> {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        struct Counted(Rc<Cell<usize>>);
+
+        impl HostObject for Counted {
+            fn type_name(&self) -> &'static str {
+                "Counted"
+            }
+
+            fn finalize(&mut self) {
+                self.0.set(self.0.get() + 1);
+            }
+        }
+
+        let mut heap = Heap::default();
+        let count = Rc::new(Cell::new(0));
+        let mark_roots = |_heap: &mut Heap| -> VMResult<()> { Ok(()) };
+        let host = heap.alloc_host(Box::new(Counted(count.clone())), mark_roots)?;
+        // A clone of the object (like the ones the collector makes while tracing) outlives the
+        // collection that frees it, it is finalized once the clone goes.
+        let clone = heap.objects.get(host.get_handle().unwrap().idx()).cloned();
+        heap.collect(mark_roots);
+        assert!(!heap.is_live(host));
+        assert_eq!(count.get(), 0);
+        drop(clone);
+        assert_eq!(count.get(), 1);
+        heap.collect(mark_roots);
+        assert_eq!(count.get(), 1);
+
+        // Finalized once when collected and, if still live, when the heap is dropped.
+        heap.alloc_host(Box::new(Counted(count.clone())), mark_roots)?;
+        heap.collect(mark_roots);
+        heap.collect(mark_roots);
+        assert_eq!(count.get(), 2);
+        let host = heap.alloc_host(Box::new(Counted(count.clone())), mark_roots)?;
+        heap.sticky(host);
+        heap.collect(mark_roots);
+        assert_eq!(count.get(), 2);
+        drop(heap);
+        assert_eq!(count.get(), 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rescan_roots() -> VMResult<()> {
+        let mut heap = Heap::default();
+        heap.set_gc_step(1);
+        // Globals (with a barrier) are skipped by the rescan, the stack is not.
+        let globals = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+        let stack = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+        let globals_mark = globals.clone();
+        let stack_mark = stack.clone();
+        let rescans = std::cell::Cell::new(0);
+        let mark_roots = |heap: &mut Heap| -> VMResult<()> {
+            if heap.is_rescanning() {
+                rescans.set(rescans.get() + 1);
+            } else {
+                for h in globals_mark.borrow().iter() {
+                    heap.mark(*h);
+                }
+            }
+            for h in stack_mark.borrow().iter() {
+                heap.mark(*h);
+            }
+            Ok(())
+        };
+        let b = heap
+            .alloc_pair(1.into(), Value::Nil, MutState::Mutable, mark_roots)
+            .unwrap();
+        let s = heap
+            .alloc_pair(2.into(), Value::Nil, MutState::Mutable, mark_roots)
+            .unwrap();
+        let v = heap
+            .alloc_vector(vec![b, s], MutState::Mutable, mark_roots)
+            .unwrap();
+        globals.borrow_mut().push(v);
+
+        // Move b to a new global and s to the stack before v is traced.
+        heap.start_marking(mark_roots);
+        heap.root_barrier(b);
+        globals.borrow_mut().push(b);
+        stack.borrow_mut().push(s);
+        heap.get_vector_mut(v.get_handle().unwrap())?.clear();
+        while heap.is_marking() {
+            heap.mark_step(mark_roots);
+        }
+        assert_eq!(rescans.get(), 1);
+        assert!(heap.is_live(b));
+        assert!(heap.is_live(s));
+        assert_eq!(heap.get_pair(b.get_handle().unwrap()).0, 1.into());
+        assert_eq!(heap.get_pair(s.get_handle().unwrap()).0, 2.into());
+        Ok(())
+    }
+
+    #[test]
+    fn test_weak() -> VMResult<()> {
+        for step in [0, 1] {
+            let mut heap = Heap::default();
+            heap.set_gc_step(step);
+            let roots = std::rc::Rc::new(std::cell::RefCell::new(vec![]));
+            let roots_mark = roots.clone();
+            let mark_roots = |heap: &mut Heap| -> VMResult<()> {
+                for h in roots_mark.borrow().iter() {
+                    heap.mark(*h);
+                }
+                Ok(())
+            };
+            let dead = heap
+                .alloc_pair(1.into(), Value::Nil, MutState::Mutable, mark_roots)
+                .unwrap();
+            let live = heap
+                .alloc_pair(2.into(), Value::Nil, MutState::Mutable, mark_roots)
+                .unwrap();
+            let weak_dead = heap.alloc_weak(dead, mark_roots).unwrap();
+            let weak_live = heap.alloc_weak(live, mark_roots).unwrap();
+            let weak_int = heap.alloc_weak(3.into(), mark_roots).unwrap();
+            // Values refer to their keys, the map must not keep either alive.
+            let dead_key = heap
+                .alloc_pair(4.into(), Value::Nil, MutState::Mutable, mark_roots)
+                .unwrap();
+            let dead_val = heap
+                .alloc_pair(dead_key, Value::Nil, MutState::Mutable, mark_roots)
+                .unwrap();
+            let live_val = heap
+                .alloc_pair(live, Value::Nil, MutState::Mutable, mark_roots)
+                .unwrap();
+            let mut map = VMMap::new();
+            map.insert(dead_key, dead_val).unwrap();
+            map.insert(live, live_val).unwrap();
+            map.insert(5.into(), 6.into()).unwrap();
+            let weak_map = heap.alloc_weak_map(map, mark_roots).unwrap();
+            heap.set_property(dead, Interned { id: 0 }, Value::True);
+            roots
+                .borrow_mut()
+                .extend([live, weak_dead, weak_live, weak_int, weak_map]);
+
+            if step == 0 {
+                heap.collect(mark_roots);
+            } else {
+                heap.start_marking(mark_roots);
+                while heap.is_marking() {
+                    heap.mark_step(mark_roots);
+                }
+            }
+            assert_eq!(heap.get_weak(weak_dead.get_handle().unwrap()), None);
+            assert_eq!(heap.get_weak(weak_live.get_handle().unwrap()), Some(live));
+            assert_eq!(
+                heap.get_weak(weak_int.get_handle().unwrap()),
+                Some(3.into())
+            );
+            assert!(!heap.is_live(dead));
+            assert!(!heap.is_live(dead_key));
+            assert!(!heap.is_live(dead_val));
+            assert!(heap.is_live(live_val));
+            assert!(heap.is_weak_map(weak_map.get_handle().unwrap()));
+            let map = heap.get_map(weak_map.get_handle().unwrap());
+            assert_eq!(map.len(), 2);
+            assert_eq!(map.get(&live), Some(&live_val));
+            assert_eq!(map.get(&5.into()), Some(&6.into()));
+            // Properties of dead objects are dropped.
+            assert!(heap.props().is_empty());
+        }
+        Ok(())
+    }
+
     #[test]
     fn test_trace_vec() -> VMResult<()> {
         let mut heap = Heap::default();
@@ -872,14 +2034,19 @@ mod tests {
         };
         let mut v = vec![];
         for x in 0..256 {
-            let inner = heap.alloc_pair(x.into(), Value::Nil, MutState::Mutable, mark_roots);
+            let inner = heap
+                .alloc_pair(x.into(), Value::Nil, MutState::Mutable, mark_roots)
+                .unwrap();
             v.push(inner);
         }
-        outers.borrow_mut().push(Value::Vector(heap.alloc(
-            Object::Vector(Arc::new(v)),
-            MutState::Mutable.flag(),
-            mark_roots,
-        )));
+        outers.borrow_mut().push(Value::Vector(
+            heap.alloc(
+                Object::Vector(Arc::new(v)),
+                MutState::Mutable.flag(),
+                mark_roots,
+            )
+            .unwrap(),
+        ));
         assert!(heap.capacity() == 512);
         assert!(heap.live_objects() == 257);
         for h in outers.borrow().iter() {
@@ -944,23 +2111,28 @@ mod tests {
             }
             Ok(())
         };
-        outers.borrow_mut().push(heap.alloc_pair(
-            1.into(),
-            2.into(),
-            MutState::Mutable,
-            mark_roots,
-        ));
-        let car_h = heap.alloc_pair(3.into(), Value::Nil, MutState::Mutable, mark_roots);
-        let cdr_h = heap.alloc_pair(4.into(), Value::Nil, MutState::Mutable, mark_roots);
-        outers
-            .borrow_mut()
-            .push(heap.alloc_pair(car_h, 2.into(), MutState::Mutable, mark_roots));
-        outers
-            .borrow_mut()
-            .push(heap.alloc_pair(1.into(), cdr_h, MutState::Mutable, mark_roots));
-        outers
-            .borrow_mut()
-            .push(heap.alloc_pair(car_h, cdr_h, MutState::Mutable, mark_roots));
+        outers.borrow_mut().push(
+            heap.alloc_pair(1.into(), 2.into(), MutState::Mutable, mark_roots)
+                .unwrap(),
+        );
+        let car_h = heap
+            .alloc_pair(3.into(), Value::Nil, MutState::Mutable, mark_roots)
+            .unwrap();
+        let cdr_h = heap
+            .alloc_pair(4.into(), Value::Nil, MutState::Mutable, mark_roots)
+            .unwrap();
+        outers.borrow_mut().push(
+            heap.alloc_pair(car_h, 2.into(), MutState::Mutable, mark_roots)
+                .unwrap(),
+        );
+        outers.borrow_mut().push(
+            heap.alloc_pair(1.into(), cdr_h, MutState::Mutable, mark_roots)
+                .unwrap(),
+        );
+        outers.borrow_mut().push(
+            heap.alloc_pair(car_h, cdr_h, MutState::Mutable, mark_roots)
+                .unwrap(),
+        );
         assert_eq!(heap.capacity(), 512);
         assert_eq!(heap.live_objects(), 6);
         heap.collect(mark_roots);
@@ -2,6 +2,8 @@ pub const FLAG_MARK: u8 = 0x01;
 pub const FLAG_STICKY: u8 = 0x02;
 pub const FLAG_MUT: u8 = 0x04;
 pub const FLAG_TRACED: u8 = 0x08;
+// A map with weak keys.
+pub const FLAG_WEAK: u8 = 0x10;
 
 #[macro_export]
 macro_rules! is_bit_set {
@@ -41,3 +43,7 @@ pub fn is_mutable(flag: u8) -> bool {
 pub fn is_traced(flag: u8) -> bool {
     is_bit_set!(flag, FLAG_TRACED)
 }
+
+pub fn is_weak(flag: u8) -> bool {
+    is_bit_set!(flag, FLAG_WEAK)
+}
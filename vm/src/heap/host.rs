@@ -0,0 +1,65 @@
+//! Host objects, Rust values owned by the VM (file handles, connections, parser state, etc) that
+//! Lisp code can hold and pass back to builtins as opaque values.
+
+use std::any::Any;
+use std::fmt::{Debug, Formatter};
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
+
+use crate::Value;
+
+/// A Rust value that can live on the heap as a Value::Host.
+///
+/// Builtins get the concrete type back with GVm::get_host_ref and GVm::get_host_mut, these check
+/// the type (a mismatch is an error, never a bad cast).
+pub trait HostObject: Any {
+    /// Name of the type, used by type and when displaying the value.
+    fn type_name(&self) -> &'static str;
+
+    /// Call mark on any Values this object holds so they are kept alive while it is.
+    fn trace(&self, _mark: &mut dyn FnMut(Value)) {}
+
+    /// Called exactly once, just before the object is dropped.  That is when the garbage collector
+    /// frees it (or, if a reference the collector made while tracing it is still around, when that
+    /// is released) or when the heap it is on is dropped.
+    fn finalize(&mut self) {}
+}
+
+/// A host object on the heap, finalizes it when dropped so finalize runs once however many
+/// references to it are around when it is freed.
+#[derive(Debug)]
+pub(crate) struct HostBox(Box<dyn HostObject>);
+
+impl HostBox {
+    // Heap objects are all in an Arc, the heap (and its host objects) stays on one thread.
+    #[allow(clippy::arc_with_non_send_sync)]
+    pub(crate) fn new(obj: Box<dyn HostObject>) -> Arc<Self> {
+        Arc::new(Self(obj))
+    }
+}
+
+impl Deref for HostBox {
+    type Target = dyn HostObject;
+
+    fn deref(&self) -> &Self::Target {
+        &*self.0
+    }
+}
+
+impl DerefMut for HostBox {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut *self.0
+    }
+}
+
+impl Drop for HostBox {
+    fn drop(&mut self) {
+        self.0.finalize();
+    }
+}
+
+impl Debug for dyn HostObject {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "#<{}>", self.type_name())
+    }
+}
@@ -0,0 +1,733 @@
+//! Save and restore the complete contents of a heap (used by VM images).
+//!
+//! Handles are saved as is so a restored heap has every object at the same index it had when
+//! saved.  Chunks are shared (Arc) between lambdas, closures and call frames, they are written
+//! once to a chunk table and referenced by index so the sharing survives a round trip.
+//! Instruction pointers in call frames are saved as offsets into their chunk's code.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{Read, Write};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::chunk::bytecode::{
+    read_bytes, read_raw_value, read_string, read_u32, read_u64, read_u8, write_bytes, write_len,
+    write_raw_value, write_str, write_u32, write_u64, write_u8,
+};
+use crate::heap::storage::Storage;
+use crate::{
+    get_code, BigInt, CallFrame, Chunk, Continuation, Error, FxHashMap, Handle, Heap, Interned,
+    Interner, PMap, PVec, SortKey, VMError, VMMap, VMResult, VMSet, Value,
+};
+
+use super::Object;
+
+const OBJ_STRING: u8 = 0;
+const OBJ_VECTOR: u8 = 1;
+const OBJ_MAP: u8 = 2;
+const OBJ_BYTES: u8 = 3;
+const OBJ_PAIR: u8 = 4;
+const OBJ_VALUE: u8 = 5;
+const OBJ_LAMBDA: u8 = 6;
+const OBJ_CLOSURE: u8 = 7;
+const OBJ_EMPTY: u8 = 8;
+const OBJ_BIGINT: u8 = 9;
+const OBJ_WEAK: u8 = 10;
+// Same as OBJ_MAP (entries in key order) for a sorted map.
+const OBJ_SORTED_MAP: u8 = 11;
+// Persistent collections are saved as their items, structure shared between them is not kept.
+const OBJ_PVEC: u8 = 12;
+const OBJ_PMAP: u8 = 13;
+const OBJ_SET: u8 = 14;
+
+// Offset used for an instruction pointer that does not point into its frame's chunk.
+const NO_OFFSET: u64 = u64::MAX;
+
+/// Table of the unique chunks referenced by a heap, keyed by Arc pointer.
+#[derive(Default)]
+struct ChunkTable {
+    index: HashMap<*const Chunk, u32>,
+    chunks: Vec<Arc<Chunk>>,
+}
+
+impl ChunkTable {
+    fn add(&mut self, chunk: &Arc<Chunk>) {
+        let ptr = Arc::as_ptr(chunk);
+        if !self.index.contains_key(&ptr) {
+            self.index.insert(ptr, self.chunks.len() as u32);
+            self.chunks.push(chunk.clone());
+        }
+    }
+
+    fn get(&self, chunk: &Arc<Chunk>) -> u32 {
+        *self
+            .index
+            .get(&Arc::as_ptr(chunk))
+            .expect("chunk missing from the image chunk table")
+    }
+}
+
+fn read_len<R: Read>(input: &mut R) -> VMResult<usize> {
+    Ok(read_u32(input)? as usize)
+}
+
+fn read_handle<R: Read>(input: &mut R) -> VMResult<Handle> {
+    Ok(Handle::new32(read_u32(input)?))
+}
+
+fn write_opt_value<W: Write>(out: &mut W, val: Option<Value>) -> VMResult<()> {
+    if let Some(val) = val {
+        write_u8(out, 1)?;
+        write_raw_value(out, val)
+    } else {
+        write_u8(out, 0)
+    }
+}
+
+fn read_opt_value<R: Read>(input: &mut R) -> VMResult<Option<Value>> {
+    if read_u8(input)? != 0 {
+        Ok(Some(read_raw_value(input)?))
+    } else {
+        Ok(None)
+    }
+}
+
+fn write_values<W: Write>(out: &mut W, vals: &[Value]) -> VMResult<()> {
+    write_len(out, vals.len())?;
+    for val in vals {
+        write_raw_value(out, *val)?;
+    }
+    Ok(())
+}
+
+fn read_values<R: Read>(input: &mut R) -> VMResult<Vec<Value>> {
+    let len = read_len(input)?;
+    let mut vals = Vec::with_capacity(len);
+    for _ in 0..len {
+        vals.push(read_raw_value(input)?);
+    }
+    Ok(vals)
+}
+
+fn ip_offset(chunk: &Chunk, ip: *const u8) -> u64 {
+    let start = get_code!(chunk) as usize;
+    let ip = ip as usize;
+    if ip >= start && ip <= start + chunk.code.len() {
+        (ip - start) as u64
+    } else {
+        NO_OFFSET
+    }
+}
+
+fn offset_ip(chunk: &Chunk, offset: u64) -> VMResult<*const u8> {
+    if offset == NO_OFFSET {
+        Ok(get_code!(chunk))
+    } else if offset as usize <= chunk.code.len() {
+        Ok(unsafe { get_code!(chunk).add(offset as usize) })
+    } else {
+        Err(VMError::new_chunk("Image: invalid call frame offset."))
+    }
+}
+
+fn write_storage<T, W, F>(out: &mut W, storage: &Storage<T>, mut write_val: F) -> VMResult<()>
+where
+    T: Clone,
+    W: Write,
+    F: FnMut(&mut W, &T) -> VMResult<()>,
+{
+    let (flags, live_objects) = storage.image_flags();
+    write_len(out, storage.capacity())?;
+    write_len(out, live_objects)?;
+    write_len(out, storage.sticky_objects())?;
+    write_u64(out, storage.grow_factor().to_bits())?;
+    write_bytes(out, &flags)?;
+    for val in storage.vals() {
+        write_val(out, val)?;
+    }
+    Ok(())
+}
+
+fn read_storage<T, R, F>(input: &mut R, mut read_val: F) -> VMResult<Storage<T>>
+where
+    T: Clone,
+    R: Read,
+    F: FnMut(&mut R) -> VMResult<T>,
+{
+    let capacity = read_len(input)?;
+    let live_objects = read_len(input)?;
+    let sticky_objects = read_len(input)?;
+    let grow_factor = f64::from_bits(read_u64(input)?);
+    let flags = read_bytes(input)?;
+    let mut vals = Vec::with_capacity(flags.len());
+    for _ in 0..flags.len() {
+        vals.push(read_val(input)?);
+    }
+    Ok(Storage::from_parts(
+        flags,
+        vals,
+        capacity,
+        live_objects,
+        sticky_objects,
+        grow_factor,
+    ))
+}
+
+fn write_call_frame<W: Write>(out: &mut W, frame: &CallFrame, chunks: &ChunkTable) -> VMResult<()> {
+    write_u64(out, frame.id as u64)?;
+    write_u32(out, chunks.get(&frame.chunk))?;
+    write_u64(out, ip_offset(&frame.chunk, frame.ip))?;
+    write_u64(out, ip_offset(&frame.chunk, frame.current_ip))?;
+    write_u64(out, frame.stack_top as u64)?;
+    write_opt_value(out, frame.this_fn)?;
+    write_values(out, &frame.defers)?;
+    write_opt_value(out, frame.on_error)?;
+    write_raw_value(out, frame.called)
+}
+
+fn read_call_frame<R: Read>(input: &mut R, chunks: &[Arc<Chunk>]) -> VMResult<CallFrame> {
+    let id = read_u64(input)? as usize;
+    let chunk = read_chunk_ref(input, chunks)?;
+    let ip = offset_ip(&chunk, read_u64(input)?)?;
+    let current_ip = offset_ip(&chunk, read_u64(input)?)?;
+    Ok(CallFrame {
+        id,
+        chunk,
+        ip,
+        current_ip,
+        stack_top: read_u64(input)? as usize,
+        this_fn: read_opt_value(input)?,
+        defers: read_values(input)?,
+        on_error: read_opt_value(input)?,
+        called: read_raw_value(input)?,
+    })
+}
+
+fn read_chunk_ref<R: Read>(input: &mut R, chunks: &[Arc<Chunk>]) -> VMResult<Arc<Chunk>> {
+    let idx = read_len(input)?;
+    chunks
+        .get(idx)
+        .cloned()
+        .ok_or_else(|| VMError::new_chunk("Image: invalid chunk reference."))
+}
+
+fn write_object<W: Write>(out: &mut W, obj: &Object, chunks: &ChunkTable) -> VMResult<()> {
+    match obj {
+        Object::String(s) => {
+            write_u8(out, OBJ_STRING)?;
+            write_str(out, s)
+        }
+        Object::Vector(v) => {
+            write_u8(out, OBJ_VECTOR)?;
+            write_values(out, v)
+        }
+        Object::Map(map) => {
+            write_u8(
+                out,
+                if map.is_sorted() {
+                    OBJ_SORTED_MAP
+                } else {
+                    OBJ_MAP
+                },
+            )?;
+            write_len(out, map.len())?;
+            for (key, val) in map.iter() {
+                write_raw_value(out, *key)?;
+                write_raw_value(out, *val)?;
+            }
+            Ok(())
+        }
+        Object::Set(set) => {
+            write_u8(out, OBJ_SET)?;
+            write_len(out, set.len())?;
+            for item in set.iter() {
+                write_raw_value(out, *item)?;
+            }
+            Ok(())
+        }
+        Object::Bytes(b) => {
+            write_u8(out, OBJ_BYTES)?;
+            write_bytes(out, b)
+        }
+        Object::Pair(pair) => {
+            write_u8(out, OBJ_PAIR)?;
+            write_raw_value(out, pair.0)?;
+            write_raw_value(out, pair.1)
+        }
+        Object::Value(val) => {
+            write_u8(out, OBJ_VALUE)?;
+            write_raw_value(out, *val)
+        }
+        Object::Lambda(chunk) => {
+            write_u8(out, OBJ_LAMBDA)?;
+            write_u32(out, chunks.get(chunk))
+        }
+        Object::Closure(closure) => {
+            write_u8(out, OBJ_CLOSURE)?;
+            write_u32(out, chunks.get(&closure.0))?;
+            write_len(out, closure.1.len())?;
+            for handle in &closure.1 {
+                write_u32(out, handle.idx() as u32)?;
+            }
+            Ok(())
+        }
+        Object::BigInt(i) => {
+            write_u8(out, OBJ_BIGINT)?;
+            write_bytes(out, &i.to_signed_bytes_be())
+        }
+        Object::PVec(vec) => {
+            write_u8(out, OBJ_PVEC)?;
+            write_len(out, vec.len())?;
+            for val in vec.iter() {
+                write_raw_value(out, *val)?;
+            }
+            Ok(())
+        }
+        Object::PMap(map) => {
+            write_u8(out, OBJ_PMAP)?;
+            write_len(out, map.len())?;
+            for (key, val) in map.iter() {
+                write_raw_value(out, *key)?;
+                write_raw_value(out, *val)?;
+            }
+            Ok(())
+        }
+        Object::Weak(target) => {
+            write_u8(out, OBJ_WEAK)?;
+            write_raw_value(out, *target)
+        }
+        Object::Host(obj) => Err(VMError::new_chunk(format!(
+            "Image: can not save host object {}.",
+            obj.type_name()
+        ))),
+        Object::Empty => write_u8(out, OBJ_EMPTY),
+    }
+}
+
+// Sorted maps are read empty and their entries pushed on sorted, the keys can only be ordered
+// once the heap they refer to is read.
+fn read_object<R: Read>(
+    input: &mut R,
+    chunks: &[Arc<Chunk>],
+    sorted: &mut Vec<Vec<(Value, Value)>>,
+) -> VMResult<Object> {
+    let tag = read_u8(input)?;
+    Ok(match tag {
+        OBJ_STRING => Object::String(Arc::new(read_string(input)?)),
+        OBJ_VECTOR => Object::Vector(Arc::new(read_values(input)?)),
+        OBJ_MAP => {
+            let len = read_len(input)?;
+            let mut map = VMMap::with_capacity(len);
+            for _ in 0..len {
+                let key = read_raw_value(input)?;
+                let val = read_raw_value(input)?;
+                map.insert(key, val)?;
+            }
+            Object::Map(Arc::new(map))
+        }
+        OBJ_SORTED_MAP => {
+            let len = read_len(input)?;
+            let mut entries = Vec::with_capacity(len);
+            for _ in 0..len {
+                entries.push((read_raw_value(input)?, read_raw_value(input)?));
+            }
+            sorted.push(entries);
+            Object::Map(Arc::new(VMMap::new_sorted()))
+        }
+        OBJ_SET => {
+            let len = read_len(input)?;
+            let mut set = VMSet::with_capacity(len);
+            for _ in 0..len {
+                set.insert(read_raw_value(input)?);
+            }
+            Object::Set(Arc::new(set))
+        }
+        OBJ_BYTES => Object::Bytes(Arc::new(read_bytes(input)?)),
+        OBJ_PAIR => {
+            let car = read_raw_value(input)?;
+            let cdr = read_raw_value(input)?;
+            Object::Pair(Arc::new((car, cdr)))
+        }
+        OBJ_VALUE => Object::Value(read_raw_value(input)?),
+        OBJ_LAMBDA => Object::Lambda(read_chunk_ref(input, chunks)?),
+        OBJ_CLOSURE => {
+            let chunk = read_chunk_ref(input, chunks)?;
+            let len = read_len(input)?;
+            let mut captures = Vec::with_capacity(len);
+            for _ in 0..len {
+                captures.push(read_handle(input)?);
+            }
+            Object::Closure(Arc::new((chunk, captures)))
+        }
+        OBJ_BIGINT => Object::BigInt(Arc::new(BigInt::from_signed_bytes_be(&read_bytes(input)?))),
+        OBJ_PVEC => {
+            let len = read_len(input)?;
+            let mut vec = PVec::new();
+            for _ in 0..len {
+                vec = vec.push(read_raw_value(input)?);
+            }
+            Object::PVec(Arc::new(vec))
+        }
+        OBJ_PMAP => {
+            let len = read_len(input)?;
+            let mut map = PMap::new();
+            for _ in 0..len {
+                let key = read_raw_value(input)?;
+                let val = read_raw_value(input)?;
+                map = map.insert(key, val);
+            }
+            Object::PMap(Arc::new(map))
+        }
+        OBJ_WEAK => Object::Weak(read_raw_value(input)?),
+        OBJ_EMPTY => Object::Empty,
+        _ => {
+            return Err(VMError::new_chunk(format!(
+                "Image: invalid heap object tag {tag:#04x}."
+            )))
+        }
+    })
+}
+
+/// How one value references another, a step on the path to an object that can not be saved (see
+/// Heap::ref_paths).
+#[derive(Clone, Debug)]
+pub enum RefStep {
+    /// Item of a vector, list or persistent vector.
+    Index(usize),
+    Car,
+    Cdr,
+    /// A key of a map.
+    Key,
+    /// The value a map has for this key.
+    Entry(Value),
+    /// An item of a set.
+    Item,
+    /// A captured value of a closure.
+    Capture(usize),
+    /// A constant of a lambda's (or call frame's) chunk.
+    Constant(usize),
+    /// A heap property.
+    Property(Interned),
+    /// A value held by a host object of this type (see HostObject::trace).
+    Inside(&'static str),
+    /// Anything else (the value in a box, defers of a call frame, etc).
+    Field(&'static str),
+}
+
+impl Heap {
+    /// Every host object in the heap, these can not be saved in an image.
+    pub(crate) fn host_objects(&self) -> Vec<Value> {
+        self.objects
+            .vals()
+            .iter()
+            .enumerate()
+            .filter(|(idx, obj)| matches!(obj, Object::Host(_)) && self.objects.is_live(*idx))
+            .map(|(idx, _)| Value::Host(Handle::new(idx)))
+            .collect()
+    }
+
+    /// Find the shortest path of references from one of roots to each target, None if a target
+    /// can not be reached from the roots.  The path is the root it starts at and the steps from it.
+    pub(crate) fn ref_paths<R: Copy>(
+        &self,
+        roots: &[(R, Value)],
+        targets: &[Value],
+    ) -> Vec<Option<(R, Vec<RefStep>)>> {
+        let mut root_of: HashMap<Value, R> = HashMap::new();
+        let mut parents: HashMap<Value, (Value, RefStep)> = HashMap::new();
+        let mut queue = VecDeque::new();
+        for (root, val) in roots {
+            if val.get_handle().is_some() && !root_of.contains_key(val) {
+                root_of.insert(*val, *root);
+                queue.push_back(*val);
+            }
+        }
+        while let Some(val) = queue.pop_front() {
+            for (step, child) in self.refs(val) {
+                if child.get_handle().is_some()
+                    && !root_of.contains_key(&child)
+                    && !parents.contains_key(&child)
+                {
+                    parents.insert(child, (val, step));
+                    queue.push_back(child);
+                }
+            }
+        }
+        targets
+            .iter()
+            .map(|target| {
+                let mut steps = Vec::new();
+                let mut val = *target;
+                while let Some((parent, step)) = parents.get(&val) {
+                    steps.push(step.clone());
+                    val = *parent;
+                }
+                steps.reverse();
+                root_of.get(&val).map(|root| (*root, steps))
+            })
+            .collect()
+    }
+
+    /// The values val references directly (the ones tracing it would mark) and how.
+    fn refs(&self, val: Value) -> Vec<(RefStep, Value)> {
+        let mut refs = Vec::new();
+        if let Some(props) = self.props().get(&val) {
+            for (key, val) in props.iter() {
+                refs.push((RefStep::Property(*key), *val));
+            }
+        }
+        let chunk_refs = |refs: &mut Vec<(RefStep, Value)>, chunk: &Chunk| {
+            for (i, constant) in chunk.constants.iter().enumerate() {
+                refs.push((RefStep::Constant(i), *constant));
+            }
+        };
+        let frame_refs = |refs: &mut Vec<(RefStep, Value)>, frame: &CallFrame| {
+            chunk_refs(refs, &frame.chunk);
+            if let Some(this_fn) = frame.this_fn {
+                refs.push((RefStep::Field("this-fn"), this_fn));
+            }
+            for defer in &frame.defers {
+                refs.push((RefStep::Field("defer"), *defer));
+            }
+            if let Some(on_error) = frame.on_error {
+                refs.push((RefStep::Field("on-error"), on_error));
+            }
+            refs.push((RefStep::Field("called"), frame.called));
+        };
+        match val {
+            Value::CallFrame(handle) => {
+                if let Some(frame) = self.callframes.get(handle.idx()) {
+                    frame_refs(&mut refs, frame);
+                }
+            }
+            Value::Continuation(handle) => {
+                if let Some(k) = self.continuations.get(handle.idx()) {
+                    frame_refs(&mut refs, &k.frame);
+                    for val in &k.stack {
+                        refs.push((RefStep::Field("stack"), *val));
+                    }
+                }
+            }
+            Value::Error(handle) => {
+                if let Some(err) = self.errors.get(handle.idx()) {
+                    refs.push((RefStep::Field("error data"), err.data));
+                }
+            }
+            Value::List(handle, start) => {
+                if let Some(Object::Vector(vec)) = self.objects.get(handle.idx()) {
+                    let start = start as usize;
+                    for (i, item) in vec.iter().enumerate().skip(start) {
+                        refs.push((RefStep::Index(i - start), *item));
+                    }
+                }
+            }
+            _ => {
+                let Some(handle) = val.get_handle() else {
+                    return refs;
+                };
+                match self.objects.get(handle.idx()) {
+                    Some(Object::Vector(vec)) => {
+                        for (i, item) in vec.iter().enumerate() {
+                            refs.push((RefStep::Index(i), *item));
+                        }
+                    }
+                    Some(Object::PVec(vec)) => {
+                        for (i, item) in vec.iter().enumerate() {
+                            refs.push((RefStep::Index(i), *item));
+                        }
+                    }
+                    Some(Object::Map(map)) => {
+                        let weak = self.objects.is_weak(handle.idx());
+                        for (key, val) in map.iter() {
+                            if !weak {
+                                refs.push((RefStep::Key, *key));
+                            }
+                            refs.push((RefStep::Entry(*key), *val));
+                        }
+                    }
+                    Some(Object::PMap(map)) => {
+                        for (key, val) in map.iter() {
+                            refs.push((RefStep::Key, *key));
+                            refs.push((RefStep::Entry(*key), *val));
+                        }
+                    }
+                    Some(Object::Set(set)) => {
+                        for item in set.iter() {
+                            refs.push((RefStep::Item, *item));
+                        }
+                    }
+                    Some(Object::Pair(pair)) => {
+                        refs.push((RefStep::Car, pair.0));
+                        refs.push((RefStep::Cdr, pair.1));
+                    }
+                    Some(Object::Value(val)) => refs.push((RefStep::Field("value"), *val)),
+                    Some(Object::Lambda(chunk)) => chunk_refs(&mut refs, chunk),
+                    Some(Object::Closure(clos)) => {
+                        chunk_refs(&mut refs, &clos.0);
+                        for (i, close) in clos.1.iter().enumerate() {
+                            refs.push((RefStep::Capture(i), Value::Value(*close)));
+                        }
+                    }
+                    Some(Object::Host(obj)) => {
+                        let name = obj.type_name();
+                        obj.trace(&mut |val| refs.push((RefStep::Inside(name), val)));
+                    }
+                    _ => {}
+                }
+            }
+        }
+        refs
+    }
+}
+
+impl Heap {
+    /// Write the entire heap to out.
+    pub(crate) fn write_image<W: Write>(&self, out: &mut W) -> VMResult<()> {
+        let mut chunks = ChunkTable::default();
+        for obj in self.objects.vals() {
+            match obj {
+                Object::Lambda(chunk) => chunks.add(chunk),
+                Object::Closure(closure) => chunks.add(&closure.0),
+                _ => {}
+            }
+        }
+        for frame in self.callframes.vals() {
+            chunks.add(&frame.chunk);
+        }
+        for k in self.continuations.vals() {
+            chunks.add(&k.frame.chunk);
+        }
+        write_len(out, chunks.chunks.len())?;
+        for chunk in &chunks.chunks {
+            chunk.write_raw(out)?;
+        }
+
+        write_storage(out, &self.objects, |out, obj| {
+            write_object(out, obj, &chunks)
+        })?;
+        write_storage(out, &self.callframes, |out, frame| {
+            write_call_frame(out, frame, &chunks)
+        })?;
+        write_storage(out, &self.continuations, |out, k| {
+            write_call_frame(out, &k.frame, &chunks)?;
+            write_u64(out, k.arg_reg as u64)?;
+            write_values(out, &k.stack)
+        })?;
+        write_storage(out, &self.errors, |out, err| {
+            write_u32(out, err.keyword.id)?;
+            write_raw_value(out, err.data)
+        })?;
+
+        let props = self.props();
+        write_len(out, props.len())?;
+        for (key, map) in props {
+            write_raw_value(out, *key)?;
+            write_len(out, map.len())?;
+            for (prop, val) in map.iter() {
+                write_u32(out, prop.id)?;
+                write_raw_value(out, *val)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read a heap written with write_image.  Chunk file names are interned into interner, it
+    /// should already contain the symbols saved with the heap.
+    pub(crate) fn read_image<R: Read>(interner: &mut Interner, input: &mut R) -> VMResult<Heap> {
+        let num_chunks = read_len(input)?;
+        let mut chunks = Vec::with_capacity(num_chunks);
+        for _ in 0..num_chunks {
+            chunks.push(Arc::new(Chunk::read_raw(interner, input)?));
+        }
+
+        let mut sorted = Vec::new();
+        let objects = read_storage(input, |input| read_object(input, &chunks, &mut sorted))?;
+        let callframes = read_storage(input, |input| read_call_frame(input, &chunks))?;
+        let continuations = read_storage(input, |input| {
+            let frame = read_call_frame(input, &chunks)?;
+            let arg_reg = read_u64(input)? as usize;
+            let stack = read_values(input)?;
+            Ok(Continuation {
+                frame,
+                arg_reg,
+                stack,
+            })
+        })?;
+        let errors = read_storage(input, |input| {
+            let keyword = Interned {
+                id: read_u32(input)?,
+            };
+            let data = read_raw_value(input)?;
+            // Backtraces are not saved with an image.
+            Ok(Error {
+                keyword,
+                data,
+                backtrace: None,
+            })
+        })?;
+
+        let num_props = read_len(input)?;
+        let mut props = FxHashMap::default();
+        for _ in 0..num_props {
+            let key = read_raw_value(input)?;
+            let len = read_len(input)?;
+            let mut map = FxHashMap::default();
+            for _ in 0..len {
+                let prop = Interned {
+                    id: read_u32(input)?,
+                };
+                map.insert(prop, read_raw_value(input)?);
+            }
+            props.insert(key, Arc::new(map));
+        }
+
+        let mut bigints = FxHashMap::default();
+        for (idx, obj) in objects.vals().iter().enumerate() {
+            if let Object::BigInt(i) = obj {
+                bigints.insert(i.clone(), Handle::new(idx));
+            }
+        }
+
+        let mut heap = Heap {
+            objects,
+            callframes,
+            continuations,
+            errors,
+            props: Some(props),
+            bigints,
+            greys: vec![],
+            weak_pending: vec![],
+            paused: 0,
+            gc_step: 0,
+            marking: false,
+            rescanning: false,
+            max_bytes: None,
+            used_bytes: 0,
+            collections: 0,
+            total_pause: Duration::ZERO,
+            last_pause: Duration::ZERO,
+            symbol_marks: None,
+            live_symbols: None,
+        };
+        let sorted_maps: Vec<usize> = heap
+            .objects
+            .vals()
+            .iter()
+            .enumerate()
+            .filter(|(_, obj)| matches!(obj, Object::Map(map) if map.is_sorted()))
+            .map(|(idx, _)| idx)
+            .collect();
+        for (idx, entries) in sorted_maps.into_iter().zip(sorted) {
+            let keyed: Vec<(Value, SortKey, Value)> = entries
+                .into_iter()
+                .map(|(key, val)| (key, SortKey::new(key, &heap, interner), val))
+                .collect();
+            if let Some(Object::Map(map)) = heap.objects.get_mut(idx) {
+                let map = Arc::make_mut(map);
+                for (key, sort_key, val) in keyed {
+                    map.insert_sorted(key, sort_key, val);
+                }
+            }
+        }
+        Ok(heap)
+    }
+}
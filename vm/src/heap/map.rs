@@ -0,0 +1,462 @@
+//! The map object.  Entries are kept in insertion order so iterating (printing, saving) a map
+//! is deterministic, a sorted map keeps them in key order instead.
+
+use std::cmp::Ordering;
+use std::collections::{btree_map, BTreeMap, HashMap};
+
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
+
+use crate::{from_i56, Heap, Interner, VMError, VMResult, Value};
+
+/// Position of a key in a sorted map: undefined, nil, booleans, numbers (by value), chars,
+/// strings, symbols and keywords (by text) and then anything else by type and then identity
+/// (handle, builtin index or interned id).
+/// Build one with GVm::sort_key, it does not refer to the heap so the order of a key can not
+/// change while it is in a map.
+#[derive(Clone, Debug)]
+pub enum SortKey {
+    Undefined,
+    Nil,
+    Bool(bool),
+    // The float value orders all numbers, ints also keep their exact value so big ints that
+    // round to the same float still order correctly.
+    Number(f64, Option<BigInt>),
+    // Group (chars, strings, symbols, keywords) and text.
+    Text(u8, String),
+    // Type, identity and list start (for a List).
+    Other(u8, u32, u16),
+}
+
+impl SortKey {
+    /// Sort key for val, heap and interner resolve strings, symbols and big ints.
+    pub fn new(val: Value, heap: &Heap, interner: &Interner) -> Self {
+        let text = |group, text: &str| SortKey::Text(group, text.to_string());
+        let interned = |i| interner.name(i).unwrap_or_default();
+        match val {
+            Value::Undefined => SortKey::Undefined,
+            Value::Nil => SortKey::Nil,
+            Value::False => SortKey::Bool(false),
+            Value::True => SortKey::Bool(true),
+            Value::Byte(b) => SortKey::Number(b as f64, Some(b.into())),
+            Value::Int(i) => {
+                let i = from_i56(&i);
+                SortKey::Number(i as f64, Some(i.into()))
+            }
+            Value::BigInt(h) => {
+                let i = heap.get_bigint(h);
+                SortKey::Number(i.to_f64().unwrap_or(f64::NAN), Some(i.clone()))
+            }
+            Value::Float(f) => SortKey::Number(f.get(), None),
+            Value::CodePoint(ch) => SortKey::Text(0, ch.to_string()),
+            Value::CharCluster(l, c) => {
+                SortKey::Text(0, String::from_utf8_lossy(&c[0..l as usize]).into_owned())
+            }
+            Value::CharClusterLong(h) => text(0, heap.get_string(h)),
+            Value::String(h) => text(1, heap.get_string(h)),
+            Value::StringConst(i) => text(1, interned(i)),
+            Value::Symbol(i) => text(2, interned(i)),
+            Value::Keyword(i) => text(3, interned(i)),
+            Value::Special(i) => SortKey::Other(0, i.id, 0),
+            Value::Builtin(i) => SortKey::Other(1, i, 0),
+            Value::List(h, start) => SortKey::Other(2, h.idx() as u32, start),
+            _ => {
+                let kind = match val {
+                    Value::Vector(_) => 3,
+                    Value::Map(_) => 4,
+                    Value::Set(_) => 5,
+                    Value::Bytes(_) => 6,
+                    Value::Pair(_) => 7,
+                    Value::Lambda(_) => 8,
+                    Value::Closure(_) => 9,
+                    Value::Continuation(_) => 10,
+                    Value::CallFrame(_) => 11,
+                    Value::Value(_) => 12,
+                    Value::Error(_) => 13,
+                    Value::PVec(_) => 14,
+                    Value::PMap(_) => 15,
+                    Value::Weak(_) => 16,
+                    _ => 17,
+                };
+                let id = val.get_handle().map_or(0, |h| h.idx() as u32);
+                SortKey::Other(kind, id, 0)
+            }
+        }
+    }
+
+    fn rank(&self) -> u8 {
+        match self {
+            SortKey::Undefined => 0,
+            SortKey::Nil => 1,
+            SortKey::Bool(_) => 2,
+            SortKey::Number(_, _) => 3,
+            SortKey::Text(_, _) => 4,
+            SortKey::Other(_, _, _) => 5,
+        }
+    }
+}
+
+impl Ord for SortKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (SortKey::Bool(a), SortKey::Bool(b)) => a.cmp(b),
+            (SortKey::Number(a, a_int), SortKey::Number(b, b_int)) => {
+                a.total_cmp(b).then_with(|| a_int.cmp(b_int))
+            }
+            (SortKey::Text(a_group, a), SortKey::Text(b_group, b)) => {
+                a_group.cmp(b_group).then_with(|| a.cmp(b))
+            }
+            (SortKey::Other(a_kind, a, a_start), SortKey::Other(b_kind, b, b_start)) => {
+                (a_kind, a, a_start).cmp(&(b_kind, b, b_start))
+            }
+            _ => self.rank().cmp(&other.rank()),
+        }
+    }
+}
+
+impl PartialOrd for SortKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for SortKey {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for SortKey {}
+
+// Keys that sort the same (1 and 1.0 for instance) stay in insertion order.
+type OrderKey = (SortKey, u64);
+
+#[derive(Clone, Debug)]
+enum Entries {
+    // Removed entries leave a hole until there are enough of them to compact.
+    Insertion {
+        entries: Vec<Option<(Value, Value)>>,
+        index: HashMap<Value, usize>,
+    },
+    Sorted {
+        tree: BTreeMap<OrderKey, (Value, Value)>,
+        index: HashMap<Value, OrderKey>,
+        next: u64,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct VMMap {
+    entries: Entries,
+}
+
+impl Default for VMMap {
+    fn default() -> Self {
+        Self::with_capacity(0)
+    }
+}
+
+impl VMMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            entries: Entries::Insertion {
+                entries: Vec::with_capacity(capacity),
+                index: HashMap::with_capacity(capacity),
+            },
+        }
+    }
+
+    /// An empty map that keeps its entries in key order.  New keys have to be added with
+    /// insert_sorted (GVm::map_insert does this), insert returns an error for them.
+    pub fn new_sorted() -> Self {
+        Self {
+            entries: Entries::Sorted {
+                tree: BTreeMap::new(),
+                index: HashMap::new(),
+                next: 0,
+            },
+        }
+    }
+
+    pub fn is_sorted(&self) -> bool {
+        matches!(self.entries, Entries::Sorted { .. })
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.entries {
+            Entries::Insertion { index, .. } => index.len(),
+            Entries::Sorted { index, .. } => index.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn contains_key(&self, key: &Value) -> bool {
+        match &self.entries {
+            Entries::Insertion { index, .. } => index.contains_key(key),
+            Entries::Sorted { index, .. } => index.contains_key(key),
+        }
+    }
+
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        match &self.entries {
+            Entries::Insertion { entries, index } => index
+                .get(key)
+                .and_then(|i| entries[*i].as_ref())
+                .map(|(_, v)| v),
+            Entries::Sorted { tree, index, .. } => {
+                index.get(key).and_then(|k| tree.get(k)).map(|(_, v)| v)
+            }
+        }
+    }
+
+    pub fn get_mut(&mut self, key: &Value) -> Option<&mut Value> {
+        match &mut self.entries {
+            Entries::Insertion { entries, index } => index
+                .get(key)
+                .and_then(|i| entries[*i].as_mut())
+                .map(|(_, v)| v),
+            Entries::Sorted { tree, index, .. } => {
+                index.get(key).and_then(|k| tree.get_mut(k)).map(|(_, v)| v)
+            }
+        }
+    }
+
+    /// Insert val for key, replacing the value of an existing key in place or adding a new key at
+    /// the end.  Returns the old value if there was one.  A new key for a sorted map is an error,
+    /// its position needs the heap (use insert_sorted or GVm::map_insert).
+    pub fn insert(&mut self, key: Value, val: Value) -> VMResult<Option<Value>> {
+        if let Some(old) = self.get_mut(&key) {
+            return Ok(Some(std::mem::replace(old, val)));
+        }
+        match &mut self.entries {
+            Entries::Insertion { entries, index } => {
+                index.insert(key, entries.len());
+                entries.push(Some((key, val)));
+                Ok(None)
+            }
+            Entries::Sorted { .. } => Err(VMError::new_vm(
+                "a new key for a sorted map needs its sort key (use map_insert)",
+            )),
+        }
+    }
+
+    /// Insert val for key, a new key in a sorted map goes in sort_key order (other maps add it at
+    /// the end and ignore sort_key).  Returns the old value if there was one.
+    pub fn insert_sorted(&mut self, key: Value, sort_key: SortKey, val: Value) -> Option<Value> {
+        match &mut self.entries {
+            Entries::Sorted { tree, index, next } => {
+                if let Some(old) = index.get(&key).and_then(|k| tree.get_mut(k)) {
+                    return Some(std::mem::replace(&mut old.1, val));
+                }
+                let order = (sort_key, *next);
+                *next += 1;
+                index.insert(key, order.clone());
+                tree.insert(order, (key, val));
+                None
+            }
+            Entries::Insertion { entries, index } => {
+                if let Some(old) = index.get(&key).and_then(|i| entries[*i].as_mut()) {
+                    return Some(std::mem::replace(&mut old.1, val));
+                }
+                index.insert(key, entries.len());
+                entries.push(Some((key, val)));
+                None
+            }
+        }
+    }
+
+    pub fn remove(&mut self, key: &Value) -> Option<Value> {
+        match &mut self.entries {
+            Entries::Insertion { entries, index } => {
+                let i = index.remove(key)?;
+                let (_, val) = entries[i].take()?;
+                // Compact once more than half the entries are holes, this keeps remove O(1)
+                // amortized and insertion order intact.
+                if entries.len() > 8 && index.len() < entries.len() / 2 {
+                    entries.retain(Option::is_some);
+                    for (i, (key, _)) in entries.iter().flatten().enumerate() {
+                        index.insert(*key, i);
+                    }
+                }
+                Some(val)
+            }
+            Entries::Sorted { tree, index, .. } => {
+                let order = index.remove(key)?;
+                tree.remove(&order).map(|(_, val)| val)
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        match &mut self.entries {
+            Entries::Insertion { entries, index } => {
+                entries.clear();
+                index.clear();
+            }
+            Entries::Sorted { tree, index, .. } => {
+                tree.clear();
+                index.clear();
+            }
+        }
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        match &self.entries {
+            Entries::Insertion { entries, .. } => Iter::Insertion(entries.iter()),
+            Entries::Sorted { tree, .. } => Iter::Sorted(tree.values()),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Value> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.iter().map(|(_, v)| v)
+    }
+}
+
+/// Iterator over the entries of a VMMap in map order.
+pub enum Iter<'a> {
+    Insertion(std::slice::Iter<'a, Option<(Value, Value)>>),
+    Sorted(btree_map::Values<'a, OrderKey, (Value, Value)>),
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a Value, &'a Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Iter::Insertion(iter) => iter.find_map(|e| e.as_ref()).map(|(k, v)| (k, v)),
+            Iter::Sorted(iter) => iter.next().map(|(k, v)| (k, v)),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a VMMap {
+    type Item = (&'a Value, &'a Value);
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl FromIterator<(Value, Value)> for VMMap {
+    fn from_iter<T: IntoIterator<Item = (Value, Value)>>(iter: T) -> Self {
+        let mut map = VMMap::new();
+        for (key, val) in iter {
+            map.insert(key, val).expect("map is not sorted");
+        }
+        map
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Vm;
+
+    #[test]
+    fn test_map_order() {
+        let mut map = VMMap::new();
+        for i in [5, 1, 3] {
+            map.insert(i.into(), Value::True).unwrap();
+        }
+        assert_eq!(
+            map.insert(1.into(), Value::False).unwrap(),
+            Some(Value::True)
+        );
+        let keys: Vec<Value> = map.keys().copied().collect();
+        assert_eq!(keys, vec![5.into(), 1.into(), 3.into()]);
+        assert_eq!(map.remove(&5.into()), Some(Value::True));
+        assert_eq!(map.get(&1.into()), Some(&Value::False));
+        assert_eq!(map.get(&3.into()), Some(&Value::True));
+        assert!(!map.contains_key(&5.into()));
+
+        let vm = Vm::new();
+        let mut sorted = VMMap::new_sorted();
+        for i in [5, 1, 3, 1] {
+            sorted.insert_sorted(i.into(), vm.sort_key(i.into()), Value::True);
+        }
+        let keys: Vec<Value> = sorted.keys().copied().collect();
+        assert_eq!(keys, vec![1.into(), 3.into(), 5.into()]);
+        assert_eq!(sorted.get(&5.into()), Some(&Value::True));
+        assert_eq!(
+            sorted.insert(5.into(), Value::False).unwrap(),
+            Some(Value::True)
+        );
+        assert_eq!(sorted.remove(&3.into()), Some(Value::True));
+        let keys: Vec<Value> = sorted.keys().copied().collect();
+        assert_eq!(keys, vec![1.into(), 5.into()]);
+    }
+
+    #[test]
+    fn test_sorted_insert() {
+        let mut sorted = VMMap::new_sorted();
+        assert!(sorted.insert(1.into(), Value::True).is_err());
+        assert!(sorted.is_empty());
+    }
+
+    #[test]
+    fn test_sort_key_other() {
+        let mut vm = Vm::new();
+        let v1 = vm.alloc_vector(vec![]).unwrap();
+        let v2 = vm.alloc_vector(vec![]).unwrap();
+        let pair = vm.alloc_pair(Value::Nil, Value::Nil).unwrap();
+        // Heap objects order by type then handle and do not depend on their contents.
+        assert!(vm.sort_key(v1) < vm.sort_key(pair));
+        assert!(vm.sort_key(v2) < vm.sort_key(pair));
+        assert_eq!(
+            vm.sort_key(v1).cmp(&vm.sort_key(v2)),
+            v1.get_handle()
+                .unwrap()
+                .idx()
+                .cmp(&v2.get_handle().unwrap().idx())
+        );
+        assert_eq!(vm.sort_key(v1), vm.sort_key(v1));
+        assert!(vm.sort_key(Value::Builtin(3)) < vm.sort_key(v1));
+    }
+
+    #[test]
+    fn test_map_remove_many() {
+        let vm = Vm::new();
+        let mut map = VMMap::new();
+        let mut sorted = VMMap::new_sorted();
+        let n = 200_000;
+        for i in 0..n {
+            map.insert(i.into(), i.into()).unwrap();
+            sorted.insert_sorted(i.into(), vm.sort_key(i.into()), i.into());
+        }
+        // Remove the even keys and put half of them back, they go to the end of the insertion
+        // ordered map and back in order in the sorted map.
+        for i in (0..n).step_by(2) {
+            assert_eq!(map.remove(&i.into()), Some(i.into()));
+            assert_eq!(sorted.remove(&i.into()), Some(i.into()));
+        }
+        for i in (0..n).step_by(4) {
+            assert_eq!(map.insert(i.into(), Value::True).unwrap(), None);
+            assert_eq!(
+                sorted.insert_sorted(i.into(), vm.sort_key(i.into()), Value::True),
+                None
+            );
+        }
+        assert_eq!(map.len(), n as usize * 3 / 4);
+        assert_eq!(sorted.len(), n as usize * 3 / 4);
+        assert_eq!(map.get(&1.into()), Some(&1.into()));
+        assert_eq!(map.get(&2.into()), None);
+        assert_eq!(map.get(&4.into()), Some(&Value::True));
+        let keys: Vec<Value> = map.keys().copied().take(3).collect();
+        assert_eq!(keys, vec![1.into(), 3.into(), 5.into()]);
+        let keys: Vec<Value> = sorted.keys().copied().take(4).collect();
+        assert_eq!(keys, vec![0.into(), 1.into(), 3.into(), 4.into()]);
+        assert_eq!(map.iter().count(), map.len());
+    }
+}
@@ -0,0 +1,608 @@
+//! Persistent (immutable) vector and map objects.  Updates return a new collection that shares all
+//! but the changed path with the old one so they are O(log n) instead of a copy.  The vector is a
+//! 32 way trie with a tail (like Clojure's vectors) and the map is a hash array mapped trie.
+
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use crate::Value;
+
+const BITS: u32 = 5;
+const WIDTH: usize = 1 << BITS;
+const MASK: usize = WIDTH - 1;
+
+#[derive(Debug)]
+enum VecNode {
+    Branch(Vec<Arc<VecNode>>),
+    Leaf(Vec<Value>),
+}
+
+impl VecNode {
+    fn children(&self) -> &[Arc<VecNode>] {
+        match self {
+            VecNode::Branch(children) => children,
+            VecNode::Leaf(_) => panic!("Persistent vector leaf used as a branch!"),
+        }
+    }
+
+    fn values(&self) -> &[Value] {
+        match self {
+            VecNode::Leaf(values) => values,
+            VecNode::Branch(_) => panic!("Persistent vector branch used as a leaf!"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PVec {
+    len: usize,
+    shift: u32,
+    root: Arc<VecNode>,
+    // The last (up to WIDTH) items, kept out of the trie so pushes are cheap.
+    tail: Vec<Value>,
+}
+
+impl Default for PVec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PVec {
+    pub fn new() -> Self {
+        Self {
+            len: 0,
+            shift: BITS,
+            root: Arc::new(VecNode::Branch(Vec::new())),
+            tail: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    fn tail_offset(&self) -> usize {
+        if self.len < WIDTH {
+            0
+        } else {
+            ((self.len - 1) >> BITS) << BITS
+        }
+    }
+
+    // The block of WIDTH items that idx is in.
+    fn block(&self, idx: usize) -> &[Value] {
+        if idx >= self.tail_offset() {
+            return &self.tail;
+        }
+        let mut node = &self.root;
+        let mut level = self.shift;
+        while level > 0 {
+            node = &node.children()[(idx >> level) & MASK];
+            level -= BITS;
+        }
+        node.values()
+    }
+
+    pub fn get(&self, idx: usize) -> Option<&Value> {
+        if idx < self.len {
+            Some(&self.block(idx)[idx & MASK])
+        } else {
+            None
+        }
+    }
+
+    /// A new vector with val added to the end.
+    pub fn push(&self, val: Value) -> Self {
+        let mut res = self.clone();
+        if res.tail.len() < WIDTH {
+            res.tail.push(val);
+            res.len += 1;
+            return res;
+        }
+        let leaf = Arc::new(VecNode::Leaf(std::mem::replace(&mut res.tail, vec![val])));
+        if (self.len >> BITS) > (1 << self.shift) {
+            // The trie is full, add a level.
+            let path = new_path(self.shift, leaf);
+            res.root = Arc::new(VecNode::Branch(vec![self.root.clone(), path]));
+            res.shift += BITS;
+        } else {
+            res.root = Arc::new(self.push_leaf(self.shift, &self.root, leaf));
+        }
+        res.len += 1;
+        res
+    }
+
+    fn push_leaf(&self, level: u32, parent: &VecNode, leaf: Arc<VecNode>) -> VecNode {
+        let mut children = parent.children().to_vec();
+        let idx = ((self.len - 1) >> level) & MASK;
+        if level == BITS {
+            children.push(leaf);
+        } else if let Some(child) = children.get(idx) {
+            let child = self.push_leaf(level - BITS, child, leaf);
+            children[idx] = Arc::new(child);
+        } else {
+            children.push(new_path(level - BITS, leaf));
+        }
+        VecNode::Branch(children)
+    }
+
+    /// A new vector with the item at idx set to val, idx may be the length to push val.  Returns
+    /// None if idx is past the end.
+    pub fn set(&self, idx: usize, val: Value) -> Option<Self> {
+        if idx == self.len {
+            return Some(self.push(val));
+        }
+        if idx > self.len {
+            return None;
+        }
+        let mut res = self.clone();
+        if idx >= self.tail_offset() {
+            res.tail[idx & MASK] = val;
+        } else {
+            res.root = Arc::new(set_node(self.shift, &self.root, idx, val));
+        }
+        Some(res)
+    }
+
+    pub fn iter(&self) -> PVecIter<'_> {
+        PVecIter {
+            vec: self,
+            idx: 0,
+            block: &[],
+        }
+    }
+}
+
+fn new_path(level: u32, node: Arc<VecNode>) -> Arc<VecNode> {
+    if level == 0 {
+        node
+    } else {
+        Arc::new(VecNode::Branch(vec![new_path(level - BITS, node)]))
+    }
+}
+
+fn set_node(level: u32, node: &VecNode, idx: usize, val: Value) -> VecNode {
+    match node {
+        VecNode::Leaf(values) => {
+            let mut values = values.clone();
+            values[idx & MASK] = val;
+            VecNode::Leaf(values)
+        }
+        VecNode::Branch(children) => {
+            let mut children = children.clone();
+            let sub = (idx >> level) & MASK;
+            children[sub] = Arc::new(set_node(level - BITS, &children[sub], idx, val));
+            VecNode::Branch(children)
+        }
+    }
+}
+
+pub struct PVecIter<'a> {
+    vec: &'a PVec,
+    idx: usize,
+    block: &'a [Value],
+}
+
+impl<'a> Iterator for PVecIter<'a> {
+    type Item = &'a Value;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.idx >= self.vec.len {
+            return None;
+        }
+        if self.idx & MASK == 0 {
+            self.block = self.vec.block(self.idx);
+        }
+        let res = &self.block[self.idx & MASK];
+        self.idx += 1;
+        Some(res)
+    }
+}
+
+impl<'a> IntoIterator for &'a PVec {
+    type Item = &'a Value;
+    type IntoIter = PVecIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl FromIterator<Value> for PVec {
+    fn from_iter<T: IntoIterator<Item = Value>>(iter: T) -> Self {
+        let mut res = PVec::new();
+        for val in iter {
+            res = res.push(val);
+        }
+        res
+    }
+}
+
+fn hash_key(key: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[derive(Clone, Debug)]
+enum MapEntry {
+    Leaf(Value, Value),
+    Node(Arc<MapNode>),
+}
+
+#[derive(Clone, Debug)]
+enum MapNode {
+    // Bit n of bitmap is set if the entry for hash bits n is present, entries are in bit order.
+    Branch(u32, Vec<MapEntry>),
+    // Keys with equal hashes (or that reached it by sharing a hash prefix), searched in order.
+    Collision(Vec<(Value, Value)>),
+}
+
+fn map_bit(hash: u64, shift: u32) -> u32 {
+    1 << ((hash >> shift) as usize & MASK)
+}
+
+fn map_index(bitmap: u32, bit: u32) -> usize {
+    (bitmap & (bit - 1)).count_ones() as usize
+}
+
+// Node holding two entries with different keys, starting at hash bits shift.
+fn merge_entries(
+    shift: u32,
+    hash1: u64,
+    entry1: MapEntry,
+    hash2: u64,
+    entry2: MapEntry,
+) -> MapNode {
+    let (MapEntry::Leaf(key1, val1), MapEntry::Leaf(key2, val2)) = (&entry1, &entry2) else {
+        panic!("Persistent map can only merge leaves!");
+    };
+    if hash1 == hash2 {
+        return MapNode::Collision(vec![(*key1, *val1), (*key2, *val2)]);
+    }
+    let (bit1, bit2) = (map_bit(hash1, shift), map_bit(hash2, shift));
+    if bit1 == bit2 {
+        let node = merge_entries(shift + BITS, hash1, entry1, hash2, entry2);
+        MapNode::Branch(bit1, vec![MapEntry::Node(Arc::new(node))])
+    } else if bit1 < bit2 {
+        MapNode::Branch(bit1 | bit2, vec![entry1, entry2])
+    } else {
+        MapNode::Branch(bit1 | bit2, vec![entry2, entry1])
+    }
+}
+
+impl MapNode {
+    fn get(&self, shift: u32, hash: u64, key: &Value) -> Option<&Value> {
+        match self {
+            MapNode::Branch(bitmap, entries) => {
+                let bit = map_bit(hash, shift);
+                if bitmap & bit == 0 {
+                    return None;
+                }
+                match &entries[map_index(*bitmap, bit)] {
+                    MapEntry::Leaf(k, v) if k == key => Some(v),
+                    MapEntry::Leaf(_, _) => None,
+                    MapEntry::Node(node) => node.get(shift + BITS, hash, key),
+                }
+            }
+            MapNode::Collision(entries) => entries.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+        }
+    }
+
+    // Returns the new node and true if key was added (vs replaced).
+    fn insert(&self, shift: u32, hash: u64, key: Value, val: Value) -> (MapNode, bool) {
+        match self {
+            MapNode::Branch(bitmap, entries) => {
+                let bit = map_bit(hash, shift);
+                let idx = map_index(*bitmap, bit);
+                let mut entries = entries.clone();
+                if bitmap & bit == 0 {
+                    entries.insert(idx, MapEntry::Leaf(key, val));
+                    return (MapNode::Branch(bitmap | bit, entries), true);
+                }
+                let added = match &entries[idx] {
+                    MapEntry::Leaf(k, _) if *k == key => {
+                        entries[idx] = MapEntry::Leaf(key, val);
+                        false
+                    }
+                    MapEntry::Leaf(k, _) => {
+                        let old = entries[idx].clone();
+                        let node = merge_entries(
+                            shift + BITS,
+                            hash_key(k),
+                            old,
+                            hash,
+                            MapEntry::Leaf(key, val),
+                        );
+                        entries[idx] = MapEntry::Node(Arc::new(node));
+                        true
+                    }
+                    MapEntry::Node(node) => {
+                        let (node, added) = node.insert(shift + BITS, hash, key, val);
+                        entries[idx] = MapEntry::Node(Arc::new(node));
+                        added
+                    }
+                };
+                (MapNode::Branch(*bitmap, entries), added)
+            }
+            MapNode::Collision(entries) => {
+                let mut entries = entries.clone();
+                if let Some(entry) = entries.iter_mut().find(|(k, _)| *k == key) {
+                    entry.1 = val;
+                    (MapNode::Collision(entries), false)
+                } else {
+                    entries.push((key, val));
+                    (MapNode::Collision(entries), true)
+                }
+            }
+        }
+    }
+
+    // Returns None if key is not in the node, otherwise what replaces the node (None if it is
+    // now empty).
+    fn remove(&self, shift: u32, hash: u64, key: &Value) -> Option<Option<MapEntry>> {
+        match self {
+            MapNode::Branch(bitmap, entries) => {
+                let bit = map_bit(hash, shift);
+                if bitmap & bit == 0 {
+                    return None;
+                }
+                let idx = map_index(*bitmap, bit);
+                let replacement = match &entries[idx] {
+                    MapEntry::Leaf(k, _) if k == key => None,
+                    MapEntry::Leaf(_, _) => return None,
+                    MapEntry::Node(node) => node.remove(shift + BITS, hash, key)?,
+                };
+                let mut entries = entries.clone();
+                let bitmap = if let Some(entry) = replacement {
+                    entries[idx] = entry;
+                    *bitmap
+                } else {
+                    entries.remove(idx);
+                    bitmap & !bit
+                };
+                Some(match entries.as_slice() {
+                    [] => None,
+                    [MapEntry::Leaf(k, v)] => Some(MapEntry::Leaf(*k, *v)),
+                    _ => Some(MapEntry::Node(Arc::new(MapNode::Branch(bitmap, entries)))),
+                })
+            }
+            MapNode::Collision(entries) => {
+                let idx = entries.iter().position(|(k, _)| k == key)?;
+                let mut entries = entries.clone();
+                entries.remove(idx);
+                Some(match entries.as_slice() {
+                    [(k, v)] => Some(MapEntry::Leaf(*k, *v)),
+                    _ => Some(MapEntry::Node(Arc::new(MapNode::Collision(entries)))),
+                })
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PMap {
+    len: usize,
+    root: Option<Arc<MapNode>>,
+}
+
+impl PMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn get(&self, key: &Value) -> Option<&Value> {
+        self.root.as_ref()?.get(0, hash_key(key), key)
+    }
+
+    pub fn contains_key(&self, key: &Value) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// A new map with key set to val.
+    pub fn insert(&self, key: Value, val: Value) -> Self {
+        let hash = hash_key(&key);
+        let (root, added) = match &self.root {
+            Some(root) => root.insert(0, hash, key, val),
+            None => (
+                MapNode::Branch(map_bit(hash, 0), vec![MapEntry::Leaf(key, val)]),
+                true,
+            ),
+        };
+        Self {
+            len: if added { self.len + 1 } else { self.len },
+            root: Some(Arc::new(root)),
+        }
+    }
+
+    /// A new map without key, None if key is not in the map.
+    pub fn remove(&self, key: &Value) -> Option<Self> {
+        let root = self.root.as_ref()?.remove(0, hash_key(key), key)?;
+        Some(match root {
+            None => Self::new(),
+            // The root is always a branch so the entry goes in its slot.
+            Some(MapEntry::Leaf(key, val)) => Self::new().insert(key, val),
+            Some(MapEntry::Node(root)) => Self {
+                len: self.len - 1,
+                root: Some(root),
+            },
+        })
+    }
+
+    /// Entries in hash order.
+    pub fn iter(&self) -> PMapIter<'_> {
+        PMapIter {
+            stack: self.root.iter().map(|root| (root.as_ref(), 0)).collect(),
+        }
+    }
+
+    pub fn keys(&self) -> impl Iterator<Item = &Value> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.iter().map(|(_, v)| v)
+    }
+}
+
+pub struct PMapIter<'a> {
+    // Nodes being iterated and the next entry in each.
+    stack: Vec<(&'a MapNode, usize)>,
+}
+
+impl<'a> Iterator for PMapIter<'a> {
+    type Item = (&'a Value, &'a Value);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (node, idx) = self.stack.last_mut()?;
+            let node: &'a MapNode = node;
+            let i = *idx;
+            *idx += 1;
+            match node {
+                MapNode::Branch(_, entries) => match entries.get(i) {
+                    Some(MapEntry::Leaf(k, v)) => return Some((k, v)),
+                    Some(MapEntry::Node(child)) => self.stack.push((child.as_ref(), 0)),
+                    None => {
+                        self.stack.pop();
+                    }
+                },
+                MapNode::Collision(entries) => match entries.get(i) {
+                    Some((k, v)) => return Some((k, v)),
+                    None => {
+                        self.stack.pop();
+                    }
+                },
+            }
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a PMap {
+    type Item = (&'a Value, &'a Value);
+    type IntoIter = PMapIter<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl FromIterator<(Value, Value)> for PMap {
+    fn from_iter<T: IntoIterator<Item = (Value, Value)>>(iter: T) -> Self {
+        let mut res = PMap::new();
+        for (key, val) in iter {
+            res = res.insert(key, val);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pvec() {
+        let empty = PVec::new();
+        let mut vecs = vec![empty.clone()];
+        let mut v = empty;
+        for i in 0..2000 {
+            v = v.push(i.into());
+            if i % 500 == 0 {
+                vecs.push(v.clone());
+            }
+        }
+        assert_eq!(v.len(), 2000);
+        for i in [0, 31, 32, 33, 1023, 1024, 1025, 1999] {
+            assert_eq!(v.get(i), Some(&(i as i64).into()));
+        }
+        assert_eq!(v.get(2000), None);
+        // Older versions are unchanged.
+        assert_eq!(vecs[0].len(), 0);
+        assert_eq!(vecs[2].len(), 501);
+        assert_eq!(vecs[2].get(500), Some(&500.into()));
+
+        let v2 = v.set(1500, Value::True).unwrap();
+        let v2 = v2.set(1999, Value::False).unwrap();
+        assert_eq!(v2.get(1500), Some(&Value::True));
+        assert_eq!(v2.get(1999), Some(&Value::False));
+        assert_eq!(v.get(1500), Some(&1500.into()));
+        assert_eq!(v.set(2000, Value::Nil).unwrap().len(), 2001);
+        assert!(v.set(2001, Value::Nil).is_none());
+
+        let items: Vec<Value> = v.iter().copied().collect();
+        let expected: Vec<Value> = (0..2000).map(|i| (i as i64).into()).collect();
+        assert_eq!(items, expected);
+        let v3: PVec = expected.iter().copied().collect();
+        assert!(v3.iter().eq(v.iter()));
+    }
+
+    #[test]
+    fn test_pmap() {
+        let mut m = PMap::new();
+        for i in 0..1000 {
+            m = m.insert(i.into(), (i * 2).into());
+        }
+        let old = m.clone();
+        assert_eq!(m.len(), 1000);
+        m = m.insert(10.into(), Value::True);
+        assert_eq!(m.len(), 1000);
+        assert_eq!(m.get(&10.into()), Some(&Value::True));
+        assert_eq!(old.get(&10.into()), Some(&20.into()));
+        assert_eq!(m.get(&1000.into()), None);
+
+        for i in 0..1000 {
+            if i % 3 != 0 {
+                m = m.remove(&i.into()).unwrap();
+            }
+        }
+        assert!(m.remove(&1.into()).is_none());
+        assert_eq!(m.len(), 334);
+        assert_eq!(m.iter().count(), 334);
+        for (k, v) in &m {
+            let Value::Int(_) = k else {
+                panic!("bad key");
+            };
+            assert!(m.get(k) == Some(v));
+        }
+        assert_eq!(old.len(), 1000);
+        assert_eq!(old.iter().count(), 1000);
+        for i in (0..1000).step_by(3) {
+            m = m.remove(&i.into()).unwrap();
+        }
+        assert!(m.is_empty());
+        assert_eq!(m.iter().count(), 0);
+    }
+
+    #[test]
+    fn test_pmap_collision() {
+        // Force keys into a collision node by building it directly.
+        let a = MapEntry::Leaf(1.into(), 1.into());
+        let b = MapEntry::Leaf(2.into(), 2.into());
+        let node = merge_entries(0, 7, a, 7, b);
+        let (node, added) = node.insert(0, 7, 3.into(), 3.into());
+        assert!(added);
+        assert_eq!(node.get(0, 7, &2.into()), Some(&2.into()));
+        let Some(Some(MapEntry::Node(node))) = node.remove(0, 7, &1.into()) else {
+            panic!("expected a node");
+        };
+        assert!(matches!(
+            node.remove(0, 7, &2.into()),
+            Some(Some(MapEntry::Leaf(_, _)))
+        ));
+    }
+}
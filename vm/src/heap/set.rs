@@ -0,0 +1,85 @@
+//! The set object, items are kept in insertion order (like maps) so iterating a set is
+//! deterministic.
+
+use crate::{VMMap, Value};
+
+#[derive(Clone, Debug, Default)]
+pub struct VMSet {
+    // Items are the keys, the values are unused.
+    items: VMMap,
+}
+
+impl VMSet {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            items: VMMap::with_capacity(capacity),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub fn contains(&self, item: &Value) -> bool {
+        self.items.contains_key(item)
+    }
+
+    /// Add item, returns false if it was already in the set.
+    pub fn insert(&mut self, item: Value) -> bool {
+        self.items
+            .insert(item, Value::Nil)
+            .expect("set items are not sorted")
+            .is_none()
+    }
+
+    /// Remove item, returns false if it was not in the set.
+    pub fn remove(&mut self, item: &Value) -> bool {
+        self.items.remove(item).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.items.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Value> {
+        self.items.keys()
+    }
+}
+
+impl FromIterator<Value> for VMSet {
+    fn from_iter<T: IntoIterator<Item = Value>>(iter: T) -> Self {
+        let mut set = VMSet::new();
+        for item in iter {
+            set.insert(item);
+        }
+        set
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set() {
+        let mut set: VMSet = [3, 1, 3, 2].into_iter().map(Value::from).collect();
+        assert_eq!(set.len(), 3);
+        assert!(!set.insert(1.into()));
+        assert!(set.insert(Value::Nil));
+        assert!(set.contains(&2.into()));
+        assert!(set.remove(&3.into()));
+        assert!(!set.remove(&3.into()));
+        let items: Vec<Value> = set.iter().copied().collect();
+        assert_eq!(items, vec![1.into(), 2.into(), Value::Nil]);
+        set.clear();
+        assert!(set.is_empty());
+    }
+}
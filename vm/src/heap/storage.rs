@@ -1,5 +1,6 @@
 use crate::bits::{
-    is_live, is_marked, is_mutable, is_traced, FLAG_MARK, FLAG_MUT, FLAG_STICKY, FLAG_TRACED,
+    is_live, is_marked, is_mutable, is_traced, is_weak, FLAG_MARK, FLAG_MUT, FLAG_STICKY,
+    FLAG_TRACED,
 };
 use crate::{clear_bit, is_bit_set, set_bit};
 
@@ -9,8 +10,13 @@ pub(super) struct Storage<T: Clone> {
     vals: Vec<T>,
     capacity: usize,
     live_objects: usize,
+    // Objects marked by the collection in progress, becomes live_objects when it is done.
+    marked: usize,
     sticky_objects: usize,
     grow_factor: f64,
+    // Free slots while an incremental collection is marking (unmarked objects may still be live).
+    free: Vec<u32>,
+    marking: bool,
 }
 
 impl<T: Clone> Storage<T> {
@@ -21,11 +27,70 @@ impl<T: Clone> Storage<T> {
             vals: Vec::with_capacity(capacity + 1),
             capacity,
             live_objects: 0,
+            marked: 0,
             sticky_objects: 0,
             grow_factor: 2.0,
+            free: Vec::new(),
+            marking: false,
         }
     }
 
+    /// Rebuild a storage from the parts saved in a VM image.
+    pub fn from_parts(
+        flags: Vec<u8>,
+        mut vals: Vec<T>,
+        capacity: usize,
+        live_objects: usize,
+        sticky_objects: usize,
+        grow_factor: f64,
+    ) -> Self {
+        vals.reserve((capacity + 1).saturating_sub(vals.len()));
+        Self {
+            flags,
+            vals,
+            capacity,
+            live_objects,
+            marked: 0,
+            sticky_objects,
+            grow_factor,
+            free: Vec::new(),
+            marking: false,
+        }
+    }
+
+    pub fn flags(&self) -> &[u8] {
+        &self.flags
+    }
+
+    pub fn vals(&self) -> &[T] {
+        &self.vals
+    }
+
+    /// Flags and live object count to save in a VM image.  While marking anything not free is saved
+    /// as live since unmarked objects may just not have been reached yet.
+    pub fn image_flags(&self) -> (Vec<u8>, usize) {
+        if !self.marking {
+            return (self.flags.clone(), self.live_objects);
+        }
+        let mut flags = self.flags.clone();
+        for flag in flags.iter_mut() {
+            set_bit!(*flag, FLAG_MARK);
+        }
+        for idx in &self.free {
+            clear_bit!(flags[*idx as usize], FLAG_MARK);
+        }
+        let live = flags.iter().filter(|flag| is_live(**flag)).count();
+        (flags, live)
+    }
+
+    pub fn grow_factor(&self) -> f64 {
+        self.grow_factor
+    }
+
+    pub fn sticky_objects(&self) -> usize {
+        self.sticky_objects
+    }
+
     pub fn get(&self, idx: usize) -> Option<&T> {
         self.vals.get(idx)
     }
@@ -47,6 +112,9 @@ impl<T: Clone> Storage<T> {
     }
 
     pub fn alloc(&mut self, obj: T, flags: u8) -> u32 {
+        if self.marking {
+            return self.alloc_marking(obj, flags);
+        }
         if self.live_objects >= self.capacity {
             let new_min = (self.live_objects as f64 * self.grow_factor) as usize;
             if new_min > self.capacity {
@@ -75,20 +143,67 @@ impl<T: Clone> Storage<T> {
         }
     }
 
+    /// Allocate while an incremental collection is marking.  Only slots that were free when it
+    /// started can be reused and the new object is live for this collection.
+    fn alloc_marking(&mut self, obj: T, flags: u8) -> u32 {
+        self.live_objects += 1;
+        self.marked += 1;
+        if let Some(idx) = self.free.pop() {
+            self.flags[idx as usize] = flags | FLAG_MARK;
+            self.vals[idx as usize] = obj;
+            return idx;
+        }
+        if self.vals.len() >= self.capacity {
+            let new_cap =
+                ((self.capacity as f64 * self.grow_factor) as usize).max(self.capacity + 1);
+            self.capacity = new_cap;
+            self.flags.reserve(new_cap - self.flags.len());
+            self.vals.reserve((new_cap - self.vals.len()) + 1);
+        }
+        let idx = self.vals.len();
+        self.vals.push(obj);
+        self.flags.push(flags | FLAG_MARK);
+        idx as u32
+    }
+
+    /// Start an incremental collection, remember the free slots and clear the marks.
+    pub fn start_marking(&mut self) {
+        self.free.clear();
+        for (idx, flag) in self.flags.iter().enumerate().rev() {
+            if !is_live(*flag) {
+                self.free.push(idx as u32);
+            }
+        }
+        self.clear_marks();
+        self.marking = true;
+    }
+
+    /// Done marking (or abandoning an incremental collection for a full one).
+    pub fn finish_marking(&mut self) {
+        self.free.clear();
+        self.marking = false;
+    }
+
+    /// Clear the marks to start a collection.  live_objects still counts everything allocated
+    /// until sweep (unmarked objects are not garbage until marking is done).
     pub fn clear_marks(&mut self) {
-        self.live_objects = 0;
-        self.live_objects = 0;
+        self.marked = 0;
         for flag in self.flags.iter_mut() {
             clear_bit!(*flag, FLAG_MARK);
             clear_bit!(*flag, FLAG_TRACED);
             // if it is sticky mark it
             if is_bit_set!(*flag, FLAG_STICKY) {
-                self.live_objects += 1;
+                self.marked += 1;
                 set_bit!(*flag, FLAG_MARK);
             }
         }
     }
 
+    /// Marking is done, anything not marked is free now.
+    pub fn sweep(&mut self) {
+        self.live_objects = self.marked;
+    }
+
     /// Is the object at index still alive after GC.
     pub fn is_live(&self, idx: usize) -> bool {
         if let Some(flag) = self.flags.get(idx) {
@@ -107,6 +222,15 @@ impl<T: Clone> Storage<T> {
         }
     }
 
+    /// Is the object at index a weak map.
+    pub fn is_weak(&self, idx: usize) -> bool {
+        if let Some(flag) = self.flags.get(idx) {
+            is_weak(*flag)
+        } else {
+            false
+        }
+    }
+
     /// Mark the object at index immutable.
     pub fn immutable(&mut self, idx: usize) {
         if let Some(flag) = self.flags.get_mut(idx) {
@@ -119,7 +243,7 @@ impl<T: Clone> Storage<T> {
     pub fn mark(&mut self, idx: usize) {
         if let Some(flag) = self.flags.get_mut(idx) {
             if !is_marked(*flag) {
-                self.live_objects += 1;
+                self.marked += 1;
                 set_bit!(*flag, FLAG_MARK);
             }
         } else {
@@ -127,6 +251,17 @@ impl<T: Clone> Storage<T> {
         }
     }
 
+    /// Clear the traced flag so the object will be traced again, returns true if it had been traced.
+    pub fn untrace(&mut self, idx: usize) -> bool {
+        if let Some(flag) = self.flags.get_mut(idx) {
+            let ret = is_traced(*flag);
+            clear_bit!(*flag, FLAG_TRACED);
+            ret
+        } else {
+            panic!("Invalid object handle in untrace!")
+        }
+    }
+
     pub fn is_traced_and_set(&mut self, idx: usize) -> bool {
         if let Some(flag) = self.flags.get_mut(idx) {
             let ret = is_traced(*flag);
@@ -169,11 +304,11 @@ impl<T: Clone> Storage<T> {
         }
     }
 
-    pub fn trace_all_live<FN: FnMut(&T)>(&mut self, mut trace: FN) {
-        for (flag, value) in self.flags.iter_mut().zip(self.vals.iter()) {
+    pub fn trace_all_live<FN: FnMut(usize, &T)>(&mut self, mut trace: FN) {
+        for (idx, (flag, value)) in self.flags.iter_mut().zip(self.vals.iter()).enumerate() {
             if is_live(*flag) {
                 set_bit!(*flag, FLAG_TRACED);
-                trace(value);
+                trace(idx, value);
             }
         }
     }
@@ -1,6 +1,7 @@
 use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
 use std::mem;
+use std::sync::atomic::{AtomicBool, Ordering};
 
 #[derive(Clone, Copy, Debug)]
 pub struct Interned {
@@ -26,16 +27,56 @@ impl Hash for Interned {
 // See https://www.reddit.com/r/rust/comments/fn1jxf/blog_post_fast_and_simple_rust_interner/
 // This is a simple string interner.  It hands out &'static str and it WILL leak memory
 // to keep them valid.  Intended to live for the programs lifetime.
-#[derive(Clone, Debug)]
+// The exception are weak and uninterned symbols (intern_weak and gensym), these are allocated
+// on their own and freed by reclaim when nothing references them anymore unless their name was
+// handed out as a &'static str.  Use name (borrowed from the interner) to look at them without
+// pinning them.  Reclaimed ids are never reused so a stale id can not alias another symbol.
+#[derive(Debug)]
 pub struct Interner {
     map: HashMap<&'static str, Interned>,
-    vals: Vec<&'static str>,
+    vals: Vec<Option<Entry>>,
+    // Number of reclaimed ids.
+    reclaimed: usize,
     // Leak buffers to keep the static lifetimes we hand out valid.
     buf: mem::ManuallyDrop<String>,
     capacity: usize,
     used: usize,
 }
 
+#[derive(Debug)]
+struct Entry {
+    name: &'static str,
+    // In map, i.e. looking up the name finds this entry.
+    interned: bool,
+    // Never reclaimed (and name is not owned by the entry when it was interned).
+    permanent: bool,
+    // Name was handed out as a &'static str so it can never be freed.
+    pinned: AtomicBool,
+}
+
+impl Entry {
+    fn new(name: &'static str, interned: bool, permanent: bool) -> Self {
+        Self {
+            name,
+            interned,
+            permanent,
+            pinned: AtomicBool::new(false),
+        }
+    }
+
+    fn is_permanent(&self) -> bool {
+        self.permanent || self.pinned.load(Ordering::Relaxed)
+    }
+
+    // The name for use as a &'static str, this keeps it from being reclaimed.
+    fn static_name(&self) -> &'static str {
+        if !self.permanent {
+            self.pinned.store(true, Ordering::Relaxed);
+        }
+        self.name
+    }
+}
+
 impl Interner {
     /// Create an interner with capacity cap (to the next power of two).
     pub fn with_capacity(cap: usize) -> Interner {
@@ -43,6 +84,7 @@ impl Interner {
         Interner {
             map: HashMap::default(),
             vals: Vec::new(),
+            reclaimed: 0,
             buf: mem::ManuallyDrop::new(String::with_capacity(cap)),
             capacity: cap,
             used: 0,
@@ -54,14 +96,24 @@ impl Interner {
         self.map.contains_key(name)
     }
 
-    fn intern_final(&mut self, name: &'static str) -> Interned {
-        let id = self.vals.len() as u32;
-        self.vals.push(name);
-        let interned = Interned { id };
-        self.map.insert(name, interned);
+    fn intern_final(&mut self, entry: Entry) -> Interned {
+        let interned = Interned {
+            id: self.vals.len() as u32,
+        };
+        if entry.interned {
+            self.map.insert(entry.name, interned);
+        }
+        self.vals.push(Some(entry));
         interned
     }
 
+    // Lookup name, if it was weak it is not anymore.
+    fn get_permanent(&mut self, name: &str) -> Option<Interned> {
+        let id = *self.map.get(name)?;
+        self.keep(id);
+        Some(id)
+    }
+
     /// If name is interned then return it, otherwise None.
     pub fn get_if_interned(&self, name: &str) -> Option<Interned> {
         self.map.get(name).copied()
@@ -71,39 +123,113 @@ impl Interner {
     /// exists or add it and and return it if not.  Use this if you already have
     /// a static str reference to avoid wasting space on making another.
     pub fn intern_static(&mut self, name: &'static str) -> Interned {
-        if let Some(&id) = self.map.get(name) {
+        if let Some(id) = self.get_permanent(name) {
             return id;
         }
-        self.intern_final(name)
+        self.intern_final(Entry::new(name, true, true))
     }
 
     /// Intern name in this interner.  Will return the existing symbol if it
     /// exists or add it and and return it if not.
     pub fn intern(&mut self, name: &str) -> Interned {
+        if let Some(id) = self.get_permanent(name) {
+            return id;
+        }
+        let name = self.static_name(name);
+        self.intern_final(Entry::new(name, true, true))
+    }
+
+    fn static_name(&mut self, name: &str) -> &'static str {
+        let cap = self.buf.capacity();
+        if cap < self.buf.len() + name.len() {
+            let new_cap = (cap.max(name.len()) + 1).next_power_of_two();
+            let new_buf = mem::ManuallyDrop::new(String::with_capacity(new_cap));
+            self.capacity += new_cap;
+            // Leak memory to keep the static lifetimes valid.
+            let _old_buf = mem::replace(&mut self.buf, new_buf);
+        }
+
+        let start = self.buf.len();
+        self.buf.push_str(name);
+        self.used += name.len();
+        unsafe { &*(&self.buf[start..] as *const str) }
+    }
+
+    fn owned_name(&mut self, name: &str) -> &'static str {
+        self.used += name.len();
+        Box::leak(name.to_string().into_boxed_str())
+    }
+
+    /// Intern name as a weak symbol, it can be reclaimed once nothing references it.  Returns the
+    /// existing symbol if name is already interned.  Interning the same name with intern or
+    /// intern_static makes it permanent.
+    pub fn intern_weak(&mut self, name: &str) -> Interned {
         if let Some(&id) = self.map.get(name) {
             return id;
         }
-        let name = {
-            let cap = self.buf.capacity();
-            if cap < self.buf.len() + name.len() {
-                let new_cap = (cap.max(name.len()) + 1).next_power_of_two();
-                let new_buf = mem::ManuallyDrop::new(String::with_capacity(new_cap));
-                self.capacity += new_cap;
-                // Leak memory to keep the static lifetimes valid.
-                let _old_buf = mem::replace(&mut self.buf, new_buf);
-            }
+        let name = self.owned_name(name);
+        self.intern_final(Entry::new(name, true, false))
+    }
 
-            let start = self.buf.len();
-            self.buf.push_str(name);
-            self.used += name.len();
-            unsafe { &*(&self.buf[start..] as *const str) }
-        };
-        self.intern_final(name)
+    /// Make a new uninterned symbol named name.  It is not equal to any other symbol (even one with
+    /// the same name) and it can be reclaimed once nothing references it.
+    pub fn gensym(&mut self, name: &str) -> Interned {
+        let name = self.owned_name(name);
+        self.intern_final(Entry::new(name, false, false))
     }
 
+    /// Make sure interned is never reclaimed.
+    pub fn keep(&mut self, interned: Interned) {
+        if let Some(Some(entry)) = self.vals.get_mut(interned.id as usize) {
+            entry.permanent = true;
+        }
+    }
+
+    /// Is interned a symbol that was made with gensym.
+    pub fn is_uninterned(&self, interned: Interned) -> bool {
+        matches!(self.vals.get(interned.id as usize), Some(Some(entry)) if !entry.interned)
+    }
+
+    /// Free the weak and uninterned symbols that are not live and whose name was never handed out
+    /// as a &'static str, returns the number reclaimed.  Live must include every id still in use
+    /// (values, chunks, globals, etc), a reclaimed id has no name.
+    pub fn reclaim<F: Fn(u32) -> bool>(&mut self, live: F) -> usize {
+        let mut reclaimed = 0;
+        for (id, slot) in self.vals.iter_mut().enumerate() {
+            let id = id as u32;
+            if let Some(entry) = slot {
+                if !entry.is_permanent() && !live(id) {
+                    if entry.interned {
+                        self.map.remove(entry.name);
+                    }
+                    self.used -= entry.name.len();
+                    // Safe since the name was leaked from a Box by owned_name, this was the only
+                    // entry with it and it was never handed out (not pinned).
+                    unsafe { drop(Box::from_raw(entry.name as *const str as *mut str)) };
+                    *slot = None;
+                    reclaimed += 1;
+                }
+            }
+        }
+        self.reclaimed += reclaimed;
+        reclaimed
+    }
+
+    /// The name of interned, a weak or uninterned symbol is never reclaimed once its name has been
+    /// returned from here (use name if that matters).
     pub fn get_string(&self, interned: Interned) -> Option<&'static str> {
-        if let Some(s) = self.vals.get(interned.id as usize) {
-            Some(s)
+        if let Some(Some(entry)) = self.vals.get(interned.id as usize) {
+            Some(entry.static_name())
+        } else {
+            None
+        }
+    }
+
+    /// The name of interned borrowed from the interner, unlike get_string this does not stop it
+    /// from being reclaimed.
+    pub fn name(&self, interned: Interned) -> Option<&str> {
+        if let Some(Some(entry)) = self.vals.get(interned.id as usize) {
+            Some(entry.name)
         } else {
             None
         }
@@ -128,6 +254,51 @@ impl Interner {
     pub fn is_empty(&self) -> bool {
         self.map.is_empty()
     }
+
+    /// Return the number of symbols including uninterned ones.
+    pub fn symbols(&self) -> usize {
+        self.vals.len() - self.reclaimed
+    }
+
+    /// Iterate over the interned symbols in id order.
+    pub fn iter(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.vals
+            .iter()
+            .flatten()
+            .filter(|entry| entry.interned)
+            .map(|entry| entry.static_name())
+    }
+
+    /// Iterate over every id in order, None for a reclaimed id, otherwise the name, if it is
+    /// interned and if it is permanent (for saving the interner with the ids intact).
+    pub(crate) fn slots(&self) -> impl ExactSizeIterator<Item = Option<(&str, bool, bool)>> + '_ {
+        self.vals.iter().map(|slot| {
+            slot.as_ref()
+                .map(|entry| (entry.name, entry.interned, entry.is_permanent()))
+        })
+    }
+
+    /// Add the next id (see slots), used to restore a saved interner.
+    pub(crate) fn push_slot(&mut self, slot: Option<(&str, bool, bool)>) {
+        let id = self.vals.len() as u32;
+        match slot {
+            Some((name, interned, permanent)) => {
+                let name = if permanent {
+                    self.static_name(name)
+                } else {
+                    self.owned_name(name)
+                };
+                self.vals.push(Some(Entry::new(name, interned, permanent)));
+                if interned {
+                    self.map.insert(name, Interned { id });
+                }
+            }
+            None => {
+                self.reclaimed += 1;
+                self.vals.push(None);
+            }
+        }
+    }
 }
 
 #[cfg(test)]
@@ -200,4 +371,85 @@ mod tests {
         assert!(i.used() == 25);
         assert!(i.len() == 5);
     }
+
+    #[test]
+    fn test_reclaim() {
+        let mut i = Interner::with_capacity(8);
+        let one = i.intern("one");
+        let weak = i.intern_weak("weak");
+        assert_eq!(i.intern_weak("one"), one);
+        assert_eq!(i.intern_weak("weak"), weak);
+        assert_eq!(i.get_if_interned("weak"), Some(weak));
+        let sym1 = i.gensym("one");
+        let sym2 = i.gensym("one");
+        assert_ne!(sym1, one);
+        assert_ne!(sym1, sym2);
+        assert_eq!(i.name(sym1), Some("one"));
+        assert!(i.is_uninterned(sym1));
+        assert!(!i.is_uninterned(one));
+        assert!(!i.is_uninterned(weak));
+        assert_eq!(i.len(), 2);
+        assert_eq!(i.symbols(), 4);
+        assert_eq!(i.used(), 3 + 4 + 3 + 3);
+
+        // Only the weak and uninterned symbols that are not live go.
+        assert_eq!(i.reclaim(|id| id == sym2.id), 2);
+        assert_eq!(i.get_string(one), Some("one"));
+        assert_eq!(i.get_string(weak), None);
+        assert_eq!(i.get_string(sym1), None);
+        assert_eq!(i.get_string(sym2), Some("one"));
+        assert_eq!(i.get_if_interned("weak"), None);
+        assert_eq!(i.get_if_interned("one"), Some(one));
+        assert_eq!(i.symbols(), 2);
+        assert_eq!(i.used(), 3 + 3);
+
+        // Reclaimed ids are not reused.
+        let weak2 = i.intern_weak("weak2");
+        assert!(![weak, sym1].contains(&weak2));
+        assert_eq!(i.name(weak2), Some("weak2"));
+        // Interning a weak symbol (or keeping it) makes it permanent.
+        assert_eq!(i.intern("weak2"), weak2);
+        i.keep(sym2);
+        assert_eq!(i.reclaim(|_| false), 0);
+        assert_eq!(i.get_string(weak2), Some("weak2"));
+        assert_eq!(i.get_string(sym2), Some("one"));
+
+        // Save and restore keeps the ids (and reclaimed ids).
+        let gone = i.intern_weak("gone");
+        let hole = i.gensym("hole");
+        assert_eq!(i.reclaim(|id| id == gone.id), 1);
+        let slots: Vec<_> = i.slots().collect();
+        let mut i2 = Interner::with_capacity(8);
+        for slot in slots {
+            i2.push_slot(slot);
+        }
+        assert_eq!(i2.get_string(one), Some("one"));
+        assert_eq!(i2.get_string(weak2), Some("weak2"));
+        assert_eq!(i2.get_string(sym2), Some("one"));
+        assert!(i2.is_uninterned(sym2));
+        assert_eq!(i2.name(gone), Some("gone"));
+        assert_eq!(i2.symbols(), i.symbols());
+        assert_eq!(i2.reclaim(|_| false), 1);
+        assert_eq!(i2.get_if_interned("gone"), None);
+        assert_eq!(i2.get_string(hole), None);
+    }
+
+    #[test]
+    fn test_reclaim_pinned() {
+        let mut i = Interner::with_capacity(8);
+        let weak = i.intern_weak("weak");
+        let sym = i.gensym("sym");
+        let other = i.gensym("other");
+        assert_eq!(i.name(sym), Some("sym"));
+        // Handing out the &'static str means it can never be freed.
+        let name: &'static str = i.get_string(weak).unwrap();
+        let sym_name = i.get_string(sym).unwrap();
+        assert_eq!(i.reclaim(|_| false), 1);
+        assert_eq!(name, "weak");
+        assert_eq!(sym_name, "sym");
+        assert_eq!(i.get_if_interned("weak"), Some(weak));
+        assert_eq!(i.name(other), None);
+        let slots: Vec<_> = i.slots().collect();
+        assert_eq!(slots[weak.id as usize], Some(("weak", true, true)));
+    }
 }
@@ -21,3 +21,5 @@ pub use crate::interner::*;
 
 pub mod fxhasher;
 pub use crate::fxhasher::*;
+
+pub use num_bigint::BigInt;
@@ -1,4 +1,6 @@
 use crate::{Handle, Heap, Interned, VMError, VMResult};
+use num_bigint::BigInt;
+use num_traits::ToPrimitive;
 use std::collections::HashMap;
 use std::fmt;
 use std::fmt::{Display, Formatter};
@@ -9,17 +11,45 @@ use std::sync::Arc;
 use crate::vm::GVm;
 
 pub type CallFuncSig<ENV> = fn(vm: &mut GVm<ENV>, registers: &[Value]) -> VMResult<Value>;
-#[derive(Copy, Clone)]
-pub struct CallFunc<ENV> {
-    pub func: CallFuncSig<ENV>,
+/// A builtin that is a Rust closure so it can carry state (configuration, a channel, a counter in
+/// a Cell, etc).  It is an Arc so the same closure can be registered with more than one VM.
+pub type CallFuncClosure<ENV> = Arc<dyn Fn(&mut GVm<ENV>, &[Value]) -> VMResult<Value>>;
+
+/// A builtin function, either a plain fn or a closure.
+pub enum CallFunc<ENV> {
+    Fn(CallFuncSig<ENV>),
+    Closure(CallFuncClosure<ENV>),
+}
+
+impl<ENV> CallFunc<ENV> {
+    pub fn call(&self, vm: &mut GVm<ENV>, registers: &[Value]) -> VMResult<Value> {
+        match self {
+            CallFunc::Fn(func) => func(vm, registers),
+            CallFunc::Closure(func) => func(vm, registers),
+        }
+    }
+
+    fn addr(&self) -> usize {
+        match self {
+            CallFunc::Fn(func) => *func as usize,
+            CallFunc::Closure(func) => Arc::as_ptr(func) as *const () as usize,
+        }
+    }
+}
+
+// Derive would require ENV: Clone.
+impl<ENV> Clone for CallFunc<ENV> {
+    fn clone(&self) -> Self {
+        match self {
+            CallFunc::Fn(func) => CallFunc::Fn(*func),
+            CallFunc::Closure(func) => CallFunc::Closure(func.clone()),
+        }
+    }
 }
 
 impl<ENV> PartialEq for CallFunc<ENV> {
     fn eq(&self, other: &CallFunc<ENV>) -> bool {
-        std::ptr::eq(
-            self.func as *const CallFuncSig<ENV>,
-            other.func as *const CallFuncSig<ENV>,
-        )
+        self.addr() == other.addr()
     }
 }
 
@@ -27,7 +57,7 @@ impl<ENV> Eq for CallFunc<ENV> {}
 
 impl<ENV> Hash for CallFunc<ENV> {
     fn hash<H: Hasher>(&self, state: &mut H) {
-        state.write_usize(self.func as usize);
+        state.write_usize(self.addr());
     }
 }
 
@@ -83,22 +113,49 @@ impl<'vm, ENV> Iterator for PairIter<'vm, ENV> {
     }
 }
 
+// Floats are stored as f64 with the f64 feature (the default), otherwise as f32 to keep Value at
+// 8 bytes.
+// Arithmetic is done in f64 either way.
+#[cfg(feature = "f64")]
+type FloatBits = f64;
+#[cfg(not(feature = "f64"))]
+type FloatBits = f32;
+
 // Do this wrap nonsense so that Value is hashable...
 #[derive(Copy, Clone, Debug)]
-pub struct F32Wrap(pub f32);
+pub struct FloatWrap(FloatBits);
 
-impl PartialEq for F32Wrap {
+impl FloatWrap {
+    pub fn new(f: f64) -> Self {
+        Self(f as FloatBits)
+    }
+
+    // The conversions are only needed when FloatBits is f32.
+    #[allow(clippy::useless_conversion)]
+    pub fn get(self) -> f64 {
+        self.0.into()
+    }
+}
+
+// Compare bits so equal floats hash the same (NaN equals itself and 0.0 is not -0.0).
+impl PartialEq for FloatWrap {
     fn eq(&self, other: &Self) -> bool {
-        (self.0 - other.0).abs() < f32::EPSILON
-        //self.0.to_bits() == other.0.to_bits()
+        self.0.to_bits() == other.0.to_bits()
     }
 }
 
-impl Eq for F32Wrap {}
+impl Eq for FloatWrap {}
 
-impl Hash for F32Wrap {
+impl Hash for FloatWrap {
+    #[allow(clippy::useless_conversion)]
     fn hash<H: Hasher>(&self, state: &mut H) {
-        state.write_u32(self.0.to_bits());
+        state.write_u64(self.0.to_bits().into());
+    }
+}
+
+impl Display for FloatWrap {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
     }
 }
 
@@ -116,6 +173,18 @@ pub fn from_i56(arr: &[u8; 7]) -> i64 {
     }
 }
 
+/// True if i can be stored in an Int without promoting it to a BigInt.
+pub fn fits_i56(i: i64) -> bool {
+    (INT_MIN..=INT_MAX).contains(&i)
+}
+
+/// Return i as an Int if it fits (see fits_i56), otherwise None.
+pub fn bigint_to_i56(i: &BigInt) -> Option<Value> {
+    i.to_i64().filter(|i| fits_i56(*i)).map(to_i56)
+}
+
+/// Note, this will truncate i if it does not fit in 56 bits, use GVm::alloc_int if i may be out
+/// of range.
 pub fn to_i56(i: i64) -> Value {
     let bytes = i.to_be_bytes();
     let bytes7 = [
@@ -128,7 +197,7 @@ pub fn to_i56(i: i64) -> Value {
 pub enum Value {
     Byte(u8),
     Int([u8; 7]), // Store a 7 byte int (i56...).
-    Float(F32Wrap),
+    Float(FloatWrap),
     CodePoint(char),
     CharCluster(u8, [u8; 6]),
     CharClusterLong(Handle), // Handle points to a String on the heap.
@@ -145,6 +214,7 @@ pub enum Value {
     String(Handle),
     Vector(Handle),
     Map(Handle),
+    Set(Handle),
     Bytes(Handle),
     Pair(Handle),
     List(Handle, u16),
@@ -154,8 +224,20 @@ pub enum Value {
     CallFrame(Handle),
     Value(Handle),
     Error(Handle),
+    BigInt(Handle), // Arbitrary precision int, only used for ints that do not fit an Int.
+    PVec(Handle),   // Persistent (immutable, structurally shared) vector.
+    PMap(Handle),   // Persistent (immutable, structurally shared) hash map.
+    Weak(Handle),   // Weak reference, does not keep its target alive.
+    Host(Handle),   // Rust value owned by the VM (see HostObject).
 }
 
+// Everything fits in a tag byte and 7 bytes of payload unless floats are f64, then the f64 payload
+// needs its own 8 bytes.
+#[cfg(not(feature = "f64"))]
+const _: () = assert!(std::mem::size_of::<Value>() == 8);
+#[cfg(feature = "f64")]
+const _: () = assert!(std::mem::size_of::<Value>() == 16);
+
 impl Default for Value {
     fn default() -> Self {
         Self::new()
@@ -164,13 +246,13 @@ impl Default for Value {
 
 impl From<f32> for Value {
     fn from(value: f32) -> Self {
-        Self::Float(F32Wrap(value))
+        Self::Float(FloatWrap::new(value.into()))
     }
 }
 
 impl From<f64> for Value {
     fn from(value: f64) -> Self {
-        Self::Float(F32Wrap(value as f32))
+        Self::Float(FloatWrap::new(value))
     }
 }
 
@@ -250,26 +332,47 @@ impl Value {
     }
 
     pub fn is_int(&self) -> bool {
-        matches!(&self, Value::Byte(_) | Value::Int(_))
+        matches!(&self, Value::Byte(_) | Value::Int(_) | Value::BigInt(_))
     }
 
     pub fn is_number(&self) -> bool {
-        matches!(&self, Value::Byte(_) | Value::Int(_) | Value::Float(_))
+        matches!(
+            &self,
+            Value::Byte(_) | Value::Int(_) | Value::BigInt(_) | Value::Float(_)
+        )
     }
 
-    pub fn get_int<ENV>(&self, _vm: &GVm<ENV>) -> VMResult<i64> {
+    /// Return an integral value as an i64, this will be an error for a BigInt that does not fit.
+    pub fn get_int<ENV>(&self, vm: &GVm<ENV>) -> VMResult<i64> {
         match &self {
             Value::Byte(b) => Ok(*b as i64),
             Value::Int(i) => Ok(from_i56(i)),
+            Value::BigInt(h) => vm.get_bigint(*h).to_i64().ok_or_else(|| {
+                VMError::new_value(format!(
+                    "Integer too large for 64 bits: {}",
+                    vm.get_bigint(*h)
+                ))
+            }),
+            _ => Err(VMError::new_value(format!("Not an integer: {self:?}"))),
+        }
+    }
+
+    /// Return any integral value as a BigInt.
+    pub fn get_bigint<ENV>(&self, vm: &GVm<ENV>) -> VMResult<BigInt> {
+        match &self {
+            Value::Byte(b) => Ok((*b).into()),
+            Value::Int(i) => Ok(from_i56(i).into()),
+            Value::BigInt(h) => Ok(vm.get_bigint(*h).clone()),
             _ => Err(VMError::new_value(format!("Not an integer: {self:?}"))),
         }
     }
 
-    pub fn get_float<ENV>(&self, _vm: &GVm<ENV>) -> VMResult<f32> {
+    pub fn get_float<ENV>(&self, vm: &GVm<ENV>) -> VMResult<f64> {
         match &self {
-            Value::Byte(b) => Ok(*b as f32),
-            Value::Int(i) => Ok(from_i56(i) as f32),
-            Value::Float(f) => Ok(f.0),
+            Value::Byte(b) => Ok(*b as f64),
+            Value::Int(i) => Ok(from_i56(i) as f64),
+            Value::BigInt(h) => Ok(vm.get_bigint(*h).to_f64().unwrap_or(f64::NAN)),
+            Value::Float(f) => Ok(f.get()),
             _ => Err(VMError::new_value(format!("Not a float: {self:?}"))),
         }
     }
@@ -289,6 +392,7 @@ impl Value {
             Value::String(handle) => Some(*handle),
             Value::Vector(handle) => Some(*handle),
             Value::Map(handle) => Some(*handle),
+            Value::Set(handle) => Some(*handle),
             Value::Bytes(handle) => Some(*handle),
             Value::Pair(handle) => Some(*handle),
             Value::List(handle, _) => Some(*handle),
@@ -298,6 +402,11 @@ impl Value {
             Value::CallFrame(handle) => Some(*handle),
             Value::Value(handle) => Some(*handle),
             Value::Error(handle) => Some(*handle),
+            Value::BigInt(handle) => Some(*handle),
+            Value::PVec(handle) => Some(*handle),
+            Value::PMap(handle) => Some(*handle),
+            Value::Weak(handle) => Some(*handle),
+            Value::Host(handle) => Some(*handle),
 
             Value::Byte(_) => None,
             Value::Int(_) => None,
@@ -398,10 +507,10 @@ impl Value {
             Value::True => "true".to_string(),
             Value::False => "false".to_string(),
             Value::Int(i) => format!("{}", from_i56(i)),
-            Value::Float(f) => format!("{}", f.0),
+            Value::Float(f) => format!("{f}"),
             Value::Byte(b) => format!("{b}"),
-            Value::Symbol(i) => vm.get_interned(*i).to_string(),
-            Value::Keyword(i) => format!(":{}", vm.get_interned(*i)),
+            Value::Symbol(i) => vm.interned_name(*i).to_string(),
+            Value::Keyword(i) => format!(":{}", vm.interned_name(*i)),
             Value::StringConst(i) => format!("\"{}\"", vm.get_interned(*i)),
             Value::Special(i) => format!("#<SpecialFn({})>", vm.get_interned(*i)),
             Value::CodePoint(ch) => format!("\\{ch}"),
@@ -437,6 +546,13 @@ impl Value {
                 res.push('}');
                 res
             }
+            Value::Set(handle) => {
+                let mut res = String::new();
+                res.push_str("#{");
+                list_out_iter(vm, &mut res, &mut vm.get_set(*handle).iter().copied());
+                res.push('}');
+                res
+            }
             Value::Pair(_) => {
                 let mut res = String::new();
                 res.push('(');
@@ -453,13 +569,50 @@ impl Value {
                 res
             }
             Value::String(handle) => format!("\"{}\"", vm.get_string(*handle)),
-            Value::Bytes(_) => "Bytes".to_string(), // XXX TODO
+            Value::Bytes(handle) => {
+                let mut res = String::from("#u8(");
+                for (i, b) in vm.get_bytes(*handle).iter().enumerate() {
+                    if i > 0 {
+                        res.push(' ');
+                    }
+                    res.push_str(&b.to_string());
+                }
+                res.push(')');
+                res
+            }
             Value::Value(handle) => vm.get_value(*handle).display_value(vm),
             Value::Error(handle) => {
                 let err = vm.get_error(*handle);
-                let key = vm.get_interned(err.keyword);
+                let key = vm.interned_name(err.keyword);
                 format!("error [{key}]: {}", err.data.display_value(vm))
             }
+            Value::BigInt(handle) => vm.get_bigint(*handle).to_string(),
+            Value::PVec(handle) => {
+                let v = vm.get_pvec(*handle);
+                let mut res = String::new();
+                res.push_str("(pvec");
+                if !v.is_empty() {
+                    res.push(' ');
+                    list_out_iter(vm, &mut res, &mut v.iter().copied());
+                }
+                res.push(')');
+                res
+            }
+            Value::PMap(handle) => {
+                let mut res = String::new();
+                res.push_str("(pmap");
+                for (key, val) in vm.get_pmap(*handle).iter() {
+                    res.push_str(&format!(
+                        " {} {}",
+                        key.display_value(vm),
+                        val.display_value(vm)
+                    ));
+                }
+                res.push(')');
+                res
+            }
+            Value::Weak(_) => "#<Weak>".to_string(),
+            Value::Host(handle) => format!("#<{}>", vm.get_host(*handle).type_name()),
         }
     }
 
@@ -481,7 +634,7 @@ impl Value {
     pub fn value_type<ENV>(&self, vm: &GVm<ENV>) -> ValueType {
         match self {
             Value::Byte(_) => ValueType::Byte,
-            Value::Int(_) => ValueType::Int,
+            Value::Int(_) | Value::BigInt(_) => ValueType::Int,
             Value::Float(_) => ValueType::Float,
             Value::CodePoint(_) => ValueType::CodePoint,
             Value::CharCluster(_, _) => ValueType::CharCluster,
@@ -498,6 +651,7 @@ impl Value {
             Value::String(_) => ValueType::String,
             Value::Vector(_) => ValueType::Vector,
             Value::Map(_) => ValueType::Map,
+            Value::Set(_) => ValueType::Set,
             Value::Bytes(_) => ValueType::Bytes,
             Value::Pair(_) => ValueType::Pair,
             Value::List(_, _) => ValueType::List,
@@ -506,11 +660,18 @@ impl Value {
             Value::Continuation(_) => ValueType::Continuation,
             Value::CallFrame(_) => ValueType::CallFrame,
             Value::Error(_) => ValueType::Error,
+            Value::PVec(_) => ValueType::PersistentVector,
+            Value::PMap(_) => ValueType::PersistentMap,
+            Value::Weak(_) => ValueType::Weak,
+            Value::Host(_) => ValueType::Host,
             Value::Value(handle) => vm.get_value(*handle).value_type(vm),
         }
     }
 
     pub fn display_type<ENV>(&self, vm: &GVm<ENV>) -> &'static str {
+        if let Value::Host(handle) = self {
+            return vm.get_host(*handle).type_name();
+        }
         self.value_type(vm).into()
     }
 
@@ -531,8 +692,8 @@ impl Value {
 
 #[derive(Clone, Debug)]
 pub struct Globals {
-    objects: Vec<Value>,
-    props: HashMap<u32, Arc<HashMap<Interned, Value>>>,
+    pub(crate) objects: Vec<Value>,
+    pub(crate) props: HashMap<u32, Arc<HashMap<Interned, Value>>>,
 }
 
 impl Default for Globals {
@@ -549,6 +710,15 @@ impl Globals {
         }
     }
 
+    /// Number of global slots (reserved or set).
+    pub fn len(&self) -> usize {
+        self.objects.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.objects.is_empty()
+    }
+
     pub fn reserve(&mut self) -> u32 {
         let index = self.objects.len();
         self.objects.push(Value::Undefined);
@@ -572,7 +742,8 @@ impl Globals {
             heap.mark(*obj);
         });
         self.props.iter().for_each(|(_, map)| {
-            for val in map.values() {
+            for (key, val) in map.iter() {
+                heap.mark_symbol(*key);
                 heap.mark(*val);
             }
         });
@@ -619,8 +790,13 @@ pub const SLOSH_CONTINUATION: &str = "Continuation";
 pub const SLOSH_CALLFRAME: &str = "CallFrame";
 pub const SLOSH_VECTOR: &str = "Vector";
 pub const SLOSH_MAP: &str = "Map";
+pub const SLOSH_SET: &str = "Set";
 pub const SLOSH_PAIR: &str = "Pair";
 pub const SLOSH_ERROR: &str = "Error";
+pub const SLOSH_PERSISTENT_VECTOR: &str = "PersistentVector";
+pub const SLOSH_PERSISTENT_MAP: &str = "PersistentMap";
+pub const SLOSH_WEAK: &str = "Weak";
+pub const SLOSH_HOST: &str = "Host";
 
 /// Enum representing the various types of values in Slosh.
 #[derive(Copy, Clone, PartialEq, Eq)]
@@ -643,6 +819,7 @@ pub enum ValueType {
     String,
     Vector,
     Map,
+    Set,
     Bytes,
     Pair,
     List,
@@ -651,6 +828,10 @@ pub enum ValueType {
     Continuation,
     CallFrame,
     Error,
+    PersistentVector,
+    PersistentMap,
+    Weak,
+    Host,
 }
 
 impl Display for ValueType {
@@ -684,10 +865,15 @@ impl From<ValueType> for &'static str {
             ValueType::CallFrame => SLOSH_CALLFRAME,
             ValueType::Vector => SLOSH_VECTOR,
             ValueType::Map => SLOSH_MAP,
+            ValueType::Set => SLOSH_SET,
             ValueType::Pair => SLOSH_PAIR,
             ValueType::List => SLOSH_PAIR,
             ValueType::String => SLOSH_STRING,
             ValueType::Error => SLOSH_ERROR,
+            ValueType::PersistentVector => SLOSH_PERSISTENT_VECTOR,
+            ValueType::PersistentMap => SLOSH_PERSISTENT_MAP,
+            ValueType::Weak => SLOSH_WEAK,
+            ValueType::Host => SLOSH_HOST,
         }
     }
 }
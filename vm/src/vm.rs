@@ -1,30 +1,62 @@
 use std::alloc;
 use std::alloc::Layout;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 
 use crate::{
-    from_i56, CallFrame, CallFunc, CallFuncSig, Chunk, Globals, Handle, Heap, Interner, VMError,
-    VMErrorObj, VMResult, Value, HALT,
+    from_i56, CallFrame, CallFunc, CallFuncClosure, CallFuncSig, Chunk, Globals, Handle, Heap,
+    Interner, VMError, VMErrorObj, VMResult, Value, HALT, RET,
 };
 
 mod cons;
 mod storage;
+use storage::CallStackIter;
 #[macro_use]
 pub mod macros;
 mod call;
 mod call_collection;
 mod exec_loop;
-
-/// Size (in elements/Values) of the stack.
+mod image;
+mod map;
+pub use image::{IMAGE_MAGIC, IMAGE_VERSION};
+mod trace;
+use trace::Trace;
+mod backtrace;
+pub use backtrace::{Backtrace, BacktraceFrame, BACKTRACE_MAX_FRAMES};
+mod restart;
+use restart::{PendingSignal, RestartEntry};
+mod binding;
+use binding::Binding;
+mod call_profile;
+pub use call_profile::{CallProfile, CallStats, Callee};
+#[cfg(feature = "profile")]
+mod profile;
+#[cfg(feature = "profile")]
+pub use profile::{Profile, DEFAULT_PROFILE_INTERVAL};
+
+/// Initial size (in elements/Values) of the stack, it will grow as needed.
 pub const STACK_CAP: usize = 1024;
 
+/// Default maximum size (in elements/Values) the stack is allowed to grow to.
+pub const DEFAULT_STACK_MAX: usize = 1024 * 1024;
+
 const DEAD_CODE: [u8; 3] = [HALT, HALT, HALT];
+// Run by a tail call to a builtin with no call frame to return to (see finish_special_call).
+const RET_CODE: [u8; 1] = [RET];
 
 pub struct GVm<ENV> {
     interner: Interner,
     heap: Option<Heap>,
     //stack: [Value; STACK_CAP],
     stack: *mut Value,
+    // Current allocated size of stack in Values.
+    stack_cap: usize,
+    // Largest size the stack is allowed to grow to, hitting this is a stack overflow.
+    stack_limit: usize,
+    // Stacks that were replaced by a larger one.  These are kept alive until the outermost
+    // execute/do_call returns (or a reset/drop) so that any register slices handed to builtins
+    // remain valid after a grow.
+    old_stacks: Vec<(*mut Value, usize)>,
     registers: *mut Value,
     globals: Globals,
     buitins: Vec<CallFunc<ENV>>,
@@ -39,6 +71,34 @@ pub struct GVm<ENV> {
     current_ip_ptr: *const u8,
     callframe_id: usize,
     defers: Vec<Value>,
+    // Remaining instruction budget (calls and backward jumps), None is unlimited.
+    fuel: Option<u64>,
+    // Set from outside (another thread, signal handler) to abort the running code.
+    interrupt: Arc<AtomicBool>,
+    // Nesting level of execute/do_call, used to finish cleaning up after an abort.
+    exec_depth: usize,
+    // Chunks passed to execute/do_call that are running, their constants are GC roots.
+    exec_chunks: Vec<Arc<Chunk>>,
+    // Run Chunk::verify on chunks passed to execute/do_call.
+    verify_chunks: bool,
+    // Traced functions and calls, None when nothing is traced.
+    trace: Option<Box<Trace>>,
+    // Snapshot registers in backtraces.
+    backtrace_registers: bool,
+    // Backtrace of the last error raised by running code.
+    last_backtrace: Option<Arc<Backtrace>>,
+    // Signal raised by the builtin being called, see restart.rs.
+    pending_signal: Option<PendingSignal>,
+    // Restarts of the signal handlers that are running.
+    restarts: Vec<RestartEntry>,
+    // Saved values of the dynamic globals bound by running code, see binding.rs.
+    bindings: Vec<Binding>,
+    #[cfg(feature = "profile")]
+    profile: Option<Box<Profile>>,
+    #[cfg(feature = "profile")]
+    profiling: bool,
+    call_profile: Option<Box<CallProfile>>,
+    call_profiling: bool,
     env: ENV,
 }
 
@@ -58,23 +118,22 @@ impl GVm<()> {
 
 impl<ENV> GVm<ENV> {
     pub fn new_with_env(env: ENV) -> Self {
+        Self::new_with_env_stack_max(env, DEFAULT_STACK_MAX)
+    }
+
+    /// Create a new VM with a stack that will grow to at most stack_max Values.  Calls that
+    /// would need more stack than this will produce a catchable stack overflow error.
+    pub fn new_with_env_stack_max(env: ENV, stack_max: usize) -> Self {
         let globals = Globals::new();
-        let stack_layout =
-            Layout::array::<Value>(STACK_CAP).expect("Failed to get memory layout for stack!");
-        let stack = unsafe { alloc::alloc(stack_layout) } as *mut Value;
-        if stack.is_null() {
-            // Out of memory...
-            panic!("Unable to allocate a stack!");
-        }
-        // Initialize the stack else it will contain garbage.
-        for i in 0..STACK_CAP {
-            let val = unsafe { stack.add(i).as_mut().expect("cant be null!") };
-            *val = Value::Undefined;
-        }
+        let stack_cap = STACK_CAP.min(stack_max);
+        let stack = alloc_stack(stack_cap);
         Self {
             interner: Interner::with_capacity(8192),
             heap: Some(Heap::new()),
-            stack, //: [Value::Undefined; STACK_CAP],
+            stack,
+            stack_cap,
+            stack_limit: stack_max,
+            old_stacks: Vec::new(),
             registers: stack,
             globals,
             buitins: Vec::new(),
@@ -88,6 +147,23 @@ impl<ENV> GVm<ENV> {
             current_ip_ptr: DEAD_CODE.as_ptr(),
             callframe_id: 0,
             defers: Vec::new(),
+            fuel: None,
+            interrupt: Arc::new(AtomicBool::new(false)),
+            exec_depth: 0,
+            exec_chunks: Vec::new(),
+            verify_chunks: false,
+            trace: None,
+            backtrace_registers: false,
+            last_backtrace: None,
+            pending_signal: None,
+            restarts: Vec::new(),
+            bindings: Vec::new(),
+            #[cfg(feature = "profile")]
+            profile: None,
+            #[cfg(feature = "profile")]
+            profiling: false,
+            call_profile: None,
+            call_profiling: false,
             env,
         }
     }
@@ -105,11 +181,99 @@ impl<ENV> GVm<ENV> {
     }
 
     pub fn stack_slice(&self) -> &[Value] {
-        unsafe { std::slice::from_raw_parts(self.stack, STACK_CAP) }
+        unsafe { std::slice::from_raw_parts(self.stack, self.stack_cap) }
     }
 
     pub fn stack_slice_mut(&mut self) -> &mut [Value] {
-        unsafe { std::slice::from_raw_parts_mut(self.stack, STACK_CAP) }
+        unsafe { std::slice::from_raw_parts_mut(self.stack, self.stack_cap) }
+    }
+
+    /// Current allocated size of the stack (in Values).
+    pub fn stack_capacity(&self) -> usize {
+        self.stack_cap
+    }
+
+    /// Maximum size the stack can grow to (in Values).
+    pub fn stack_limit(&self) -> usize {
+        self.stack_limit
+    }
+
+    /// Remaining fuel, None if execution is not limited.
+    pub fn fuel(&self) -> Option<u64> {
+        self.fuel
+    }
+
+    /// Limit execution to fuel steps (calls and backward jumps), None removes the limit.
+    /// Running out raises a :fuel error.  The fuel stays at zero after that (so an on-error
+    /// handler can not keep going) until set again.
+    pub fn set_fuel(&mut self, fuel: Option<u64>) {
+        self.fuel = fuel;
+    }
+
+    /// Flag that aborts the running code with an :interrupt error when set to true.  It can be
+    /// set from another thread and is checked at the same points fuel is used.  It stays set
+    /// until the outermost execute/do_call returns, then it is cleared.
+    pub fn interrupt_flag(&self) -> Arc<AtomicBool> {
+        self.interrupt.clone()
+    }
+
+    /// Use a unit of fuel and check for an interrupt.  Called by the exec loop on calls and
+    /// backward jumps.
+    #[inline]
+    fn check_limits(&mut self) -> VMResult<()> {
+        if self.interrupt.load(Ordering::Relaxed) {
+            return Err(VMError::new_interrupt("Execution interrupted."));
+        }
+        if let Some(fuel) = &mut self.fuel {
+            if *fuel == 0 {
+                return Err(VMError::new_fuel("Execution fuel exhausted."));
+            }
+            *fuel -= 1;
+        }
+        Ok(())
+    }
+
+    /// Put the VM back into the state saved by execute or do_call after running out of fuel or
+    /// memory or being interrupted so it can be used again.
+    fn abort_cleanup(&mut self, bindings_depth: usize) {
+        self.unbind_to(bindings_depth);
+        self.err_frame = None;
+        if self.exec_depth == 0 {
+            self.interrupt.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Take the defers an uncaught error left behind, the current frame's and the ones saved in
+    /// the call frames of its callers (down to the do_call), in the order they run.
+    fn take_unwound_defers(&mut self) -> Vec<Value> {
+        let mut defers: Vec<Value> = std::mem::take(&mut self.defers);
+        defers.reverse();
+        for frame in CallStackIter::new(self) {
+            defers.extend(frame.defers.iter().rev());
+        }
+        defers
+    }
+
+    /// Run defers from take_unwound_defers, there is already an error so theirs are dropped.
+    fn run_unwound_defers(&mut self, defers: Vec<Value>) {
+        for defer in &defers {
+            self.heap_sticky(*defer);
+        }
+        for defer in defers {
+            let _ = match defer {
+                Value::Lambda(h) => {
+                    let l = self.get_lambda(h);
+                    self.do_call(l, &[], None)
+                }
+                Value::Closure(h) => {
+                    let (l, caps) = self.get_closure(h);
+                    let caps = caps.to_vec();
+                    self.do_call(l, &[], Some(&caps[..]))
+                }
+                _ => Ok(Value::Nil),
+            };
+            self.heap_unsticky(defer);
+        }
     }
 
     /// Return the register for idx.
@@ -119,7 +283,10 @@ impl<ENV> GVm<ENV> {
 
     pub fn register_slice<'b>(&self) -> &'b [Value] {
         unsafe {
-            std::slice::from_raw_parts(self.stack.add(self.stack_top), STACK_CAP - self.stack_top)
+            std::slice::from_raw_parts(
+                self.stack.add(self.stack_top),
+                self.stack_cap - self.stack_top,
+            )
         }
     }
 
@@ -133,6 +300,16 @@ impl<ENV> GVm<ENV> {
         }
     }
 
+    /// Return the register number held by register idx (for MOVI/MOVII), errors if it is not an
+    /// integer or not a register of the current frame.
+    fn register_index(&self, idx: usize) -> VMResult<usize> {
+        let i = self.register_int(idx)?;
+        match usize::try_from(i) {
+            Ok(reg) if self.stack_top + reg <= self.stack_max => Ok(reg),
+            _ => Err(VMError::new_vm(format!("Register index {i} out of range."))),
+        }
+    }
+
     /// Return the current register for idx, if it is stored on heap then dereference it first.
     pub fn register_unref(&self, idx: usize) -> Value {
         let reg = self.register(idx);
@@ -169,14 +346,53 @@ impl<ENV> GVm<ENV> {
         }
     }
 
+    /// Make sure the stack can hold a frame with registers up to stack_max (plus a little headroom
+    /// for the call frame and arguments of the next call), growing it if needed.
+    /// Returns a stack overflow error if this would exceed the stack limit.
+    fn ensure_stack(&mut self, stack_max: usize) -> VMResult<()> {
+        let needed = stack_max + 3;
+        if needed <= self.stack_cap {
+            return Ok(());
+        }
+        if needed > self.stack_limit {
+            return Err(VMError::new_stack(format!(
+                "Stack overflow, exceeded the limit of {} values.",
+                self.stack_limit
+            )));
+        }
+        let mut new_cap = self.stack_cap.max(1) * 2;
+        while new_cap < needed {
+            new_cap *= 2;
+        }
+        let new_cap = new_cap.min(self.stack_limit);
+        let new_stack = alloc_stack(new_cap);
+        unsafe {
+            std::ptr::copy_nonoverlapping(self.stack, new_stack, self.stack_cap);
+            let reg_offset = self.registers.offset_from(self.stack) as usize;
+            self.registers = new_stack.add(reg_offset);
+        }
+        self.old_stacks.push((self.stack, self.stack_cap));
+        self.stack = new_stack;
+        self.stack_cap = new_cap;
+        Ok(())
+    }
+
+    /// Free any stacks left over from growing the stack.
+    /// Only safe when nothing (i.e. a builtin) is holding onto a slice of registers, this is the
+    /// case once the outermost execute or do_call returns.
+    fn free_old_stacks(&mut self) {
+        for (stack, cap) in self.old_stacks.drain(..) {
+            free_stack(stack, cap);
+        }
+    }
+
     fn mk_str(&mut self, reg1: u16, reg2: u16) -> VMResult<Value> {
         let mut val = String::new();
         for reg in reg1..=reg2 {
             let v = self.register_unref(reg as usize);
             val.push_str(&v.pretty_value(self));
         }
-        let val = self.alloc_string(val);
-        Ok(val)
+        self.alloc_string(val)
     }
 
     fn is_eq(&self, reg1: u16, reg2: u16) -> VMResult<Value> {
@@ -200,15 +416,33 @@ impl<ENV> GVm<ENV> {
     }
 
     pub fn add_builtin(&mut self, func: CallFuncSig<ENV>) -> Value {
+        self.push_builtin(CallFunc::Fn(func))
+    }
+
+    /// Add a closure as a builtin, unlike add_builtin it can capture state.  Use Cell, RefCell,
+    /// etc for state the closure changes.
+    pub fn add_builtin_closure<F>(&mut self, func: F) -> Value
+    where
+        F: Fn(&mut GVm<ENV>, &[Value]) -> VMResult<Value> + 'static,
+    {
+        self.push_builtin(CallFunc::Closure(Arc::new(func)))
+    }
+
+    /// Add a shared closure as a builtin (see add_builtin_closure).
+    pub fn add_builtin_shared(&mut self, func: CallFuncClosure<ENV>) -> Value {
+        self.push_builtin(CallFunc::Closure(func))
+    }
+
+    fn push_builtin(&mut self, func: CallFunc<ENV>) -> Value {
         let result = self.buitins.len();
-        self.buitins.push(CallFunc { func });
+        self.buitins.push(func);
         Value::Builtin(result as u32)
     }
 
-    /// Return the builtin function at idx.
+    /// Return the builtin function at idx, use call on it to run it.
     /// Note, will panic if idx is not a valid builtin index.
-    pub fn get_builtin(&self, idx: u32) -> &CallFuncSig<ENV> {
-        &self.buitins[idx as usize].func
+    pub fn get_builtin(&self, idx: u32) -> CallFunc<ENV> {
+        self.buitins[idx as usize].clone()
     }
 
     pub fn is_equal_pair(&self, val1: Value, val2: Value) -> VMResult<Value> {
@@ -216,11 +450,15 @@ impl<ENV> GVm<ENV> {
         if val1 == val2 {
             val = Value::True;
         } else if val1.is_int() && val2.is_int() {
-            if val1.get_int(self)? == val2.get_int(self)? {
+            // Big ints never equal an Int and equal big ints share a handle (so were checked above).
+            if !matches!(val1, Value::BigInt(_))
+                && !matches!(val2, Value::BigInt(_))
+                && val1.get_int(self)? == val2.get_int(self)?
+            {
                 val = Value::True;
             }
         } else if val1.is_number() && val2.is_number() {
-            if (val1.get_float(self)? - val2.get_float(self)?).abs() < f32::EPSILON {
+            if (val1.get_float(self)? - val2.get_float(self)?).abs() < f64::EPSILON {
                 val = Value::True;
             }
         } else {
@@ -259,6 +497,48 @@ impl<ENV> GVm<ENV> {
                         }
                     }
                 }
+                Value::Set(h1) => {
+                    if let Value::Set(h2) = val2 {
+                        let s1 = self.heap().get_set(h1);
+                        let s2 = self.heap().get_set(h2);
+                        if s1.len() == s2.len() && s1.iter().all(|item| s2.contains(item)) {
+                            val = Value::True;
+                        }
+                    }
+                }
+                Value::PVec(h1) => {
+                    if let Value::PVec(h2) = val2 {
+                        let v1 = self.heap().get_pvec(h1);
+                        let v2 = self.heap().get_pvec(h2);
+                        if v1.len() == v2.len() {
+                            val = Value::True;
+                            for (a, b) in v1.iter().zip(v2.iter()) {
+                                val = self.is_equal_pair(*a, *b)?;
+                                if val == Value::False {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                }
+                Value::PMap(h1) => {
+                    if let Value::PMap(h2) = val2 {
+                        let m1 = self.heap().get_pmap(h1);
+                        let m2 = self.heap().get_pmap(h2);
+                        if m1.len() == m2.len() {
+                            val = Value::True;
+                            for (key, v1) in m1.iter() {
+                                val = match m2.get(key) {
+                                    Some(v2) => self.is_equal_pair(*v1, *v2)?,
+                                    None => Value::False,
+                                };
+                                if val == Value::False {
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                }
                 Value::Bytes(h1) => {
                     if let Value::Bytes(h2) = val2 {
                         let b1 = self.heap().get_bytes(h1);
@@ -315,6 +595,41 @@ impl<ENV> GVm<ENV> {
         Ok(val)
     }
 
+    /// If true then execute and do_call verify the chunk (and any lambdas in its constants)
+    /// before running it.  Use this when chunks are built from an untrusted source, chunks read
+    /// with Chunk::read_bytecode are always verified.
+    pub fn set_verify_chunks(&mut self, verify: bool) {
+        self.verify_chunks = verify;
+    }
+
+    pub fn verify_chunks(&self) -> bool {
+        self.verify_chunks
+    }
+
+    /// Verify chunk and any lambdas in its constants, see Chunk::verify.  The captures of those
+    /// lambdas are also checked, CLOSE reads them from chunk's registers.
+    pub fn verify_chunk(&self, chunk: &Chunk) -> VMResult<()> {
+        chunk.verify(self.globals.len())?;
+        let max_reg = chunk.input_regs + chunk.extra_regs;
+        for constant in &chunk.constants {
+            if let Value::Lambda(handle) = constant {
+                let lambda = self.get_lambda(*handle);
+                if let Some(reg) = lambda
+                    .captures
+                    .iter()
+                    .flatten()
+                    .find(|r| **r as usize > max_reg)
+                {
+                    return Err(VMError::new_chunk(format!(
+                        "Verify: lambda captures register {reg} out of range."
+                    )));
+                }
+                self.verify_chunk(&lambda)?;
+            }
+        }
+        Ok(())
+    }
+
     /// Runs a lambda.  Will save and restore the VM state even on error, chunk is expected to be a
     /// callable with params and any captures (closure) in caps.
     /// This is useful for macro expansion, eval and things like that.  It can be safely used while
@@ -325,11 +640,17 @@ impl<ENV> GVm<ENV> {
         params: &[Value],
         caps: Option<&[Handle]>,
     ) -> VMResult<Value> {
+        if self.verify_chunks {
+            self.verify_chunk(&chunk)?;
+        }
+        self.ensure_stack(self.stack_max + 1 + chunk.input_regs + chunk.extra_regs)?;
         let stack_top = self.stack_top;
         let stack_max = self.stack_max;
         let ip = self.ip_ptr;
         let this_fn = self.this_fn;
         let on_error = self.on_error;
+        // The call starts with no defers, ours run when the caller returns.
+        let defers = std::mem::take(&mut self.defers);
         self.this_fn = None;
         self.on_error = None;
         self.stack_top = self.stack_max + 1;
@@ -358,43 +679,113 @@ impl<ENV> GVm<ENV> {
                 mov_register!(self, cap_first + i, Value::Value(*c));
             }
         }
+        self.exec_depth += 1;
+        let call_depth = self.call_profile_depth();
+        let trace_depth = self.trace_depth();
+        let restarts_depth = self.restarts_depth();
+        let bindings_depth = self.bindings_depth();
+        self.trace_do_call(&chunk, params);
+        self.exec_chunks.push(chunk.clone());
         let res = self.execute2(chunk).map(|_| self.stack(self.stack_top));
+        self.exec_chunks.pop();
+        self.call_profile_truncate(call_depth);
+        if let Err(e) = &res {
+            self.trace_error(e, trace_depth);
+        }
+        self.trace_truncate(trace_depth);
+        self.restarts_truncate(restarts_depth);
+        self.exec_depth -= 1;
+        if self.exec_depth == 0 {
+            self.free_old_stacks();
+        }
+        // An error returned to a builtin is its to handle, the defers of the frames it unwound are
+        // dropped.  Only an uncaught error (from the outermost do_call) runs them.
+        let unwound = match &res {
+            Err(e) if !e.is_abort() && self.exec_depth == 0 => self.take_unwound_defers(),
+            _ => Vec::new(),
+        };
+        // However the call ended it can not leave any bindings behind.
+        self.unbind_to(bindings_depth);
         self.stack_top = stack_top;
         self.stack_max = stack_max;
         self.ip_ptr = ip;
         self.this_fn = this_fn;
         self.on_error = on_error;
+        self.defers = defers;
+        if let Err(e) = &res {
+            if e.is_abort() {
+                self.abort_cleanup(bindings_depth);
+            }
+        }
+        self.run_unwound_defers(unwound);
         res
     }
 
     /// Executes chunk.  Will save the current VM state and restore on success or leave it on error.
-    /// This allows a debugger to work with the "broken" image.
+    /// This allows a debugger to work with the "broken" image (dynamic bindings included, reset
+    /// restores them).  Running out of fuel or memory or being interrupted is the exception, the
+    /// state is restored so the VM can be reused.
     pub fn execute(&mut self, chunk: Arc<Chunk>) -> VMResult<Value> {
+        if self.verify_chunks {
+            self.verify_chunk(&chunk)?;
+        }
+        self.ensure_stack(self.stack_max + chunk.input_regs + chunk.extra_regs)?;
         let stack_top = self.stack_top;
         let stack_max = self.stack_max;
         let ip = self.ip_ptr;
         let this_fn = self.this_fn;
         let on_error = self.on_error;
+        let defers = std::mem::take(&mut self.defers);
         self.this_fn = None;
         self.stack_top = self.stack_max;
         self.stack_max = self.stack_top + chunk.input_regs + chunk.extra_regs;
 
         // Return on error without resetting the VM.
         // This is to allow debugging a live image/vm.
-        self.execute2(chunk)?;
+        self.exec_depth += 1;
+        let call_depth = self.call_profile_depth();
+        let trace_depth = self.trace_depth();
+        let restarts_depth = self.restarts_depth();
+        let bindings_depth = self.bindings_depth();
+        self.exec_chunks.push(chunk.clone());
+        let res = self.execute2(chunk);
+        self.exec_chunks.pop();
+        self.call_profile_truncate(call_depth);
+        self.trace_truncate(trace_depth);
+        self.restarts_truncate(restarts_depth);
+        self.exec_depth -= 1;
+        if self.exec_depth == 0 {
+            self.free_old_stacks();
+        }
+        if let Err(e) = res {
+            if e.is_abort() {
+                self.stack_top = stack_top;
+                self.stack_max = stack_max;
+                self.ip_ptr = ip;
+                self.this_fn = this_fn;
+                self.on_error = on_error;
+                self.defers = defers;
+                self.abort_cleanup(bindings_depth);
+            }
+            return Err(e);
+        }
         let res = self.stack(self.stack_top);
+        self.unbind_to(bindings_depth);
 
         self.stack_top = stack_top;
         self.stack_max = stack_max;
         self.ip_ptr = ip;
         self.this_fn = this_fn;
         self.on_error = on_error;
+        self.defers = defers;
         Ok(res)
     }
 
     /// Reset the VM to default settings.  Useful for cleaning up if you want to abort an execute()
-    /// that errored out.
+    /// that errored out, its dynamic bindings are restored.  No code is run, any defers left by
+    /// the error are dropped.
     pub fn reset(&mut self) {
+        self.defers.clear();
         self.this_fn = None;
         self.on_error = None;
         self.err_frame = None;
@@ -403,8 +794,15 @@ impl<ENV> GVm<ENV> {
         self.ip_ptr = DEAD_CODE.as_ptr();
         self.current_ip_ptr = DEAD_CODE.as_ptr();
         self.callframe_id = 0;
-        // XXX TODO- should probably run any defers before the reset.
-        self.defers = Vec::new();
+        self.exec_depth = 0;
+        self.exec_chunks.clear();
+        self.trace_truncate(0);
+        self.last_backtrace = None;
+        self.pending_signal = None;
+        self.restarts.clear();
+        self.unbind_to(0);
+        self.interrupt.store(false, Ordering::Relaxed);
+        self.free_old_stacks();
     }
 
     fn execute2(&mut self, chunk: Arc<Chunk>) -> VMResult<()> {
@@ -412,42 +810,72 @@ impl<ENV> GVm<ENV> {
 
         let mut done = false;
         let mut result = Ok(());
+        let trace_depth = self.trace_depth();
         while !done {
-            result = if let Err((e, echunk)) = self.exec_loop(chunk.clone()) {
-                if self.err_frame.is_none() {
-                    self.err_frame = Some(CallFrame {
-                        id: 0,
-                        chunk: echunk,
-                        stack_top: self.stack_top,
-                        ip: self.ip_ptr,
-                        current_ip: self.current_ip_ptr,
-                        this_fn: self.this_fn,
-                        defers: std::mem::take(&mut self.defers),
-                        on_error: self.on_error,
-                        called: Value::Undefined,
-                    });
-                }
-                if let Some(on_error) = self.on_error {
-                    self.make_registers();
-                    *self.register_mut(1) = Value::Keyword(self.intern(e.key));
-                    *self.register_mut(2) = match &e.obj {
-                        VMErrorObj::Message(msg) => Value::StringConst(self.intern(msg)),
-                        VMErrorObj::Object(v) => *v,
-                    };
-                    self.on_error = None;
-                    match self.make_call(on_error, chunk.clone(), 0, 2, true) {
+            result = if let Err((mut e, echunk)) = self.exec_loop(chunk.clone()) {
+                self.error_backtrace(&mut e, &echunk);
+                let resumed = match self.on_error {
+                    Some(on_error) => self.call_signal_handler(on_error, echunk.clone(), &e),
+                    None => {
+                        self.pending_signal = None;
+                        None
+                    }
+                };
+                if let Some(resumed) = resumed {
+                    // A signal, the handler was called at the raise point.
+                    match resumed {
                         Ok(c) => {
                             chunk = c;
                             Err(e)
                         }
-                        Err((ne, _c)) => {
+                        Err((mut ne, c)) => {
+                            self.error_backtrace(&mut ne, &c);
+                            self.trace_error(&ne, trace_depth);
                             done = true;
                             Err(ne)
                         }
                     }
                 } else {
-                    done = true;
-                    Err(e)
+                    if self.err_frame.is_none() {
+                        self.err_frame = Some(CallFrame {
+                            id: 0,
+                            chunk: echunk.clone(),
+                            stack_top: self.stack_top,
+                            ip: self.ip_ptr,
+                            current_ip: self.current_ip_ptr,
+                            this_fn: self.this_fn,
+                            // The defers stay, a handler replacing this frame runs them.
+                            defers: self.defers.clone(),
+                            on_error: self.on_error,
+                            called: Value::Undefined,
+                        });
+                    }
+                    if let Some(on_error) = self.on_error {
+                        self.trace_unwind(&e, self.stack_top);
+                        self.make_registers();
+                        *self.register_mut(1) = Value::Keyword(self.intern(e.key));
+                        *self.register_mut(2) = match &e.obj {
+                            VMErrorObj::Message(msg) => Value::StringConst(self.intern(msg)),
+                            VMErrorObj::Object(v) => *v,
+                        };
+                        self.on_error = None;
+                        match self.make_call(on_error, chunk.clone(), 0, 2, true) {
+                            Ok(c) => {
+                                chunk = c;
+                                Err(e)
+                            }
+                            Err((mut ne, c)) => {
+                                self.error_backtrace(&mut ne, &c);
+                                self.trace_error(&ne, trace_depth);
+                                done = true;
+                                Err(ne)
+                            }
+                        }
+                    } else {
+                        self.trace_error(&e, trace_depth);
+                        done = true;
+                        Err(e)
+                    }
                 }
             } else {
                 self.err_frame = None;
@@ -460,6 +888,35 @@ impl<ENV> GVm<ENV> {
     }
 }
 
+impl<ENV> Drop for GVm<ENV> {
+    fn drop(&mut self) {
+        self.free_old_stacks();
+        free_stack(self.stack, self.stack_cap);
+    }
+}
+
+/// Allocate a stack of cap Values, all initialized to Undefined.
+fn alloc_stack(cap: usize) -> *mut Value {
+    let stack_layout =
+        Layout::array::<Value>(cap.max(1)).expect("Failed to get memory layout for stack!");
+    let stack = unsafe { alloc::alloc(stack_layout) } as *mut Value;
+    if stack.is_null() {
+        // Out of memory...
+        panic!("Unable to allocate a stack!");
+    }
+    // Initialize the stack else it will contain garbage.
+    for i in 0..cap {
+        unsafe { stack.add(i).write(Value::Undefined) };
+    }
+    stack
+}
+
+fn free_stack(stack: *mut Value, cap: usize) {
+    let stack_layout =
+        Layout::array::<Value>(cap.max(1)).expect("Failed to get memory layout for stack!");
+    unsafe { alloc::dealloc(stack as *mut u8, stack_layout) };
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -684,7 +1141,7 @@ mod tests {
         assert!(result == 255 + 256);
 
         let mut vm = Vm::new();
-        *vm.stack_mut(0) = vm.new_upval(1.into());
+        *vm.stack_mut(0) = vm.new_upval(1.into()).unwrap();
         *vm.stack_mut(1) = 10.into();
         *vm.stack_mut(2) = 1.into();
         let mut chunk = Arc::try_unwrap(chunk).unwrap();
@@ -925,7 +1382,7 @@ mod tests {
         chunk.encode2(MOV, 3, 1, Some(line)).unwrap();
         chunk.encode1(SRET, 3, Some(line))?;
         chunk.args = 2;
-        let add = vm.alloc_lambda(Arc::new(chunk));
+        let add = vm.alloc_lambda(Arc::new(chunk)).unwrap();
 
         let mut chunk = Chunk::new("no_file", 1);
         let line = 1;
@@ -935,7 +1392,7 @@ mod tests {
         chunk.encode2(MOV, 3, 1, Some(line)).unwrap();
         chunk.encode1(SRET, 3, Some(line))?;
         chunk.args = 1;
-        let add_ten = vm.alloc_lambda(Arc::new(chunk));
+        let add_ten = vm.alloc_lambda(Arc::new(chunk)).unwrap();
 
         let mut chunk = Chunk::new("no_file", 1);
         let line = 1;
@@ -969,7 +1426,7 @@ mod tests {
         chunk.encode2(ADD, 3, 1, Some(line)).unwrap();
         chunk.encode1(SRET, 3, Some(line))?;
         chunk.args = 2;
-        let add = vm.alloc_lambda(Arc::new(chunk));
+        let add = vm.alloc_lambda(Arc::new(chunk)).unwrap();
 
         let mut chunk = Chunk::new("no_file", 1);
         let line = 1;
@@ -981,7 +1438,7 @@ mod tests {
         // The TCALL will keep HALT from executing.
         chunk.encode0(HALT, Some(line))?;
         chunk.args = 1;
-        let add_ten = vm.alloc_lambda(Arc::new(chunk));
+        let add_ten = vm.alloc_lambda(Arc::new(chunk)).unwrap();
 
         let mut chunk = Chunk::new("no_file", 1);
         let line = 1;
@@ -1023,7 +1480,7 @@ mod tests {
             if !registers.is_empty() {
                 return Err(VMError::new_vm("test make_str: wrong number of args."));
             }
-            let s = vm.alloc_string("builtin hello".into());
+            let s = vm.alloc_string("builtin hello".into()).unwrap();
             Ok(s)
         }
         let mut vm = Vm::new();
@@ -1036,7 +1493,7 @@ mod tests {
         chunk.encode3(CALL, 10, 2, 3, Some(line)).unwrap();
         chunk.encode1(SRET, 3, Some(line))?;
         chunk.args = 2;
-        let add = vm.alloc_lambda(Arc::new(chunk));
+        let add = vm.alloc_lambda(Arc::new(chunk)).unwrap();
 
         let mut chunk = Chunk::new("no_file", 1);
         let line = 1;
@@ -1045,7 +1502,7 @@ mod tests {
         chunk.encode2(TCALL, 10, 2, Some(line)).unwrap();
         chunk.encode0(RET, Some(line))?;
         chunk.args = 2;
-        let tadd = vm.alloc_lambda(Arc::new(chunk));
+        let tadd = vm.alloc_lambda(Arc::new(chunk)).unwrap();
 
         let mut chunk = Chunk::new("no_file", 1);
         let line = 1;
@@ -1055,7 +1512,7 @@ mod tests {
         chunk.encode3(CALL, 4, 1, 2, Some(line)).unwrap();
         chunk.encode1(SRET, 2, Some(line))?;
         chunk.args = 1;
-        let add_ten = vm.alloc_lambda(Arc::new(chunk));
+        let add_ten = vm.alloc_lambda(Arc::new(chunk)).unwrap();
 
         let mut chunk = Chunk::new("no_file", 1);
         let line = 1;
@@ -1561,4 +2018,168 @@ mod tests {
         assert!(res.unwrap_err().to_string() == "[rt]: Divide by zero error.");
         Ok(())
     }
+
+    #[test]
+    fn test_reclaim_symbols() -> VMResult<()> {
+        fn gc(vm: &mut Vm, _registers: &[Value]) -> VMResult<Value> {
+            vm.gc();
+            Ok(Value::Nil)
+        }
+        let mut vm = Vm::new();
+        let weak = vm.intern_weak("weak-kept");
+        let gensym = vm.gensym("kept");
+        let kept = vm
+            .alloc_vector(vec![Value::Symbol(weak), Value::Keyword(gensym)])
+            .unwrap();
+        let slot = vm.reserve_global();
+        vm.set_global(slot, kept);
+        let weak_garbage = vm.intern_weak("weak-garbage");
+        let garbage = vm.gensym("garbage");
+        let in_chunk = vm.gensym("in-chunk");
+        let symbols = vm.symbols_len();
+
+        // Only referenced by the chunk, it must survive while the chunk runs.
+        let mut chunk = Chunk::new("no_file", 1);
+        let gc_const = chunk.add_constant(vm.add_builtin(gc)) as u16;
+        let sym_const = chunk.add_constant(Value::Symbol(in_chunk)) as u16;
+        chunk.encode2(CONST, 1, gc_const, Some(1))?;
+        chunk.encode3(CALL, 1, 0, 2, Some(1))?;
+        chunk.encode2(CONST, 0, sym_const, Some(1))?;
+        chunk.encode0(RET, Some(1))?;
+        let res = vm.execute(Arc::new(chunk))?;
+        assert_eq!(res, Value::Symbol(in_chunk));
+        assert_eq!(vm.get_interned(in_chunk), "in-chunk");
+
+        assert_eq!(vm.symbols_len(), symbols - 2);
+        assert_eq!(vm.get_if_interned("weak-kept"), Some(weak));
+        assert_eq!(vm.get_if_interned("weak-garbage"), None);
+        assert_eq!(vm.get_interned(gensym), "kept");
+        assert!(vm.is_uninterned(gensym));
+        assert!(!vm.is_uninterned(weak));
+        // Reclaimed ids are not reused, a stale id has no name instead of aliasing a new symbol.
+        let new = vm.gensym("new");
+        assert!(new != garbage && new != weak_garbage);
+        assert_eq!(vm.interned_name(new), "new");
+        Ok(())
+    }
+
+    #[test]
+    fn test_builtin_closure() -> VMResult<()> {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let mut vm = Vm::new();
+        let calls = Rc::new(Cell::new(0));
+        let counter = calls.clone();
+        let count = vm.add_builtin_closure(move |_vm, registers| {
+            counter.set(counter.get() + registers.len() as i64);
+            Ok(counter.get().into())
+        });
+        let offset = 100;
+        let shared: CallFuncClosure<()> =
+            Arc::new(move |vm, registers| Ok((registers[0].get_int(vm)? + offset).into()));
+        let add_offset = vm.add_builtin_shared(shared.clone());
+        let add_offset2 = vm.add_builtin_shared(shared);
+        assert_ne!(add_offset, add_offset2);
+
+        let mut chunk = Chunk::new("no_file", 1);
+        let count_const = chunk.add_constant(count) as u16;
+        let add_const = chunk.add_constant(add_offset2) as u16;
+        chunk.encode2(CONST, 1, count_const, Some(1))?;
+        chunk.encode3(CALL, 1, 2, 4, Some(1))?;
+        chunk.encode3(CALL, 1, 1, 4, Some(1))?;
+        chunk.encode2(CONST, 2, add_const, Some(1))?;
+        chunk.encode2(MOV, 5, 4, Some(1))?;
+        chunk.encode3(CALL, 2, 1, 4, Some(1))?;
+        chunk.encode2(MOV, 0, 4, Some(1))?;
+        chunk.encode0(RET, Some(1))?;
+        let res = vm.execute(Arc::new(chunk))?;
+        assert_eq!(res.get_int(&vm)?, 103);
+        assert_eq!(calls.get(), 3);
+        let Value::Builtin(idx) = count else {
+            panic!("expected a builtin");
+        };
+        assert_eq!(vm.get_builtin(idx).call(&mut vm, &[])?.get_int(&vm)?, 3);
+        Ok(())
+    }
+
+    #[test]
+    fn test_verify_captures() -> VMResult<()> {
+        let mut vm = Vm::new();
+        let mut inner = Chunk::new("no_file", 1);
+        inner.encode1(SRET, 1, Some(1))?;
+        inner.input_regs = 1;
+        inner.captures = Some(vec![2]);
+        let good = vm.alloc_lambda(Arc::new(inner.clone())).unwrap();
+        inner.captures = Some(vec![2, 3]);
+        let bad = vm.alloc_lambda(Arc::new(inner)).unwrap();
+
+        let mut chunk = Chunk::new("no_file", 1);
+        chunk.extra_regs = 2;
+        let lambda = chunk.add_constant(good) as u16;
+        chunk.encode2(CONST, 1, lambda, Some(1))?;
+        chunk.encode2(CLOSE, 1, 1, Some(1))?;
+        chunk.encode0(RET, Some(1))?;
+        vm.verify_chunk(&chunk)?;
+        // CLOSE would read register 3 of a chunk with 2.
+        chunk.constants[lambda as usize] = bad;
+        let err = vm.verify_chunk(&chunk).unwrap_err();
+        assert!(err.to_string().contains("captures register 3"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_movi() -> VMResult<()> {
+        let mut vm = Vm::new();
+        let mut chunk = Chunk::new("no_file", 1);
+        chunk.input_regs = 3;
+        chunk.encode2(MOVI, 1, 2, Some(1))?;
+        chunk.encode2(MOVII, 2, 1, Some(1))?;
+        chunk.encode2(ADD, 2, 3, Some(1))?;
+        chunk.encode1(SRET, 2, Some(1))?;
+        let chunk = Arc::new(chunk);
+        // R(3) = R(2) then R(2) = R(3), 7 + 7.
+        *vm.stack_mut(1) = 3.into();
+        *vm.stack_mut(2) = 7.into();
+        assert_eq!(vm.execute(chunk.clone())?.get_int(&vm)?, 14);
+
+        // Indexes outside the frame's registers are errors, not stack reads or writes.
+        for idx in [4, 100_000, -1] {
+            vm.reset();
+            *vm.stack_mut(1) = idx.into();
+            let err = vm.execute(chunk.clone()).unwrap_err();
+            assert!(err.to_string().contains("out of range"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_free_old_stacks() -> VMResult<()> {
+        fn grow(vm: &mut Vm, registers: &[Value]) -> VMResult<Value> {
+            let cap = vm.stack_capacity();
+            vm.ensure_stack(cap * 2)?;
+            assert_eq!(vm.old_stacks.len(), 1);
+            // The old stack is still around so registers is valid.
+            registers[0].get_int(vm)?;
+            Ok((vm.stack_capacity() as i64).into())
+        }
+        let mut vm = Vm::new();
+        let mut chunk = Chunk::new("no_file", 1);
+        let line = 1;
+        chunk.input_regs = 2;
+        let const1 = chunk.add_constant(vm.add_builtin(grow)) as u16;
+        chunk.encode2(CONST, 10, const1, Some(line))?;
+        chunk.encode2(MOV, 2, 1, Some(line))?;
+        chunk.encode3(CALL, 10, 1, 1, Some(line))?;
+        chunk.encode1(SRET, 1, Some(line))?;
+        chunk.extra_regs = 10;
+        *vm.stack_mut(1) = 1.into();
+        let cap = vm.stack_capacity();
+        let new_cap = vm.execute(Arc::new(chunk))?.get_int(&vm)?;
+        assert!(new_cap as usize > cap);
+        assert_eq!(vm.stack_capacity(), new_cap as usize);
+        // Nothing can hold the old stack once the outer execute returns.
+        assert!(vm.old_stacks.is_empty());
+        Ok(())
+    }
 }
@@ -0,0 +1,127 @@
+//! Call stacks captured when errors are created or raised.
+
+use std::sync::Arc;
+
+use super::storage::CallStackIter;
+use crate::{Chunk, GVm, VMError, Value};
+
+/// Most frames kept in a backtrace, the outermost frames past this are counted but dropped.
+pub const BACKTRACE_MAX_FRAMES: usize = 256;
+
+#[derive(Clone, Debug)]
+pub struct BacktraceFrame {
+    pub chunk: Arc<Chunk>,
+    /// Offset in the chunk's code of the instruction that was running.
+    pub offset: usize,
+    /// The frame's registers (starting at register 0), only captured when backtrace registers
+    /// are turned on.
+    pub registers: Option<Vec<Value>>,
+}
+
+impl BacktraceFrame {
+    pub fn line(&self) -> Option<u32> {
+        self.chunk.offset_to_line(self.offset)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Backtrace {
+    /// Innermost frame first.
+    pub frames: Vec<BacktraceFrame>,
+    /// Number of outer frames left out (see BACKTRACE_MAX_FRAMES).
+    pub skipped: usize,
+}
+
+impl Backtrace {
+    /// All the register values captured, these are kept alive by the backtrace.
+    pub(crate) fn values(&self) -> impl Iterator<Item = Value> + '_ {
+        self.frames
+            .iter()
+            .flat_map(|frame| frame.registers.iter().flatten().copied())
+    }
+
+    fn push(&mut self, frame: BacktraceFrame) {
+        if self.frames.len() < BACKTRACE_MAX_FRAMES {
+            self.frames.push(frame);
+        } else {
+            self.skipped += 1;
+        }
+    }
+}
+
+impl<ENV> GVm<ENV> {
+    /// Also snapshot each frame's registers when capturing a backtrace (off by default).
+    pub fn set_backtrace_registers(&mut self, on: bool) {
+        self.backtrace_registers = on;
+    }
+
+    pub fn backtrace_registers(&self) -> bool {
+        self.backtrace_registers
+    }
+
+    /// Backtrace of the last error raised by running code.
+    pub fn last_backtrace(&self) -> Option<Arc<Backtrace>> {
+        self.last_backtrace.clone()
+    }
+
+    fn backtrace_frame(
+        &self,
+        chunk: &Arc<Chunk>,
+        ip: *const u8,
+        stack_top: usize,
+    ) -> Option<BacktraceFrame> {
+        // The ip may be left over from other code (for instance after a do_call), skip it then.
+        let start = chunk.code.as_ptr() as usize;
+        let ip = ip as usize;
+        if ip < start || ip >= start + chunk.code.len() {
+            return None;
+        }
+        let registers = if self.backtrace_registers {
+            let end = (stack_top + chunk.input_regs + chunk.extra_regs + 1).min(self.stack_cap);
+            Some(self.stack_slice()[stack_top..end].to_vec())
+        } else {
+            None
+        };
+        Some(BacktraceFrame {
+            chunk: chunk.clone(),
+            offset: ip - start,
+            registers,
+        })
+    }
+
+    /// Capture the call stack, chunk is the chunk currently running.
+    pub fn capture_backtrace(&self, chunk: &Arc<Chunk>) -> Backtrace {
+        let mut backtrace = Backtrace::default();
+        if let Some(frame) = self.backtrace_frame(chunk, self.current_ip_ptr, self.stack_top) {
+            backtrace.push(frame);
+        }
+        for frame in CallStackIter::new(self) {
+            if let Some(frame) =
+                self.backtrace_frame(&frame.chunk, frame.current_ip, frame.stack_top)
+            {
+                backtrace.push(frame);
+            }
+        }
+        backtrace
+    }
+
+    /// Add the current call stack to err's backtrace (after any frames it already has from
+    /// nested code) and remember it as the last backtrace.
+    pub(super) fn error_backtrace(&mut self, err: &mut VMError, chunk: &Arc<Chunk>) {
+        let outer = self.capture_backtrace(chunk);
+        let backtrace = match err.backtrace.take() {
+            Some(inner) => {
+                let mut backtrace = (*inner).clone();
+                for frame in outer.frames {
+                    backtrace.push(frame);
+                }
+                backtrace.skipped += outer.skipped;
+                backtrace
+            }
+            None => outer,
+        };
+        let backtrace = Arc::new(backtrace);
+        err.backtrace = Some(backtrace.clone());
+        self.last_backtrace = Some(backtrace);
+    }
+}
@@ -0,0 +1,66 @@
+//! Dynamic bindings.  BIND saves a global's value on the binding stack before setting it and
+//! UNBIND puts it back.  Anything else that leaves a frame (a return, a continuation jumping out,
+//! an error escaping execute/do_call or a reset) restores the bindings the frame left behind so a
+//! dynamic global never keeps a bound value past its binding form.
+
+use crate::{GVm, Heap, Value};
+
+pub(super) struct Binding {
+    // Stack top of the frame that made the binding.
+    stack_top: usize,
+    slot: u32,
+    old: Value,
+}
+
+impl<ENV> GVm<ENV> {
+    /// Set global slot to val, saving the current value to restore with unbind.
+    pub(super) fn bind(&mut self, slot: u32, val: Value) {
+        let old = self.globals.get(slot);
+        self.bindings.push(Binding {
+            stack_top: self.stack_top,
+            slot,
+            old,
+        });
+        self.set_global(slot, val);
+    }
+
+    /// Restore the last count bindings.
+    pub(super) fn unbind(&mut self, count: usize) {
+        let depth = self.bindings.len().saturating_sub(count);
+        self.unbind_to(depth);
+    }
+
+    /// Restore the bindings made by frames at or above stack_top.
+    pub(super) fn unbind_frames(&mut self, stack_top: usize) {
+        while matches!(self.bindings.last(), Some(binding) if binding.stack_top >= stack_top) {
+            self.unbind(1);
+        }
+    }
+
+    /// The frame at the current stack top is returning, restore any bindings it still has.
+    #[inline]
+    pub(super) fn bindings_return(&mut self) {
+        if !self.bindings.is_empty() {
+            self.unbind_frames(self.stack_top);
+        }
+    }
+
+    pub(super) fn bindings_depth(&self) -> usize {
+        self.bindings.len()
+    }
+
+    /// Restore bindings (newest first) until depth are left.
+    pub(super) fn unbind_to(&mut self, depth: usize) {
+        while self.bindings.len() > depth {
+            if let Some(binding) = self.bindings.pop() {
+                self.set_global(binding.slot, binding.old);
+            }
+        }
+    }
+
+    pub(super) fn bindings_mark(&self, heap: &mut Heap) {
+        for binding in &self.bindings {
+            heap.mark(binding.old);
+        }
+    }
+}
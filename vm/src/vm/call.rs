@@ -1,5 +1,7 @@
 use std::sync::Arc;
 
+use super::RET_CODE;
+use crate::Callee;
 use crate::{mov_register, CallFrame, Chunk, Continuation, GVm, VMError, VMResult, Value};
 
 /// Vm functions to handle runtime calling of anything callable.
@@ -21,7 +23,7 @@ impl<ENV> GVm<ENV> {
             r.copy_from_slice(
                 &self.register_slice()[rest_reg as usize..(rest_reg as usize + rest_len)],
             );
-            self.alloc_list_ro(r)
+            self.alloc_rest_list(r)
         };
         (rest_reg.into(), v)
     }
@@ -120,7 +122,11 @@ impl<ENV> GVm<ENV> {
                 let on_error = frame.on_error;
                 let new_chunk = frame.chunk.clone();
                 self.copy_frame_defers(); // Do this BEFORE we change stack_top...
+                self.trace_return(res);
+                self.restarts_return();
+                self.bindings_return();
                 self.stack_top = stack_top;
+                self.call_profile_return();
                 self.stack_max = self.stack_top + new_chunk.input_regs + new_chunk.extra_regs;
                 self.ip_ptr = ip_ptr;
                 self.current_ip_ptr = current_ip;
@@ -129,7 +135,10 @@ impl<ENV> GVm<ENV> {
                 *self.stack_mut(res_reg) = res;
                 new_chunk
             } else {
+                // Nothing to return to (called from do_call for instance), return res from here
+                // instead of running the rest of the chunk.
                 *self.stack_mut(res_reg) = res;
+                self.ip_ptr = RET_CODE.as_ptr();
                 chunk
             }
         } else {
@@ -147,29 +156,37 @@ impl<ENV> GVm<ENV> {
         tail_call: bool,
     ) -> Result<Arc<Chunk>, (VMError, Arc<Chunk>)> {
         let mut do_cont = false;
+        self.trace_call(lambda, first_reg, num_args, tail_call);
         let result = match lambda {
             Value::Builtin(f_idx) => {
                 let last_reg = (first_reg + num_args + 1) as usize;
                 // Useful if the builtin runs bytecode that errors otherwise a waste...
                 let frame = self.make_call_frame(chunk.clone(), lambda, false);
-                let f = &self.buitins[f_idx as usize];
+                let profile_top = self.stack_top + first_reg as usize + 1;
+                self.call_profile_enter(Callee::Builtin(f_idx), None, profile_top);
+                let f = self.buitins[f_idx as usize].clone();
                 let regs = self.register_slice();
 
-                let res =
-                    (f.func)(self, &regs[(first_reg + 1) as usize..last_reg]).map_err(|e| {
-                        if self.err_frame().is_some() {
-                            let call_frame = self.alloc_callframe(frame);
-                            mov_register!(self, first_reg as usize, call_frame);
-                            self.stack_top += first_reg as usize;
-                        }
-                        (e, chunk.clone())
-                    })?;
+                let res = f.call(self, &regs[(first_reg + 1) as usize..last_reg]);
+                self.call_profile_exit(profile_top);
+                self.trace_builtin_return(lambda, first_reg, &res);
+                let res = res.map_err(|e| {
+                    // A signal resumes here, leave the stack alone.
+                    if !self.signal_raised(first_reg, tail_call) && self.err_frame().is_some() {
+                        let call_frame = self.alloc_callframe(frame);
+                        mov_register!(self, first_reg as usize, call_frame);
+                        self.stack_top += first_reg as usize;
+                    }
+                    (e, chunk.clone())
+                })?;
                 Ok(self.finish_special_call(chunk, tail_call, first_reg, res))
             }
             Value::Lambda(handle) => {
                 let stack_top = self.stack_top;
                 let l = self.heap().get_lambda(handle);
                 check_num_args(&l, num_args).map_err(|e| (e, chunk.clone()))?;
+                self.check_call_stack(&l, first_reg, tail_call)
+                    .map_err(|e| (e, chunk.clone()))?;
                 if !tail_call {
                     let frame = self.make_call_frame(chunk, lambda, true);
                     let aframe = self.alloc_callframe(frame);
@@ -183,14 +200,20 @@ impl<ENV> GVm<ENV> {
                     let (rest_reg, h) = self.setup_rest(&l, first_reg, num_args);
                     *self.stack_mut(stack_top + rest_reg) = h;
                 }
-                // XXX TODO- maybe test for stack overflow vs waiting for a panic.
                 self.clear_opts(&l, first_reg, num_args);
+                self.call_profile_enter(
+                    Callee::Chunk(Arc::as_ptr(&l) as usize),
+                    Some(&l),
+                    self.stack_top,
+                );
                 Ok(l)
             }
             Value::Closure(handle) => {
                 let stack_top = self.stack_top;
                 let (l, _) = self.heap().get_closure(handle);
                 check_num_args(&l, num_args).map_err(|e| (e, chunk.clone()))?;
+                self.check_call_stack(&l, first_reg, tail_call)
+                    .map_err(|e| (e, chunk.clone()))?;
                 let frame = if !tail_call {
                     let frame = self.make_call_frame(chunk, lambda, true);
                     self.stack_top += first_reg as usize;
@@ -225,6 +248,11 @@ impl<ENV> GVm<ENV> {
                     *self.stack_mut(stack_top + first_reg as usize) = aframe;
                 }
                 self.clear_opts(&l, first_reg, num_args);
+                self.call_profile_enter(
+                    Callee::Chunk(Arc::as_ptr(&l) as usize),
+                    Some(&l),
+                    self.stack_top,
+                );
                 Ok(l)
             }
             Value::Continuation(handle) => {
@@ -266,6 +294,30 @@ impl<ENV> GVm<ENV> {
                     .map_err(|e| (e, chunk.clone()))?;
                 Ok(self.finish_special_call(chunk, tail_call, first_reg, res))
             }
+            Value::Bytes(handle) => {
+                let res = self
+                    .call_bytes(handle, first_reg, num_args)
+                    .map_err(|e| (e, chunk.clone()))?;
+                Ok(self.finish_special_call(chunk, tail_call, first_reg, res))
+            }
+            Value::Set(handle) => {
+                let res = self
+                    .call_set(handle, first_reg, num_args)
+                    .map_err(|e| (e, chunk.clone()))?;
+                Ok(self.finish_special_call(chunk, tail_call, first_reg, res))
+            }
+            Value::PMap(handle) => {
+                let res = self
+                    .call_pmap(handle, first_reg, num_args)
+                    .map_err(|e| (e, chunk.clone()))?;
+                Ok(self.finish_special_call(chunk, tail_call, first_reg, res))
+            }
+            Value::PVec(handle) => {
+                let res = self
+                    .call_pvec(handle, first_reg, num_args)
+                    .map_err(|e| (e, chunk.clone()))?;
+                Ok(self.finish_special_call(chunk, tail_call, first_reg, res))
+            }
             Value::Pair(_) | Value::List(_, _) => {
                 let res = self
                     .call_list(lambda, first_reg, num_args)
@@ -302,6 +354,7 @@ impl<ENV> GVm<ENV> {
                     self.stack_slice_mut()[..k.stack.len()].copy_from_slice(&k.stack[..]);
                     *self.stack_mut(k.arg_reg) = arg;
                     self.stack_top = k.frame.stack_top;
+                    self.call_profile_return();
                     self.stack_max =
                         self.stack_top + k.frame.chunk.input_regs + k.frame.chunk.extra_regs;
                     self.ip_ptr = k.frame.ip;
@@ -311,6 +364,8 @@ impl<ENV> GVm<ENV> {
                     let chunk = k.frame.chunk.clone();
                     // Put the heap back, if this doesn't happen will panic on next access attempt.
                     self.heap = Some(heap);
+                    // Bindings made by the frames jumped out of.
+                    self.unbind_frames(self.stack_top + 1);
                     Ok(chunk)
                 }
                 _ => panic!("Must be a continuation!"),
@@ -320,6 +375,17 @@ impl<ENV> GVm<ENV> {
         }
     }
 
+    /// Make sure the stack has room for a call to l, growing it if needed.
+    /// Produces a stack overflow error (before any VM state is changed) if it can not grow enough.
+    fn check_call_stack(&mut self, l: &Chunk, first_reg: u16, tail_call: bool) -> VMResult<()> {
+        let stack_top = if tail_call {
+            self.stack_top
+        } else {
+            self.stack_top + first_reg as usize
+        };
+        self.ensure_stack(stack_top + l.input_regs + l.extra_regs)
+    }
+
     /// Clear out the unused optional regs.
     /// Will clear working set to avoid writing to globals or closures by accident.
     fn clear_opts(&mut self, l: &Chunk, first_reg: u16, num_args: u16) {
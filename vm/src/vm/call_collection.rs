@@ -71,6 +71,88 @@ impl<ENV> GVm<ENV> {
         }
     }
 
+    pub(crate) fn call_set(
+        &mut self,
+        handle: Handle,
+        first_reg: u16,
+        num_args: u16,
+    ) -> VMResult<Value> {
+        let default = match num_args {
+            1 => Value::Nil,
+            2 => self.register(first_reg as usize + 2),
+            _ => return Err(VMError::new_vm("Set wrong number of arguments.")),
+        };
+        let item = self.register(first_reg as usize + 1);
+        if self.heap().get_set(handle).contains(&item) {
+            Ok(item)
+        } else {
+            Ok(default)
+        }
+    }
+
+    pub(crate) fn call_pmap(
+        &mut self,
+        handle: Handle,
+        first_reg: u16,
+        num_args: u16,
+    ) -> VMResult<Value> {
+        let map = self.heap().get_pmap(handle);
+        match num_args {
+            1 => Ok(*map
+                .get(&self.register(first_reg as usize + 1))
+                .unwrap_or(&Value::Nil)),
+            2 => Ok(match map.get(&self.register(first_reg as usize + 1)) {
+                Some(val) => *val,
+                None => self.register(first_reg as usize + 2),
+            }),
+            _ => Err(VMError::new_vm("Map wrong number of arguments.")),
+        }
+    }
+
+    pub(crate) fn call_pvec(
+        &mut self,
+        handle: Handle,
+        first_reg: u16,
+        num_args: u16,
+    ) -> VMResult<Value> {
+        let default = match num_args {
+            1 => Value::Nil,
+            2 => self.register(first_reg as usize + 2),
+            _ => return Err(VMError::new_vm("Vector wrong number of arguments.")),
+        };
+        let v = self.heap().get_pvec(handle);
+        let idx = self.register(first_reg as usize + 1).get_int(self)?;
+        let idx = if idx >= 0 { idx } else { v.len() as i64 + idx };
+        if idx >= 0 {
+            Ok(*v.get(idx as usize).unwrap_or(&default))
+        } else {
+            Ok(default)
+        }
+    }
+
+    pub(crate) fn call_bytes(
+        &mut self,
+        handle: Handle,
+        first_reg: u16,
+        num_args: u16,
+    ) -> VMResult<Value> {
+        let default = match num_args {
+            1 => Value::Nil,
+            2 => self.register(first_reg as usize + 2),
+            _ => return Err(VMError::new_vm("Bytes wrong number of arguments.")),
+        };
+        let b = self.heap().get_bytes(handle);
+        let idx = self.register(first_reg as usize + 1).get_int(self)?;
+        let idx = if idx >= 0 { idx } else { b.len() as i64 + idx };
+        if idx >= 0 {
+            Ok(b.get(idx as usize)
+                .map(|b| (*b as i64).into())
+                .unwrap_or(default))
+        } else {
+            Ok(default)
+        }
+    }
+
     pub(crate) fn call_list(
         &mut self,
         head: Value,
@@ -0,0 +1,170 @@
+//! Function call profiler.  It is always built and switched on at runtime, while off it costs a
+//! flag check per call (the op code profiler in profile.rs needs the "profile" feature).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::{Chunk, GVm};
+
+/// Something the call profiler keeps stats for.  Lambdas and closures are identified by their
+/// chunk.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Callee {
+    Chunk(usize),
+    Builtin(u32),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CallStats {
+    pub calls: u64,
+    /// Time from call to return, including anything it called.  Recursive calls are not counted
+    /// twice.
+    pub inclusive: Duration,
+    /// Time spent in the function itself (inclusive minus time spent in calls it made).
+    pub exclusive: Duration,
+    /// The chunk for lambdas and closures, None for builtins.
+    pub chunk: Option<Arc<Chunk>>,
+    // Number of calls currently on the stack.
+    active: u32,
+}
+
+#[derive(Debug)]
+struct CallEntry {
+    callee: Callee,
+    // Stack top of the call, entries above the stack top are returned from.
+    stack_top: usize,
+    start: Instant,
+    // Time spent in calls made from this one.
+    child: Duration,
+}
+
+/// Function call counts and times collected by the call profiler.
+#[derive(Debug, Default)]
+pub struct CallProfile {
+    pub stats: HashMap<Callee, CallStats>,
+    stack: Vec<CallEntry>,
+}
+
+impl CallProfile {
+    fn enter(&mut self, callee: Callee, chunk: Option<&Arc<Chunk>>, stack_top: usize) {
+        let stats = self.stats.entry(callee).or_default();
+        stats.calls += 1;
+        stats.active += 1;
+        if stats.chunk.is_none() {
+            stats.chunk = chunk.cloned();
+        }
+        self.stack.push(CallEntry {
+            callee,
+            stack_top,
+            start: Instant::now(),
+            child: Duration::ZERO,
+        });
+    }
+
+    fn exit(&mut self) {
+        if let Some(entry) = self.stack.pop() {
+            let elapsed = entry.start.elapsed();
+            if let Some(stats) = self.stats.get_mut(&entry.callee) {
+                stats.active -= 1;
+                if stats.active == 0 {
+                    stats.inclusive += elapsed;
+                }
+                stats.exclusive += elapsed.saturating_sub(entry.child);
+            }
+            if let Some(parent) = self.stack.last_mut() {
+                parent.child += elapsed;
+            }
+        }
+    }
+
+    /// Finish every call at or above stack_top.
+    fn unwind(&mut self, stack_top: usize) {
+        while matches!(self.stack.last(), Some(entry) if entry.stack_top >= stack_top) {
+            self.exit();
+        }
+    }
+
+    /// Finish calls until only depth are left.
+    fn truncate(&mut self, depth: usize) {
+        while self.stack.len() > depth {
+            self.exit();
+        }
+    }
+}
+
+impl<ENV> GVm<ENV> {
+    /// Start the call profiler (discarding any previous call profile).
+    pub fn start_call_profile(&mut self) {
+        self.call_profile = Some(Box::new(CallProfile::default()));
+        self.call_profiling = true;
+    }
+
+    /// Stop the call profiler, calls in progress are counted up to now.  The profile is kept
+    /// until the next start_call_profile or take_call_profile.
+    pub fn stop_call_profile(&mut self) {
+        if let Some(profile) = &mut self.call_profile {
+            profile.truncate(0);
+        }
+        self.call_profiling = false;
+    }
+
+    pub fn is_call_profiling(&self) -> bool {
+        self.call_profiling
+    }
+
+    pub fn call_profile(&self) -> Option<&CallProfile> {
+        self.call_profile.as_deref()
+    }
+
+    /// Stop the call profiler and return the profile.
+    pub fn take_call_profile(&mut self) -> Option<CallProfile> {
+        self.stop_call_profile();
+        self.call_profile.take().map(|p| *p)
+    }
+
+    /// A call to callee is starting with its registers at stack_top.
+    #[inline]
+    pub(super) fn call_profile_enter(
+        &mut self,
+        callee: Callee,
+        chunk: Option<&Arc<Chunk>>,
+        stack_top: usize,
+    ) {
+        if self.call_profiling {
+            if let Some(profile) = &mut self.call_profile {
+                // Anything still at or above this stack top was replaced (tail call) or exited
+                // without a return (continuation or error).
+                profile.unwind(stack_top);
+                profile.enter(callee, chunk, stack_top);
+            }
+        }
+    }
+
+    /// Finish any calls at or above stack_top (used for builtins).
+    #[inline]
+    pub(super) fn call_profile_exit(&mut self, stack_top: usize) {
+        if self.call_profiling {
+            if let Some(profile) = &mut self.call_profile {
+                profile.unwind(stack_top);
+            }
+        }
+    }
+
+    /// Returned to the frame at the current stack top, finish any calls above it.
+    #[inline]
+    pub(super) fn call_profile_return(&mut self) {
+        self.call_profile_exit(self.stack_top + 1);
+    }
+
+    /// Number of calls in progress, used by execute/do_call to clean up when they return.
+    pub(super) fn call_profile_depth(&self) -> usize {
+        self.call_profile.as_ref().map_or(0, |p| p.stack.len())
+    }
+
+    pub(super) fn call_profile_truncate(&mut self, depth: usize) {
+        if let Some(profile) = &mut self.call_profile {
+            profile.truncate(depth);
+        }
+    }
+}
@@ -10,7 +10,7 @@ impl<ENV> GVm<ENV> {
             for i in (start..=end).rev() {
                 let car = self.register_unref(i as usize);
                 let cdr = last_cdr;
-                last_cdr = self.alloc_pair(car, cdr);
+                last_cdr = self.alloc_pair(car, cdr)?;
             }
             set_register!(self, dest as usize, last_cdr);
         }
@@ -33,7 +33,7 @@ impl<ENV> GVm<ENV> {
                         let (car, cdr) = lst.get_pair(self).expect("Pair/List not a Pair or List?");
                         loop_cdr = cdr;
                         let cdr = last_cdr;
-                        last_cdr = self.alloc_pair(car, Value::Nil);
+                        last_cdr = self.alloc_pair(car, Value::Nil)?;
                         match cdr {
                             Value::Nil => head = last_cdr,
                             Value::Pair(h) => {
@@ -56,7 +56,7 @@ impl<ENV> GVm<ENV> {
                                         .expect("Pair/List not a Pair or List?");
                                     loop_cdr = ncdr;
                                     let cdr = last_cdr;
-                                    last_cdr = self.alloc_pair(car, Value::Nil);
+                                    last_cdr = self.alloc_pair(car, Value::Nil)?;
                                     match cdr {
                                         Value::Nil => head = last_cdr,
                                         Value::Pair(h) => {
@@ -1,9 +1,8 @@
 use crate::opcodes::*;
 use crate::{
-    from_i56, CallFrame, Chunk, Continuation, Error, GVm, VMError, VMErrorObj, VMResult, Value,
-    STACK_CAP,
+    from_i56, CallFrame, Chunk, Continuation, Error, GVm, VMError, VMErrorObj, VMMap, VMResult,
+    VMSet, Value,
 };
-use std::collections::HashMap;
 use std::marker::PhantomData;
 use std::sync::Arc;
 use unicode_segmentation::UnicodeSegmentation;
@@ -33,6 +32,17 @@ impl<ENV> GVm<ENV> {
                         }
                     }
                 }
+                Value::PMap(handle) => {
+                    let map = self.get_pmap(handle);
+                    for i in 0..len {
+                        let key = self.register(dest + i);
+                        if let Some(item) = map.get(&key) {
+                            *self.register_mut(dest + i) = *item;
+                        } else {
+                            *self.register_mut(dest + i) = Value::Undefined;
+                        }
+                    }
+                }
                 Value::Vector(handle) => {
                     let vector = self.get_vector(handle);
                     for i in 0..len {
@@ -152,7 +162,7 @@ impl<ENV> GVm<ENV> {
                 b[0..len].copy_from_slice(ch.as_bytes());
                 Value::CharCluster(len as u8, b)
             } else {
-                let h = self.alloc_string(ch.to_string());
+                let h = self.alloc_string(ch.to_string())?;
                 Value::CharClusterLong(h.get_handle().expect("just allocated, missing handle!"))
             }
         } else {
@@ -226,6 +236,43 @@ impl<ENV> GVm<ENV> {
                     self.make_err("vm-missing", key)
                 }
             }
+            Value::PVec(h) => {
+                let v = self.get_pvec(h);
+                let idx = self.register_int(i as usize)?;
+                let idx = if idx >= 0 { idx } else { v.len() as i64 + idx };
+                if idx < 0 {
+                    let iv = idx.into();
+                    self.make_err("vm-missing", iv)
+                } else if let Some(val) = v.get(idx as usize) {
+                    *val
+                } else {
+                    let iv = idx.into();
+                    self.make_err("vm-missing", iv)
+                }
+            }
+            Value::PMap(h) => {
+                let map = self.get_pmap(h);
+                let key = self.register(i as usize);
+                if let Some(val) = map.get(&key) {
+                    *val
+                } else {
+                    self.make_err("vm-missing", key)
+                }
+            }
+            Value::Bytes(h) => {
+                let b = self.get_bytes(h);
+                let idx = self.register_int(i as usize)?;
+                let idx = if idx >= 0 { idx } else { b.len() as i64 + idx };
+                if idx < 0 {
+                    let iv = idx.into();
+                    self.make_err("vm-missing", iv)
+                } else if let Some(byte) = b.get(idx as usize) {
+                    (*byte as i64).into()
+                } else {
+                    let iv = idx.into();
+                    self.make_err("vm-missing", iv)
+                }
+            }
             Value::StringConst(_) => self.get_string_idx(data, i)?,
             Value::String(_) => self.get_string_idx(data, i)?,
             Value::Error(_) => data, // Pass the error on (for stacked GETs).
@@ -314,9 +361,32 @@ impl<ENV> GVm<ENV> {
             }
             Value::Map(h) => {
                 let key = self.register(i as usize);
-                let map = self.get_map_mut(h)?;
-                let slot = map.entry(key);
-                *slot.or_insert(Value::Undefined) = src;
+                self.map_insert(h, key, src)?;
+            }
+            Value::Bytes(h) => {
+                let idx = self.register_int(i as usize)?;
+                let byte = src
+                    .get_int(self)
+                    .ok()
+                    .and_then(|b| u8::try_from(b).ok())
+                    .ok_or_else(|| {
+                        VMError::new_vm(format!(
+                            "bytes can only hold ints from 0 to 255, got {}.",
+                            src.display_value(self)
+                        ))
+                    })?;
+                let b = self.get_bytes_mut(h)?;
+                let idx = if idx >= 0 { idx } else { b.len() as i64 + idx };
+                if idx < 0 {
+                    return Err(VMError::new_vm(format!(
+                        "index out of bounds, {idx}/{}.",
+                        b.len()
+                    )));
+                }
+                if idx as usize >= b.len() {
+                    b.resize(idx as usize + 1, 0);
+                }
+                b[idx as usize] = byte;
             }
             _ => {
                 return Err(VMError::new_vm(format!(
@@ -341,7 +411,7 @@ impl<ENV> GVm<ENV> {
             let regs = unsafe {
                 std::slice::from_raw_parts_mut(
                     self.stack.add(self.stack_top),
-                    STACK_CAP - self.stack_top,
+                    self.stack_cap - self.stack_top,
                 )
             };
             for reg in regs
@@ -359,6 +429,8 @@ impl<ENV> GVm<ENV> {
             }
             self.current_ip_ptr = self.ip_ptr;
             opcode = decode_u8!(self.ip_ptr);
+            #[cfg(feature = "profile")]
+            self.profile_op(opcode, &chunk);
             match opcode {
                 NOP => {}
                 HALT => {
@@ -371,6 +443,9 @@ impl<ENV> GVm<ENV> {
                         chunk = self.make_call(defer, chunk, first_reg, 0, false)?;
                         self.make_registers();
                     } else {
+                        self.trace_return(self.register(0));
+                        self.restarts_return();
+                        self.bindings_return();
                         // Clear used regs to make sure no closures or globals get overwritten later.
                         for r in self.stack_top + 1..=self.stack_max {
                             *self.stack_mut(r) = Value::Undefined;
@@ -384,6 +459,7 @@ impl<ENV> GVm<ENV> {
                             chunk = frame.chunk.clone();
                             self.copy_frame_defers(); // Do this BEFORE we change stack_top...
                             self.stack_top = stack_top;
+                            self.call_profile_return();
                             self.make_registers();
                             self.stack_max = self.stack_top + chunk.input_regs + chunk.extra_regs;
                             self.ip_ptr = ip_ptr;
@@ -404,6 +480,9 @@ impl<ENV> GVm<ENV> {
                     } else {
                         let src = decode1!(self.ip_ptr, wide);
                         let val = self.register(src as usize);
+                        self.trace_return(val);
+                        self.restarts_return();
+                        self.bindings_return();
                         let old_top = self.stack_top;
                         // Clear used regs to make sure no closures or globals get overwritten later.
                         for r in self.stack_top + 1..=self.stack_max {
@@ -418,6 +497,7 @@ impl<ENV> GVm<ENV> {
                             chunk = frame.chunk.clone();
                             self.copy_frame_defers(); // Do this BEFORE we change stack_top...
                             self.stack_top = stack_top;
+                            self.call_profile_return();
                             self.make_registers();
                             self.stack_max = self.stack_top + chunk.input_regs + chunk.extra_regs;
                             self.ip_ptr = ip_ptr;
@@ -443,16 +523,16 @@ impl<ENV> GVm<ENV> {
                     let (dest, src) = decode2!(self.ip_ptr, wide);
                     let val = self.register_unref(src as usize);
                     let dest = self
-                        .register_int(dest as usize)
+                        .register_index(dest as usize)
                         .map_err(|e| (e, chunk.clone()))?;
-                    mov_register!(self, dest as usize, val);
+                    mov_register!(self, dest, val);
                 }
                 MOVII => {
                     let (dest, src) = decode2!(self.ip_ptr, wide);
                     let src = self
-                        .register_int(src as usize)
+                        .register_index(src as usize)
                         .map_err(|e| (e, chunk.clone()))?;
-                    let val = self.register_unref(src as usize);
+                    let val = self.register_unref(src);
                     mov_register!(self, dest as usize, val);
                 }
                 GET => self.get(wide).map_err(|e| (e, chunk.clone()))?,
@@ -504,7 +584,8 @@ impl<ENV> GVm<ENV> {
                                 if rest.is_empty() {
                                     *self.register_mut(dest + (len - 1)) = Value::Nil;
                                 } else {
-                                    *self.register_mut(dest + (len - 1)) = self.alloc_list_ro(rest);
+                                    *self.register_mut(dest + (len - 1)) =
+                                        self.alloc_list_ro(rest).map_err(|e| (e, chunk.clone()))?;
                                 }
                             }
                             _ => return Err((VMError::new_vm("not a sequence"), chunk)),
@@ -566,6 +647,20 @@ impl<ENV> GVm<ENV> {
                     };
                     mov_register!(self, dest as usize, self.globals.get(idx));
                 }
+                BIND => {
+                    let src = decode1!(self.ip_ptr, wide);
+                    let idx = if wide {
+                        decode_u32!(self.ip_ptr)
+                    } else {
+                        decode_u16!(self.ip_ptr) as u32
+                    };
+                    let val = self.register(src as usize);
+                    self.bind(idx, val);
+                }
+                UNBIND => {
+                    let count = decode1!(self.ip_ptr, wide);
+                    self.unbind(count as usize);
+                }
                 CLRREG => {
                     let dest = decode1!(self.ip_ptr, wide);
                     mov_register!(self, dest as usize, Value::Undefined);
@@ -608,7 +703,7 @@ impl<ENV> GVm<ENV> {
                                 if let Value::Value(b) = r {
                                     caps.push(b);
                                 } else {
-                                    let val = self.new_upval(r);
+                                    let val = self.new_upval(r).map_err(|e| (e, chunk.clone()))?;
                                     mov_register!(self, *c as usize, val);
                                     caps.push(val.get_handle().unwrap());
                                 }
@@ -621,12 +716,15 @@ impl<ENV> GVm<ENV> {
                             chunk,
                         ));
                     };
-                    let new_closure = self.alloc_closure(lambda, caps);
+                    let new_closure = self
+                        .alloc_closure(lambda, caps)
+                        .map_err(|e| (e, chunk.clone()))?;
                     set_register!(self, dest as usize, new_closure);
                 }
                 CALL => {
                     let (lambda, num_args, first_reg) = decode3!(self.ip_ptr, wide);
                     let lambda = self.register(lambda as usize);
+                    self.check_limits().map_err(|e| (e, chunk.clone()))?;
                     chunk = self.make_call(lambda, chunk, first_reg, num_args, false)?;
                     self.make_registers();
                 }
@@ -638,12 +736,14 @@ impl<ENV> GVm<ENV> {
                     };
                     let (num_args, first_reg) = decode2!(self.ip_ptr, wide);
                     let lambda = self.get_global(idx);
+                    self.check_limits().map_err(|e| (e, chunk.clone()))?;
                     chunk = self.make_call(lambda, chunk, first_reg, num_args, false)?;
                     self.make_registers();
                 }
                 TCALL => {
                     let (lambda, num_args) = decode2!(self.ip_ptr, wide);
                     let lambda = self.register(lambda as usize);
+                    self.check_limits().map_err(|e| (e, chunk.clone()))?;
                     chunk = self.make_call(lambda, chunk, 0, num_args, true)?;
                     self.make_registers(); // In case of a builtin call
                 }
@@ -655,12 +755,14 @@ impl<ENV> GVm<ENV> {
                     };
                     let num_args = decode1!(self.ip_ptr, wide);
                     let lambda = self.get_global(idx);
+                    self.check_limits().map_err(|e| (e, chunk.clone()))?;
                     chunk = self.make_call(lambda, chunk, 0, num_args, true)?;
                     self.make_registers(); // In case of a builtin call
                 }
                 CALLM => {
                     let (num_args, first_reg) = decode2!(self.ip_ptr, wide);
                     if let Some(this_fn) = self.this_fn {
+                        self.check_limits().map_err(|e| (e, chunk.clone()))?;
                         chunk = self.make_call(this_fn, chunk, first_reg, num_args, false)?;
                         self.make_registers();
                     } else {
@@ -677,6 +779,7 @@ impl<ENV> GVm<ENV> {
                 TCALLM => {
                     let num_args = decode1!(self.ip_ptr, wide);
                     if let Some(this_fn) = self.this_fn {
+                        self.check_limits().map_err(|e| (e, chunk.clone()))?;
                         chunk = self.make_call(this_fn, chunk, 0, num_args, true)?;
                         self.make_registers(); // In case of a builtin call
                     } else {
@@ -692,18 +795,18 @@ impl<ENV> GVm<ENV> {
                 }
                 JMP => {
                     let jmp = decode1!(self.ip_ptr, wide);
-                    self.ip_ptr = get_code_at!(chunk, chunk.jump_table[jmp as usize] as isize);
+                    jump!(self, chunk, jmp);
                 }
                 JMPT => {
                     let (test, jmp) = decode2!(self.ip_ptr, wide);
                     if self.register(test as usize).is_truethy() {
-                        self.ip_ptr = get_code_at!(chunk, chunk.jump_table[jmp as usize] as isize);
+                        jump!(self, chunk, jmp);
                     }
                 }
                 JMPF => {
                     let (test, jmp) = decode2!(self.ip_ptr, wide);
                     if self.register(test as usize).is_falsey() {
-                        self.ip_ptr = get_code_at!(chunk, chunk.jump_table[jmp as usize] as isize);
+                        jump!(self, chunk, jmp);
                     }
                 }
                 JMPEQ => {
@@ -717,7 +820,7 @@ impl<ENV> GVm<ENV> {
                         .get_int(self)
                         .map_err(|e| (e, chunk.clone()))?;
                     if op1 == op2 {
-                        self.ip_ptr = get_code_at!(chunk, chunk.jump_table[jmp as usize] as isize);
+                        jump!(self, chunk, jmp);
                     }
                 }
                 JMPLT => {
@@ -729,7 +832,7 @@ impl<ENV> GVm<ENV> {
                         .register_int(op2 as usize)
                         .map_err(|e| (e, chunk.clone()))?;
                     if op1 < op2 {
-                        self.ip_ptr = get_code_at!(chunk, chunk.jump_table[jmp as usize] as isize);
+                        jump!(self, chunk, jmp);
                     }
                 }
                 JMPGT => {
@@ -741,19 +844,19 @@ impl<ENV> GVm<ENV> {
                         .register_int(op2 as usize)
                         .map_err(|e| (e, chunk.clone()))?;
                     if op1 > op2 {
-                        self.ip_ptr = get_code_at!(chunk, chunk.jump_table[jmp as usize] as isize);
+                        jump!(self, chunk, jmp);
                     }
                 }
                 JMPU => {
                     let (test, jmp) = decode2!(self.ip_ptr, wide);
                     if self.register(test as usize).is_undef() {
-                        self.ip_ptr = get_code_at!(chunk, chunk.jump_table[jmp as usize] as isize);
+                        jump!(self, chunk, jmp);
                     }
                 }
                 JMPNU => {
                     let (test, jmp) = decode2!(self.ip_ptr, wide);
                     if !self.register(test as usize).is_undef() {
-                        self.ip_ptr = get_code_at!(chunk, chunk.jump_table[jmp as usize] as isize);
+                        jump!(self, chunk, jmp);
                     }
                 }
                 JMPRU => {
@@ -763,8 +866,7 @@ impl<ENV> GVm<ENV> {
                         let len = len as usize;
                         for i in 0..len {
                             if self.register(test + i).is_undef() {
-                                self.ip_ptr =
-                                    get_code_at!(chunk, chunk.jump_table[jmp as usize] as isize);
+                                jump!(self, chunk, jmp);
                                 break;
                             }
                         }
@@ -783,8 +885,7 @@ impl<ENV> GVm<ENV> {
                             }
                         }
                         if jump {
-                            self.ip_ptr =
-                                get_code_at!(chunk, chunk.jump_table[jmp as usize] as isize);
+                            jump!(self, chunk, jmp);
                         }
                     }
                 }
@@ -845,6 +946,7 @@ impl<ENV> GVm<ENV> {
                             VMError {
                                 key: key_str,
                                 obj: VMErrorObj::Message(self.get_interned(i).to_string()),
+                                backtrace: None,
                             },
                             chunk,
                         ));
@@ -853,6 +955,7 @@ impl<ENV> GVm<ENV> {
                             VMError {
                                 key: key_str,
                                 obj: VMErrorObj::Object(val),
+                                backtrace: None,
                             },
                             chunk,
                         ));
@@ -870,7 +973,12 @@ impl<ENV> GVm<ENV> {
                             chunk,
                         ));
                     };
-                    let err = Error { keyword, data };
+                    let backtrace = Some(Arc::new(self.capture_backtrace(&chunk)));
+                    let err = Error {
+                        keyword,
+                        data,
+                        backtrace,
+                    };
                     let err = self.alloc_error(err);
                     set_register!(self, dest as usize, err);
                 }
@@ -929,9 +1037,30 @@ impl<ENV> GVm<ENV> {
                         self.on_error = Some(on_error);
                     }
                 }
-                ADD => binary_math!(self, chunk, self.ip_ptr, |a, b| a + b, wide),
-                SUB => binary_math!(self, chunk, self.ip_ptr, |a, b| a - b, wide),
-                MUL => binary_math!(self, chunk, self.ip_ptr, |a, b| a * b, wide),
+                ADD => binary_math!(
+                    self,
+                    chunk,
+                    self.ip_ptr,
+                    |a, b| a + b,
+                    i64::checked_add,
+                    wide
+                ),
+                SUB => binary_math!(
+                    self,
+                    chunk,
+                    self.ip_ptr,
+                    |a, b| a - b,
+                    i64::checked_sub,
+                    wide
+                ),
+                MUL => binary_math!(
+                    self,
+                    chunk,
+                    self.ip_ptr,
+                    |a, b| a * b,
+                    i64::checked_mul,
+                    wide
+                ),
                 DIV => div_math!(self, chunk, self.ip_ptr, wide),
                 NUMEQ => compare_int!(
                     self,
@@ -959,13 +1088,19 @@ impl<ENV> GVm<ENV> {
                 NUMGTE => compare!(self, chunk, self.ip_ptr, |a, b| a >= b, wide, true),
                 INC => {
                     let (dest, i) = decode2!(self.ip_ptr, wide);
-                    match self.register(dest as usize) {
-                        Value::Byte(v) => {
-                            *self.register_mut(dest as usize) = Value::Byte(v + i as u8)
-                        }
-                        Value::Int(v) => {
-                            let v = from_i56(&v);
-                            *self.register_mut(dest as usize) = (v + i as i64).into()
+                    let val = match self.register(dest as usize) {
+                        Value::Byte(v) => match v.checked_add(i as u8) {
+                            Some(v) if i <= u8::MAX as u16 => Value::Byte(v),
+                            _ => self
+                                .alloc_int(v as i64 + i as i64)
+                                .map_err(|e| (e, chunk.clone()))?,
+                        },
+                        Value::Int(v) => self
+                            .alloc_int(from_i56(&v) + i as i64)
+                            .map_err(|e| (e, chunk.clone()))?,
+                        Value::BigInt(h) => {
+                            let v = self.get_bigint(h) + i;
+                            self.alloc_bigint(v).map_err(|e| (e, chunk.clone()))?
                         }
                         _ => {
                             return Err((
@@ -976,17 +1111,24 @@ impl<ENV> GVm<ENV> {
                                 chunk,
                             ))
                         }
-                    }
+                    };
+                    *self.register_mut(dest as usize) = val;
                 }
                 DEC => {
                     let (dest, i) = decode2!(self.ip_ptr, wide);
-                    match self.register(dest as usize) {
-                        Value::Byte(v) => {
-                            *self.register_mut(dest as usize) = Value::Byte(v - i as u8)
-                        }
-                        Value::Int(v) => {
-                            let v = from_i56(&v);
-                            *self.register_mut(dest as usize) = (v - i as i64).into()
+                    let val = match self.register(dest as usize) {
+                        Value::Byte(v) => match v.checked_sub(i as u8) {
+                            Some(v) if i <= u8::MAX as u16 => Value::Byte(v),
+                            _ => self
+                                .alloc_int(v as i64 - i as i64)
+                                .map_err(|e| (e, chunk.clone()))?,
+                        },
+                        Value::Int(v) => self
+                            .alloc_int(from_i56(&v) - i as i64)
+                            .map_err(|e| (e, chunk.clone()))?,
+                        Value::BigInt(h) => {
+                            let v = self.get_bigint(h) - i;
+                            self.alloc_bigint(v).map_err(|e| (e, chunk.clone()))?
                         }
                         _ => {
                             return Err((
@@ -997,13 +1139,14 @@ impl<ENV> GVm<ENV> {
                                 chunk,
                             ))
                         }
-                    }
+                    };
+                    *self.register_mut(dest as usize) = val;
                 }
                 CONS => {
                     let (dest, op2, op3) = decode3!(self.ip_ptr, wide);
                     let car = self.register(op2 as usize);
                     let cdr = self.register(op3 as usize);
-                    let pair = self.alloc_pair(car, cdr);
+                    let pair = self.alloc_pair(car, cdr).map_err(|e| (e, chunk.clone()))?;
                     set_register!(self, dest as usize, pair);
                 }
                 CAR => {
@@ -1055,21 +1198,23 @@ impl<ENV> GVm<ENV> {
                 VEC => {
                     let (dest, start, end) = decode3!(self.ip_ptr, wide);
                     if end == start {
-                        let vh = self.alloc_vector(Vec::new());
+                        let vh = self
+                            .alloc_vector(Vec::new())
+                            .map_err(|e| (e, chunk.clone()))?;
                         set_register!(self, dest as usize, vh);
                     } else {
                         let mut v = Vec::new();
                         for i in start..end {
                             v.push(self.register(i as usize));
                         }
-                        let vh = self.alloc_vector(v);
+                        let vh = self.alloc_vector(v).map_err(|e| (e, chunk.clone()))?;
                         set_register!(self, dest as usize, vh);
                     }
                 }
                 MAPMK => {
                     let (dest, start, end) = decode3!(self.ip_ptr, wide);
                     let map = if end == start {
-                        HashMap::new()
+                        VMMap::new()
                     } else if (end - start) % 2 != 0 {
                         return Err((
                             VMError::new_vm(
@@ -1079,22 +1224,48 @@ impl<ENV> GVm<ENV> {
                             chunk.clone(),
                         ));
                     } else {
-                        let mut map = HashMap::new();
-                        for i in (start..end).step_by(2) {
-                            map.insert(self.register(i as usize), self.register(i as usize + 1));
-                        }
-                        map
+                        (start..end)
+                            .step_by(2)
+                            .map(|i| (self.register(i as usize), self.register(i as usize + 1)))
+                            .collect()
                     };
-                    let mh = self.alloc_map(map);
+                    let mh = self.alloc_map(map).map_err(|e| (e, chunk.clone()))?;
                     set_register!(self, dest as usize, mh);
                 }
+                SETMK => {
+                    let (dest, start, end) = decode3!(self.ip_ptr, wide);
+                    let set: VMSet = (start..end).map(|i| self.register(i as usize)).collect();
+                    let sh = self.alloc_set(set).map_err(|e| (e, chunk.clone()))?;
+                    set_register!(self, dest as usize, sh);
+                }
+                BYTESMK => {
+                    let (dest, start, end) = decode3!(self.ip_ptr, wide);
+                    let mut bytes = Vec::with_capacity((end - start) as usize);
+                    for i in start..end {
+                        let val = self.register(i as usize);
+                        match val.get_int(self).ok().and_then(|b| u8::try_from(b).ok()) {
+                            Some(b) => bytes.push(b),
+                            None => return Err((
+                                VMError::new_vm(format!(
+                                    "byte-vector: bytes can only hold ints from 0 to 255, got {}",
+                                    val.display_value(self)
+                                )),
+                                chunk.clone(),
+                            )),
+                        }
+                    }
+                    let bh = self.alloc_bytes(bytes).map_err(|e| (e, chunk.clone()))?;
+                    set_register!(self, dest as usize, bh);
+                }
                 VECMK => {
                     let (dest, op) = decode2!(self.ip_ptr, wide);
                     let len = self
                         .register(op as usize)
                         .get_int(self)
                         .map_err(|e| (e, chunk.clone()))?;
-                    let val = self.alloc_vector(Vec::with_capacity(len as usize));
+                    let val = self
+                        .alloc_vector(Vec::with_capacity(len as usize))
+                        .map_err(|e| (e, chunk.clone()))?;
                     set_register!(self, dest as usize, val);
                 }
                 VECELS => {
@@ -1150,7 +1321,7 @@ impl<ENV> GVm<ENV> {
                     for _ in 0..len {
                         v.push(dfn);
                     }
-                    let val = self.alloc_vector(v);
+                    let val = self.alloc_vector(v).map_err(|e| (e, chunk.clone()))?;
                     set_register!(self, dest as usize, val);
                 }
                 LEN => {
@@ -1171,6 +1342,7 @@ impl<ENV> GVm<ENV> {
                             len
                         }
                         Value::Vector(h) => self.get_vector(h).len() as i64,
+                        Value::Bytes(h) => self.get_bytes(h).len() as i64,
                         Value::List(h, i) => self.get_vector(h).len() as i64 - i as i64,
                         Value::Pair(h) => {
                             let mut len: i64 = 1;
@@ -1183,6 +1355,9 @@ impl<ENV> GVm<ENV> {
                             len
                         }
                         Value::Map(h) => self.get_map(h).len() as i64,
+                        Value::Set(h) => self.get_set(h).len() as i64,
+                        Value::PVec(h) => self.get_pvec(h).len() as i64,
+                        Value::PMap(h) => self.get_pmap(h).len() as i64,
                         Value::Nil | Value::False => 0,
                         _ => 1, /*Err(VMError::new_vm(format!(
                                     "len: net valid for value of type {}",
@@ -1206,6 +1381,14 @@ impl<ENV> GVm<ENV> {
                         Value::Map(h) => {
                             self.get_map_mut(h).map_err(|e| (e, chunk.clone()))?.clear();
                         }
+                        Value::Set(h) => {
+                            self.get_set_mut(h).map_err(|e| (e, chunk.clone()))?.clear();
+                        }
+                        Value::Bytes(h) => {
+                            self.get_bytes_mut(h)
+                                .map_err(|e| (e, chunk.clone()))?
+                                .clear();
+                        }
                         Value::String(h) => {
                             self.get_string_mut(h)
                                 .map_err(|e| (e, chunk.clone()))?
@@ -0,0 +1,414 @@
+//! VM images, a snapshot of the VM state (interner, globals and heap) that can be loaded into a
+//! fresh VM to pick up where the saved one left off (for instance a VM with a startup script
+//! already evaluated or a saved REPL session).
+//!
+//! Builtins are native code and are not saved, an image saves Builtin values by index so it must
+//! be loaded into a VM that registered the same builtins in the same order.  Likewise anything
+//! the VM's environment knows about (symbols it interned, globals it reserved) has to match what
+//! was in the saving VM, an image should be loaded into a VM set up the same way as the one that
+//! saved it.
+
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use crate::chunk::bytecode::{
+    read_raw_value, read_string, read_u16, read_u32, read_u8, write_len, write_raw_value,
+    write_str, write_u16, write_u32, write_u8,
+};
+use crate::{GVm, Globals, Heap, Interned, Interner, RefStep, VMError, VMResult, Value};
+
+/// Magic bytes at the start of a VM image.
+pub const IMAGE_MAGIC: &[u8; 4] = b"SLIM";
+/// Current version of the image format, bump on any incompatible change.
+pub const IMAGE_VERSION: u16 = 3;
+
+// Flags for a saved interner slot, a slot of 0 is an unused (reclaimed) id.
+const SLOT_USED: u8 = 0x01;
+const SLOT_INTERNED: u8 = 0x02;
+const SLOT_PERMANENT: u8 = 0x04;
+
+/// Where a path to an object that can not be saved starts.
+#[derive(Copy, Clone)]
+enum RefRoot {
+    Global(u32),
+    GlobalProperty(u32, Interned),
+}
+
+impl<ENV> GVm<ENV> {
+    /// Write an image of this VM's interner, globals and heap to out.  Should not be called while
+    /// the VM is executing (from a builtin for instance), the stack is not saved.
+    /// Runs a full collection first so garbage is not saved.  Host objects can not be saved, if
+    /// any are still live this returns an error naming each one and a path to it.
+    pub fn write_image<W: Write>(&mut self, out: &mut W) -> VMResult<()> {
+        self.write_image_named(out, |_| None)
+    }
+
+    /// Same as write_image but uses global_name to name globals (by slot) in the paths to host
+    /// objects, a global without a name is shown as its slot.
+    pub fn write_image_named<W, F>(&mut self, out: &mut W, global_name: F) -> VMResult<()>
+    where
+        W: Write,
+        F: Fn(u32) -> Option<Interned>,
+    {
+        self.gc();
+        self.check_host_objects(global_name)?;
+        out.write_all(IMAGE_MAGIC)?;
+        write_u16(out, IMAGE_VERSION)?;
+        write_len(out, self.buitins.len())?;
+
+        // Save every id (including uninterned and reclaimed ones) so symbols keep their ids.
+        write_len(out, self.interner.slots().len())?;
+        for slot in self.interner.slots() {
+            if let Some((name, interned, permanent)) = slot {
+                let mut flags = SLOT_USED;
+                if interned {
+                    flags |= SLOT_INTERNED;
+                }
+                if permanent {
+                    flags |= SLOT_PERMANENT;
+                }
+                write_u8(out, flags)?;
+                write_str(out, name)?;
+            } else {
+                write_u8(out, 0)?;
+            }
+        }
+
+        write_len(out, self.globals.objects.len())?;
+        for val in &self.globals.objects {
+            write_raw_value(out, *val)?;
+        }
+        write_len(out, self.globals.props.len())?;
+        for (global, props) in &self.globals.props {
+            write_u32(out, *global)?;
+            write_len(out, props.len())?;
+            for (prop, val) in props.iter() {
+                write_u32(out, prop.id)?;
+                write_raw_value(out, *val)?;
+            }
+        }
+
+        self.heap().write_image(out)
+    }
+
+    /// Error listing every live host object with a path to it from a global, Ok if there are none.
+    fn check_host_objects<F: Fn(u32) -> Option<Interned>>(&self, global_name: F) -> VMResult<()> {
+        let hosts = self.heap().host_objects();
+        if hosts.is_empty() {
+            return Ok(());
+        }
+        let mut roots = Vec::new();
+        for (slot, val) in self.globals.objects.iter().enumerate() {
+            roots.push((RefRoot::Global(slot as u32), *val));
+        }
+        for (global, props) in &self.globals.props {
+            for (prop, val) in props.iter() {
+                roots.push((RefRoot::GlobalProperty(*global, *prop), *val));
+            }
+        }
+        let global = |slot: u32| match global_name(slot) {
+            Some(name) => format!("global {}", self.get_interned(name)),
+            None => format!("global slot {slot}"),
+        };
+        let mut msg = "Image: can not save host objects:".to_string();
+        for (host, path) in hosts.iter().zip(self.heap().ref_paths(&roots, &hosts)) {
+            msg.push_str(&format!("\n  {}", host.display_value(self)));
+            let Some((root, steps)) = path else {
+                msg.push_str(" (not referenced from a global)");
+                continue;
+            };
+            msg.push_str(" at ");
+            msg.push_str(&match root {
+                RefRoot::Global(slot) => global(slot),
+                RefRoot::GlobalProperty(slot, prop) => {
+                    format!("property {} of {}", self.get_interned(prop), global(slot))
+                }
+            });
+            for step in steps {
+                msg.push_str(" -> ");
+                msg.push_str(&match step {
+                    RefStep::Index(i) => format!("[{i}]"),
+                    RefStep::Car => "car".to_string(),
+                    RefStep::Cdr => "cdr".to_string(),
+                    RefStep::Key => "key".to_string(),
+                    RefStep::Entry(key) => format!("value for {}", key.display_value(self)),
+                    RefStep::Item => "item".to_string(),
+                    RefStep::Capture(i) => format!("capture {i}"),
+                    RefStep::Constant(i) => format!("constant {i}"),
+                    RefStep::Property(prop) => format!("property {}", self.get_interned(prop)),
+                    RefStep::Inside(type_name) => format!("inside #<{type_name}>"),
+                    RefStep::Field(field) => field.to_string(),
+                });
+            }
+        }
+        Err(VMError::new_chunk(msg))
+    }
+
+    /// Replace this VM's interner, globals and heap with an image saved by write_image and reset
+    /// the VM.  The image must have been saved from a VM with the same builtins and its symbols
+    /// must start with the ones already interned in this VM (i.e. saved from a VM that was set
+    /// up the same way) or an error is returned and the VM is unchanged.
+    pub fn read_image<R: Read>(&mut self, input: &mut R) -> VMResult<()> {
+        let mut magic = [0_u8; 4];
+        input.read_exact(&mut magic)?;
+        if &magic != IMAGE_MAGIC {
+            return Err(VMError::new_chunk("Image: not a VM image."));
+        }
+        let version = read_u16(input)?;
+        if version != IMAGE_VERSION {
+            return Err(VMError::new_chunk(format!(
+                "Image: unsupported version {version}, expected {IMAGE_VERSION}."
+            )));
+        }
+        let builtins = read_u32(input)? as usize;
+        if builtins != self.buitins.len() {
+            return Err(VMError::new_chunk(format!(
+                "Image: saved with {builtins} builtins but the VM has {}.",
+                self.buitins.len()
+            )));
+        }
+
+        let num_symbols = read_u32(input)? as usize;
+        if num_symbols < self.interner.slots().len() {
+            return Err(VMError::new_chunk(
+                "Image: does not contain the symbols already interned in the VM.",
+            ));
+        }
+        let mut interner = Interner::with_capacity(8192);
+        let mut current = self.interner.slots();
+        for id in 0..num_symbols {
+            let flags = read_u8(input)?;
+            let slot = if flags & SLOT_USED != 0 {
+                Some(read_string(input)?)
+            } else {
+                None
+            };
+            if let Some(Some((cur, _, _))) = current.next() {
+                if slot.as_deref() != Some(cur) {
+                    return Err(VMError::new_chunk(format!(
+                        "Image: symbol {id} is {}, expected {cur}.",
+                        slot.as_deref().unwrap_or("unused")
+                    )));
+                }
+            }
+            interner.push_slot(slot.as_deref().map(|name| {
+                (
+                    name,
+                    flags & SLOT_INTERNED != 0,
+                    flags & SLOT_PERMANENT != 0,
+                )
+            }));
+        }
+        drop(current);
+
+        let num_globals = read_u32(input)? as usize;
+        let mut globals = Globals::new();
+        for _ in 0..num_globals {
+            globals.objects.push(read_raw_value(input)?);
+        }
+        let num_props = read_u32(input)? as usize;
+        for _ in 0..num_props {
+            let global = read_u32(input)?;
+            let len = read_u32(input)? as usize;
+            let mut props = HashMap::with_capacity(len);
+            for _ in 0..len {
+                let prop = Interned {
+                    id: read_u32(input)?,
+                };
+                props.insert(prop, read_raw_value(input)?);
+            }
+            globals.props.insert(global, Arc::new(props));
+        }
+
+        let mut heap = Heap::read_image(&mut interner, input)?;
+        // GC settings belong to this VM, not the image.
+        heap.set_gc_step(self.heap().gc_step());
+        heap.set_max_bytes(self.heap().max_bytes());
+
+        // Drop the old VM state (defers, bindings) before it is mixed up with the new heap.
+        self.reset();
+        self.interner = interner;
+        self.globals = globals;
+        self.heap = Some(heap);
+        // Anything left on the stack refers to the old heap.
+        self.stack_slice_mut().fill(Value::Undefined);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chunk, PMap, PVec, VMMap, Vm, RET, SRET};
+
+    #[test]
+    fn test_image_round_trip() -> VMResult<()> {
+        let mut vm = Vm::new();
+        let slot = vm.reserve_global();
+        let s = vm.alloc_string("a string".to_string()).unwrap();
+        let sym = Value::Symbol(vm.intern("sym"));
+        let pair = vm.alloc_pair(sym, s).unwrap();
+        vm.set_global(slot, pair);
+        let doc = vm.intern("doc");
+        vm.set_global_property(slot, doc, s);
+        let prop = vm.intern("prop");
+        vm.set_heap_property(pair, "prop", Value::True);
+
+        let mut chunk = Chunk::new("image_file", 1);
+        chunk.args = 1;
+        chunk.input_regs = 2;
+        chunk.encode1(SRET, 1, Some(1))?;
+        let lambda = vm.alloc_lambda(Arc::new(chunk)).unwrap();
+        let lslot = vm.reserve_global();
+        vm.set_global(lslot, lambda);
+        let gensym = vm.gensym("sym");
+        let gslot = vm.reserve_global();
+        vm.set_global(gslot, Value::Symbol(gensym));
+
+        let mut out = Vec::new();
+        vm.write_image(&mut out)?;
+
+        let mut vm2 = Vm::new();
+        vm2.read_image(&mut &out[..])?;
+        let pair2 = vm2.get_global(slot);
+        let (car, cdr) = pair2.get_pair(&vm2).unwrap();
+        let sym2 = vm2.intern("sym");
+        assert_eq!(car, Value::Symbol(sym2));
+        assert_eq!(vm2.get_interned(sym2), "sym");
+        assert_eq!(vm2.get_string(cdr.get_handle().unwrap()), "a string");
+        assert_eq!(vm2.get_global_property(slot, doc), Some(cdr));
+        assert_eq!(
+            vm2.get_heap_property_interned(pair2, prop),
+            Some(Value::True)
+        );
+        if let Value::Lambda(h) = vm2.get_global(lslot) {
+            let l = vm2.get_lambda(h);
+            assert_eq!(l.file_name, "image_file");
+            assert_eq!(l.args, 1);
+        } else {
+            panic!("expected a lambda global");
+        }
+        assert_eq!(vm2.get_global(gslot), Value::Symbol(gensym));
+        assert!(vm2.is_uninterned(gensym));
+        assert_eq!(vm2.get_interned(gensym), "sym");
+
+        // Objects survive GC in the restored VM and it can still run code.
+        for i in 0..2000 {
+            vm2.alloc_string(format!("garbage {i}")).unwrap();
+        }
+        assert_eq!(vm2.get_string(cdr.get_handle().unwrap()), "a string");
+        let mut chunk = Chunk::new("run", 1);
+        let c = chunk.add_constant(Value::Int([0, 0, 0, 0, 0, 0, 7]));
+        chunk.encode2(crate::CONST, 0, c as u16, Some(1))?;
+        chunk.encode0(RET, Some(1))?;
+        let res = vm2.execute(Arc::new(chunk))?;
+        assert_eq!(res, Value::Int([0, 0, 0, 0, 0, 0, 7]));
+
+        // A VM with symbols the image does not know about can not load it.
+        let mut vm3 = Vm::new();
+        vm3.intern("not-in-image");
+        assert!(vm3.read_image(&mut &out[..]).is_err());
+        assert!(vm3.read_image(&mut &b"NOPE"[..]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_persistent() -> VMResult<()> {
+        let mut vm = Vm::new();
+        let s = vm.alloc_string("item".to_string()).unwrap();
+        let vec: PVec = (0..100).map(Value::from).chain([s]).collect();
+        let pvec = vm.alloc_pvec(vec).unwrap();
+        let map = PMap::new().insert(Value::Nil, pvec).insert(s, 1.into());
+        let pmap = vm.alloc_pmap(map).unwrap();
+        let slot = vm.reserve_global();
+        vm.set_global(slot, pmap);
+
+        let mut out = Vec::new();
+        vm.write_image(&mut out)?;
+        let mut vm2 = Vm::new();
+        vm2.read_image(&mut &out[..])?;
+        let Value::PMap(h) = vm2.get_global(slot) else {
+            panic!("expected a persistent map global");
+        };
+        let map = vm2.get_pmap(h);
+        assert_eq!(map.len(), 2);
+        assert_eq!(map.get(&s), Some(&1.into()));
+        let Some(Value::PVec(h)) = map.get(&Value::Nil) else {
+            panic!("expected a persistent vector");
+        };
+        let vec = vm2.get_pvec(*h);
+        assert_eq!(vec.len(), 101);
+        assert_eq!(vec.get(99), Some(&99.into()));
+        assert_eq!(
+            vm2.get_string(vec.get(100).unwrap().get_handle().unwrap()),
+            "item"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_sorted_map() -> VMResult<()> {
+        let mut vm = Vm::new();
+        let map = vm.alloc_map(VMMap::new_sorted()).unwrap();
+        let Value::Map(h) = map else {
+            panic!("expected a map");
+        };
+        let b = vm.alloc_string("b".to_string()).unwrap();
+        let d = vm.alloc_string("d".to_string()).unwrap();
+        for key in [d, b, 2.into()] {
+            vm.map_insert(h, key, Value::True)?;
+        }
+        let slot = vm.reserve_global();
+        vm.set_global(slot, map);
+
+        let mut out = Vec::new();
+        vm.write_image(&mut out)?;
+        let mut vm2 = Vm::new();
+        vm2.read_image(&mut &out[..])?;
+        let Value::Map(h) = vm2.get_global(slot) else {
+            panic!("expected a map global");
+        };
+        // New keys still go in order after loading.
+        let c = vm2.alloc_string("c".to_string()).unwrap();
+        vm2.map_insert(h, c, Value::True)?;
+        vm2.map_insert(h, 1.into(), Value::True)?;
+        let keys: Vec<String> = vm2
+            .get_map(h)
+            .keys()
+            .map(|key| key.display_value(&vm2))
+            .collect();
+        assert_eq!(keys, vec!["1", "2", "\"b\"", "\"c\"", "\"d\""]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_image_host() -> VMResult<()> {
+        struct Handle;
+
+        impl crate::HostObject for Handle {
+            fn type_name(&self) -> &'static str {
+                "Handle"
+            }
+        }
+
+        let mut vm = Vm::new();
+        let host = vm.alloc_host(Handle)?;
+        let pair = vm.alloc_pair(1.into(), host)?;
+        let vec = vm.alloc_vector(vec![Value::Nil, pair])?;
+        let slot = vm.reserve_global();
+        vm.set_global(slot, vec);
+        let err = vm.write_image(&mut Vec::new()).unwrap_err();
+        let path = format!("\n  #<Handle> at global slot {slot} -> [1] -> cdr");
+        assert!(err.to_string().ends_with(&path));
+
+        // Once nothing references it the collection run before writing frees it.
+        vm.set_global(slot, Value::Nil);
+        let mut out = Vec::new();
+        vm.write_image(&mut out)?;
+        let mut vm2 = Vm::new();
+        vm2.read_image(&mut &out[..])?;
+        assert_eq!(vm2.get_global(slot), Value::Nil);
+        Ok(())
+    }
+}
@@ -119,6 +119,15 @@ macro_rules! compare_int {
                     get_float!($vm, op1).map_err(|e| (e, $chunk.clone()))?,
                     get_float!($vm, op2).map_err(|e| (e, $chunk.clone()))?,
                 )
+            } else if matches!(op1, $crate::Value::BigInt(_))
+                || matches!(op2, $crate::Value::BigInt(_))
+            {
+                // The macro expansion trips this.
+                #[allow(clippy::redundant_closure_call)]
+                $comp_fn(
+                    op1.get_bigint($vm).map_err(|e| (e, $chunk.clone()))?,
+                    op2.get_bigint($vm).map_err(|e| (e, $chunk.clone()))?,
+                )
             } else {
                 // The macro expansion trips this.
                 #[allow(clippy::redundant_closure_call)]
@@ -169,9 +178,10 @@ macro_rules! get_int {
 macro_rules! get_float {
     ($vm:expr, $val:expr) => {{
         match $val {
-            $crate::Value::Byte(b) => Ok(b as f32),
-            $crate::Value::Int(i) => Ok(crate::from_i56(&i) as f32),
-            $crate::Value::Float(f) => Ok(f.0),
+            $crate::Value::Byte(b) => Ok(b as f64),
+            $crate::Value::Int(i) => Ok(crate::from_i56(&i) as f64),
+            $crate::Value::Float(f) => Ok(f.get()),
+            $crate::Value::BigInt(_) => $val.get_float($vm),
             _ => Err($crate::VMError::new_value(format!(
                 "Not a float: {:?}",
                 $val
@@ -180,18 +190,45 @@ macro_rules! get_float {
     }};
 }
 
+/// Apply an integer op to op1 and op2.  Uses $checked_fn (an i64 checked_* function) and
+/// promotes to a BigInt on overflow, if either op is already a BigInt uses $bin_fn on BigInts.
+/// Results that fit are always returned as an Int.
+macro_rules! int_math {
+    ($vm:expr, $chunk:expr, $op1:expr, $op2:expr, $bin_fn:expr, $checked_fn:expr) => {{
+        if matches!($op1, $crate::Value::BigInt(_)) || matches!($op2, $crate::Value::BigInt(_)) {
+            let op1 = $op1.get_bigint($vm).map_err(|e| (e, $chunk.clone()))?;
+            let op2 = $op2.get_bigint($vm).map_err(|e| (e, $chunk.clone()))?;
+            // The macro expansion trips this.
+            #[allow(clippy::redundant_closure_call)]
+            $vm.alloc_bigint($bin_fn(op1, op2))
+        } else {
+            let op1 = get_int!($vm, $op1).map_err(|e| (e, $chunk.clone()))?;
+            let op2 = get_int!($vm, $op2).map_err(|e| (e, $chunk.clone()))?;
+            match $checked_fn(op1, op2) {
+                Some(val) => $vm.alloc_int(val),
+                // The macro expansion trips this.
+                #[allow(clippy::redundant_closure_call)]
+                None => $vm.alloc_bigint($bin_fn(
+                    $crate::BigInt::from(op1),
+                    $crate::BigInt::from(op2),
+                )),
+            }
+        }
+    }};
+}
+
 macro_rules! binary_math {
-    ($vm:expr, $chunk:expr, $code:expr, $bin_fn:expr, $wide:expr) => {{
+    ($vm:expr, $chunk:expr, $code:expr, $bin_fn:expr, $checked_fn:expr, $wide:expr) => {{
         let (dest, op2) = decode2!($code, $wide);
         let op1 = $vm.register(dest as usize);
         let op2 = $vm.register(op2 as usize);
         match (op1, op2) {
             ($crate::Value::Float(op1_f), $crate::Value::Float(op2_f)) => {
-                *$vm.register_mut(dest as usize) = $bin_fn(op1_f.0, op2_f.0).into();
+                *$vm.register_mut(dest as usize) = $bin_fn(op1_f.get(), op2_f.get()).into();
             }
             ($crate::Value::Float(op1_f), _) => {
                 *$vm.register_mut(dest as usize) = $bin_fn(
-                    op1_f.0,
+                    op1_f.get(),
                     get_float!($vm, op2).map_err(|e| (e, $chunk.clone()))?,
                 )
                 .into();
@@ -199,16 +236,13 @@ macro_rules! binary_math {
             (_, $crate::Value::Float(op2_f)) => {
                 *$vm.register_mut(dest as usize) = $bin_fn(
                     get_float!($vm, op1).map_err(|e| (e, $chunk.clone()))?,
-                    op2_f.0,
+                    op2_f.get(),
                 )
                 .into();
             }
             (_, _) => {
-                *$vm.register_mut(dest as usize) = $bin_fn(
-                    get_int!($vm, op1).map_err(|e| (e, $chunk.clone()))?,
-                    get_int!($vm, op2).map_err(|e| (e, $chunk.clone()))?,
-                )
-                .into();
+                let val = int_math!($vm, $chunk, op1, op2, $bin_fn, $checked_fn);
+                *$vm.register_mut(dest as usize) = val.map_err(|e| (e, $chunk.clone()))?;
             }
         }
     }};
@@ -221,37 +255,40 @@ macro_rules! div_math {
         let op2 = $vm.register(op2 as usize);
         match (op1, op2) {
             ($crate::Value::Float(op1_f), $crate::Value::Float(op2_f)) => {
-                let op1 = op1_f.0;
-                let op2 = op2_f.0;
+                let op1 = op1_f.get();
+                let op2 = op2_f.get();
                 if op2 == 0.0 {
                     return Err(($crate::VMError::new_vm("Divide by zero error."), $chunk));
                 }
                 *$vm.register_mut(dest as usize) = (op1 / op2).into();
             }
             ($crate::Value::Float(op1_f), _) => {
-                let op1 = op1_f.0;
-                let op2 = get_float!($vm, op2).map_err(|e| (e, $chunk.clone()))? as f32;
+                let op1 = op1_f.get();
+                let op2 = get_float!($vm, op2).map_err(|e| (e, $chunk.clone()))?;
                 if op2 == 0.0 {
                     return Err(($crate::VMError::new_vm("Divide by zero error."), $chunk));
                 }
                 *$vm.register_mut(dest as usize) = (op1 / op2).into();
             }
             (_, $crate::Value::Float(op2_f)) => {
-                let op1 = get_float!($vm, op1).map_err(|e| (e, $chunk.clone()))? as f32;
-                let op2 = op2_f.0;
+                let op1 = get_float!($vm, op1).map_err(|e| (e, $chunk.clone()))?;
+                let op2 = op2_f.get();
                 if op2 == 0.0 {
                     return Err(($crate::VMError::new_vm("Divide by zero error."), $chunk));
                 }
                 *$vm.register_mut(dest as usize) = (op1 / op2).into();
             }
             (_, _) => {
-                let op1 = get_int!($vm, op1).map_err(|e| (e, $chunk.clone()))?;
-                let op2 = get_int!($vm, op2).map_err(|e| (e, $chunk.clone()))?;
-                if op2 == 0 {
+                if matches!(
+                    op2,
+                    $crate::Value::Int([0, 0, 0, 0, 0, 0, 0]) | $crate::Value::Byte(0)
+                ) {
                     return Err(($crate::VMError::new_vm("Divide by zero error."), $chunk));
                 }
-                let val = op1 / op2;
-                *$vm.register_mut(dest as usize) = val.into();
+                // i64::checked_div only fails for a zero divisor (checked above) or
+                // i64::MIN / -1 which can not happen with 56 bit ints.
+                let val = int_math!($vm, $chunk, op1, op2, |a, b| a / b, i64::checked_div);
+                *$vm.register_mut(dest as usize) = val.map_err(|e| (e, $chunk.clone()))?;
             }
         }
     }};
@@ -278,3 +315,15 @@ macro_rules! mov_register {
         *$vm.register_mut($idx) = $val;
     }};
 }
+
+#[macro_export]
+macro_rules! jump {
+    ($vm:expr, $chunk:expr, $jmp:expr) => {{
+        let target = get_code_at!($chunk, $chunk.jump_table[$jmp as usize] as isize);
+        // Backward jumps (loops) use fuel.
+        if target <= $vm.current_ip_ptr {
+            $vm.check_limits().map_err(|e| (e, $chunk.clone()))?;
+        }
+        $vm.ip_ptr = target;
+    }};
+}
@@ -0,0 +1,57 @@
+//! Key order for sorted maps.
+
+use crate::{GVm, Handle, SortKey, VMResult, Value};
+
+impl<ENV> GVm<ENV> {
+    /// Key order of val in a sorted map: undefined, nil, booleans, numbers (by value), chars,
+    /// strings, symbols and keywords (by text) and then anything else by type and heap object.
+    pub fn sort_key(&self, val: Value) -> SortKey {
+        SortKey::new(val, self.heap(), &self.interner)
+    }
+
+    /// Set key to val in a map, a new key goes in key order in a sorted map and at the end
+    /// otherwise.  Returns the old value if there was one.
+    pub fn map_insert(
+        &mut self,
+        handle: Handle,
+        key: Value,
+        val: Value,
+    ) -> VMResult<Option<Value>> {
+        if !self.get_map(handle).is_sorted() {
+            return self.get_map_mut(handle)?.insert(key, val);
+        }
+        let sort_key = self.sort_key(key);
+        Ok(self.get_map_mut(handle)?.insert_sorted(key, sort_key, val))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{VMMap, Vm};
+
+    #[test]
+    fn test_sorted_map() -> VMResult<()> {
+        let mut vm = Vm::new();
+        let b = Value::StringConst(vm.intern("b"));
+        let a = vm.alloc_string("a".to_string()).unwrap();
+        let kw = Value::Keyword(vm.intern("a"));
+        let big = vm.alloc_int(1_i64 << 60).unwrap();
+        let map = vm.alloc_map(VMMap::new_sorted()).unwrap();
+        let Value::Map(h) = map else {
+            panic!("expected a map");
+        };
+        let keys = [kw, b, 2.5.into(), Value::Nil, a, big, 3.into(), Value::True];
+        for key in keys {
+            vm.map_insert(h, key, Value::True)?;
+        }
+        assert_eq!(vm.map_insert(h, 3.into(), Value::False)?, Some(Value::True));
+        let sorted: Vec<Value> = vm.get_map(h).keys().copied().collect();
+        assert_eq!(
+            sorted,
+            vec![Value::Nil, Value::True, 2.5.into(), 3.into(), big, a, b, kw]
+        );
+        assert_eq!(vm.get_map(h).get(&3.into()), Some(&Value::False));
+        Ok(())
+    }
+}
@@ -0,0 +1,169 @@
+//! Opcode profiler, only built with the "profile" feature so the exec loop is untouched
+//! otherwise (the call profiler is in call_profile.rs).
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use super::storage::CallStackIter;
+use crate::opcodes::op_name;
+use crate::{Chunk, GVm};
+
+/// Default number of op codes executed between samples.
+pub const DEFAULT_PROFILE_INTERVAL: u64 = 1000;
+
+/// Data collected while profiling.  Op codes are all counted, lines, functions and stacks are
+/// sampled every interval op codes.  Functions are named by the file and line their chunk starts
+/// on.
+#[derive(Clone, Debug)]
+pub struct Profile {
+    /// Number of times each op code was executed (indexed by op code).
+    pub op_counts: Vec<u64>,
+    /// Samples by (file, line).
+    pub lines: HashMap<(&'static str, u32), u64>,
+    /// Samples by function.
+    pub functions: HashMap<String, u64>,
+    /// Samples by call stack, outermost function first separated by ';' (folded stack format).
+    pub stacks: HashMap<String, u64>,
+    /// Total number of samples taken.
+    pub samples: u64,
+    interval: u64,
+    countdown: u64,
+}
+
+fn function_name(chunk: &Chunk) -> String {
+    format!("{}:{}", chunk.file_name, chunk.start_line())
+}
+
+fn top<K: Clone + Ord>(map: &HashMap<K, u64>, count: usize) -> Vec<(K, u64)> {
+    let mut items: Vec<(K, u64)> = map.iter().map(|(k, v)| (k.clone(), *v)).collect();
+    items.sort_by(|(k1, v1), (k2, v2)| v2.cmp(v1).then_with(|| k1.cmp(k2)));
+    items.truncate(count);
+    items
+}
+
+impl Profile {
+    pub fn new(interval: u64) -> Self {
+        let interval = interval.max(1);
+        Self {
+            op_counts: vec![0; 256],
+            lines: HashMap::new(),
+            functions: HashMap::new(),
+            stacks: HashMap::new(),
+            samples: 0,
+            interval,
+            countdown: interval,
+        }
+    }
+
+    pub fn interval(&self) -> u64 {
+        self.interval
+    }
+
+    /// Human readable report of the count hottest lines, functions and op codes.
+    pub fn report(&self, count: usize) -> String {
+        let mut out = String::new();
+        let samples = self.samples.max(1) as f64;
+        let _ = writeln!(
+            out,
+            "Samples: {} (every {} ops)",
+            self.samples, self.interval
+        );
+        let _ = writeln!(out, "\nHot lines:");
+        for ((file, line), n) in top(&self.lines, count) {
+            let pct = n as f64 * 100.0 / samples;
+            let _ = writeln!(out, "{n:>10} {pct:>6.2}%  {file}:{line}");
+        }
+        let _ = writeln!(out, "\nHot functions:");
+        for (name, n) in top(&self.functions, count) {
+            let pct = n as f64 * 100.0 / samples;
+            let _ = writeln!(out, "{n:>10} {pct:>6.2}%  {name}");
+        }
+        let ops: HashMap<u8, u64> = self
+            .op_counts
+            .iter()
+            .enumerate()
+            .filter(|(_, n)| **n > 0)
+            .map(|(op, n)| (op as u8, *n))
+            .collect();
+        let total = ops.values().sum::<u64>().max(1) as f64;
+        let _ = writeln!(out, "\nOp codes:");
+        for (op, n) in top(&ops, count) {
+            let pct = n as f64 * 100.0 / total;
+            let name = op_name(op).unwrap_or("???");
+            let _ = writeln!(out, "{n:>10} {pct:>6.2}%  {name}");
+        }
+        out
+    }
+
+    /// The sampled stacks in folded format (one "outer;...;inner count" per line), suitable for
+    /// flamegraph tools.
+    pub fn folded(&self) -> String {
+        let mut stacks: Vec<(&String, &u64)> = self.stacks.iter().collect();
+        stacks.sort();
+        let mut out = String::new();
+        for (stack, n) in stacks {
+            let _ = writeln!(out, "{stack} {n}");
+        }
+        out
+    }
+}
+
+impl<ENV> GVm<ENV> {
+    /// Start profiling (discarding any previous profile), sampling every interval op codes.
+    pub fn start_profile(&mut self, interval: u64) {
+        self.profile = Some(Box::new(Profile::new(interval)));
+        self.profiling = true;
+    }
+
+    /// Stop collecting, the profile is kept until the next start_profile or take_profile.
+    pub fn stop_profile(&mut self) {
+        self.profiling = false;
+    }
+
+    pub fn is_profiling(&self) -> bool {
+        self.profiling
+    }
+
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_deref()
+    }
+
+    /// Stop profiling and return the profile.
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profiling = false;
+        self.profile.take().map(|p| *p)
+    }
+
+    /// Called by the exec loop for every op code when profiling.
+    #[inline]
+    pub(super) fn profile_op(&mut self, op: u8, chunk: &Chunk) {
+        if !self.profiling {
+            return;
+        }
+        let Some(mut profile) = self.profile.take() else {
+            return;
+        };
+        profile.op_counts[op as usize] += 1;
+        profile.countdown -= 1;
+        if profile.countdown == 0 {
+            profile.countdown = profile.interval;
+            self.sample(&mut profile, chunk);
+        }
+        self.profile = Some(profile);
+    }
+
+    fn sample(&self, profile: &mut Profile, chunk: &Chunk) {
+        profile.samples += 1;
+        let offset = unsafe { self.current_ip_ptr.offset_from(chunk.code.as_ptr()) as usize };
+        let line = chunk.offset_to_line(offset).unwrap_or(0);
+        *profile.lines.entry((chunk.file_name, line)).or_default() += 1;
+        let name = function_name(chunk);
+        *profile.functions.entry(name.clone()).or_default() += 1;
+        let mut stack: Vec<String> = CallStackIter::new(self)
+            .map(|frame| function_name(&frame.chunk))
+            .collect();
+        stack.reverse();
+        stack.push(name);
+        *profile.stacks.entry(stack.join(";")).or_default() += 1;
+    }
+}
@@ -0,0 +1,126 @@
+//! Resumable errors.  An error raised with signal calls the on-error handler at the raise point
+//! (instead of in place of the erroring frame) so the handler's result is the value of the signal,
+//! the handler can pick one of the restarts offered by the raise site to produce it.
+
+use std::sync::Arc;
+
+use crate::{Chunk, GVm, Heap, Interned, VMError, VMErrorObj, Value};
+
+// Result of make_call, the chunk to continue with or the error and the chunk it happened in.
+type CallResult = Result<Arc<Chunk>, (VMError, Arc<Chunk>)>;
+
+pub(super) struct PendingSignal {
+    restarts: Value,
+    // Register and tail call flag of the call that raised the signal, set by make_call.
+    at: Option<(u16, bool)>,
+}
+
+pub(super) struct RestartEntry {
+    // Stack top of the handler, the restarts are available until it returns.
+    stack_top: usize,
+    restarts: Value,
+}
+
+impl<ENV> GVm<ENV> {
+    /// Make a resumable error for a builtin to return.  If an on-error handler is installed it is
+    /// called where the builtin was called and its result becomes the builtin's result, otherwise
+    /// this is a normal error.  restarts (a map of keyword to function or value, or nil) are
+    /// available to the handler with restarts (see invoke-restart in builtins).
+    pub fn signal(&mut self, key: Interned, data: Value, restarts: Value) -> VMError {
+        self.pending_signal = Some(PendingSignal { restarts, at: None });
+        VMError {
+            key: self.get_interned(key),
+            obj: VMErrorObj::Object(data),
+            backtrace: None,
+        }
+    }
+
+    /// Restarts offered by the innermost signal whose handler is still running.
+    pub fn restarts(&self) -> Option<Value> {
+        self.restarts
+            .iter()
+            .rev()
+            .find(|entry| entry.stack_top <= self.stack_top)
+            .map(|entry| entry.restarts)
+    }
+
+    /// A builtin called by make_call returned an error, record where if it was a signal.
+    pub(super) fn signal_raised(&mut self, first_reg: u16, tail_call: bool) -> bool {
+        match &mut self.pending_signal {
+            Some(signal) if signal.at.is_none() => {
+                signal.at = Some((first_reg, tail_call));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Call handler for the signal that produced err (if the last error was a signal), returns
+    /// None if it was not.
+    pub(super) fn call_signal_handler(
+        &mut self,
+        handler: Value,
+        chunk: Arc<Chunk>,
+        err: &VMError,
+    ) -> Option<CallResult> {
+        let PendingSignal {
+            restarts,
+            at: Some((first_reg, tail_call)),
+        } = self.pending_signal.take()?
+        else {
+            return None;
+        };
+        self.make_registers();
+        let key = Value::Keyword(self.intern_static(err.key));
+        let data = match &err.obj {
+            VMErrorObj::Message(msg) => Value::StringConst(self.intern(msg)),
+            VMErrorObj::Object(v) => *v,
+        };
+        *self.register_mut(first_reg as usize + 1) = key;
+        *self.register_mut(first_reg as usize + 2) = data;
+        let res = self.make_call(handler, chunk, first_reg, 2, tail_call);
+        if res.is_ok() {
+            // Same as any other error the handler is not called for errors it raises, the call
+            // frame restores it when the handler returns.
+            self.on_error = None;
+            self.restarts_drop_stale(self.stack_top);
+            self.restarts.push(RestartEntry {
+                stack_top: self.stack_top,
+                restarts,
+            });
+        }
+        Some(res)
+    }
+
+    fn restarts_drop_stale(&mut self, stack_top: usize) {
+        while matches!(self.restarts.last(), Some(entry) if entry.stack_top >= stack_top) {
+            self.restarts.pop();
+        }
+    }
+
+    /// The frame at the current stack top is returning, its restarts (if it is a handler) are
+    /// gone.
+    #[inline]
+    pub(super) fn restarts_return(&mut self) {
+        if !self.restarts.is_empty() {
+            self.restarts_drop_stale(self.stack_top);
+        }
+    }
+
+    pub(super) fn restarts_depth(&self) -> usize {
+        self.restarts.len()
+    }
+
+    pub(super) fn restarts_truncate(&mut self, depth: usize) {
+        self.restarts.truncate(depth);
+    }
+
+    pub(super) fn restarts_mark(&self, heap: &mut Heap) {
+        if let Some(signal) = &self.pending_signal {
+            heap.mark(signal.restarts);
+        }
+        for entry in &self.restarts {
+            heap.mark(entry.restarts);
+        }
+    }
+}
@@ -1,6 +1,10 @@
 use crate::heap::Error;
-use crate::{CallFrame, Chunk, Continuation, Handle, Heap, Interned, MutState, VMResult, Value};
-use std::collections::HashMap;
+use crate::{
+    bigint_to_i56, fits_i56, CallFrame, Chunk, Continuation, Handle, Heap, HeapStats, HostObject,
+    Interned, MutState, PMap, PVec, VMError, VMMap, VMResult, VMSet, Value,
+};
+use num_bigint::BigInt;
+use std::any::Any;
 use std::sync::Arc;
 
 use crate::GVm;
@@ -68,10 +72,17 @@ impl<ENV> GVm<ENV> {
         self.stack_max
     }
 
+    /// The name of i, this pins a weak or uninterned symbol so it is never reclaimed (use
+    /// interned_name when a borrowed str will do).
     pub fn get_interned(&self, i: Interned) -> &'static str {
         self.interner.get_string(i).expect("Invalid interned value")
     }
 
+    /// The name of i borrowed from the VM, does not stop i from being reclaimed.
+    pub fn interned_name(&self, i: Interned) -> &str {
+        self.interner.name(i).expect("Invalid interned value")
+    }
+
     pub fn intern_static(&mut self, string: &'static str) -> Interned {
         self.interner.intern_static(string)
     }
@@ -84,7 +95,36 @@ impl<ENV> GVm<ENV> {
         self.interner.get_if_interned(string)
     }
 
+    /// Intern string as a weak symbol, it is reclaimed by a full GC once nothing references it
+    /// (use this for symbols made from runtime data).
+    pub fn intern_weak(&mut self, string: &str) -> Interned {
+        self.interner.intern_weak(string)
+    }
+
+    /// Make a new uninterned symbol (see Interner::gensym).
+    pub fn gensym(&mut self, name: &str) -> Interned {
+        self.interner.gensym(name)
+    }
+
+    /// Make sure symbol is never reclaimed, for symbols held outside of any value (global names
+    /// for instance).
+    pub fn keep_symbol(&mut self, symbol: Interned) {
+        self.interner.keep(symbol);
+    }
+
+    pub fn is_uninterned(&self, symbol: Interned) -> bool {
+        self.interner.is_uninterned(symbol)
+    }
+
+    /// Number of symbols in the interner including uninterned ones.
+    pub fn symbols_len(&self) -> usize {
+        self.interner.symbols()
+    }
+
     pub fn set_global(&mut self, slot: u32, value: Value) {
+        if let Some(heap) = self.heap.as_mut() {
+            heap.root_barrier(value);
+        }
         self.globals.set(slot, value);
     }
 
@@ -100,109 +140,188 @@ impl<ENV> GVm<ENV> {
         Heap::sizeof_object()
     }
 
-    pub fn alloc_pair(&mut self, car: Value, cdr: Value) -> Value {
+    pub fn alloc_pair(&mut self, car: Value, cdr: Value) -> VMResult<Value> {
         let mut heap = self.heap.take().expect("VM must have a Heap!");
         let res = heap.alloc_pair(car, cdr, MutState::Mutable, |heap| self.mark_roots(heap));
-        self.heap = Some(heap);
+        self.restore_heap(heap);
         res
     }
 
-    pub fn alloc_pair_ro(&mut self, car: Value, cdr: Value) -> Value {
+    pub fn alloc_pair_ro(&mut self, car: Value, cdr: Value) -> VMResult<Value> {
         let mut heap = self.heap.take().expect("VM must have a Heap!");
         let res = heap.alloc_pair(car, cdr, MutState::Immutable, |heap| self.mark_roots(heap));
-        self.heap = Some(heap);
+        self.restore_heap(heap);
         res
     }
 
-    pub fn alloc_string(&mut self, s: String) -> Value {
+    pub fn alloc_string(&mut self, s: String) -> VMResult<Value> {
         let mut heap = self.heap.take().expect("VM must have a Heap!");
         let res = heap.alloc_string(s, MutState::Mutable, |heap| self.mark_roots(heap));
-        self.heap = Some(heap);
+        self.restore_heap(heap);
         res
     }
 
-    pub fn alloc_string_ro(&mut self, s: String) -> Value {
+    pub fn alloc_string_ro(&mut self, s: String) -> VMResult<Value> {
         let mut heap = self.heap.take().expect("VM must have a Heap!");
         let res = heap.alloc_string(s, MutState::Immutable, |heap| self.mark_roots(heap));
-        self.heap = Some(heap);
+        self.restore_heap(heap);
         res
     }
 
-    pub fn alloc_char(&mut self, ch: &str) -> Value {
+    pub fn alloc_char(&mut self, ch: &str) -> VMResult<Value> {
         if ch.len() < 7 {
             let mut v: [u8; 6] = [0; 6];
             for (i, c) in ch.bytes().enumerate() {
                 v[i] = c;
             }
-            Value::CharCluster(ch.len() as u8, v)
-        } else if let Value::String(handle) = self.alloc_string_ro(ch.to_string()) {
-            Value::CharClusterLong(handle)
+            Ok(Value::CharCluster(ch.len() as u8, v))
+        } else if let Value::String(handle) = self.alloc_string_ro(ch.to_string())? {
+            Ok(Value::CharClusterLong(handle))
         } else {
             panic!("Invalid alloc_string!");
         }
     }
 
-    pub fn alloc_vector(&mut self, v: Vec<Value>) -> Value {
+    pub fn alloc_vector(&mut self, v: Vec<Value>) -> VMResult<Value> {
         let mut heap = self.heap.take().expect("VM must have a Heap!");
         let res = heap.alloc_vector(v, MutState::Mutable, |heap| self.mark_roots(heap));
-        self.heap = Some(heap);
+        self.restore_heap(heap);
         res
     }
 
-    pub fn alloc_vector_ro(&mut self, v: Vec<Value>) -> Value {
+    pub fn alloc_vector_ro(&mut self, v: Vec<Value>) -> VMResult<Value> {
         let mut heap = self.heap.take().expect("VM must have a Heap!");
         let res = heap.alloc_vector(v, MutState::Immutable, |heap| self.mark_roots(heap));
-        self.heap = Some(heap);
+        self.restore_heap(heap);
         res
     }
 
-    pub fn alloc_map(&mut self, map: HashMap<Value, Value>) -> Value {
+    pub fn alloc_map(&mut self, map: VMMap) -> VMResult<Value> {
         let mut heap = self.heap.take().expect("VM must have a Heap!");
         let res = heap.alloc_map(map, MutState::Mutable, |heap| self.mark_roots(heap));
-        self.heap = Some(heap);
+        self.restore_heap(heap);
         res
     }
 
-    pub fn alloc_map_ro(&mut self, map: HashMap<Value, Value>) -> Value {
+    pub fn alloc_map_ro(&mut self, map: VMMap) -> VMResult<Value> {
         let mut heap = self.heap.take().expect("VM must have a Heap!");
         let res = heap.alloc_map(map, MutState::Immutable, |heap| self.mark_roots(heap));
-        self.heap = Some(heap);
+        self.restore_heap(heap);
         res
     }
 
-    pub fn alloc_list_ro(&mut self, v: Vec<Value>) -> Value {
+    pub fn alloc_set(&mut self, set: VMSet) -> VMResult<Value> {
         let mut heap = self.heap.take().expect("VM must have a Heap!");
-        let res = Value::List(
-            heap.alloc_vector(v, MutState::Immutable, |heap| self.mark_roots(heap))
-                .get_handle()
-                .expect("Allocated vector not a vector?"),
-            0,
-        );
-        self.heap = Some(heap);
+        let res = heap.alloc_set(set, MutState::Mutable, |heap| self.mark_roots(heap));
+        self.restore_heap(heap);
+        res
+    }
+
+    pub fn alloc_set_ro(&mut self, set: VMSet) -> VMResult<Value> {
+        let mut heap = self.heap.take().expect("VM must have a Heap!");
+        let res = heap.alloc_set(set, MutState::Immutable, |heap| self.mark_roots(heap));
+        self.restore_heap(heap);
+        res
+    }
+
+    /// Allocate a map with weak keys (see Heap::alloc_weak_map).
+    pub fn alloc_weak_map(&mut self, map: VMMap) -> VMResult<Value> {
+        let mut heap = self.heap.take().expect("VM must have a Heap!");
+        let res = heap.alloc_weak_map(map, |heap| self.mark_roots(heap));
+        self.restore_heap(heap);
         res
     }
 
-    pub fn alloc_bytes(&mut self, v: Vec<u8>) -> Value {
+    /// Allocate a weak reference to target.
+    pub fn alloc_weak(&mut self, target: Value) -> VMResult<Value> {
+        let mut heap = self.heap.take().expect("VM must have a Heap!");
+        let res = heap.alloc_weak(target, |heap| self.mark_roots(heap));
+        self.restore_heap(heap);
+        res
+    }
+
+    pub fn alloc_pvec(&mut self, vec: PVec) -> VMResult<Value> {
+        let mut heap = self.heap.take().expect("VM must have a Heap!");
+        let res = heap.alloc_pvec(vec, |heap| self.mark_roots(heap));
+        self.restore_heap(heap);
+        res
+    }
+
+    pub fn alloc_pmap(&mut self, map: PMap) -> VMResult<Value> {
+        let mut heap = self.heap.take().expect("VM must have a Heap!");
+        let res = heap.alloc_pmap(map, |heap| self.mark_roots(heap));
+        self.restore_heap(heap);
+        res
+    }
+
+    /// Give the VM ownership of a Rust value, it can be passed around in Lisp as an opaque value.
+    pub fn alloc_host<T: HostObject>(&mut self, obj: T) -> VMResult<Value> {
+        let mut heap = self.heap.take().expect("VM must have a Heap!");
+        let res = heap.alloc_host(Box::new(obj), |heap| self.mark_roots(heap));
+        self.restore_heap(heap);
+        res
+    }
+
+    pub fn alloc_list_ro(&mut self, v: Vec<Value>) -> VMResult<Value> {
+        let mut heap = self.heap.take().expect("VM must have a Heap!");
+        let res = heap
+            .alloc_vector(v, MutState::Immutable, |heap| self.mark_roots(heap))
+            .map(|v| Value::List(v.get_handle().expect("Allocated vector not a vector?"), 0));
+        self.restore_heap(heap);
+        res
+    }
+
+    /// Allocate the rest (&) argument list for a call (see Heap::alloc_rest_list).
+    pub(crate) fn alloc_rest_list(&mut self, v: Vec<Value>) -> Value {
+        let mut heap = self.heap.take().expect("VM must have a Heap!");
+        let res = heap.alloc_rest_list(v, |heap| self.mark_roots(heap));
+        self.restore_heap(heap);
+        res
+    }
+
+    pub fn alloc_bytes(&mut self, v: Vec<u8>) -> VMResult<Value> {
         let mut heap = self.heap.take().expect("VM must have a Heap!");
         // alloc must not save mark_roots (it does not) since we broke heap away from self.
         let res = heap.alloc_bytes(v, MutState::Mutable, |heap| self.mark_roots(heap));
-        self.heap = Some(heap);
+        self.restore_heap(heap);
         res
     }
 
-    pub fn alloc_lambda(&mut self, l: Arc<Chunk>) -> Value {
+    pub fn alloc_lambda(&mut self, l: Arc<Chunk>) -> VMResult<Value> {
         let mut heap = self.heap.take().expect("VM must have a Heap!");
         // alloc must not save mark_roots (it does not) since we broke heap away from self.
         let res = heap.alloc_lambda(l, |heap| self.mark_roots(heap));
-        self.heap = Some(heap);
+        self.restore_heap(heap);
         res
     }
 
-    pub fn alloc_closure(&mut self, l: Arc<Chunk>, v: Vec<Handle>) -> Value {
+    pub fn alloc_closure(&mut self, l: Arc<Chunk>, v: Vec<Handle>) -> VMResult<Value> {
         let mut heap = self.heap.take().expect("VM must have a Heap!");
         // alloc must not save mark_roots (it does not) since we broke heap away from self.
         let res = heap.alloc_closure(l, v, |heap| self.mark_roots(heap));
-        self.heap = Some(heap);
+        self.restore_heap(heap);
+        res
+    }
+
+    /// Return i as an Int if it fits, otherwise allocate it as a BigInt.
+    pub fn alloc_int(&mut self, i: i64) -> VMResult<Value> {
+        if fits_i56(i) {
+            Ok(i.into())
+        } else {
+            self.alloc_bigint(i.into())
+        }
+    }
+
+    /// Return i as an Int if it fits, otherwise allocate it as a BigInt.  Equal big ints will
+    /// share a handle.
+    pub fn alloc_bigint(&mut self, i: BigInt) -> VMResult<Value> {
+        if let Some(val) = bigint_to_i56(&i) {
+            return Ok(val);
+        }
+        let mut heap = self.heap.take().expect("VM must have a Heap!");
+        // alloc must not save mark_roots (it does not) since we broke heap away from self.
+        let res = heap.alloc_bigint(i, |heap| self.mark_roots(heap));
+        self.restore_heap(heap);
         res
     }
 
@@ -210,7 +329,7 @@ impl<ENV> GVm<ENV> {
         let mut heap = self.heap.take().expect("VM must have a Heap!");
         // alloc must not save mark_roots (it does not) since we broke heap away from self.
         let res = heap.alloc_continuation(k, |heap| self.mark_roots(heap));
-        self.heap = Some(heap);
+        self.restore_heap(heap);
         res
     }
 
@@ -218,16 +337,16 @@ impl<ENV> GVm<ENV> {
         let mut heap = self.heap.take().expect("VM must have a Heap!");
         // alloc must not save mark_roots (it does not) since we broke heap away from self.
         let res = heap.alloc_callframe(frame, |heap| self.mark_roots(heap));
-        self.heap = Some(heap);
+        self.restore_heap(heap);
         res
     }
 
     /// Allocate a Value on the heap.  Moving a value to the heap is useful for captured variable
     /// for instance.
-    pub fn alloc_value(&mut self, val: Value) -> Value {
+    pub fn alloc_value(&mut self, val: Value) -> VMResult<Value> {
         let mut heap = self.heap.take().expect("VM must have a Heap!");
         let res = heap.alloc_value(val, MutState::Mutable, |heap| self.mark_roots(heap));
-        self.heap = Some(heap);
+        self.restore_heap(heap);
         res
     }
 
@@ -235,7 +354,7 @@ impl<ENV> GVm<ENV> {
     pub fn alloc_error(&mut self, err: Error) -> Value {
         let mut heap = self.heap.take().expect("VM must have a Heap!");
         let res = heap.alloc_error(err, MutState::Mutable, |heap| self.mark_roots(heap));
-        self.heap = Some(heap);
+        self.restore_heap(heap);
         res
     }
 
@@ -263,6 +382,44 @@ impl<ENV> GVm<ENV> {
         self.heap_mut().unpause_gc();
     }
 
+    /// Collect incrementally, tracing at most step objects per allocation while a collection is in
+    /// progress (bounds GC pauses).  A step of 0 (the default) does stop the world collections.
+    pub fn set_gc_step(&mut self, step: usize) {
+        self.heap_mut().set_gc_step(step);
+    }
+
+    pub fn gc_step(&self) -> usize {
+        self.heap().gc_step()
+    }
+
+    /// Limit the heap to about max bytes of live objects (None for no limit).  An allocation that
+    /// would go over the limit, even after a full collection, fails with an :oom error (see
+    /// Heap::set_max_bytes).
+    pub fn set_heap_max(&mut self, max: Option<usize>) {
+        self.heap_mut().set_max_bytes(max);
+    }
+
+    pub fn heap_max(&self) -> Option<usize> {
+        self.heap().max_bytes()
+    }
+
+    /// Factor the heap grows by when a collection does not free enough space.
+    pub fn set_grow_factor(&mut self, grow_factor: f64) {
+        self.heap_mut().set_grow_factor(grow_factor);
+    }
+
+    pub fn heap_stats(&self) -> HeapStats {
+        self.heap().stats()
+    }
+
+    /// Force a full garbage collection, returns false (and does nothing) if GC is paused.
+    pub fn gc(&mut self) -> bool {
+        let mut heap = self.heap.take().expect("VM must have a Heap!");
+        let res = heap.gc(|heap| self.mark_roots(heap));
+        self.restore_heap(heap);
+        res
+    }
+
     pub fn get_heap_property(&self, key_val: Value, prop: &str) -> Option<Value> {
         if let Some(interned) = self.get_if_interned(prop) {
             self.heap().get_property(key_val, interned)
@@ -289,6 +446,9 @@ impl<ENV> GVm<ENV> {
     }
 
     pub fn set_global_property(&mut self, global: u32, prop: Interned, value: Value) {
+        if let Some(heap) = self.heap.as_mut() {
+            heap.root_barrier(value);
+        }
         self.globals.set_property(global, prop, value)
     }
 
@@ -312,18 +472,30 @@ impl<ENV> GVm<ENV> {
         self.heap_mut().get_vector_mut(handle)
     }
 
-    pub fn get_map(&self, handle: Handle) -> &HashMap<Value, Value> {
+    pub fn get_map(&self, handle: Handle) -> &VMMap {
         self.heap().get_map(handle)
     }
 
-    pub fn get_map_mut(&mut self, handle: Handle) -> VMResult<&mut HashMap<Value, Value>> {
+    pub fn get_map_mut(&mut self, handle: Handle) -> VMResult<&mut VMMap> {
         self.heap_mut().get_map_mut(handle)
     }
 
+    pub fn get_set(&self, handle: Handle) -> &VMSet {
+        self.heap().get_set(handle)
+    }
+
+    pub fn get_set_mut(&mut self, handle: Handle) -> VMResult<&mut VMSet> {
+        self.heap_mut().get_set_mut(handle)
+    }
+
     pub fn get_bytes(&self, handle: Handle) -> &[u8] {
         self.heap().get_bytes(handle)
     }
 
+    pub fn get_bytes_mut(&mut self, handle: Handle) -> VMResult<&mut Vec<u8>> {
+        self.heap_mut().get_bytes_mut(handle)
+    }
+
     pub fn get_pair(&self, handle: Handle) -> (Value, Value) {
         self.heap().get_pair(handle)
     }
@@ -344,6 +516,60 @@ impl<ENV> GVm<ENV> {
         self.heap().get_closure(handle)
     }
 
+    pub fn get_bigint(&self, handle: Handle) -> &BigInt {
+        self.heap().get_bigint(handle)
+    }
+
+    pub fn get_pvec(&self, handle: Handle) -> &PVec {
+        self.heap().get_pvec(handle)
+    }
+
+    pub fn get_pmap(&self, handle: Handle) -> &PMap {
+        self.heap().get_pmap(handle)
+    }
+
+    /// Target of a weak reference, None if it has been collected.
+    pub fn get_weak(&self, handle: Handle) -> Option<Value> {
+        self.heap().get_weak(handle)
+    }
+
+    pub fn get_host(&self, handle: Handle) -> &dyn HostObject {
+        self.heap().get_host(handle)
+    }
+
+    /// Get a host object as its concrete type, an error if val is not a host object of type T.
+    pub fn get_host_ref<T: HostObject>(&self, val: Value) -> VMResult<&T> {
+        if let Value::Host(handle) = val {
+            let obj: &dyn Any = self.heap().get_host(handle);
+            if let Some(obj) = obj.downcast_ref::<T>() {
+                return Ok(obj);
+            }
+        }
+        Err(VMError::new_conversion(format!(
+            "Wrong type, expected a host object, got {}.",
+            val.display_type(self)
+        )))
+    }
+
+    /// Get a host object as its concrete (mutable) type, an error if val is not a host object of
+    /// type T.
+    pub fn get_host_mut<T: HostObject>(&mut self, val: Value) -> VMResult<&mut T> {
+        let type_name = val.display_type(self);
+        if let Value::Host(handle) = val {
+            let obj: &mut dyn Any = self.heap_mut().get_host_mut(handle)?;
+            if let Some(obj) = obj.downcast_mut::<T>() {
+                return Ok(obj);
+            }
+        }
+        Err(VMError::new_conversion(format!(
+            "Wrong type, expected a host object, got {type_name}."
+        )))
+    }
+
+    pub fn is_weak_map(&self, handle: Handle) -> bool {
+        self.heap().is_weak_map(handle)
+    }
+
     pub fn get_continuation(&self, handle: Handle) -> &Continuation {
         self.heap().get_continuation(handle)
     }
@@ -364,7 +590,7 @@ impl<ENV> GVm<ENV> {
         self.heap().get_error(handle)
     }
 
-    pub fn new_upval(&mut self, val: Value) -> Value {
+    pub fn new_upval(&mut self, val: Value) -> VMResult<Value> {
         self.alloc_value(val)
     }
 
@@ -374,7 +600,11 @@ impl<ENV> GVm<ENV> {
 
     pub fn make_err(&mut self, key: &'static str, data: Value) -> Value {
         let keyword = self.intern_static(key);
-        let err = Error { keyword, data };
+        let err = Error {
+            keyword,
+            data,
+            backtrace: None,
+        };
         self.alloc_error(err)
     }
 
@@ -397,11 +627,24 @@ impl<ENV> GVm<ENV> {
         }
     }
 
+    /// Put the heap back after anything that may have run a collection.  A full collection records
+    /// the live symbols so any unreferenced weak or uninterned symbols are reclaimed here.
+    fn restore_heap(&mut self, mut heap: Heap) {
+        if let Some(live) = heap.take_live_symbols() {
+            self.interner.reclaim(|id| live.contains(&id));
+        }
+        self.heap = Some(heap);
+    }
+
     fn mark_roots(&mut self, heap: &mut Heap) -> VMResult<()> {
-        self.globals.mark(heap);
+        // Globals are stored with a write barrier (see set_global) so the rescan that finishes an
+        // incremental collection can skip them.
+        if !heap.is_rescanning() {
+            self.globals.mark(heap);
+        }
         // TODO- add a bound to ENV so we can call a mark_roots?  I think we need this for the
         // temporarily held doc_string for instance but also generally useful?
-        for i in 0..self.stack_max {
+        for i in 0..=self.stack_max {
             heap.mark(self.stack(i));
         }
         if let Some(this_fn) = self.this_fn {
@@ -416,6 +659,15 @@ impl<ENV> GVm<ENV> {
         for defer in &self.defers {
             heap.mark(*defer);
         }
+        self.trace_mark(heap);
+        self.restarts_mark(heap);
+        self.bindings_mark(heap);
+        for val in self.last_backtrace.iter().flat_map(|b| b.values()) {
+            heap.mark(val);
+        }
+        for chunk in &self.exec_chunks {
+            heap.mark_chunk(chunk);
+        }
         Ok(())
     }
 }
@@ -0,0 +1,464 @@
+//! Call tracing, functions marked with trace report their calls, returns and errors to a
+//! callback (or stderr).
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::{Chunk, GVm, Heap, Interned, VMError, VMErrorObj, VMResult, Value};
+
+/// Something being traced.  Lambdas and closures are identified by their chunk so every closure
+/// made from the same lambda is traced.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+enum TraceKey {
+    Chunk(usize),
+    Builtin(u32),
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum TraceEvent {
+    Call,
+    TailCall,
+    Return,
+    Error,
+}
+
+impl TraceEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            TraceEvent::Call => "call",
+            TraceEvent::TailCall => "tail-call",
+            TraceEvent::Return => "return",
+            TraceEvent::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct TraceEntry {
+    name: Interned,
+    // Stack top of the call, a return to this stack top finishes it.
+    stack_top: usize,
+}
+
+#[derive(Debug, Default)]
+pub(super) struct Trace {
+    // Name to report for each traced thing, the chunk is held so its address is not reused.
+    traced: HashMap<TraceKey, (Interned, Option<Arc<Chunk>>)>,
+    // Traced calls in progress.
+    stack: Vec<TraceEntry>,
+    callback: Option<Value>,
+    // Values being handed to the callback, these may not be on the stack.
+    pending: Vec<Value>,
+    // Set while the callback is running so it is not traced.
+    in_callback: bool,
+}
+
+impl Trace {
+    /// Drop calls at or above stack_top, these were replaced or exited without a return
+    /// (a continuation).
+    fn drop_stale(&mut self, stack_top: usize) {
+        while matches!(self.stack.last(), Some(entry) if entry.stack_top >= stack_top) {
+            self.stack.pop();
+        }
+    }
+}
+
+impl<ENV> GVm<ENV> {
+    fn trace_key(&self, val: Value) -> Option<TraceKey> {
+        match val {
+            Value::Lambda(h) => Some(TraceKey::Chunk(Arc::as_ptr(&self.get_lambda(h)) as usize)),
+            Value::Closure(h) => {
+                Some(TraceKey::Chunk(Arc::as_ptr(&self.get_closure(h).0) as usize))
+            }
+            Value::Builtin(idx) => Some(TraceKey::Builtin(idx)),
+            _ => None,
+        }
+    }
+
+    /// Trace calls to val (a lambda, closure or builtin), reporting them as name.
+    pub fn trace(&mut self, name: Interned, val: Value) -> VMResult<()> {
+        let chunk = match val {
+            Value::Lambda(h) => Some(self.get_lambda(h)),
+            Value::Closure(h) => Some(self.get_closure(h).0),
+            Value::Builtin(_) => None,
+            _ => {
+                return Err(VMError::new_vm(format!(
+                    "trace: {} is not a function",
+                    self.get_interned(name)
+                )))
+            }
+        };
+        let key = self.trace_key(val).expect("traced value must have a key");
+        self.trace
+            .get_or_insert_with(Default::default)
+            .traced
+            .insert(key, (name, chunk));
+        Ok(())
+    }
+
+    /// Stop tracing anything traced as name, returns false if nothing was.
+    pub fn untrace(&mut self, name: Interned) -> bool {
+        let Some(trace) = &mut self.trace else {
+            return false;
+        };
+        let len = trace.traced.len();
+        trace.traced.retain(|_, (n, _)| *n != name);
+        let removed = trace.traced.len() != len;
+        self.trace_cleanup();
+        removed
+    }
+
+    /// Stop tracing everything.
+    pub fn untrace_all(&mut self) {
+        if let Some(trace) = &mut self.trace {
+            trace.traced.clear();
+        }
+        self.trace_cleanup();
+    }
+
+    /// Names of everything currently traced.
+    pub fn traced(&self) -> Vec<Interned> {
+        self.trace
+            .as_ref()
+            .map_or_else(Vec::new, |t| t.traced.values().map(|(n, _)| *n).collect())
+    }
+
+    /// Set a lambda or closure to receive trace events instead of printing them to stderr.  It is
+    /// called with the event (:call, :tail-call, :return or :error), the call depth (starting at
+    /// 1), the traced name and the arguments (a vector), return value or error (a pair of the
+    /// error key and object).
+    pub fn set_trace_callback(&mut self, callback: Option<Value>) -> VMResult<()> {
+        if !matches!(
+            callback,
+            None | Some(Value::Lambda(_)) | Some(Value::Closure(_))
+        ) {
+            return Err(VMError::new_vm(
+                "trace callback must be a lambda or closure".to_string(),
+            ));
+        }
+        self.trace.get_or_insert_with(Default::default).callback = callback;
+        self.trace_cleanup();
+        Ok(())
+    }
+
+    pub fn trace_callback(&self) -> Option<Value> {
+        self.trace.as_ref().and_then(|t| t.callback)
+    }
+
+    // Drop the trace state once nothing is traced so the call path is back to one check.
+    fn trace_cleanup(&mut self) {
+        if matches!(&self.trace, Some(t) if t.traced.is_empty() && t.callback.is_none()) {
+            self.trace = None;
+        }
+    }
+
+    pub(super) fn trace_mark(&self, heap: &mut Heap) {
+        if let Some(trace) = &self.trace {
+            if let Some(callback) = trace.callback {
+                heap.mark(callback);
+            }
+            for val in &trace.pending {
+                heap.mark(*val);
+            }
+            for (name, _) in trace.traced.values() {
+                heap.mark_symbol(*name);
+            }
+            for entry in &trace.stack {
+                heap.mark_symbol(entry.name);
+            }
+        }
+    }
+
+    /// Name lambda is traced as, None if not traced or a callback is running.
+    fn trace_name(&self, lambda: Value) -> Option<Interned> {
+        let trace = self.trace.as_ref()?;
+        if trace.in_callback {
+            return None;
+        }
+        let key = self.trace_key(lambda)?;
+        trace.traced.get(&key).map(|(name, _)| *name)
+    }
+
+    fn trace_push(&mut self, name: Interned, stack_top: usize, stale_from: usize) -> usize {
+        let trace = self.trace.as_mut().expect("tracing");
+        trace.drop_stale(stale_from);
+        trace.stack.push(TraceEntry { name, stack_top });
+        trace.stack.len()
+    }
+
+    /// make_call is about to call lambda, report it if traced.
+    #[inline]
+    pub(super) fn trace_call(
+        &mut self,
+        lambda: Value,
+        first_reg: u16,
+        num_args: u16,
+        tail_call: bool,
+    ) {
+        if self.trace.is_none() {
+            return;
+        }
+        let Some(name) = self.trace_name(lambda) else {
+            return;
+        };
+        let first_reg = first_reg as usize;
+        let args: Vec<Value> = (1..=num_args as usize)
+            .map(|i| self.register(first_reg + i))
+            .collect();
+        let (stack_top, stale_from) = match lambda {
+            Value::Builtin(_) => {
+                let top = self.stack_top + first_reg + 1;
+                (top, top)
+            }
+            // Replaces the traced call at this stack top (if any) so keeps its depth.
+            _ if tail_call => (self.stack_top, self.stack_top),
+            _ => (self.stack_top + first_reg, self.stack_top + first_reg),
+        };
+        let depth = self.trace_push(name, stack_top, stale_from);
+        let event = if tail_call {
+            TraceEvent::TailCall
+        } else {
+            TraceEvent::Call
+        };
+        self.trace_emit(event, depth, name, TraceData::Args(args));
+    }
+
+    /// A builtin called by make_call finished, report it if traced.
+    #[inline]
+    pub(super) fn trace_builtin_return(
+        &mut self,
+        lambda: Value,
+        first_reg: u16,
+        res: &VMResult<Value>,
+    ) {
+        if self.trace.is_none() {
+            return;
+        }
+        if self.trace_name(lambda).is_none() {
+            return;
+        }
+        let stack_top = self.stack_top + first_reg as usize + 1;
+        let trace = self.trace.as_mut().expect("tracing");
+        trace.drop_stale(stack_top + 1);
+        if !matches!(trace.stack.last(), Some(entry) if entry.stack_top == stack_top) {
+            return;
+        }
+        let depth = trace.stack.len();
+        let entry = trace.stack.pop().expect("traced call");
+        let data = match res {
+            Ok(val) => TraceData::Return(*val),
+            Err(e) => TraceData::Error(e),
+        };
+        self.trace_emit(data.event(), depth, entry.name, data);
+    }
+
+    /// The frame at the current stack top is returning val, report any traced calls it finishes.
+    #[inline]
+    pub(super) fn trace_return(&mut self, val: Value) {
+        let Some(trace) = &mut self.trace else {
+            return;
+        };
+        if trace.in_callback {
+            return;
+        }
+        trace.drop_stale(self.stack_top + 1);
+        while let Some(trace) = &mut self.trace {
+            if !matches!(trace.stack.last(), Some(entry) if entry.stack_top == self.stack_top) {
+                break;
+            }
+            let depth = trace.stack.len();
+            let entry = trace.stack.pop().expect("traced call");
+            self.trace_emit(
+                TraceEvent::Return,
+                depth,
+                entry.name,
+                TraceData::Return(val),
+            );
+        }
+    }
+
+    /// do_call is about to run chunk with params at the current stack top, report it if traced.
+    pub(super) fn trace_do_call(&mut self, chunk: &Arc<Chunk>, params: &[Value]) {
+        let Some(trace) = &self.trace else {
+            return;
+        };
+        if trace.in_callback {
+            return;
+        }
+        let key = TraceKey::Chunk(Arc::as_ptr(chunk) as usize);
+        let Some((name, _)) = trace.traced.get(&key) else {
+            return;
+        };
+        let name = *name;
+        let depth = self.trace_push(name, self.stack_top, self.stack_top);
+        self.trace_emit(
+            TraceEvent::Call,
+            depth,
+            name,
+            TraceData::Args(params.to_vec()),
+        );
+    }
+
+    /// Number of traced calls in progress.
+    pub(super) fn trace_depth(&self) -> usize {
+        self.trace.as_ref().map_or(0, |t| t.stack.len())
+    }
+
+    /// Report err for every traced call above depth, these are being unwound.
+    pub(super) fn trace_error(&mut self, err: &VMError, depth: usize) {
+        while let Some(trace) = &mut self.trace {
+            if trace.stack.len() <= depth {
+                break;
+            }
+            let entry_depth = trace.stack.len();
+            let entry = trace.stack.pop().expect("traced call");
+            if !trace.in_callback {
+                self.trace_emit(
+                    TraceEvent::Error,
+                    entry_depth,
+                    entry.name,
+                    TraceData::Error(err),
+                );
+            }
+        }
+    }
+
+    /// Report err for every traced call at or above stack_top, an error handler is replacing
+    /// the erroring frame so these are unwound.
+    pub(super) fn trace_unwind(&mut self, err: &VMError, stack_top: usize) {
+        let Some(trace) = &self.trace else {
+            return;
+        };
+        let depth = trace
+            .stack
+            .iter()
+            .position(|entry| entry.stack_top >= stack_top)
+            .unwrap_or(trace.stack.len());
+        self.trace_error(err, depth);
+    }
+
+    /// Forget traced calls above depth without reporting them.
+    pub(super) fn trace_truncate(&mut self, depth: usize) {
+        if let Some(trace) = &mut self.trace {
+            trace.stack.truncate(depth);
+        }
+    }
+
+    fn trace_emit(&mut self, event: TraceEvent, depth: usize, name: Interned, data: TraceData) {
+        let Some(callback) = self.trace_callback() else {
+            let indent = "  ".repeat(depth.saturating_sub(1));
+            let name = self.get_interned(name);
+            match data {
+                TraceData::Args(args) => {
+                    let mut call = format!("({name}");
+                    for arg in args {
+                        call.push(' ');
+                        call.push_str(&arg.display_value(self));
+                    }
+                    call.push(')');
+                    let tail = if event == TraceEvent::TailCall {
+                        " [tail call]"
+                    } else {
+                        ""
+                    };
+                    eprintln!("{indent}{depth}: {call}{tail}");
+                }
+                TraceData::Return(val) => {
+                    eprintln!(
+                        "{indent}{depth}: {name} returned {}",
+                        val.display_value(self)
+                    );
+                }
+                TraceData::Error(err) => eprintln!("{indent}{depth}: {name} error {err}"),
+            }
+            return;
+        };
+        let trace = self.trace.as_mut().expect("tracing");
+        let pending = trace.pending.len();
+        match &data {
+            TraceData::Args(args) => trace.pending.extend_from_slice(args),
+            TraceData::Return(val) => trace.pending.push(*val),
+            TraceData::Error(err) => {
+                if let VMErrorObj::Object(v) = &err.obj {
+                    trace.pending.push(*v);
+                }
+            }
+        }
+        let data = match self.trace_data(data) {
+            Ok(data) => data,
+            Err(e) => {
+                eprintln!("trace callback error: {e}");
+                if let Some(trace) = &mut self.trace {
+                    trace.pending.truncate(pending);
+                }
+                return;
+            }
+        };
+        let params = [
+            Value::Keyword(self.intern_static(event.name())),
+            (depth as i64).into(),
+            Value::Symbol(name),
+            data,
+        ];
+        let (chunk, caps) = match callback {
+            Value::Lambda(h) => (self.get_lambda(h), None),
+            Value::Closure(h) => {
+                let (chunk, caps) = self.get_closure(h);
+                (chunk, Some(caps.to_vec()))
+            }
+            _ => return,
+        };
+        if let Some(trace) = &mut self.trace {
+            trace.in_callback = true;
+        }
+        // do_call restores everything the exec loop needs except these.
+        let current_ip = self.current_ip_ptr;
+        if let Err(e) = self.do_call(chunk, &params, caps.as_deref()) {
+            eprintln!("trace callback error: {e}");
+        }
+        self.current_ip_ptr = current_ip;
+        self.make_registers();
+        if let Some(trace) = &mut self.trace {
+            trace.in_callback = false;
+            trace.pending.truncate(pending);
+        }
+    }
+
+    /// The data argument for the trace callback.
+    fn trace_data(&mut self, data: TraceData) -> VMResult<Value> {
+        match data {
+            TraceData::Args(args) => self.alloc_vector(args),
+            TraceData::Return(val) => Ok(val),
+            TraceData::Error(err) => {
+                let key = Value::Keyword(self.intern_static(err.key));
+                let obj = match &err.obj {
+                    VMErrorObj::Message(msg) => {
+                        let msg = self.alloc_string(msg.clone())?;
+                        if let Some(trace) = &mut self.trace {
+                            trace.pending.push(msg);
+                        }
+                        msg
+                    }
+                    VMErrorObj::Object(v) => *v,
+                };
+                self.alloc_pair(key, obj)
+            }
+        }
+    }
+}
+
+enum TraceData<'a> {
+    Args(Vec<Value>),
+    Return(Value),
+    Error(&'a VMError),
+}
+
+impl TraceData<'_> {
+    fn event(&self) -> TraceEvent {
+        match self {
+            TraceData::Args(_) => TraceEvent::Call,
+            TraceData::Return(_) => TraceEvent::Return,
+            TraceData::Error(_) => TraceEvent::Error,
+        }
+    }
+}